@@ -0,0 +1,456 @@
+//! Animated PNGs (APNG), as described in [the APNG
+//! spec](https://wiki.mozilla.org/APNG_Specification), which the third
+//! edition of the PNG spec adopted.
+//!
+//! An APNG is an ordinary PNG with three more chunk types: `acTL` says how
+//! many frames there are, an `fcTL` before each frame gives its size,
+//! position, and timing, and `fdAT` chunks hold the image data of every frame
+//! but the first, just like IDAT chunks but with a sequence number in front.
+//! The first frame's image data is in the IDAT chunks, if it has an `fcTL`
+//! before them; if it doesn't, the IDAT image is only shown by decoders that
+//! don't understand APNG, and isn't a frame.
+//!
+//! A frame is stored as only the part of the canvas that changes, so a frame
+//! on its own is that part, not the whole picture at that point.
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::info::ImageHeader;
+use crate::png::Png;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+/// The contents of an `fcTL` chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameControl {
+    /// Where this chunk is in the sequence of `fcTL` and `fdAT` chunks,
+    /// counting from 0.
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Where the frame goes on the canvas.
+    pub x_offset: u32,
+    pub y_offset: u32,
+    /// How long to show the frame for: `delay_num / delay_den` seconds, where
+    /// a `delay_den` of 0 means 100.
+    pub delay_num: u16,
+    pub delay_den: u16,
+    /// What to do with the frame's area before the next frame: 0 leaves it,
+    /// 1 clears it, and 2 puts back what was there before.
+    pub dispose_op: u8,
+    /// 0 replaces the area with the frame, and 1 draws the frame over it.
+    pub blend_op: u8,
+}
+
+impl FrameControl {
+    /// The length of an `fcTL` chunk's data.
+    const LENGTH: usize = 26;
+
+    /// Parse an `fcTL` chunk's data.
+    pub fn parse(data: &[u8]) -> crate::Result<Self> {
+        if data.len() != Self::LENGTH {
+            return Err(ApngError::boxed(format!(
+                "fcTL is {} bytes long (expected {})",
+                data.len(),
+                Self::LENGTH
+            )));
+        }
+        let u32_at = |i: usize| u32::from_be_bytes(data[i..i + 4].try_into().unwrap());
+        let u16_at = |i: usize| u16::from_be_bytes(data[i..i + 2].try_into().unwrap());
+        Ok(FrameControl {
+            sequence_number: u32_at(0),
+            width: u32_at(4),
+            height: u32_at(8),
+            x_offset: u32_at(12),
+            y_offset: u32_at(16),
+            delay_num: u16_at(20),
+            delay_den: u16_at(22),
+            dispose_op: data[24],
+            blend_op: data[25],
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(Self::LENGTH);
+        for n in &[
+            self.sequence_number,
+            self.width,
+            self.height,
+            self.x_offset,
+            self.y_offset,
+        ] {
+            data.extend_from_slice(&n.to_be_bytes());
+        }
+        data.extend_from_slice(&self.delay_num.to_be_bytes());
+        data.extend_from_slice(&self.delay_den.to_be_bytes());
+        data.push(self.dispose_op);
+        data.push(self.blend_op);
+        data
+    }
+}
+
+/// One frame of an animation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub control: FrameControl,
+    /// The frame's compressed image data, from its IDAT or `fdAT` chunks
+    /// (without their sequence numbers), joined together.
+    pub data: Vec<u8>,
+}
+
+/// Every frame of the animation in `png`, in order. Returns `Err` if it
+/// isn't an APNG, or its `fcTL` and `fdAT` chunks are out of sequence or
+/// damaged.
+pub fn frames(png: &Png) -> crate::Result<Vec<Frame>> {
+    if png.chunk_by_type(ChunkType::ACTL).is_none() {
+        return Err(ApngError::boxed(
+            "There's no acTL chunk, so it isn't animated".to_string(),
+        ));
+    }
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut sequence_number = 0;
+    let mut check_sequence = |n: u32| {
+        if n == sequence_number {
+            sequence_number += 1;
+            Ok(())
+        } else {
+            Err(ApngError::boxed(format!(
+                "Chunk number {} in the animation is out of sequence (expected {})",
+                n, sequence_number
+            )))
+        }
+    };
+    for chunk in png.chunks() {
+        match *chunk.chunk_type() {
+            ChunkType::FCTL => {
+                let control = FrameControl::parse(chunk.data())?;
+                check_sequence(control.sequence_number)?;
+                frames.push(Frame {
+                    control,
+                    data: Vec::new(),
+                });
+            }
+            // IDAT only belongs to the first frame, and only if its fcTL comes
+            // first.
+            ChunkType::IDAT => {
+                if let [first] = frames.as_mut_slice() {
+                    first.data.extend_from_slice(chunk.data());
+                }
+            }
+            ChunkType::FDAT => {
+                let data = chunk.data();
+                if data.len() < 4 {
+                    return Err(ApngError::boxed("fdAT chunk is truncated".to_string()));
+                }
+                check_sequence(u32::from_be_bytes(data[..4].try_into()?))?;
+                match frames.last_mut() {
+                    Some(frame) => frame.data.extend_from_slice(&data[4..]),
+                    None => {
+                        return Err(ApngError::boxed(
+                            "There's an fdAT chunk before the first fcTL".to_string(),
+                        ))
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(empty) = frames.iter().position(|f| f.data.is_empty()) {
+        return Err(ApngError::boxed(format!(
+            "Frame {} has no image data",
+            empty + 1
+        )));
+    }
+    Ok(frames)
+}
+
+/// Each frame of the animation in `png` as a PNG of its own, in order. Each
+/// has the animation's header with the frame's size, and the chunks before
+/// the image data that say how to show it (like `PLTE`, `gAMA`, and `tRNS`).
+/// Returns `Err` if [frames](fn.frames.html) does.
+pub fn split(png: &Png) -> crate::Result<Vec<Png>> {
+    let header = ImageHeader::from_png(png)?;
+    let first_idat = png
+        .chunks()
+        .iter()
+        .position(|c| c.chunk_type() == &ChunkType::IDAT)
+        .unwrap_or(png.chunk_count());
+    let shared: Vec<&Chunk> = png.chunks()[..first_idat]
+        .iter()
+        .filter(|c| ![ChunkType::IHDR, ChunkType::ACTL, ChunkType::FCTL].contains(c.chunk_type()))
+        .collect();
+    frames(png)?
+        .into_iter()
+        .enumerate()
+        .map(|(n, frame)| {
+            let mut ihdr = png
+                .chunk_by_type(ChunkType::IHDR)
+                .expect("ImageHeader::from_png found it")
+                .data()
+                .to_vec();
+            ihdr[0..4].copy_from_slice(&frame.control.width.to_be_bytes());
+            ihdr[4..8].copy_from_slice(&frame.control.height.to_be_bytes());
+            let fits = u64::from(frame.control.x_offset) + u64::from(frame.control.width)
+                <= u64::from(header.width)
+                && u64::from(frame.control.y_offset) + u64::from(frame.control.height)
+                    <= u64::from(header.height);
+            if !fits {
+                return Err(
+                    ApngError::boxed(format!("Frame {} doesn't fit on the canvas", n + 1)).into(),
+                );
+            }
+            let mut chunks = vec![Chunk::new(ChunkType::IHDR, ihdr)];
+            chunks.extend(
+                shared
+                    .iter()
+                    .map(|c| Chunk::new(*c.chunk_type(), c.data().to_vec())),
+            );
+            chunks.push(Chunk::new(ChunkType::IDAT, frame.data));
+            chunks.push(Chunk::new(ChunkType::IEND, vec![]));
+            Ok(Png::from_chunks(chunks))
+        })
+        .collect()
+}
+
+/// Build an animation out of `frames`, which are shown in order for
+/// `delay_num / delay_den` seconds each, and played `plays` times (0 for
+/// forever). Every frame fills the whole canvas. The animation gets the first
+/// frame's chunks that say how to show it (like `PLTE` and `gAMA`). Returns
+/// `Err` if there are no frames, or they don't all have the same header and
+/// palette.
+pub fn build(frames: &[Png], delay_num: u16, delay_den: u16, plays: u32) -> crate::Result<Png> {
+    let first = frames
+        .first()
+        .ok_or_else(|| ApngError::boxed("There are no frames".to_string()))?;
+    let header = ImageHeader::from_png(first)?;
+    let palette = |png: &Png| {
+        png.chunk_by_type(ChunkType::PLTE)
+            .map(|c| c.data().to_vec())
+    };
+    for (n, frame) in frames.iter().enumerate().skip(1) {
+        if ImageHeader::from_png(frame)? != header || palette(frame) != palette(first) {
+            return Err(ApngError::boxed(format!(
+                "Frame {} doesn't have the same size, color type, and palette as the first",
+                n + 1
+            )));
+        }
+    }
+    let control = |sequence_number| FrameControl {
+        sequence_number,
+        width: header.width,
+        height: header.height,
+        x_offset: 0,
+        y_offset: 0,
+        delay_num,
+        delay_den,
+        dispose_op: 0,
+        blend_op: 0,
+    };
+    let image_data = |png: &Png| -> Vec<u8> {
+        png.chunks()
+            .iter()
+            .filter(|c| c.chunk_type() == &ChunkType::IDAT)
+            .flat_map(|c| c.data().iter().copied())
+            .collect()
+    };
+
+    let first_idat = first
+        .chunks()
+        .iter()
+        .position(|c| c.chunk_type() == &ChunkType::IDAT)
+        .unwrap_or(first.chunk_count());
+    let mut chunks: Vec<Chunk> = first.chunks()[..first_idat]
+        .iter()
+        .filter(|c| ![ChunkType::ACTL, ChunkType::FCTL].contains(c.chunk_type()))
+        .map(|c| Chunk::new(*c.chunk_type(), c.data().to_vec()))
+        .collect();
+    let mut actl = (frames.len() as u32).to_be_bytes().to_vec();
+    actl.extend_from_slice(&plays.to_be_bytes());
+    chunks.push(Chunk::new(ChunkType::ACTL, actl));
+    chunks.push(Chunk::new(ChunkType::FCTL, control(0).to_bytes()));
+    chunks.push(Chunk::new(ChunkType::IDAT, image_data(first)));
+    let mut sequence_number = 1;
+    for frame in &frames[1..] {
+        chunks.push(Chunk::new(
+            ChunkType::FCTL,
+            control(sequence_number).to_bytes(),
+        ));
+        let mut fdat = (sequence_number + 1).to_be_bytes().to_vec();
+        fdat.extend(image_data(frame));
+        chunks.push(Chunk::new(ChunkType::FDAT, fdat));
+        sequence_number += 2;
+    }
+    chunks.push(Chunk::new(ChunkType::IEND, vec![]));
+    Ok(Png::from_chunks(chunks))
+}
+
+/// A PNG isn't the animation it should be.
+#[derive(Debug)]
+pub struct ApngError {
+    reason: String,
+}
+impl ApngError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for ApngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad APNG: {}", self.reason)
+    }
+}
+impl Error for ApngError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::str::FromStr;
+
+    fn control(sequence_number: u32, width: u32, height: u32) -> FrameControl {
+        FrameControl {
+            sequence_number,
+            width,
+            height,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: 1,
+            delay_den: 12,
+            dispose_op: 0,
+            blend_op: 0,
+        }
+    }
+
+    fn fdat(sequence_number: u32, data: &[u8]) -> Chunk {
+        let mut bytes = sequence_number.to_be_bytes().to_vec();
+        bytes.extend_from_slice(data);
+        Chunk::new(ChunkType::FDAT, bytes)
+    }
+
+    /// A 1x1 animation with three frames: the IDAT image, then two more.
+    fn animation() -> Png {
+        let mut png = testing::minimal_png();
+        let idat = png.chunks()[1].data().to_vec();
+        png.insert_chunk(
+            1,
+            Chunk::new(ChunkType::ACTL, [0, 0, 0, 3, 0, 0, 0, 0].to_vec()),
+        );
+        png.insert_chunk(2, Chunk::new(ChunkType::FCTL, control(0, 1, 1).to_bytes()));
+        let end = png.chunk_count() - 1;
+        png.insert_chunk(
+            end,
+            Chunk::new(ChunkType::FCTL, control(1, 1, 1).to_bytes()),
+        );
+        png.insert_chunk(end + 1, fdat(2, &idat[..3]));
+        png.insert_chunk(end + 2, fdat(3, &idat[3..]));
+        png.insert_chunk(
+            end + 3,
+            Chunk::new(ChunkType::FCTL, control(4, 1, 1).to_bytes()),
+        );
+        png.insert_chunk(end + 4, fdat(5, &idat));
+        png
+    }
+
+    #[test]
+    fn test_frame_control_round_trip() {
+        let control = FrameControl {
+            x_offset: 3,
+            dispose_op: 1,
+            ..control(7, 10, 20)
+        };
+        assert_eq!(FrameControl::parse(&control.to_bytes()).unwrap(), control);
+        assert!(FrameControl::parse(&[0; 25]).is_err());
+    }
+
+    #[test]
+    fn test_frames() {
+        let png = animation();
+        let idat = png.chunk_by_type(ChunkType::IDAT).unwrap().data().to_vec();
+        let frames = frames(&png).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|f| f.data == idat));
+        assert_eq!(frames[2].control.sequence_number, 4);
+        assert!(super::frames(&testing::minimal_png()).is_err());
+    }
+
+    #[test]
+    fn test_frames_out_of_sequence() {
+        let mut png = animation();
+        let last = png.chunk_count() - 2;
+        png.chunks_mut()[last] = fdat(9, b"data");
+        assert!(frames(&png).is_err());
+    }
+
+    #[test]
+    fn test_build_then_split() {
+        let still = testing::minimal_png();
+        let stills = [
+            testing::minimal_png(),
+            testing::minimal_png(),
+            testing::minimal_png(),
+        ];
+        let png = build(&stills, 1, 12, 0).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            types,
+            ["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "fcTL", "fdAT", "IEND"]
+        );
+        assert_eq!(
+            png.chunk_by_type(ChunkType::ACTL).unwrap().data(),
+            &[0, 0, 0, 3, 0, 0, 0, 0]
+        );
+        let frames = frames(&png).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            (frames[1].control.delay_num, frames[1].control.delay_den),
+            (1, 12)
+        );
+        let split = split(&png).unwrap();
+        assert!(split
+            .iter()
+            .all(|frame| frame.as_bytes() == still.as_bytes()));
+    }
+
+    #[test]
+    fn test_build_mismatched_frames() {
+        assert!(build(&[], 1, 12, 0).is_err());
+        let mut bigger = testing::minimal_png();
+        let mut ihdr = bigger.chunks()[0].data().to_vec();
+        ihdr[3] = 2;
+        bigger.chunks_mut()[0] = Chunk::new(ChunkType::IHDR, ihdr);
+        assert!(build(&[testing::minimal_png(), bigger], 1, 12, 0).is_err());
+    }
+
+    #[test]
+    fn test_split() {
+        let mut png = animation();
+        png.insert_chunk(
+            1,
+            Chunk::new(ChunkType::from_str("gAMA").unwrap(), vec![0; 4]),
+        );
+        let frames = split(&png).unwrap();
+        assert_eq!(frames.len(), 3);
+        for frame in &frames {
+            let types: Vec<String> = frame
+                .chunks()
+                .iter()
+                .map(|c| c.chunk_type().to_string())
+                .collect();
+            assert_eq!(types, ["IHDR", "gAMA", "IDAT", "IEND"]);
+            assert_eq!(
+                frame.chunk_by_type(ChunkType::IDAT).unwrap().data(),
+                png.chunk_by_type(ChunkType::IDAT).unwrap().data()
+            );
+        }
+
+        // A frame bigger than the canvas.
+        let mut png = animation();
+        png.chunks_mut()[2] = Chunk::new(ChunkType::FCTL, control(0, 2, 1).to_bytes());
+        assert!(split(&png).is_err());
+    }
+}
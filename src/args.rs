@@ -1,91 +1,1600 @@
-use crate::chunk_type::ChunkType;
-use std::path::PathBuf;
+use crate::completion;
+use crate::report::ErrorFormat;
+use crate::storage;
+use clap::{ArgGroup, Args, Parser, ValueEnum};
+use clap_complete::ArgValueCompleter;
+use pngme::carrier::Format;
+use pngme::digest;
+use pngme::fields::Fields;
+use pngme::info::AncillarySummary;
+use pngme::transform::{Compress, KdfParams};
+use pngme::ChunkType;
+use std::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use structopt::clap::AppSettings;
-use structopt::StructOpt;
 
 /*
 pngme encode ./dice.png ruSt "This is a secret message!
+pngme encode ./dice.png "This is a secret message!"
+pngme encode ./dice.png ruSt "This is a secret message!" --compress --encrypt
+pngme encode ./dice.png ruSt "This is a secret message!" --encrypt --kdf-memory 65536 --kdf-iterations 3
+pngme encode ./dice.png ruSt "This is a secret message!" --encrypt --pad
+pngme encode ./dice.png ruSt "This is a secret message!" --method trailer
+pngme encode ./dice.png RuST "This is a secret message!" --allow-unsafe
 pngme decode ./dice.png ruSt
+pngme decode ./dice.png ruSt --nth 2
+pngme decode ./dice.png ruSt --output ./archive.tar --resume
+pngme decode ./dice.png ruSt --raw > archive.tar
+pngme encode ./dice.png ruSt "This is a secret message!" --split-shares 3 --threshold 2 --carriers ./two.png ./three.png
+pngme decode ./three.png ruSt --combine ./dice.png
+pngme encode ./dice.png ruSt "This is a secret message!" --stripe --carriers ./two.png ./three.png
+pngme combine ./carriers ruSt ./message.txt
+pngme plan ./carriers --payload-size 50MB
+pngme decode ./dice.png ruSt --quiet
+pngme decode ./dice.png ruSt --errors json
+pngme encode ./dice.png ruSt --field author=gabe --field build=1234
+pngme decode ./dice.png ruSt --fields
+pngme decode ./dice.png ruSt --field build
+pngme encode ./dice.png "This is a secret message!" --label diary
+pngme decode ./dice.png --label diary
+pngme encode ./dice.png "This is a secret message!" --method spread
+pngme decode ./dice.png --spread
+pngme encode ./photo.jpg ruSt "This is a secret message!"
+pngme decode ./photo.jpg ruSt
+pngme print ./sticker.webp
+pngme encode ./meme.gif ruSt "This is a secret message!"
+pngme decode ./not-really-a.png ruSt --format gif
+pngme print ./dice.png --no-verify-crc
+pngme encode ./dice.png ruSt "This is a secret message!" --preserve
+pngme encode ./dice.png ruSt "This is a secret message!" - | gzip > dice.png.gz
+pngme encode ./dice.png ruSt "This is a secret message!" --payload-warning 50
 pngme remove ./dice.png ruSt
+pngme remove ./dice.png ruSt --nth 2
 pngme print ./dice.png
+pngme p ./dice.png
+pngme print ./dice.png --format json
+pngme export ./dice.png ./dice.json
+pngme import ./dice.json ./dice.png
+pngme info ./dice.png
+pngme strip ./dice.png --trailing
+pngme strip ./dice.png --type tEXt --type tIME ./clean.png
+pngme strip ./dice.png --type tEXt --trailing --dry-run
+pngme dedupe ./dice.png
+pngme set-meta ./dice.png --title "Dice" --author "Gabe" --copyright "CC BY 4.0"
+pngme set-dpi ./dice.png 300
+pngme set-dpi ./dice.png 11811 --ppm
+pngme set-transparent ./dice.png "#ffffff"
+pngme set-background ./dice.png 0,0,255
+pngme export-meta ./dice.png ./meta.json
+pngme import-meta ./regenerated.png ./meta.json
+pngme xmp import ./dice.png ./dice.xmp
+pngme xmp export ./dice.png ./dice.xmp
+pngme icc extract ./dice.png ./dice.icc
+pngme icc embed ./dice.png ./DisplayP3.icc --name "Display P3"
+pngme apng split ./rolling.png ./frames
+pngme apng build ./frames/frame-*.png ./rolling.png --fps 12
+pngme sort ./dice.png ./sorted.png
+pngme move ./dice.png ruSt --before IDAT
+pngme move ./dice.png tEXt --nth 2 --index 1 ./moved.png
+pngme corrupt ./dice.png ./broken.png --chunk IDAT --flip-bits 4 --seed 7
+pngme corrupt ./dice.png ./broken.png --chunk ruSt --zero-crc
+pngme cat ./dice.png ru
+pngme type make rust --critical
+pngme types
+pngme formats
+pngme explain ruSt
+pngme encode ./dice.png ruSt "This is a secret message!" --sign key.pem
+pngme verify ./dice.png ruSt --pubkey pub.pem
+pngme verify ./dice.png --crc
+pngme hash ./dice.png --algo blake3
+pngme pixel-hash ./dice.png ./stripped.png
+pngme phash ./dice.png ./dice.jpg
+pngme imgdiff ./dice.png ./encoded.png
+pngme key generate mine
+pngme encode ./dice.png ruSt "This is a secret message!" --sign mine
+pngme verify ./dice.png ruSt --pubkey mine
+pngme encode ./dice.png ruSt "This is a secret message!" --filter 'gpg --encrypt -r alice'
+pngme decode ./dice.png ruSt --filter 'gpg --decrypt'
+pngme decode ./dice.png ruSt --passphrase-file /run/secrets/pngme
+pngme decode ./dice.png ruSt --extract ./out
+pngme decode ./dice.png ruSt --output auto
+pngme watch ./renders --encode-with stamp.toml
+pngme filter clean --local local.toml < dice.png
+pngme filter smudge --local local.toml < dice.png
+pngme man > pngme.1
+pngme man --out-dir ./man
+pngme shell ./dice.png
+pngme serve --listen 127.0.0.1:8080
 */
 
-#[derive(StructOpt)]
-#[structopt(global_settings(&[AppSettings::VersionlessSubcommands]))]
+#[derive(Parser)]
+#[command(
+    name = "pngme",
+    version,
+    about = "Hide secret messages inside your PNGs"
+)]
 pub struct Cli {
-    #[structopt(subcommand)]
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Don't print an error when the chunk you asked for isn't found"
+    )]
+    pub quiet: bool,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = ErrorFormat::Text,
+        help = "How to print errors to stderr"
+    )]
+    pub errors: ErrorFormat,
+    #[arg(
+        long,
+        global = true,
+        help = "Don't check chunk CRCs when only reading a PNG (faster, but damaged chunks go unnoticed)"
+    )]
+    pub no_verify_crc: bool,
+    #[arg(
+        long,
+        global = true,
+        help = "When rewriting a file, keep the modification time and (on Unix) permissions of the original"
+    )]
+    pub preserve: bool,
+    #[arg(
+        long,
+        global = true,
+        help = "Overwrite an output file that already exists (writing back to the input file is always allowed)"
+    )]
+    pub force: bool,
+    #[command(subcommand)]
     pub subcommand: Subcommand,
 }
 
-#[derive(StructOpt, Debug, PartialEq)]
+#[derive(Args, Debug, PartialEq)]
+#[command(group = ArgGroup::new("spanning"))]
 pub struct EncodeArgs {
-    #[structopt(parse(from_os_str), help = "Path to the input PNG")]
+    #[arg(help = "Path to the input PNG, JPEG, WebP, or GIF")]
     pub input_file_path: PathBuf,
-    #[structopt(
-            parse(try_from_str = ChunkType::from_str),
-            help = "Chunk type (like 'ruSt')"
-        )]
-    pub chunk_type: ChunkType,
-    #[structopt(help = "Your secret message")]
-    pub message: String,
-    #[structopt(parse(from_os_str), help = "Path to the output PNG (optional)")]
+    #[arg(
+        long,
+        value_enum,
+        help = "The image's format, instead of going by how the file starts"
+    )]
+    pub format: Option<ImageFormat>,
+    #[arg(
+        help = "Chunk type (like 'ruSt'), or leave it out and give just your secret message to use a random chunk type"
+    )]
+    pub chunk_type_or_message: String,
+    #[arg(help = "Your secret message")]
+    pub message: Option<String>,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
     pub output_file_path: Option<PathBuf>,
+    #[arg(
+        long = "field",
+        value_name = "NAME=VALUE",
+        value_parser = parse_field,
+        conflicts_with = "label",
+        help = "Hide a named field instead of a message, stored with the others as CBOR (give the chunk type but no message)"
+    )]
+    pub fields: Vec<(String, String)>,
+    #[arg(long, help = "Record the chunk type in the 'tyPe' manifest chunk")]
+    pub record: bool,
+    #[arg(
+        long,
+        conflicts_with = "record",
+        help = "Hide the message under a random chunk type, and find it again by this label (asks for a passphrase to encrypt the label index)"
+    )]
+    pub label: Option<String>,
+    #[arg(
+        long,
+        help = "Put the message at a random position among the existing chunks instead of at the end"
+    )]
+    pub randomize_position: bool,
+    #[arg(
+        long,
+        help = "Sign the message with this Ed25519 private key (a PKCS#8 PEM file, or the name of a key in the key store)"
+    )]
+    pub sign: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Pipe the message through this shell command before hiding it"
+    )]
+    pub filter: Option<String>,
+    #[arg(long, help = "Compress the message")]
+    pub compress: bool,
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        requires = "compress",
+        value_parser = parse_compression_level,
+        help = "How hard to compress the message, from 0 (not at all) to 9 (smallest), or 'auto' to leave a message that already looks compressed alone (default 6)"
+    )]
+    pub compression_level: Option<CompressionLevel>,
+    #[arg(
+        long,
+        help = "Encrypt the message with a passphrase (you will be asked for it)"
+    )]
+    pub encrypt: bool,
+    #[arg(
+        long,
+        requires = "encrypt",
+        help = "Pad the message with random bytes before encrypting it, so its length only shows roughly how big it is"
+    )]
+    pub pad: bool,
+    #[arg(
+        long,
+        requires = "encrypt",
+        value_name = "KiB",
+        help = "How much memory to use deriving the key from the passphrase (default 19456)"
+    )]
+    pub kdf_memory: Option<u32>,
+    #[arg(
+        long,
+        requires = "encrypt",
+        value_name = "N",
+        help = "How many passes to make deriving the key from the passphrase (default 2)"
+    )]
+    pub kdf_iterations: Option<u32>,
+    #[arg(
+        long,
+        help = "Split the message into several chunks of at most this many bytes"
+    )]
+    pub split_size: Option<NonZeroUsize>,
+    #[arg(
+        long,
+        value_name = "N",
+        group = "spanning",
+        requires_all = ["threshold", "carriers"],
+        conflicts_with = "label",
+        help = "Split the message into this many Shamir shares, one for the input PNG and one for each of the --carriers"
+    )]
+    pub split_shares: Option<u8>,
+    #[arg(
+        long,
+        value_name = "K",
+        requires = "split_shares",
+        help = "How many of the shares it takes to get the message back"
+    )]
+    pub threshold: Option<u8>,
+    #[arg(
+        long,
+        value_name = "PNG",
+        num_args = 1..,
+        requires = "spanning",
+        help = "The other PNGs to hide part of the message in, each changed in place"
+    )]
+    pub carriers: Vec<PathBuf>,
+    #[arg(
+        long,
+        group = "spanning",
+        requires = "carriers",
+        conflicts_with_all = ["label", "split_size", "sign"],
+        help = "Cut the message into pieces, one for the input PNG and one for each of the --carriers, with a manifest in each"
+    )]
+    pub stripe: bool,
+    #[arg(
+        long,
+        help = "Hide the message in a chunk type that could damage the image or get dropped: one that's critical, public, or unsafe to copy"
+    )]
+    pub allow_unsafe: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = EncodeMethod::Chunk,
+        help = "Where to hide the message: in a chunk, after the end of the image, or spread across the existing ancillary chunks"
+    )]
+    pub method: EncodeMethod,
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = AncillarySummary::DEFAULT_WARNING_PERCENT,
+        value_parser = clap::value_parser!(u8).range(1..=100),
+        help = "Warn when chunks that aren't in the PNG standard make up more than this percentage of the file"
+    )]
+    pub payload_warning: u8,
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+}
+
+/// Where `encode` hides the message.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum EncodeMethod {
+    /// In an ancillary chunk.
+    Chunk,
+    /// After the IEND chunk. See `pngme::trailer`.
+    Trailer,
+    /// Across the ancillary chunks that are already there. See
+    /// `pngme::spread`.
+    Spread,
+}
+
+/// How hard `encode` compresses the message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompressionLevel {
+    /// A zlib level, from 0 to 9.
+    Level(u32),
+    /// The default level, unless the message already looks compressed (or
+    /// encrypted), in which case it isn't compressed at all.
+    Auto,
+}
+
+/// A compression level is 0 to 9, or `auto`.
+fn parse_compression_level(s: &str) -> Result<CompressionLevel, String> {
+    if s.eq_ignore_ascii_case("auto") {
+        return Ok(CompressionLevel::Auto);
+    }
+    match s.parse::<u32>() {
+        Ok(level) if level <= 9 => Ok(CompressionLevel::Level(level)),
+        _ => Err(format!("'{}' is not a level from 0 to 9, or auto", s)),
+    }
+}
+
+impl EncodeArgs {
+    /// The chunk type (or `None` if it was left out) and the message.
+    /// Clap can't leave out a positional argument in the middle, so with only
+    /// two positional arguments, the second one is the message.
+    pub fn chunk_type_and_message(&self) -> crate::Result<(Option<ChunkType>, &str)> {
+        if self.label.is_some() && self.message.is_some() {
+            return Err(Box::new(UsageError(
+                "--label picks the chunk type, so give just the message".to_string(),
+            )));
+        }
+        if !self.fields.is_empty() {
+            if self.message.is_some() {
+                return Err(Box::new(UsageError(
+                    "--field takes the place of the message, so give just the chunk type"
+                        .to_string(),
+                )));
+            }
+            return Ok((Some(self.chunk_type_or_message.parse()?), ""));
+        }
+        match &self.message {
+            Some(message) => Ok((Some(self.chunk_type_or_message.parse()?), message)),
+            None => Ok((None, &self.chunk_type_or_message)),
+        }
+    }
+
+    /// The fields given with `--field`, if any were. A field given twice keeps
+    /// its last value.
+    pub fn fields(&self) -> Option<Fields> {
+        if self.fields.is_empty() {
+            return None;
+        }
+        let mut fields = Fields::new();
+        for (name, value) in &self.fields {
+            fields.insert(name, value);
+        }
+        Some(fields)
+    }
+
+    /// How to compress `message`, or `None` if it shouldn't be.
+    pub fn compression(&self, message: &[u8]) -> Option<Compress> {
+        if !self.compress {
+            return None;
+        }
+        match self.compression_level {
+            Some(CompressionLevel::Level(level)) => Some(Compress::with_level(level)),
+            Some(CompressionLevel::Auto) if !Compress::is_worthwhile(message) => None,
+            Some(CompressionLevel::Auto) | None => Some(Compress::new()),
+        }
+    }
+
+    /// The KDF settings to encrypt with, using the defaults for any that
+    /// weren't given.
+    pub fn kdf_params(&self) -> KdfParams {
+        let defaults = KdfParams::default();
+        KdfParams {
+            memory_kib: self.kdf_memory.unwrap_or(defaults.memory_kib),
+            iterations: self.kdf_iterations.unwrap_or(defaults.iterations),
+        }
+    }
+
+    /// Check for options that only make sense for chunks. Clap can only say
+    /// that options conflict with each other, not with one value of an option.
+    pub fn check_method(&self) -> Result<(), UsageError> {
+        if self.method == EncodeMethod::Trailer
+            && (self.split_size.is_some() || self.randomize_position || self.sign.is_some())
+        {
+            return Err(UsageError(
+                "--method trailer can't be used with --split-size, --randomize-position, or --sign"
+                    .to_string(),
+            ));
+        }
+        // A spread message has no chunk type of its own.
+        if self.method == EncodeMethod::Spread
+            && (self.message.is_some()
+                || !self.fields.is_empty()
+                || self.label.is_some()
+                || self.record
+                || self.split_size.is_some()
+                || self.randomize_position
+                || self.sign.is_some())
+        {
+            return Err(UsageError(
+                "--method spread takes just the message, and can't be used with --field, --label, --record, --split-size, --randomize-position, or --sign"
+                    .to_string(),
+            ));
+        }
+        if !self.carriers.is_empty() && self.method != EncodeMethod::Chunk {
+            return Err(UsageError(
+                "--split-shares and --stripe only work with --method chunk".to_string(),
+            ));
+        }
+        if let Some(shares) = self.split_shares {
+            if usize::from(shares) != self.carriers.len() + 1 {
+                return Err(UsageError(format!(
+                    "--split-shares {} needs {} --carriers besides the input PNG, not {}",
+                    shares,
+                    usize::from(shares).saturating_sub(1),
+                    self.carriers.len()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check for options that need a PNG, when the message is going in a
+    /// JPEG, WebP, or GIF.
+    pub fn check_other_format(&self) -> Result<(), UsageError> {
+        if self.method != EncodeMethod::Chunk
+            || self.label.is_some()
+            || self.record
+            || self.randomize_position
+            || self.sign.is_some()
+            || self.split_size.is_some()
+            || !self.carriers.is_empty()
+        {
+            return Err(UsageError(
+                "JPEGs, WebPs, and GIFs can only hold a message with --method chunk, without --label, --record, --randomize-position, --sign, --split-size, --split-shares, or --stripe"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether encoding only adds chunks to the end of the input file, so
+    /// they can be written without rewriting the rest of it. Anything that
+    /// looks at or changes the chunks already there needs the whole PNG, and
+    /// objects in an object store can only be replaced whole.
+    pub fn appends_in_place(&self) -> bool {
+        self.output_file_path
+            .as_ref()
+            .is_none_or(|path| path == &self.input_file_path)
+            && self.method == EncodeMethod::Chunk
+            && self.label.is_none()
+            && !self.record
+            && !self.randomize_position
+            && self.carriers.is_empty()
+            && !storage::is_remote(&self.input_file_path)
+    }
+}
+
+/// Where to get a passphrase from, for subcommands that might need one.
+#[derive(Args, Debug, Default, PartialEq)]
+pub struct PassphraseArgs {
+    #[arg(
+        long,
+        help = "Read the passphrase from the first line of this file instead of asking for it"
+    )]
+    pub passphrase_file: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "SERVICE",
+        conflicts_with = "passphrase_file",
+        help = "Get the passphrase from the keychain, under this service name, or store it there the first time (needs the keyring feature)"
+    )]
+    pub use_keyring: Option<String>,
+}
+
+/// The command-line arguments don't make sense together.
+#[derive(Debug)]
+pub struct UsageError(String);
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl Error for UsageError {}
+
+/// Check that `chunk_type` is one that a message can safely be hidden in,
+/// unless `allow_unsafe` (`--allow-unsafe`) is set. Decoders and editors
+/// treat chunks differently depending on their type, so the wrong one can
+/// damage the image or lose the message.
+pub fn check_chunk_type(chunk_type: ChunkType, allow_unsafe: bool) -> Result<(), UsageError> {
+    if allow_unsafe {
+        return Ok(());
+    }
+    let mut reasons = Vec::new();
+    if chunk_type.is_standard() {
+        reasons.push("it's a standard chunk type, so decoders will try to read the message as one");
+    } else if chunk_type.is_public() {
+        reasons.push("it's public, which is only for chunk types in the PNG specification");
+    }
+    if chunk_type.is_critical() {
+        reasons.push("it's critical, so decoders that don't know it will refuse to show the image");
+    }
+    if !chunk_type.is_safe_to_copy() {
+        reasons.push("it isn't safe to copy, so editors will drop it when they change the image");
+    }
+    if reasons.is_empty() {
+        return Ok(());
+    }
+    Err(UsageError(format!(
+        "Refusing to hide a message in a {} chunk: {} (give --allow-unsafe to do it anyway, or use a type shaped like ruSt)",
+        chunk_type,
+        reasons.join("; ")
+    )))
 }
 
-#[derive(StructOpt, Debug, PartialEq)]
+#[derive(Args, Debug, PartialEq)]
 pub struct DecodeArgs {
-    #[structopt(parse(from_os_str), help = "Path to the PNG")]
+    #[arg(help = "Path to the PNG, JPEG, WebP, or GIF")]
     pub file_path: PathBuf,
-    #[structopt(
-            parse(try_from_str = ChunkType::from_str),
+    #[arg(
+        long,
+        value_enum,
+        help = "The image's format, instead of going by how the file starts"
+    )]
+    pub format: Option<ImageFormat>,
+    #[arg(
+            value_parser = parse_chunk_type,
+            add = ArgValueCompleter::new(completion::chunk_types),
+            required_unless_present_any = ["label", "spread"],
             help = "Chunk type (like 'ruSt')"
         )]
+    pub chunk_type: Option<ChunkType>,
+    #[arg(
+        long,
+        conflicts_with = "chunk_type",
+        help = "Find the message by the label it was encoded with instead of by chunk type"
+    )]
+    pub label: Option<String>,
+    #[arg(
+        long,
+        conflicts_with_all = ["chunk_type", "label"],
+        help = "Put back together a message that was encoded with --method spread"
+    )]
+    pub spread: bool,
+    #[arg(
+        long,
+        default_value = "1",
+        help = "Which chunk of the given type to use, counting from 1"
+    )]
+    pub nth: NonZeroUsize,
+    #[arg(
+        long,
+        help = "Pipe the message through this shell command before showing it"
+    )]
+    pub filter: Option<String>,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Unpack the message into this directory, if it's a tar or zip archive, instead of showing it"
+    )]
+    pub extract: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with = "extract",
+        help = "Write the message to this file instead of showing it; 'auto' names the file after the chunk type, with an extension that fits its contents"
+    )]
+    pub output: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "output",
+        conflicts_with_all = ["filter", "combine", "spread"],
+        help = "Carry on writing a split message to --output where an earlier run stopped, keeping the pieces already there that are intact"
+    )]
+    pub resume: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["label", "spread", "filter", "extract", "output", "fields", "field", "combine", "ignore_case"],
+        help = "Write the chunk's data to stdout just as it's stored, without opening it, as it's read rather than all at once"
+    )]
+    pub raw: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["extract", "output"],
+        help = "Show the named fields of a message that was encoded with --field"
+    )]
+    pub fields: bool,
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["extract", "output", "fields"],
+        help = "Show just the value of this field of a message that was encoded with --field"
+    )]
+    pub field: Option<String>,
+    #[arg(
+        long,
+        value_name = "PNG",
+        num_args = 1..,
+        conflicts_with_all = ["label", "spread"],
+        help = "Put the message back together from the Shamir shares in this PNG and these others"
+    )]
+    pub combine: Vec<PathBuf>,
+    #[arg(
+        long,
+        conflicts_with_all = ["label", "spread"],
+        help = "Match the chunk type whatever the case of its letters"
+    )]
+    pub ignore_case: bool,
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+}
+
+impl DecodeArgs {
+    /// Check for options that need a PNG, when the message is in a JPEG,
+    /// WebP, or GIF.
+    pub fn check_other_format(&self) -> Result<(), UsageError> {
+        if self.label.is_some() || self.spread || !self.combine.is_empty() || self.resume {
+            return Err(UsageError(
+                "--label, --spread, --combine, and --resume only work with PNGs".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that `--resume` has a file on disk to pick up from.
+    pub fn check_resume(&self) -> Result<(), UsageError> {
+        match &self.output {
+            Some(path) if path == Path::new("auto") || storage::is_remote(path) => Err(UsageError(
+                "--resume needs --output to name a file on disk".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether to show the message as named fields.
+    pub fn shows_fields(&self) -> bool {
+        self.fields || self.field.is_some()
+    }
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct CombineArgs {
+    #[arg(help = "Directory of PNGs that the message was striped across")]
+    pub dir: PathBuf,
+    #[arg(value_parser = parse_chunk_type, help = "Chunk type (like 'ruSt')")]
     pub chunk_type: ChunkType,
+    #[arg(help = "Path to write the message to (optional, defaults to stdout)")]
+    pub output_file_path: Option<PathBuf>,
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
 }
 
-#[derive(StructOpt, Debug, PartialEq)]
+#[derive(Args, Debug, PartialEq)]
+pub struct PlanArgs {
+    #[arg(help = "Directory of PNGs to carry the message")]
+    pub dir: PathBuf,
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "How big the message is, after any compression or encryption (like 50MB or 10KiB)"
+    )]
+    pub payload_size: u64,
+}
+
+#[derive(Args, Debug, PartialEq)]
 pub struct RemoveArgs {
-    #[structopt(parse(from_os_str), help = "Path to the PNG")]
+    #[arg(help = "Path to the PNG, JPEG, WebP, or GIF")]
     pub file_path: PathBuf,
-    #[structopt(
-            parse(try_from_str = ChunkType::from_str),
+    #[arg(
+        long,
+        value_enum,
+        help = "The image's format, instead of going by how the file starts"
+    )]
+    pub format: Option<ImageFormat>,
+    #[arg(
+            value_parser = parse_chunk_type,
+            add = ArgValueCompleter::new(completion::chunk_types),
             help = "Chunk type (like 'ruSt')"
         )]
     pub chunk_type: ChunkType,
+    #[arg(
+        long,
+        default_value = "1",
+        help = "Which chunk of the given type to use, counting from 1"
+    )]
+    pub nth: NonZeroUsize,
+    #[arg(long, help = "Match the chunk type whatever the case of its letters")]
+    pub ignore_case: bool,
+}
+
+#[derive(Args, Debug, PartialEq)]
+#[command(group = ArgGroup::new("what").required(true).multiple(true))]
+pub struct StripArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
+    pub output_file_path: Option<PathBuf>,
+    #[arg(
+        long = "type",
+        group = "what",
+        value_parser = parse_chunk_type,
+        add = ArgValueCompleter::new(completion::chunk_types),
+        help = "Remove every chunk of this type (can be given more than once)"
+    )]
+    pub chunk_types: Vec<ChunkType>,
+    #[arg(long, group = "what", help = "Remove any data after the IEND chunk")]
+    pub trailing: bool,
+    #[arg(
+        long,
+        help = "List what would be removed, and how many bytes that would save, without changing anything"
+    )]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct DedupeArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
+    pub output_file_path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+#[command(group = ArgGroup::new("keywords").required(true).multiple(true))]
+pub struct SetMetaArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
+    pub output_file_path: Option<PathBuf>,
+    #[arg(long, group = "keywords", help = "Short title or caption")]
+    pub title: Option<String>,
+    #[arg(long, group = "keywords", help = "Name of the image's creator")]
+    pub author: Option<String>,
+    #[arg(long, group = "keywords", help = "Description of the image")]
+    pub description: Option<String>,
+    #[arg(long, group = "keywords", help = "Copyright notice")]
+    pub copyright: Option<String>,
+    #[arg(long, group = "keywords", help = "Time the original image was created")]
+    pub creation_time: Option<String>,
+    #[arg(long, group = "keywords", help = "Software used to create the image")]
+    pub software: Option<String>,
+    #[arg(long, group = "keywords", help = "Legal disclaimer")]
+    pub disclaimer: Option<String>,
+    #[arg(
+        long,
+        group = "keywords",
+        help = "Warning of the nature of the content"
+    )]
+    pub warning: Option<String>,
+    #[arg(long, group = "keywords", help = "Device used to create the image")]
+    pub source: Option<String>,
+    #[arg(long, group = "keywords", help = "Miscellaneous comment")]
+    pub comment: Option<String>,
+}
+
+impl SetMetaArgs {
+    /// Each keyword that was given, spelled the way the PNG spec spells it,
+    /// and its text.
+    pub fn keywords(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("Title", &self.title),
+            ("Author", &self.author),
+            ("Description", &self.description),
+            ("Copyright", &self.copyright),
+            ("Creation Time", &self.creation_time),
+            ("Software", &self.software),
+            ("Disclaimer", &self.disclaimer),
+            ("Warning", &self.warning),
+            ("Source", &self.source),
+            ("Comment", &self.comment),
+        ]
+        .iter()
+        .filter_map(|(keyword, text)| Some((*keyword, text.as_deref()?)))
+        .collect()
+    }
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct SortArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
+    pub output_file_path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+#[command(group = ArgGroup::new("position").required(true))]
+pub struct MoveArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(
+            value_parser = parse_chunk_type,
+            add = ArgValueCompleter::new(completion::chunk_types),
+            help = "Type of the chunk to move (like 'ruSt')"
+        )]
+    pub chunk_type: ChunkType,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
+    pub output_file_path: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value = "1",
+        help = "Which chunk of the given type to move, counting from 1"
+    )]
+    pub nth: NonZeroUsize,
+    #[arg(
+        long,
+        group = "position",
+        value_parser = parse_chunk_type,
+        add = ArgValueCompleter::new(completion::chunk_types),
+        help = "Move it to just before the first chunk of this type"
+    )]
+    pub before: Option<ChunkType>,
+    #[arg(
+        long,
+        group = "position",
+        value_parser = parse_chunk_type,
+        add = ArgValueCompleter::new(completion::chunk_types),
+        help = "Move it to just after the last chunk of this type"
+    )]
+    pub after: Option<ChunkType>,
+    #[arg(
+        long,
+        group = "position",
+        help = "Move it so it's this chunk, counting from 0"
+    )]
+    pub index: Option<usize>,
+}
+
+impl MoveArgs {
+    /// Check that `--index` is one of the `chunk_count` chunks in the PNG.
+    pub fn check_index(&self, chunk_count: usize) -> Result<(), UsageError> {
+        match self.index {
+            Some(index) if index >= chunk_count => Err(UsageError(format!(
+                "--index {} is past the last chunk (there are {} chunks, counting from 0)",
+                index, chunk_count
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Args, Debug, PartialEq)]
+#[command(group = ArgGroup::new("damage").required(true).multiple(true))]
+pub struct CorruptArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
+    pub output_file_path: Option<PathBuf>,
+    #[arg(
+        long = "chunk",
+        value_parser = parse_chunk_type,
+        add = ArgValueCompleter::new(completion::chunk_types),
+        required_if_eq_any = [("zero_crc", "true"), ("zero_length", "true")],
+        help = "Damage this chunk instead of the whole file"
+    )]
+    pub chunk_type: Option<ChunkType>,
+    #[arg(
+        long,
+        default_value = "1",
+        help = "Which chunk of the given type to damage, counting from 1"
+    )]
+    pub nth: NonZeroUsize,
+    #[arg(
+        long,
+        value_name = "N",
+        group = "damage",
+        help = "Flip this many random bits in the chunk's data, or anywhere after the PNG signature"
+    )]
+    pub flip_bits: Option<usize>,
+    #[arg(long, group = "damage", help = "Set the chunk's CRC to 0")]
+    pub zero_crc: bool,
+    #[arg(long, group = "damage", help = "Set the chunk's length to 0")]
+    pub zero_length: bool,
+    #[arg(
+        long,
+        help = "Seed for picking the bits to flip, to damage a file the same way again"
+    )]
+    pub seed: Option<u64>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct VerifyArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(
+            value_parser = parse_chunk_type,
+            add = ArgValueCompleter::new(completion::chunk_types),
+            required_unless_present = "crc",
+            help = "Chunk type (like 'ruSt')"
+        )]
+    pub chunk_type: Option<ChunkType>,
+    #[arg(
+        long,
+        required_unless_present = "crc",
+        help = "The signer's Ed25519 public key (a PEM file, or the name of a key in the key store)"
+    )]
+    pub pubkey: Option<PathBuf>,
+    #[arg(
+        long,
+        conflicts_with_all = ["chunk_type", "pubkey"],
+        help = "Instead of checking a signature, check every chunk's CRC and report each one"
+    )]
+    pub crc: bool,
 }
 
-#[derive(StructOpt, Debug, PartialEq)]
+#[derive(Args, Debug, PartialEq)]
 pub struct PrintArgs {
-    #[structopt(parse(from_os_str), help = "Path to the PNG")]
+    #[arg(help = "Path to the PNG, JPEG, WebP, or GIF")]
+    pub file_path: PathBuf,
+    #[arg(
+        long,
+        value_enum,
+        help = "The image's format, instead of going by how the file starts (--format is how to print it)"
+    )]
+    pub image_format: Option<ImageFormat>,
+    #[arg(long, value_enum, default_value_t = PrintFormat::Text, help = "How to print the chunks")]
+    pub format: PrintFormat,
+}
+
+impl PrintArgs {
+    /// Check for options that need a PNG, when printing a JPEG, WebP, or
+    /// GIF.
+    pub fn check_other_format(&self) -> Result<(), UsageError> {
+        if self.format != PrintFormat::Text {
+            return Err(UsageError("--format json only works with PNGs".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// How `print` shows chunks.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum PrintFormat {
+    /// One chunk per line, with text chunks decoded.
+    Text,
+    /// The JSON form in `schema`.
+    Json,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ExportArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Path to write the JSON to (optional, defaults to stdout)")]
+    pub output_file_path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ImportArgs {
+    #[arg(help = "Path to the JSON, as written by export")]
+    pub json_path: PathBuf,
+    #[arg(help = "Path to the PNG to write, or - for stdout")]
+    pub output_file_path: PathBuf,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct SetDpiArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Dots (pixels) per inch, or pixels per meter with --ppm")]
+    pub density: f64,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
+    pub output_file_path: Option<PathBuf>,
+    #[arg(long, help = "Give the density in pixels per meter instead")]
+    pub ppm: bool,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct SetTransparentArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(
+        value_parser = parse_samples,
+        help = "The color to make transparent: a gray level, red,green,blue (or #rrggbb), or a palette index"
+    )]
+    pub color: Samples,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
+    pub output_file_path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct SetBackgroundArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(
+        value_parser = parse_samples,
+        help = "The background color: a gray level, red,green,blue (or #rrggbb), or a palette index"
+    )]
+    pub color: Samples,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
+    pub output_file_path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ExportMetaArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Path to write the JSON to (optional, defaults to stdout)")]
+    pub output_file_path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ImportMetaArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Path to the JSON, as written by export-meta")]
+    pub json_path: PathBuf,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
+    pub output_file_path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct XmpImportArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Path to the XMP sidecar file")]
+    pub xmp_path: PathBuf,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
+    pub output_file_path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct XmpExportArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Path to write the XMP packet to (optional, defaults to stdout)")]
+    pub output_file_path: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Subcommand, PartialEq)]
+pub enum XmpSubcommand {
+    #[command(about = "Embed an XMP sidecar file, replacing any XMP already there")]
+    Import(XmpImportArgs),
+    #[command(about = "Write out the XMP packet in a PNG")]
+    Export(XmpExportArgs),
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct IccExtractArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Path to write the ICC profile to (optional, defaults to stdout)")]
+    pub output_file_path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct IccEmbedArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Path to the ICC profile")]
+    pub profile_path: PathBuf,
+    #[arg(help = "Path to the output PNG, or - for stdout (optional)")]
+    pub output_file_path: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value = "ICC profile",
+        help = "The name to store the profile under, 1 to 79 Latin-1 characters"
+    )]
+    pub name: String,
+}
+
+#[derive(Debug, clap::Subcommand, PartialEq)]
+pub enum IccSubcommand {
+    #[command(about = "Write out the ICC profile in a PNG")]
+    Extract(IccExtractArgs),
+    #[command(about = "Embed an ICC profile, replacing any profile or sRGB chunk already there")]
+    Embed(IccEmbedArgs),
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ApngSplitArgs {
+    #[arg(help = "Path to the animated PNG")]
+    pub file_path: PathBuf,
+    #[arg(help = "Directory to write the frames to, which is created if it doesn't exist")]
+    pub out_dir: PathBuf,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ApngBuildArgs {
+    /// The frames, then the output, in one list so that `--fps` can go
+    /// anywhere, like `apng build frames/*.png --fps 12 out.png`.
+    #[arg(
+        num_args = 1..,
+        required = true,
+        value_name = "PATHS",
+        help = "The frames, in order, which all have to be the same size and color type, then the path to the animated PNG to write, or - for stdout"
+    )]
+    pub paths: Vec<PathBuf>,
+    #[arg(
+        long,
+        default_value_t = 10,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Frames per second"
+    )]
+    pub fps: u16,
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "How many times to play it, or 0 for forever"
+    )]
+    pub plays: u32,
+}
+
+impl ApngBuildArgs {
+    /// The frames, and the path to write the animation to. Returns `Err` if
+    /// there's only one path.
+    pub fn frames_and_output(&self) -> Result<(&[PathBuf], &Path), UsageError> {
+        match self.paths.split_last() {
+            Some((output, frames)) if !frames.is_empty() => Ok((frames, output)),
+            _ => Err(UsageError(
+                "Give at least one frame, then the path to write the animation to".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, clap::Subcommand, PartialEq)]
+pub enum ApngSubcommand {
+    #[command(about = "Write each frame of an animated PNG as a PNG of its own")]
+    Split(ApngSplitArgs),
+    #[command(about = "Build an animated PNG out of PNGs of the same size")]
+    Build(ApngBuildArgs),
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct InfoArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = AncillarySummary::DEFAULT_WARNING_PERCENT,
+        value_parser = clap::value_parser!(u8).range(1..=100),
+        help = "Warn when chunks that aren't in the PNG standard make up more than this percentage of the file"
+    )]
+    pub payload_warning: u8,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct HashArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(long, value_enum, default_value_t = HashAlgorithm::Sha256, help = "Hash function to use")]
+    pub algo: HashAlgorithm,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct PixelHashArgs {
+    #[arg(required = true, help = "Paths to the PNGs")]
+    pub file_paths: Vec<PathBuf>,
+    #[arg(long, value_enum, default_value_t = HashAlgorithm::Sha256, help = "Hash function to use")]
+    pub algo: HashAlgorithm,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct PhashArgs {
+    #[arg(required = true, help = "Paths to the PNGs, JPEGs, WebPs, or GIFs")]
+    pub file_paths: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ImgdiffArgs {
+    #[arg(help = "Path to the first PNG, JPEG, WebP, or GIF")]
+    pub first: PathBuf,
+    #[arg(help = "Path to the second PNG, JPEG, WebP, or GIF")]
+    pub second: PathBuf,
+}
+
+/// The hash functions `hash` and `pixel-hash` can use. See `pngme::digest`.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn algorithm(self) -> digest::Algorithm {
+        match self {
+            HashAlgorithm::Sha256 => digest::Algorithm::Sha256,
+            HashAlgorithm::Blake3 => digest::Algorithm::Blake3,
+        }
+    }
+}
+
+/// The image formats pngme can hide messages in. See `pngme::carrier`.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Gif,
+}
+
+impl ImageFormat {
+    pub fn format(self) -> Format {
+        match self {
+            ImageFormat::Png => Format::Png,
+            ImageFormat::Jpeg => Format::Jpeg,
+            ImageFormat::Webp => Format::Webp,
+            ImageFormat::Gif => Format::Gif,
+        }
+    }
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct CatArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[arg(
+        value_parser = parse_type_prefix,
+        help = "The first letters of the fragments' chunk type (like 'ru')"
+    )]
+    pub type_prefix: String,
+    #[arg(help = "Path to write the payload to (optional, defaults to stdout)")]
+    pub output_file_path: Option<PathBuf>,
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+}
+
+/// Parse a chunk type given on the command line.
+fn parse_chunk_type(s: &str) -> Result<ChunkType, String> {
+    ChunkType::from_str(s).map_err(|e| e.to_string())
+}
+
+/// A field is given as `NAME=VALUE`. The value can have `=` in it, but the
+/// name can't be empty.
+fn parse_field(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("'{}' is not NAME=VALUE", s)),
+    }
+}
+
+/// A size is a number of bytes, optionally followed by a unit: KB, MB, or GB
+/// for powers of 1000, or KiB, MiB, or GiB for powers of 1024.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000 * 1000,
+        "gb" => 1000 * 1000 * 1000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        _ => return Err(format!("'{}' is not a size like 50MB or 10KiB", s)),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("'{}' is not a size like 50MB or 10KiB", s))
+}
+
+/// A color given on the command line, as the samples of one pixel. What they
+/// mean depends on the image: see
+/// [Color::in_png](../pngme/color/enum.Color.html#method.in_png).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Samples(pub Vec<u16>);
+
+/// Samples are numbers separated by commas, like `255,0,0`, or a hex color
+/// like `#ff0000`.
+fn parse_samples(s: &str) -> Result<Samples, String> {
+    let error = || format!("'{}' is not a color like 255,0,0 or #ff0000", s);
+    match s.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 && hex.is_ascii() => (0..6)
+            .step_by(2)
+            .map(|i| u16::from_str_radix(&hex[i..i + 2], 16).map_err(|_| error()))
+            .collect::<Result<Vec<u16>, String>>()
+            .map(Samples),
+        Some(_) => Err(error()),
+        None => s
+            .split(',')
+            .map(|sample| sample.trim().parse::<u16>().map_err(|_| error()))
+            .collect::<Result<Vec<u16>, String>>()
+            .map(Samples),
+    }
+}
+
+/// A chunk type prefix is 1 to 4 ASCII letters.
+fn parse_type_prefix(s: &str) -> Result<String, String> {
+    if s.is_empty() || s.len() > 4 || !s.bytes().all(|b| b.is_ascii_alphabetic()) {
+        Err(format!("'{}' is not 1 to 4 ASCII letters", s))
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct TypeMakeArgs {
+    #[arg(
+        value_parser = parse_chunk_type,
+        help = "Four letters to build the chunk type from, in any case"
+    )]
+    pub letters: ChunkType,
+    #[arg(long, help = "Make the chunk critical instead of ancillary")]
+    pub critical: bool,
+    #[arg(long, help = "Make the chunk public instead of private")]
+    pub public: bool,
+    #[arg(long, help = "Make the chunk unsafe to copy")]
+    pub unsafe_to_copy: bool,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ExplainArgs {
+    #[arg(value_parser = parse_chunk_type, help = "The chunk type to explain")]
+    pub chunk_type: ChunkType,
+}
+
+#[derive(Debug, clap::Subcommand, PartialEq)]
+pub enum TypeSubcommand {
+    #[command(about = "Change the case of four letters to get the chunk properties you want")]
+    Make(TypeMakeArgs),
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct WatchArgs {
+    #[arg(help = "Directory to watch for new PNGs")]
+    pub dir: PathBuf,
+    #[arg(long, help = "TOML file listing the chunks to add to each new PNG")]
+    pub encode_with: PathBuf,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ManArgs {
+    #[arg(
+        long,
+        help = "Write a page for pngme and every subcommand into this directory instead of printing pngme's page"
+    )]
+    pub out_dir: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ServeArgs {
+    #[arg(
+        long,
+        default_value = "127.0.0.1:8080",
+        help = "Address and port to listen on"
+    )]
+    pub listen: SocketAddr,
+    #[arg(
+        long,
+        help = "Let /encode hide messages in chunk types that could damage the image or get dropped"
+    )]
+    pub allow_unsafe: bool,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ShellArgs {
+    #[arg(help = "Path to the PNG")]
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+    #[arg(
+        long,
+        help = "Let encode hide messages in chunk types that could damage the image or get dropped"
+    )]
+    pub allow_unsafe: bool,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct FilterCleanArgs {
+    #[arg(
+        long,
+        help = "TOML file listing this machine's local chunks, which are stripped too"
+    )]
+    pub local: Option<PathBuf>,
+    #[arg(
+        long,
+        value_parser = parse_chunk_type,
+        help = "Also strip chunks of this type (can be given more than once)"
+    )]
+    pub strip: Vec<ChunkType>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct FilterSmudgeArgs {
+    #[arg(
+        long,
+        help = "TOML file listing this machine's local chunks, which are added back"
+    )]
+    pub local: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Subcommand, PartialEq)]
+pub enum FilterSubcommand {
+    #[command(about = "Strip volatile and local chunks from a PNG on stdin (git's clean filter)")]
+    Clean(FilterCleanArgs),
+    #[command(about = "Add local chunks back to a PNG on stdin (git's smudge filter)")]
+    Smudge(FilterSmudgeArgs),
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct KeyNameArgs {
+    #[arg(help = "Name of the key")]
+    pub name: String,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct KeyExportArgs {
+    #[arg(help = "Name of the key")]
+    pub name: String,
+    #[arg(long, help = "Export the private key instead of the public key")]
+    pub private: bool,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct KeyImportArgs {
+    #[arg(help = "Name to save the key under")]
+    pub name: String,
+    #[arg(help = "Path to a PEM private or public key")]
     pub file_path: PathBuf,
 }
 
-#[derive(Debug, StructOpt, PartialEq)]
+#[derive(Debug, clap::Subcommand, PartialEq)]
+pub enum KeySubcommand {
+    #[command(about = "Make a new signing keypair")]
+    Generate(KeyNameArgs),
+    #[command(about = "List saved keys")]
+    List,
+    #[command(about = "Print a saved key as PEM")]
+    Export(KeyExportArgs),
+    #[command(about = "Save a PEM key from a file")]
+    Import(KeyImportArgs),
+}
+
+#[derive(Debug, clap::Subcommand, PartialEq)]
 pub enum Subcommand {
-    #[structopt(about = "Add a secret message to a PNG")]
+    #[command(visible_alias = "e", about = "Add a secret message to a PNG")]
     Encode(EncodeArgs),
-    #[structopt(about = "Show the secret message in a PNG")]
+    #[command(visible_alias = "d", about = "Show the secret message in a PNG")]
     Decode(DecodeArgs),
-    #[structopt(about = "Remove a secret message from a PNG")]
+    #[command(visible_alias = "r", about = "Remove a secret message from a PNG")]
     Remove(RemoveArgs),
-    #[structopt(about = "Print every chunk in a PNG")]
+    #[command(about = "Remove every chunk of some types, or data after the end of a PNG")]
+    Strip(StripArgs),
+    #[command(about = "Remove chunks that are exact copies of an earlier chunk")]
+    Dedupe(DedupeArgs),
+    #[command(
+        name = "set-meta",
+        about = "Set the standard text keywords, like Title and Author, replacing any already there"
+    )]
+    SetMeta(SetMetaArgs),
+    #[command(
+        name = "set-dpi",
+        about = "Set the pixel density in the pHYs chunk, replacing any already there"
+    )]
+    SetDpi(SetDpiArgs),
+    #[command(
+        name = "set-transparent",
+        about = "Make one color transparent with a tRNS chunk, replacing any already there"
+    )]
+    SetTransparent(SetTransparentArgs),
+    #[command(
+        name = "set-background",
+        about = "Set the color to show the image on with a bKGD chunk, replacing any already there"
+    )]
+    SetBackground(SetBackgroundArgs),
+    #[command(
+        name = "export-meta",
+        about = "Write the text of every tEXt, zTXt, and iTXt chunk as JSON"
+    )]
+    ExportMeta(ExportMetaArgs),
+    #[command(
+        name = "import-meta",
+        about = "Add the text that export-meta writes, replacing text with the same keywords"
+    )]
+    ImportMeta(ImportMetaArgs),
+    #[command(subcommand, about = "Embed or extract XMP metadata")]
+    Xmp(XmpSubcommand),
+    #[command(subcommand, about = "Extract or embed an ICC color profile")]
+    Icc(IccSubcommand),
+    #[command(subcommand, about = "Work with animated PNGs")]
+    Apng(ApngSubcommand),
+    #[command(about = "Put the chunks in an order the PNG spec allows")]
+    Sort(SortArgs),
+    #[command(about = "Move a chunk somewhere else in a PNG")]
+    Move(MoveArgs),
+    #[command(about = "Damage a PNG on purpose, to test how decoders cope")]
+    Corrupt(CorruptArgs),
+    #[command(visible_alias = "p", about = "Print every chunk in a PNG")]
     Print(PrintArgs),
+    #[command(about = "Write every chunk in a PNG as JSON")]
+    Export(ExportArgs),
+    #[command(about = "Build a PNG from the JSON that export writes")]
+    Import(ImportArgs),
+    #[command(about = "Show an image's size and a summary of its image data")]
+    Info(InfoArgs),
+    #[command(about = "Show a digest of each chunk, and of the whole PNG ignoring chunk order")]
+    Hash(HashArgs),
+    #[command(
+        about = "Show a digest of just the image data of each PNG, which metadata doesn't change"
+    )]
+    PixelHash(PixelHashArgs),
+    #[command(
+        about = "Show a perceptual hash of each image, which stays close through re-encoding and resizing (needs the image feature)"
+    )]
+    Phash(PhashArgs),
+    #[command(
+        about = "Count the pixels that differ between two images of the same size, and by how much (needs the image feature)"
+    )]
+    Imgdiff(ImgdiffArgs),
+    #[command(about = "Join a message that was split across several chunks")]
+    Cat(CatArgs),
+    #[command(about = "Join a message that was striped across a directory of PNGs")]
+    Combine(CombineArgs),
+    #[command(
+        about = "Show how much each PNG in a directory can plausibly carry, and which to stripe a message across"
+    )]
+    Plan(PlanArgs),
+    #[command(subcommand, about = "Work with chunk types")]
+    Type(TypeSubcommand),
+    #[command(about = "List the chunk types pngme knows about")]
+    Types,
+    #[command(about = "List the image formats pngme can hide messages in, and how")]
+    Formats,
+    #[command(about = "Explain what each letter of a chunk type means")]
+    Explain(ExplainArgs),
+    #[command(about = "Check the signature on a secret message")]
+    Verify(VerifyArgs),
+    #[command(subcommand, about = "Manage signing keys")]
+    Key(KeySubcommand),
+    #[command(about = "Add chunks to every PNG created in a directory")]
+    Watch(WatchArgs),
+    #[command(subcommand, about = "Run as a git clean or smudge filter")]
+    Filter(FilterSubcommand),
+    #[command(about = "Generate man pages")]
+    Man(ManArgs),
+    #[command(
+        about = "Load a PNG once, then list, decode, encode, and remove chunks interactively"
+    )]
+    Shell(ShellArgs),
+    #[command(about = "Encode, decode, and strip PNGs sent over HTTP")]
+    Serve(ServeArgs),
 }
 
 mod test {
     #[allow(unused_imports)]
     use super::*;
+    #[allow(unused_imports)]
+    use clap::CommandFactory;
+
+    #[test]
+    pub fn test_cli_is_consistent() {
+        Cli::command().debug_assert();
+    }
 
     #[test]
     pub fn test_encode() {
         let expected = Subcommand::Encode(EncodeArgs {
             input_file_path: PathBuf::from("/a/b/c"),
-            chunk_type: ChunkType::from_str("RuSt").unwrap(),
-            message: "Secret decoder ring".to_string(),
+            format: None,
+            chunk_type_or_message: "RuSt".to_string(),
+            message: Some("Secret decoder ring".to_string()),
             output_file_path: None,
+            fields: vec![],
+            split_size: None,
+            split_shares: None,
+            threshold: None,
+            carriers: vec![],
+            stripe: false,
+            record: false,
+            label: None,
+            randomize_position: false,
+            sign: None,
+            filter: None,
+            compress: false,
+            compression_level: None,
+            encrypt: false,
+            pad: false,
+            kdf_memory: None,
+            kdf_iterations: None,
+            allow_unsafe: false,
+            method: EncodeMethod::Chunk,
+            payload_warning: 30,
+            passphrase: PassphraseArgs::default(),
         });
-        let cli = Cli::from_iter(vec![
+        let cli = Cli::parse_from(vec![
             "pngme",
             "encode",
             "/a/b/c",
@@ -101,11 +1610,33 @@ mod test {
     pub fn test_encode_with_output_file() {
         let expected = Subcommand::Encode(EncodeArgs {
             input_file_path: PathBuf::from("/a/b/c"),
-            chunk_type: ChunkType::from_str("RuSt").unwrap(),
-            message: "Secret decoder ring".to_string(),
+            format: None,
+            chunk_type_or_message: "RuSt".to_string(),
+            message: Some("Secret decoder ring".to_string()),
             output_file_path: Some(PathBuf::from("/output/file/path")),
+            fields: vec![],
+            split_size: None,
+            split_shares: None,
+            threshold: None,
+            carriers: vec![],
+            stripe: false,
+            record: false,
+            label: None,
+            randomize_position: false,
+            sign: None,
+            filter: None,
+            compress: false,
+            compression_level: None,
+            encrypt: false,
+            pad: false,
+            kdf_memory: None,
+            kdf_iterations: None,
+            allow_unsafe: false,
+            method: EncodeMethod::Chunk,
+            payload_warning: 30,
+            passphrase: PassphraseArgs::default(),
         });
-        let cli = Cli::from_iter(vec![
+        let cli = Cli::parse_from(vec![
             "pngme",
             "encode",
             "/a/b/c",
@@ -118,44 +1649,1633 @@ mod test {
         assert_eq!(expected, actual);
     }
 
+    // Shift-JIS filenames read as Latin-1 aren't valid UTF-8, and have to
+    // come through exactly as they were given.
+    #[cfg(unix)]
     #[test]
-    pub fn test_decode() {
-        let expected = Subcommand::Decode(DecodeArgs {
-            file_path: PathBuf::from("/a/b/c"),
-            chunk_type: ChunkType::from_str("PnGm").unwrap(),
-        });
-        let cli = Cli::from_iter(vec!["pngme", "decode", "/a/b/c", "PnGm"]);
-        let actual = cli.subcommand;
+    pub fn test_paths_that_are_not_utf8() {
+        use std::ffi::{OsStr, OsString};
+        use std::os::unix::ffi::OsStrExt;
 
-        assert_eq!(expected, actual);
+        let input = OsStr::from_bytes(b"/a/\x83e\x83X\x83g.png");
+        let output = OsStr::from_bytes(b"/b/\x93\xfa\x96{.png");
+        let cli = Cli::parse_from(vec![
+            OsString::from("pngme"),
+            OsString::from("encode"),
+            input.to_os_string(),
+            OsString::from("RuSt"),
+            OsString::from("Secret"),
+            output.to_os_string(),
+        ]);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert_eq!(args.input_file_path.as_os_str(), input);
+            assert_eq!(args.output_file_path.unwrap().as_os_str(), output);
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+
+        let cli = Cli::parse_from(vec![
+            OsString::from("pngme"),
+            OsString::from("strip"),
+            input.to_os_string(),
+            OsString::from("--trailing"),
+        ]);
+        if let Subcommand::Strip(args) = cli.subcommand {
+            assert_eq!(args.file_path.as_os_str(), input);
+        } else {
+            panic!("Expected the strip subcommand");
+        }
     }
 
     #[test]
-    pub fn test_remove() {
-        let expected = Subcommand::Remove(RemoveArgs {
-            file_path: PathBuf::from("/a/b/c"),
-            chunk_type: ChunkType::from_str("imAG").unwrap(),
+    pub fn test_encode_with_split_size() {
+        let expected = Subcommand::Encode(EncodeArgs {
+            input_file_path: PathBuf::from("/a/b/c"),
+            format: None,
+            chunk_type_or_message: "RuSt".to_string(),
+            message: Some("Secret decoder ring".to_string()),
+            output_file_path: None,
+            fields: vec![],
+            split_size: NonZeroUsize::new(4),
+            split_shares: None,
+            threshold: None,
+            carriers: vec![],
+            stripe: false,
+            record: false,
+            label: None,
+            randomize_position: false,
+            sign: None,
+            filter: None,
+            compress: false,
+            compression_level: None,
+            encrypt: false,
+            pad: false,
+            kdf_memory: None,
+            kdf_iterations: None,
+            allow_unsafe: false,
+            method: EncodeMethod::Chunk,
+            payload_warning: 30,
+            passphrase: PassphraseArgs::default(),
         });
-        let cli = Cli::from_iter(vec!["pngme", "remove", "/a/b/c", "imAG"]);
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "Secret decoder ring",
+            "--split-size",
+            "4",
+        ]);
         let actual = cli.subcommand;
 
         assert_eq!(expected, actual);
     }
 
     #[test]
-    pub fn test_print() {
-        let expected = Subcommand::Print(PrintArgs {
-            file_path: PathBuf::from("/a/b/c"),
-        });
-        let cli = Cli::from_iter(vec!["pngme", "print", "/a/b/c"]);
-        let actual = cli.subcommand;
+    pub fn test_encode_with_trailer_method() {
+        let cli = Cli::parse_from(vec![
+            "pngme", "encode", "/a/b/c", "RuSt", "Secret", "--method", "trailer",
+        ]);
 
-        assert_eq!(expected, actual);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert_eq!(args.method, EncodeMethod::Trailer);
+            assert!(args.check_method().is_ok());
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "Secret",
+            "--method",
+            "trailer",
+            "--split-size",
+            "4",
+        ]);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert!(args.check_method().is_err());
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+    }
+
+    #[test]
+    pub fn test_encode_into_other_formats() {
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "encode",
+            "a.jpg",
+            "RuSt",
+            "Secret",
+            "--compress",
+            "--encrypt",
+        ]);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert!(args.check_other_format().is_ok());
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+
+        let cli = Cli::parse_from(vec![
+            "pngme", "encode", "a.jpg", "RuSt", "Secret", "--method", "trailer",
+        ]);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert!(args.check_other_format().is_err());
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+    }
+
+    #[test]
+    pub fn test_image_format() {
+        let cli = Cli::parse_from(vec!["pngme", "decode", "a.png", "ruSt", "--format", "gif"]);
+        if let Subcommand::Decode(args) = cli.subcommand {
+            assert_eq!(args.format.map(ImageFormat::format), Some(Format::Gif));
+        } else {
+            panic!("Expected the decode subcommand");
+        }
+
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "print",
+            "a.png",
+            "--image-format",
+            "webp",
+            "--format",
+            "text",
+        ]);
+        if let Subcommand::Print(args) = cli.subcommand {
+            assert_eq!(args.image_format, Some(ImageFormat::Webp));
+            assert_eq!(args.format, PrintFormat::Text);
+        } else {
+            panic!("Expected the print subcommand");
+        }
+    }
+
+    #[test]
+    pub fn test_encode_appends_in_place() {
+        let encode_args = |extra: &[&str]| {
+            let mut args = vec!["pngme", "encode", "/a/b/c", "RuSt", "Secret"];
+            args.extend_from_slice(extra);
+            match Cli::parse_from(args).subcommand {
+                Subcommand::Encode(args) => args,
+                _ => panic!("Expected the encode subcommand"),
+            }
+        };
+        assert!(encode_args(&[]).appends_in_place());
+        assert!(encode_args(&["/a/b/c", "--split-size", "4"]).appends_in_place());
+        assert!(!encode_args(&["/a/b/d"]).appends_in_place());
+        assert!(!encode_args(&["--record"]).appends_in_place());
+        assert!(!encode_args(&["--randomize-position"]).appends_in_place());
+        assert!(!encode_args(&["--method", "trailer"]).appends_in_place());
+        match Cli::parse_from(vec![
+            "pngme",
+            "encode",
+            "s3://bucket/dice.png",
+            "RuSt",
+            "Secret",
+        ])
+        .subcommand
+        {
+            Subcommand::Encode(args) => assert!(!args.appends_in_place()),
+            _ => panic!("Expected the encode subcommand"),
+        }
+    }
+
+    #[test]
+    pub fn test_encode_with_kdf_settings() {
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "Secret",
+            "--encrypt",
+            "--kdf-memory",
+            "65536",
+            "--kdf-iterations",
+            "3",
+        ]);
+
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert_eq!(args.kdf_memory, Some(65536));
+            assert_eq!(args.kdf_iterations, Some(3));
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+
+        // The KDF settings mean nothing without encryption.
+        let result = Cli::try_parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "Secret",
+            "--kdf-memory",
+            "65536",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    pub fn test_check_chunk_type() {
+        let checked =
+            |chunk_type: &str| check_chunk_type(ChunkType::from_str(chunk_type).unwrap(), false);
+        assert!(checked("ruSt").is_ok());
+        let error = checked("tRNS").unwrap_err().to_string();
+        assert!(error.contains("standard chunk type"), "{}", error);
+        let error = checked("RuST").unwrap_err().to_string();
+        assert!(error.contains("critical"), "{}", error);
+        assert!(error.contains("safe to copy"), "{}", error);
+        assert!(!error.contains("public"), "{}", error);
+        assert!(checked("rUSt").unwrap_err().to_string().contains("public"));
+        assert!(check_chunk_type(ChunkType::IDAT, true).is_ok());
+    }
+
+    #[test]
+    pub fn test_encode_allow_unsafe() {
+        let allow_unsafe = |extra: &[&'static str]| {
+            let mut argv = vec!["pngme", "encode", "/a/b/c", "ruSt", "Secret"];
+            argv.extend_from_slice(extra);
+            match Cli::parse_from(argv).subcommand {
+                Subcommand::Encode(args) => args.allow_unsafe,
+                _ => panic!("Expected the encode subcommand"),
+            }
+        };
+        assert!(!allow_unsafe(&[]));
+        assert!(allow_unsafe(&["--allow-unsafe"]));
+    }
+
+    #[test]
+    pub fn test_encode_with_pad() {
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "Secret",
+            "--encrypt",
+            "--pad",
+        ]);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert!(args.pad);
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+        // Unencrypted padding hides nothing.
+        assert!(
+            Cli::try_parse_from(vec!["pngme", "encode", "/a/b/c", "RuSt", "Secret", "--pad"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    pub fn test_encode_with_compression_level() {
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "Secret",
+            "--compress",
+            "--compression-level",
+            "auto",
+        ]);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert_eq!(args.compression_level, Some(CompressionLevel::Auto));
+            assert!(args.compression(b"Secret Secret Secret").is_some());
+            let every_byte: Vec<u8> = (0..=255).collect();
+            assert!(args.compression(&every_byte).is_none());
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+        assert!(Cli::try_parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "Secret",
+            "--compression-level",
+            "9",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_parse_compression_level() {
+        assert_eq!(parse_compression_level("0"), Ok(CompressionLevel::Level(0)));
+        assert_eq!(parse_compression_level("9"), Ok(CompressionLevel::Level(9)));
+        assert_eq!(parse_compression_level("AUTO"), Ok(CompressionLevel::Auto));
+        for bad in ["", "10", "-1", "best"] {
+            assert!(parse_compression_level(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    pub fn test_encode_with_spread_method() {
+        let cli = Cli::parse_from(vec![
+            "pngme", "encode", "/a/b/c", "Secret", "--method", "spread",
+        ]);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert_eq!(args.method, EncodeMethod::Spread);
+            assert!(args.check_method().is_ok());
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+
+        // There's no chunk type to give.
+        let cli = Cli::parse_from(vec![
+            "pngme", "encode", "/a/b/c", "RuSt", "Secret", "--method", "spread",
+        ]);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert!(args.check_method().is_err());
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+    }
+
+    #[test]
+    pub fn test_encode_with_shares() {
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "Secret",
+            "--split-shares",
+            "3",
+            "--threshold",
+            "2",
+            "--carriers",
+            "/d",
+            "/e",
+        ]);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert_eq!((args.split_shares, args.threshold), (Some(3), Some(2)));
+            assert_eq!(
+                args.carriers,
+                vec![PathBuf::from("/d"), PathBuf::from("/e")]
+            );
+            assert!(args.check_method().is_ok());
+            assert!(!args.appends_in_place());
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+
+        // One share per image.
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "Secret",
+            "--split-shares",
+            "3",
+            "--threshold",
+            "2",
+            "--carriers",
+            "/d",
+        ]);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert!(args.check_method().is_err());
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+        assert!(Cli::try_parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "Secret",
+            "--split-shares",
+            "2",
+        ])
+        .is_err());
+
+        let cli = Cli::parse_from(vec!["pngme", "decode", "/a/b/c", "RuSt", "--combine", "/d"]);
+        if let Subcommand::Decode(args) = cli.subcommand {
+            assert_eq!(args.combine, vec![PathBuf::from("/d")]);
+        } else {
+            panic!("Expected the decode subcommand");
+        }
+        assert!(Cli::try_parse_from(vec![
+            "pngme",
+            "decode",
+            "/a/b/c",
+            "--spread",
+            "--combine",
+            "/d"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_encode_with_stripe() {
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "Secret",
+            "--stripe",
+            "--carriers",
+            "/d",
+        ]);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert!(args.stripe);
+            assert!(args.check_method().is_ok());
+            assert!(!args.appends_in_place());
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+        for extra in [
+            &["--split-size", "4"][..],
+            &["--split-shares", "2", "--threshold", "2"],
+        ] {
+            let mut argv = vec![
+                "pngme",
+                "encode",
+                "/a/b/c",
+                "RuSt",
+                "Secret",
+                "--stripe",
+                "--carriers",
+                "/d",
+            ];
+            argv.extend_from_slice(extra);
+            assert!(Cli::try_parse_from(argv).is_err(), "{:?}", extra);
+        }
+        assert!(Cli::try_parse_from(vec![
+            "pngme", "encode", "/a/b/c", "RuSt", "Secret", "--stripe"
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "Secret",
+            "--carriers",
+            "/d"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_combine() {
+        let expected = Subcommand::Combine(CombineArgs {
+            dir: PathBuf::from("/a/b"),
+            chunk_type: ChunkType::from_str("RuSt").unwrap(),
+            output_file_path: None,
+            passphrase: PassphraseArgs::default(),
+        });
+        let cli = Cli::parse_from(vec!["pngme", "combine", "/a/b", "RuSt"]);
+        assert_eq!(expected, cli.subcommand);
+    }
+
+    #[test]
+    pub fn test_plan() {
+        let expected = Subcommand::Plan(PlanArgs {
+            dir: PathBuf::from("/a/b"),
+            payload_size: 50_000_000,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "plan", "/a/b", "--payload-size", "50MB"]);
+        assert_eq!(expected, cli.subcommand);
+        assert!(Cli::try_parse_from(vec!["pngme", "plan", "/a/b"]).is_err());
+    }
+
+    #[test]
+    pub fn test_parse_size() {
+        assert_eq!(parse_size("1234"), Ok(1234));
+        assert_eq!(parse_size("10KiB"), Ok(10240));
+        assert_eq!(parse_size("2 mb"), Ok(2_000_000));
+        assert_eq!(parse_size("1GiB"), Ok(1 << 30));
+        for bad in ["", "MB", "1.5MB", "10 parsecs", "99999999999GB"] {
+            assert!(parse_size(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    pub fn test_encode_with_label() {
+        let cli = Cli::parse_from(vec![
+            "pngme", "encode", "/a/b/c", "Secret", "--label", "diary",
+        ]);
+
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert_eq!(args.label, Some("diary".to_string()));
+            let (chunk_type, message) = args.chunk_type_and_message().unwrap();
+            assert_eq!(chunk_type, None);
+            assert_eq!(message, "Secret");
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+
+        // The label replaces the chunk type.
+        let cli = Cli::parse_from(vec![
+            "pngme", "encode", "/a/b/c", "RuSt", "Secret", "--label", "diary",
+        ]);
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert!(args.chunk_type_and_message().is_err());
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+    }
+
+    #[test]
+    pub fn test_encode_without_chunk_type() {
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "Secret decoder ring",
+            "--record",
+        ]);
+
+        if let Subcommand::Encode(args) = cli.subcommand {
+            let (chunk_type, message) = args.chunk_type_and_message().unwrap();
+            assert_eq!(chunk_type, None);
+            assert_eq!(message, "Secret decoder ring");
+            assert!(args.record);
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+    }
+
+    #[test]
+    pub fn test_encode_with_bad_chunk_type() {
+        let cli = Cli::parse_from(vec!["pngme", "encode", "/a/b/c", "Ru5t", "Secret"]);
+
+        if let Subcommand::Encode(args) = cli.subcommand {
+            assert!(args.chunk_type_and_message().is_err());
+        } else {
+            panic!("Expected the encode subcommand");
+        }
+    }
+
+    #[test]
+    pub fn test_decode() {
+        let expected = Subcommand::Decode(DecodeArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            format: None,
+            chunk_type: Some(ChunkType::from_str("PnGm").unwrap()),
+            label: None,
+            spread: false,
+            nth: NonZeroUsize::new(1).unwrap(),
+            filter: None,
+            extract: None,
+            output: None,
+            resume: false,
+            raw: false,
+            fields: false,
+            field: None,
+            combine: vec![],
+            ignore_case: false,
+            passphrase: PassphraseArgs::default(),
+        });
+        let cli = Cli::parse_from(vec!["pngme", "decode", "/a/b/c", "PnGm"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_encode_with_fields() {
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "encode",
+            "/a/b/c",
+            "RuSt",
+            "--field",
+            "author=gabe",
+            "--field",
+            "equation=a=b",
+            "--field",
+            "author=someone else",
+        ]);
+        let args = match cli.subcommand {
+            Subcommand::Encode(args) => args,
+            _ => panic!("Expected the encode subcommand"),
+        };
+        let (chunk_type, _) = args.chunk_type_and_message().unwrap();
+        assert_eq!(chunk_type, Some(ChunkType::from_str("RuSt").unwrap()));
+        let fields = args.fields().unwrap();
+        assert_eq!(fields.get("author"), Some("someone else"));
+        assert_eq!(fields.get("equation"), Some("a=b"));
+
+        let cli = Cli::parse_from(vec![
+            "pngme", "encode", "/a/b/c", "RuSt", "Secret", "--field", "a=b",
+        ]);
+        match cli.subcommand {
+            Subcommand::Encode(args) => assert!(args.chunk_type_and_message().is_err()),
+            _ => panic!("Expected the encode subcommand"),
+        }
+        for bad in &["author", "=gabe"] {
+            let result =
+                Cli::try_parse_from(vec!["pngme", "encode", "/a/b/c", "RuSt", "--field", bad]);
+            assert!(result.is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    pub fn test_decode_fields() {
+        let cli = Cli::parse_from(vec![
+            "pngme", "decode", "/a/b/c", "RuSt", "--field", "build",
+        ]);
+        match cli.subcommand {
+            Subcommand::Decode(args) => {
+                assert!(args.shows_fields());
+                assert_eq!(args.field, Some("build".to_string()));
+            }
+            _ => panic!("Expected the decode subcommand"),
+        }
+        let result = Cli::try_parse_from(vec![
+            "pngme", "decode", "/a/b/c", "RuSt", "--fields", "--output", "auto",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    pub fn test_decode_with_label() {
+        let cli = Cli::parse_from(vec!["pngme", "decode", "/a/b/c", "--label", "diary"]);
+        if let Subcommand::Decode(args) = cli.subcommand {
+            assert_eq!(args.chunk_type, None);
+            assert_eq!(args.label, Some("diary".to_string()));
+        } else {
+            panic!("Expected the decode subcommand");
+        }
+
+        assert!(Cli::try_parse_from(vec!["pngme", "decode", "/a/b/c"]).is_err());
+        assert!(Cli::try_parse_from(vec![
+            "pngme", "decode", "/a/b/c", "PnGm", "--label", "diary"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_decode_with_passphrase_file() {
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "decode",
+            "/a/b/c",
+            "PnGm",
+            "--passphrase-file",
+            "/run/secrets/pngme",
+        ]);
+        if let Subcommand::Decode(args) = cli.subcommand {
+            assert_eq!(
+                args.passphrase.passphrase_file,
+                Some(PathBuf::from("/run/secrets/pngme"))
+            );
+        } else {
+            panic!("Expected the decode subcommand");
+        }
+    }
+
+    #[test]
+    pub fn test_decode_with_keyring() {
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "decode",
+            "/a/b/c",
+            "PnGm",
+            "--use-keyring",
+            "diary",
+        ]);
+        if let Subcommand::Decode(args) = cli.subcommand {
+            assert_eq!(args.passphrase.use_keyring, Some("diary".to_string()));
+        } else {
+            panic!("Expected the decode subcommand");
+        }
+
+        assert!(Cli::try_parse_from(vec![
+            "pngme",
+            "decode",
+            "/a/b/c",
+            "PnGm",
+            "--use-keyring",
+            "diary",
+            "--passphrase-file",
+            "/run/secrets/pngme",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_decode_extract() {
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "decode",
+            "/a/b/c",
+            "PnGm",
+            "--extract",
+            "out",
+        ]);
+        if let Subcommand::Decode(args) = cli.subcommand {
+            assert_eq!(args.extract, Some(PathBuf::from("out")));
+        } else {
+            panic!("Expected the decode subcommand");
+        }
+    }
+
+    #[test]
+    pub fn test_decode_output() {
+        let cli = Cli::parse_from(vec![
+            "pngme", "decode", "/a/b/c", "PnGm", "--output", "auto",
+        ]);
+        if let Subcommand::Decode(args) = cli.subcommand {
+            assert_eq!(args.output, Some(PathBuf::from("auto")));
+        } else {
+            panic!("Expected the decode subcommand");
+        }
+        assert!(Cli::try_parse_from(vec![
+            "pngme",
+            "decode",
+            "/a/b/c",
+            "PnGm",
+            "--output",
+            "auto",
+            "--extract",
+            "out",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_decode_resume() {
+        let cli = Cli::parse_from(vec![
+            "pngme", "decode", "/a/b/c", "PnGm", "--output", "out.tar", "--resume",
+        ]);
+        if let Subcommand::Decode(args) = cli.subcommand {
+            assert!(args.resume);
+            assert!(args.check_resume().is_ok());
+        } else {
+            panic!("Expected the decode subcommand");
+        }
+        assert!(
+            Cli::try_parse_from(vec!["pngme", "decode", "/a/b/c", "PnGm", "--resume"]).is_err()
+        );
+        let cli = Cli::parse_from(vec![
+            "pngme", "decode", "/a/b/c", "PnGm", "--output", "auto", "--resume",
+        ]);
+        if let Subcommand::Decode(args) = cli.subcommand {
+            assert!(args.check_resume().is_err());
+        } else {
+            panic!("Expected the decode subcommand");
+        }
+    }
+
+    #[test]
+    pub fn test_decode_raw() {
+        let cli = Cli::parse_from(vec!["pngme", "decode", "/a/b/c", "PnGm", "--raw"]);
+        if let Subcommand::Decode(args) = cli.subcommand {
+            assert!(args.raw);
+        } else {
+            panic!("Expected the decode subcommand");
+        }
+        assert!(Cli::try_parse_from(vec![
+            "pngme", "decode", "/a/b/c", "PnGm", "--raw", "--output", "out",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_decode_spread() {
+        let cli = Cli::parse_from(vec!["pngme", "decode", "/a/b/c", "--spread"]);
+        if let Subcommand::Decode(args) = cli.subcommand {
+            assert_eq!(args.chunk_type, None);
+            assert!(args.spread);
+        } else {
+            panic!("Expected the decode subcommand");
+        }
+
+        assert!(
+            Cli::try_parse_from(vec!["pngme", "decode", "/a/b/c", "PnGm", "--spread"]).is_err()
+        );
+    }
+
+    #[test]
+    pub fn test_decode_with_filter() {
+        let expected = Subcommand::Decode(DecodeArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            format: None,
+            chunk_type: Some(ChunkType::from_str("PnGm").unwrap()),
+            label: None,
+            spread: false,
+            nth: NonZeroUsize::new(1).unwrap(),
+            filter: Some("gpg --decrypt".to_string()),
+            extract: None,
+            output: None,
+            resume: false,
+            raw: false,
+            fields: false,
+            field: None,
+            combine: vec![],
+            ignore_case: false,
+            passphrase: PassphraseArgs::default(),
+        });
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "decode",
+            "/a/b/c",
+            "PnGm",
+            "--filter",
+            "gpg --decrypt",
+        ]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_quiet() {
+        let cli = Cli::parse_from(vec!["pngme", "decode", "/a/b/c", "PnGm", "--quiet"]);
+        assert!(cli.quiet);
+        let cli = Cli::parse_from(vec!["pngme", "-q", "decode", "/a/b/c", "PnGm"]);
+        assert!(cli.quiet);
+        let cli = Cli::parse_from(vec!["pngme", "decode", "/a/b/c", "PnGm"]);
+        assert!(!cli.quiet);
+    }
+
+    #[test]
+    pub fn test_errors_format() {
+        let cli = Cli::parse_from(vec!["pngme", "decode", "/a/b/c", "PnGm"]);
+        assert_eq!(cli.errors, ErrorFormat::Text);
+        let cli = Cli::parse_from(vec![
+            "pngme", "decode", "/a/b/c", "PnGm", "--errors", "json",
+        ]);
+        assert_eq!(cli.errors, ErrorFormat::Json);
+        let result = Cli::try_parse_from(vec!["pngme", "--errors", "xml", "print", "/a/b/c"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    pub fn test_no_verify_crc() {
+        let cli = Cli::parse_from(vec!["pngme", "print", "/a/b/c", "--no-verify-crc"]);
+        assert!(cli.no_verify_crc);
+        let cli = Cli::parse_from(vec!["pngme", "print", "/a/b/c"]);
+        assert!(!cli.no_verify_crc);
+    }
+
+    #[test]
+    pub fn test_remove() {
+        let expected = Subcommand::Remove(RemoveArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            format: None,
+            chunk_type: ChunkType::from_str("imAG").unwrap(),
+            nth: NonZeroUsize::new(1).unwrap(),
+            ignore_case: false,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "remove", "/a/b/c", "imAG"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_strip() {
+        let expected = Subcommand::Strip(StripArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            output_file_path: Some(PathBuf::from("/d/e/f")),
+            chunk_types: vec![ChunkType::from_str("tIME").unwrap()],
+            trailing: true,
+            dry_run: false,
+        });
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "strip",
+            "/a/b/c",
+            "/d/e/f",
+            "--type",
+            "tIME",
+            "--trailing",
+        ]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_strip_dry_run() {
+        let cli = Cli::parse_from(vec!["pngme", "strip", "/a/b/c", "--trailing", "--dry-run"]);
+        if let Subcommand::Strip(args) = cli.subcommand {
+            assert!(args.dry_run);
+        } else {
+            panic!("Expected the strip subcommand");
+        }
+        assert!(Cli::try_parse_from(vec!["pngme", "strip", "/a/b/c", "--dry-run"]).is_err());
+    }
+
+    #[test]
+    pub fn test_strip_needs_something_to_strip() {
+        let result = Cli::try_parse_from(vec!["pngme", "strip", "/a/b/c"]);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    pub fn test_dedupe() {
+        let expected = Subcommand::Dedupe(DedupeArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            output_file_path: None,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "dedupe", "/a/b/c"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_sort() {
+        let expected = Subcommand::Sort(SortArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            output_file_path: Some(PathBuf::from("/d/e/f")),
+        });
+        let cli = Cli::parse_from(vec!["pngme", "sort", "/a/b/c", "/d/e/f"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_move() {
+        let expected = Subcommand::Move(MoveArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            chunk_type: ChunkType::from_str("ruSt").unwrap(),
+            output_file_path: None,
+            nth: NonZeroUsize::new(1).unwrap(),
+            before: Some(ChunkType::from_str("IDAT").unwrap()),
+            after: None,
+            index: None,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "move", "/a/b/c", "ruSt", "--before", "IDAT"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_move_check_index() {
+        let args = |index: &str| match Cli::parse_from(vec![
+            "pngme", "move", "/a/b/c", "ruSt", "--index", index,
+        ])
+        .subcommand
+        {
+            Subcommand::Move(args) => args,
+            _ => panic!("Expected the move subcommand"),
+        };
+        assert!(args("5").check_index(6).is_ok());
+        let error = args("6").check_index(6).unwrap_err().to_string();
+        assert!(error.contains("--index 6"), "{}", error);
+    }
+
+    #[test]
+    pub fn test_move_needs_one_position() {
+        assert!(Cli::try_parse_from(vec!["pngme", "move", "/a/b/c", "ruSt"]).is_err());
+        assert!(Cli::try_parse_from(vec![
+            "pngme", "move", "/a/b/c", "ruSt", "--index", "1", "--after", "tEXt",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_corrupt() {
+        let expected = Subcommand::Corrupt(CorruptArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            output_file_path: Some(PathBuf::from("/d/e/f")),
+            chunk_type: Some(ChunkType::from_str("IDAT").unwrap()),
+            nth: NonZeroUsize::new(1).unwrap(),
+            flip_bits: Some(4),
+            zero_crc: false,
+            zero_length: false,
+            seed: Some(7),
+        });
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "corrupt",
+            "/a/b/c",
+            "/d/e/f",
+            "--chunk",
+            "IDAT",
+            "--flip-bits",
+            "4",
+            "--seed",
+            "7",
+        ]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_corrupt_needs_damage_and_chunk() {
+        assert!(Cli::try_parse_from(vec!["pngme", "corrupt", "/a/b/c"]).is_err());
+        assert!(Cli::try_parse_from(vec!["pngme", "corrupt", "/a/b/c", "--zero-crc"]).is_err());
+        assert!(
+            Cli::try_parse_from(vec!["pngme", "corrupt", "/a/b/c", "--flip-bits", "1"]).is_ok()
+        );
+    }
+
+    #[test]
+    pub fn test_decode_nth() {
+        let expected = Subcommand::Decode(DecodeArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            format: None,
+            chunk_type: Some(ChunkType::from_str("PnGm").unwrap()),
+            label: None,
+            spread: false,
+            nth: NonZeroUsize::new(3).unwrap(),
+            filter: None,
+            extract: None,
+            output: None,
+            resume: false,
+            raw: false,
+            fields: false,
+            field: None,
+            combine: vec![],
+            ignore_case: false,
+            passphrase: PassphraseArgs::default(),
+        });
+        let cli = Cli::parse_from(vec!["pngme", "decode", "/a/b/c", "PnGm", "--nth", "3"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_remove_nth() {
+        let expected = Subcommand::Remove(RemoveArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            format: None,
+            chunk_type: ChunkType::from_str("imAG").unwrap(),
+            nth: NonZeroUsize::new(2).unwrap(),
+            ignore_case: false,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "remove", "/a/b/c", "imAG", "--nth", "2"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_ignore_case() {
+        let cli = Cli::parse_from(vec!["pngme", "remove", "/a/b/c", "rust", "--ignore-case"]);
+        if let Subcommand::Remove(args) = cli.subcommand {
+            assert!(args.ignore_case);
+        } else {
+            panic!("Expected the remove subcommand");
+        }
+        let cli = Cli::parse_from(vec!["pngme", "decode", "/a/b/c", "rust", "--ignore-case"]);
+        if let Subcommand::Decode(args) = cli.subcommand {
+            assert!(args.ignore_case);
+        } else {
+            panic!("Expected the decode subcommand");
+        }
+        assert!(Cli::try_parse_from(vec![
+            "pngme",
+            "decode",
+            "/a/b/c",
+            "--label",
+            "diary",
+            "--ignore-case"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_nth_must_be_positive() {
+        let result = Cli::try_parse_from(vec!["pngme", "decode", "/a/b/c", "PnGm", "--nth", "0"]);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    pub fn test_set_meta() {
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "set-meta",
+            "/a/b/c",
+            "--creation-time",
+            "2020-01-01",
+            "--title",
+            "Dice",
+        ]);
+        match cli.subcommand {
+            Subcommand::SetMeta(args) => assert_eq!(
+                args.keywords(),
+                [("Title", "Dice"), ("Creation Time", "2020-01-01")]
+            ),
+            _ => panic!("Expected the set-meta subcommand"),
+        }
+        assert!(Cli::try_parse_from(vec!["pngme", "set-meta", "/a/b/c"]).is_err());
+    }
+
+    #[test]
+    pub fn test_print() {
+        let expected = Subcommand::Print(PrintArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            image_format: None,
+            format: PrintFormat::Text,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "print", "/a/b/c"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_print_json() {
+        let expected = Subcommand::Print(PrintArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            image_format: None,
+            format: PrintFormat::Json,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "print", "/a/b/c", "--format", "json"]);
+        assert_eq!(expected, cli.subcommand);
+    }
+
+    #[test]
+    pub fn test_export_and_import() {
+        let expected = Subcommand::Export(ExportArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            output_file_path: None,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "export", "/a/b/c"]);
+        assert_eq!(expected, cli.subcommand);
+
+        let expected = Subcommand::Import(ImportArgs {
+            json_path: PathBuf::from("/a/b/c.json"),
+            output_file_path: PathBuf::from("/a/b/c"),
+        });
+        let cli = Cli::parse_from(vec!["pngme", "import", "/a/b/c.json", "/a/b/c"]);
+        assert_eq!(expected, cli.subcommand);
+        assert!(Cli::try_parse_from(vec!["pngme", "import", "/a/b/c.json"]).is_err());
+    }
+
+    #[test]
+    pub fn test_info() {
+        let expected = Subcommand::Info(InfoArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            payload_warning: 30,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "info", "/a/b/c"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+        let cli = Cli::parse_from(vec!["pngme", "info", "/a/b/c", "--payload-warning", "50"]);
+        if let Subcommand::Info(args) = cli.subcommand {
+            assert_eq!(args.payload_warning, 50);
+        } else {
+            panic!("Expected the info subcommand");
+        }
+        assert!(
+            Cli::try_parse_from(vec!["pngme", "info", "/a/b/c", "--payload-warning", "0"]).is_err()
+        );
+    }
+
+    #[test]
+    pub fn test_hash() {
+        let cli = Cli::parse_from(vec!["pngme", "hash", "/a/b/c"]);
+        let expected = Subcommand::Hash(HashArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            algo: HashAlgorithm::Sha256,
+        });
+        assert_eq!(expected, cli.subcommand);
+
+        let cli = Cli::parse_from(vec!["pngme", "hash", "/a/b/c", "--algo", "blake3"]);
+        if let Subcommand::Hash(args) = cli.subcommand {
+            assert_eq!(args.algo, HashAlgorithm::Blake3);
+        } else {
+            panic!("Expected the hash subcommand");
+        }
+        assert!(Cli::try_parse_from(vec!["pngme", "hash", "/a/b/c", "--algo", "md5"]).is_err());
+    }
+
+    #[test]
+    pub fn test_pixel_hash() {
+        let cli = Cli::parse_from(vec!["pngme", "pixel-hash", "/a/b/c", "/d/e/f"]);
+        let expected = Subcommand::PixelHash(PixelHashArgs {
+            file_paths: vec![PathBuf::from("/a/b/c"), PathBuf::from("/d/e/f")],
+            algo: HashAlgorithm::Sha256,
+        });
+        assert_eq!(expected, cli.subcommand);
+        assert!(Cli::try_parse_from(vec!["pngme", "pixel-hash"]).is_err());
+    }
+
+    #[test]
+    pub fn test_phash() {
+        let cli = Cli::parse_from(vec!["pngme", "phash", "/a/b/c"]);
+        let expected = Subcommand::Phash(PhashArgs {
+            file_paths: vec![PathBuf::from("/a/b/c")],
+        });
+        assert_eq!(expected, cli.subcommand);
+        assert!(Cli::try_parse_from(vec!["pngme", "phash"]).is_err());
+    }
+
+    #[test]
+    pub fn test_imgdiff() {
+        let cli = Cli::parse_from(vec!["pngme", "imgdiff", "/a/b/c", "/d/e/f"]);
+        let expected = Subcommand::Imgdiff(ImgdiffArgs {
+            first: PathBuf::from("/a/b/c"),
+            second: PathBuf::from("/d/e/f"),
+        });
+        assert_eq!(expected, cli.subcommand);
+        assert!(Cli::try_parse_from(vec!["pngme", "imgdiff", "/a/b/c"]).is_err());
+    }
+
+    #[test]
+    pub fn test_cat() {
+        let expected = Subcommand::Cat(CatArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            type_prefix: "ru".to_string(),
+            output_file_path: None,
+            passphrase: PassphraseArgs::default(),
+        });
+        let cli = Cli::parse_from(vec!["pngme", "cat", "/a/b/c", "ru"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_cat_bad_prefix() {
+        let result = Cli::try_parse_from(vec!["pngme", "cat", "/a/b/c", "ru5"]);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    pub fn test_type_make() {
+        let expected = Subcommand::Type(TypeSubcommand::Make(TypeMakeArgs {
+            letters: ChunkType::from_str("rust").unwrap(),
+            critical: true,
+            public: false,
+            unsafe_to_copy: false,
+        }));
+        let cli = Cli::parse_from(vec!["pngme", "type", "make", "rust", "--critical"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_set_dpi() {
+        let expected = Subcommand::SetDpi(SetDpiArgs {
+            file_path: PathBuf::from("a.png"),
+            density: 11811.0,
+            output_file_path: Some(PathBuf::from("b.png")),
+            ppm: true,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "set-dpi", "a.png", "11811", "b.png", "--ppm"]);
+
+        assert_eq!(expected, cli.subcommand);
+        assert!(Cli::try_parse_from(vec!["pngme", "set-dpi", "a.png", "lots"]).is_err());
+    }
+
+    #[test]
+    pub fn test_set_transparent() {
+        let expected = Subcommand::SetTransparent(SetTransparentArgs {
+            file_path: PathBuf::from("a.png"),
+            color: Samples(vec![255, 0, 16]),
+            output_file_path: None,
+        });
+        for color in &["255,0,16", "#ff0010", "255, 0, 16"] {
+            let cli = Cli::parse_from(vec!["pngme", "set-transparent", "a.png", color]);
+            assert_eq!(expected, cli.subcommand);
+        }
+        for color in &["#ff00", "#gg0000", "red", "1,,2", "70000"] {
+            let result = Cli::try_parse_from(vec!["pngme", "set-transparent", "a.png", color]);
+            assert!(result.is_err(), "{}", color);
+        }
+    }
+
+    #[test]
+    pub fn test_set_background() {
+        let expected = Subcommand::SetBackground(SetBackgroundArgs {
+            file_path: PathBuf::from("a.png"),
+            color: Samples(vec![3]),
+            output_file_path: Some(PathBuf::from("b.png")),
+        });
+        let cli = Cli::parse_from(vec!["pngme", "set-background", "a.png", "3", "b.png"]);
+
+        assert_eq!(expected, cli.subcommand);
+    }
+
+    #[test]
+    pub fn test_icc() {
+        let expected = Subcommand::Icc(IccSubcommand::Embed(IccEmbedArgs {
+            file_path: PathBuf::from("a.png"),
+            profile_path: PathBuf::from("p3.icc"),
+            output_file_path: None,
+            name: "Display P3".to_string(),
+        }));
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "icc",
+            "embed",
+            "a.png",
+            "p3.icc",
+            "--name",
+            "Display P3",
+        ]);
+        assert_eq!(expected, cli.subcommand);
+
+        let cli = Cli::parse_from(vec!["pngme", "icc", "embed", "a.png", "p3.icc"]);
+        match cli.subcommand {
+            Subcommand::Icc(IccSubcommand::Embed(args)) => assert_eq!(args.name, "ICC profile"),
+            _ => panic!("expected icc embed"),
+        }
+    }
+
+    #[test]
+    pub fn test_apng_split() {
+        let expected = Subcommand::Apng(ApngSubcommand::Split(ApngSplitArgs {
+            file_path: PathBuf::from("a.png"),
+            out_dir: PathBuf::from("frames"),
+        }));
+        let cli = Cli::parse_from(vec!["pngme", "apng", "split", "a.png", "frames"]);
+
+        assert_eq!(expected, cli.subcommand);
+    }
+
+    #[test]
+    pub fn test_apng_build() {
+        let expected = Subcommand::Apng(ApngSubcommand::Build(ApngBuildArgs {
+            paths: vec![
+                PathBuf::from("1.png"),
+                PathBuf::from("2.png"),
+                PathBuf::from("out.png"),
+            ],
+            fps: 12,
+            plays: 0,
+        }));
+        let cli = Cli::parse_from(vec![
+            "pngme", "apng", "build", "1.png", "2.png", "--fps", "12", "out.png",
+        ]);
+
+        assert_eq!(expected, cli.subcommand);
+        match cli.subcommand {
+            Subcommand::Apng(ApngSubcommand::Build(args)) => {
+                let (frames, output) = args.frames_and_output().unwrap();
+                assert_eq!(frames.len(), 2);
+                assert_eq!(output, Path::new("out.png"));
+            }
+            _ => panic!("expected apng build"),
+        }
+        let cli = Cli::parse_from(vec!["pngme", "apng", "build", "out.png"]);
+        match cli.subcommand {
+            Subcommand::Apng(ApngSubcommand::Build(args)) => {
+                assert!(args.frames_and_output().is_err())
+            }
+            _ => panic!("expected apng build"),
+        }
+        let result = Cli::try_parse_from(vec![
+            "pngme", "apng", "build", "1.png", "o.png", "--fps", "0",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    pub fn test_types() {
+        let cli = Cli::parse_from(vec!["pngme", "types"]);
+
+        assert_eq!(Subcommand::Types, cli.subcommand);
+    }
+
+    #[test]
+    pub fn test_formats() {
+        let cli = Cli::parse_from(vec!["pngme", "formats"]);
+
+        assert_eq!(Subcommand::Formats, cli.subcommand);
+    }
+
+    #[test]
+    pub fn test_explain() {
+        let expected = Subcommand::Explain(ExplainArgs {
+            chunk_type: ChunkType::from_str("ruSt").unwrap(),
+        });
+        let cli = Cli::parse_from(vec!["pngme", "explain", "ruSt"]);
+
+        assert_eq!(expected, cli.subcommand);
+    }
+
+    #[test]
+    pub fn test_verify() {
+        let expected = Subcommand::Verify(VerifyArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            chunk_type: Some(ChunkType::from_str("ruSt").unwrap()),
+            pubkey: Some(PathBuf::from("pub.pem")),
+            crc: false,
+        });
+        let cli = Cli::parse_from(vec![
+            "pngme", "verify", "/a/b/c", "ruSt", "--pubkey", "pub.pem",
+        ]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_verify_crc() {
+        let expected = Subcommand::Verify(VerifyArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            chunk_type: None,
+            pubkey: None,
+            crc: true,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "verify", "/a/b/c", "--crc"]);
+        assert_eq!(expected, cli.subcommand);
+
+        assert!(Cli::try_parse_from(vec!["pngme", "verify", "/a/b/c"]).is_err());
+        assert!(Cli::try_parse_from(vec!["pngme", "verify", "/a/b/c", "ruSt", "--crc"]).is_err());
+    }
+
+    #[test]
+    pub fn test_export_and_import_meta() {
+        let expected = Subcommand::ExportMeta(ExportMetaArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            output_file_path: Some(PathBuf::from("meta.json")),
+        });
+        let cli = Cli::parse_from(vec!["pngme", "export-meta", "/a/b/c", "meta.json"]);
+        assert_eq!(expected, cli.subcommand);
+
+        let expected = Subcommand::ImportMeta(ImportMetaArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            json_path: PathBuf::from("meta.json"),
+            output_file_path: None,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "import-meta", "/a/b/c", "meta.json"]);
+        assert_eq!(expected, cli.subcommand);
+        assert!(Cli::try_parse_from(vec!["pngme", "import-meta", "/a/b/c"]).is_err());
+    }
+
+    #[test]
+    pub fn test_xmp() {
+        let expected = Subcommand::Xmp(XmpSubcommand::Import(XmpImportArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            xmp_path: PathBuf::from("c.xmp"),
+            output_file_path: None,
+        }));
+        let cli = Cli::parse_from(vec!["pngme", "xmp", "import", "/a/b/c", "c.xmp"]);
+        assert_eq!(expected, cli.subcommand);
+
+        let expected = Subcommand::Xmp(XmpSubcommand::Export(XmpExportArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            output_file_path: Some(PathBuf::from("c.xmp")),
+        }));
+        let cli = Cli::parse_from(vec!["pngme", "xmp", "export", "/a/b/c", "c.xmp"]);
+        assert_eq!(expected, cli.subcommand);
+        assert!(Cli::try_parse_from(vec!["pngme", "xmp", "import", "/a/b/c"]).is_err());
+    }
+
+    #[test]
+    pub fn test_key_generate() {
+        let expected = Subcommand::Key(KeySubcommand::Generate(KeyNameArgs {
+            name: "mine".to_string(),
+        }));
+        let cli = Cli::parse_from(vec!["pngme", "key", "generate", "mine"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_key_export() {
+        let expected = Subcommand::Key(KeySubcommand::Export(KeyExportArgs {
+            name: "mine".to_string(),
+            private: true,
+        }));
+        let cli = Cli::parse_from(vec!["pngme", "key", "export", "mine", "--private"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_key_import() {
+        let expected = Subcommand::Key(KeySubcommand::Import(KeyImportArgs {
+            name: "theirs".to_string(),
+            file_path: PathBuf::from("pub.pem"),
+        }));
+        let cli = Cli::parse_from(vec!["pngme", "key", "import", "theirs", "pub.pem"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_watch() {
+        let expected = Subcommand::Watch(WatchArgs {
+            dir: PathBuf::from("/renders"),
+            encode_with: PathBuf::from("stamp.toml"),
+        });
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "watch",
+            "/renders",
+            "--encode-with",
+            "stamp.toml",
+        ]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_filter_clean() {
+        let expected = Subcommand::Filter(FilterSubcommand::Clean(FilterCleanArgs {
+            local: Some(PathBuf::from("local.toml")),
+            strip: vec![
+                ChunkType::from_str("tEXt").unwrap(),
+                ChunkType::from_str("biLd").unwrap(),
+            ],
+        }));
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "filter",
+            "clean",
+            "--local",
+            "local.toml",
+            "--strip",
+            "tEXt",
+            "--strip",
+            "biLd",
+        ]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_filter_smudge() {
+        let expected =
+            Subcommand::Filter(FilterSubcommand::Smudge(FilterSmudgeArgs { local: None }));
+        let cli = Cli::parse_from(vec!["pngme", "filter", "smudge"]);
+        let actual = cli.subcommand;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    pub fn test_man() {
+        let expected = Subcommand::Man(ManArgs {
+            out_dir: Some(PathBuf::from("./man")),
+        });
+        let cli = Cli::parse_from(vec!["pngme", "man", "--out-dir", "./man"]);
+        assert_eq!(expected, cli.subcommand);
+    }
+
+    #[test]
+    pub fn test_shell() {
+        let expected = Subcommand::Shell(ShellArgs {
+            file_path: PathBuf::from("/a/b/c"),
+            passphrase: PassphraseArgs::default(),
+            allow_unsafe: false,
+        });
+        let cli = Cli::parse_from(vec!["pngme", "shell", "/a/b/c"]);
+        assert_eq!(expected, cli.subcommand);
+    }
+
+    #[test]
+    pub fn test_serve() {
+        let cli = Cli::parse_from(vec!["pngme", "serve"]);
+        let expected = Subcommand::Serve(ServeArgs {
+            listen: "127.0.0.1:8080".parse().unwrap(),
+            allow_unsafe: false,
+        });
+        assert_eq!(expected, cli.subcommand);
+        let cli = Cli::parse_from(vec![
+            "pngme",
+            "serve",
+            "--listen",
+            "0.0.0.0:80",
+            "--allow-unsafe",
+        ]);
+        let expected = Subcommand::Serve(ServeArgs {
+            listen: "0.0.0.0:80".parse().unwrap(),
+            allow_unsafe: true,
+        });
+        assert_eq!(expected, cli.subcommand);
+        assert!(Cli::try_parse_from(vec!["pngme", "serve", "--listen", "nowhere"]).is_err());
+    }
+
+    #[test]
+    pub fn test_aliases() {
+        let cli = Cli::parse_from(vec!["pngme", "e", "/a/b/c", "RuSt", "Secret"]);
+        assert!(matches!(cli.subcommand, Subcommand::Encode(_)));
+        let cli = Cli::parse_from(vec!["pngme", "d", "/a/b/c", "RuSt"]);
+        assert!(matches!(cli.subcommand, Subcommand::Decode(_)));
+        let cli = Cli::parse_from(vec!["pngme", "r", "/a/b/c", "RuSt"]);
+        assert!(matches!(cli.subcommand, Subcommand::Remove(_)));
+        let cli = Cli::parse_from(vec!["pngme", "p", "/a/b/c"]);
+        assert!(matches!(cli.subcommand, Subcommand::Print(_)));
     }
 
     #[test]
     pub fn test_unknown_subcommand() {
-        let result = Cli::from_iter_safe(vec!["pngme", "blah-blah", "some-argument"]);
+        let result = Cli::try_parse_from(vec!["pngme", "blah-blah", "some-argument"]);
 
         assert!(result.is_err());
     }
@@ -0,0 +1,178 @@
+//! How much a PNG can plausibly carry, and how to stripe a payload across
+//! several of them.
+//!
+//! Compression can make a PNG smaller than its pixels, but hardly ever
+//! bigger: even zlib's worst case only adds a few bytes. So a PNG that's
+//! bigger than its pixels would be uncompressed looks like it's carrying
+//! something. The room between the two is what each method can plausibly use,
+//! less what the method itself adds.
+use crate::info::ImageHeader;
+use crate::png::Png;
+use crate::split;
+use crate::spread;
+use crate::trailer;
+use std::error::Error;
+use std::fmt;
+
+/// The length, type, and CRC around a chunk's data.
+const CHUNK_OVERHEAD: u64 = 4 + 4 + 4;
+
+/// How many payload bytes a PNG can plausibly carry with each method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capacity {
+    /// In a new ancillary chunk.
+    pub chunk: u64,
+    /// After the IEND chunk.
+    pub trailer: u64,
+    /// Across the ancillary chunks already there.
+    pub spread: u64,
+}
+
+impl Capacity {
+    /// Work out what `png` can carry. Returns `Err` if its IHDR chunk is
+    /// missing or damaged.
+    pub fn of(png: &Png) -> crate::Result<Self> {
+        let header = ImageHeader::from_png(png)?;
+        let room = header
+            .raw_data_len()
+            .saturating_sub(png.total_size() as u64);
+        let carriers = png
+            .chunks()
+            .iter()
+            .filter(|c| spread::is_carrier(c))
+            .count() as u64;
+        Ok(Capacity {
+            chunk: room.saturating_sub(CHUNK_OVERHEAD),
+            trailer: room.saturating_sub(trailer::FOOTER_LENGTH as u64),
+            spread: if carriers == 0 {
+                0
+            } else {
+                room.saturating_sub(carriers * spread::FOOTER_LENGTH as u64)
+            },
+        })
+    }
+}
+
+/// The bytes each of `carriers` PNGs gets when a payload of `payload_len`
+/// bytes is striped across them: its fragment, with its header and chunk,
+/// and a manifest chunk.
+pub fn stripe_cost(payload_len: u64, carriers: u64) -> u64 {
+    let fragment = payload_len.div_ceil(carriers.max(1)).max(1);
+    let manifest = 4 + carriers * (4 + 32);
+    CHUNK_OVERHEAD + split::HEADER_LENGTH as u64 + fragment + CHUNK_OVERHEAD + manifest
+}
+
+/// The length of each fragment that striping a payload of `payload_len` bytes
+/// across `carriers` PNGs makes, cut the way `split::stripe_payload` cuts it.
+/// There can be fewer fragments than carriers: 5 bytes across 4 carriers is
+/// 2, 2, and 1 bytes.
+pub fn stripe_fragment_lengths(payload_len: u64, carriers: u64) -> Vec<u64> {
+    if payload_len == 0 {
+        return vec![0];
+    }
+    let fragment = payload_len.div_ceil(carriers.max(1));
+    (0..payload_len.div_ceil(fragment))
+        .map(|n| fragment.min(payload_len - fragment * n))
+        .collect()
+}
+
+/// Pick as few carriers as possible to stripe a payload of `payload_len` bytes
+/// across, given the chunk capacity of each. Returns the indexes of the
+/// carriers, biggest first, or `Err` if even all of them can't hold it.
+pub fn plan_stripe(
+    chunk_capacities: &[u64],
+    payload_len: u64,
+) -> Result<Vec<usize>, CapacityError> {
+    let mut by_size: Vec<usize> = (0..chunk_capacities.len()).collect();
+    by_size.sort_by_key(|&i| std::cmp::Reverse(chunk_capacities[i]));
+    // Striping cuts the payload into equal fragments, so the smallest carrier
+    // used has to hold one.
+    (1..=by_size.len())
+        .find(|&count| {
+            // Each capacity already allows for one chunk's overhead.
+            let needed = stripe_cost(payload_len, count as u64) - CHUNK_OVERHEAD;
+            chunk_capacities[by_size[count - 1]] >= needed
+        })
+        .map(|count| by_size[..count].to_vec())
+        .ok_or(CapacityError {
+            payload_len,
+            total: chunk_capacities.iter().sum(),
+        })
+}
+
+/// A payload is too big for the carriers it was meant for.
+#[derive(Debug, PartialEq)]
+pub struct CapacityError {
+    payload_len: u64,
+    /// What all the carriers could carry between them, if the payload didn't
+    /// have to be cut into equal fragments.
+    total: u64,
+}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Not enough room: {} bytes is more than the PNGs can plausibly carry (about {} bytes between them)",
+            self.payload_len, self.total
+        )
+    }
+}
+
+impl Error for CapacityError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::testing;
+
+    #[test]
+    fn test_capacity() {
+        // A 1x1 image has no room at all.
+        let capacity = Capacity::of(&testing::minimal_png()).unwrap();
+        assert_eq!(capacity.chunk, 0);
+        assert_eq!(capacity.spread, 0);
+
+        // 100x100 8-bit RGBA is 40,100 bytes of scanlines, but the tiny
+        // IDAT from minimal_png stays tiny.
+        let mut png = testing::png_with(vec![testing::text_chunk("Title", "Dice")]);
+        let mut ihdr = png.chunks()[0].data().to_vec();
+        ihdr[..8].copy_from_slice(&[0, 0, 0, 100, 0, 0, 0, 100]);
+        png.chunks_mut()[0] = Chunk::new(ChunkType::IHDR, ihdr);
+        let room = 40_100 - png.total_size() as u64;
+        let capacity = Capacity::of(&png).unwrap();
+        assert_eq!(capacity.chunk, room - 12);
+        assert_eq!(capacity.trailer, room - 16);
+        assert_eq!(capacity.spread, room - 12);
+    }
+
+    #[test]
+    fn test_capacity_without_header() {
+        assert!(Capacity::of(&Png::from_chunks(vec![])).is_err());
+    }
+
+    #[test]
+    fn test_plan_stripe() {
+        let cost = |count| stripe_cost(1000, count) - CHUNK_OVERHEAD;
+        // One carrier is enough.
+        assert_eq!(plan_stripe(&[5, cost(1), 7], 1000), Ok(vec![1]));
+        // Two are needed, and the biggest are used.
+        assert_eq!(
+            plan_stripe(&[cost(2), 5, cost(2) + 1], 1000),
+            Ok(vec![2, 0])
+        );
+        assert!(plan_stripe(&[cost(2) - 1, cost(2) - 1], 1000).is_err());
+        assert!(plan_stripe(&[], 1000).is_err());
+    }
+
+    #[test]
+    fn test_stripe_fragment_lengths() {
+        assert_eq!(stripe_fragment_lengths(10, 3), vec![4, 4, 2]);
+        // The fourth carrier would be left with nothing.
+        assert_eq!(stripe_fragment_lengths(5, 4), vec![2, 2, 1]);
+        assert_eq!(stripe_fragment_lengths(3, 5), vec![1, 1, 1]);
+        assert_eq!(stripe_fragment_lengths(0, 2), vec![0]);
+    }
+}
@@ -0,0 +1,188 @@
+//! Images that can carry metadata in typed blocks, which can be read, added,
+//! and removed without touching the image itself.
+//!
+//! A PNG's blocks are its chunks. JPEGs, WebPs, and GIFs have blocks of their
+//! own, and pngme keeps each payload in one or more of them, labelled with a
+//! chunk type, so that every format can be handled the same way.
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::gif::{self, Gif};
+use crate::jpeg::{self, Jpeg};
+use crate::png::{ChunkNotFoundError, Png};
+use crate::webp::{self, Webp};
+use std::convert::TryFrom;
+
+/// A container with typed, removable metadata blocks.
+pub trait MetadataCarrier {
+    /// The image format this is in.
+    fn format(&self) -> Format;
+
+    /// Every block that can be read and removed, in order, as a chunk with
+    /// its type and data. That's every chunk of a PNG, and every payload
+    /// pngme has added to other formats.
+    fn blocks(&self) -> crate::Result<Vec<Chunk>>;
+
+    /// The `n`th (counting from 0) block of type `chunk_type`.
+    fn block(&self, chunk_type: ChunkType, n: usize) -> crate::Result<Chunk> {
+        let blocks = self.blocks()?;
+        let present: Vec<ChunkType> = blocks.iter().map(|c| *c.chunk_type()).collect();
+        blocks
+            .into_iter()
+            .filter(|c| c.chunk_type() == &chunk_type)
+            .nth(n)
+            .ok_or_else(|| {
+                Box::new(ChunkNotFoundError::new(chunk_type, n).with_present(present))
+                    as crate::Error
+            })
+    }
+
+    /// Add a block of type `chunk_type` holding `data`, where the format
+    /// keeps new metadata.
+    fn append_block(&mut self, chunk_type: ChunkType, data: &[u8]) -> crate::Result<()>;
+
+    /// Remove the `n`th (counting from 0) block of type `chunk_type`, and
+    /// return it.
+    fn remove_block(&mut self, chunk_type: ChunkType, n: usize) -> crate::Result<Chunk>;
+
+    /// One line for each part of the file, for people to read: the blocks
+    /// that are part of the image or its own metadata, and the payloads.
+    fn describe(&self) -> crate::Result<Vec<String>>;
+
+    fn as_bytes(&self) -> Vec<u8>;
+}
+
+/// An image format that can carry metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Webp,
+    Gif,
+}
+
+impl Format {
+    /// Every format pngme can hide messages in.
+    pub const ALL: [Format; 4] = [Format::Png, Format::Jpeg, Format::Webp, Format::Gif];
+
+    /// How many bytes from the start of a file [sniff](#method.sniff) needs
+    /// to tell the formats apart.
+    pub const SNIFF_LENGTH: usize = 12;
+
+    /// The format `bytes` are in, going by how they start, if they're in one
+    /// pngme knows.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&Png::STANDARD_HEADER) {
+            Some(Format::Png)
+        } else if jpeg::is_jpeg(bytes) {
+            Some(Format::Jpeg)
+        } else if webp::is_webp(bytes) {
+            Some(Format::Webp)
+        } else if gif::is_gif(bytes) {
+            Some(Format::Gif)
+        } else {
+            None
+        }
+    }
+
+    /// The format's usual name, like `PNG`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Png => "PNG",
+            Format::Jpeg => "JPEG",
+            Format::Webp => "WebP",
+            Format::Gif => "GIF",
+        }
+    }
+
+    /// The ways `encode` can hide a message in this format, by the names
+    /// `--method` takes.
+    pub fn methods(self) -> &'static [&'static str] {
+        match self {
+            Format::Png => &["chunk", "trailer", "spread"],
+            Format::Jpeg | Format::Webp | Format::Gif => &["chunk"],
+        }
+    }
+
+    /// How much a message in this format can hold, and what limits it.
+    pub fn capacity(self) -> &'static str {
+        match self {
+            Format::Png => "up to 2 GiB per chunk, or more across chunks with --split-size; a trailer has no limit",
+            Format::Jpeg => "up to 65,519 bytes per APP15 segment, across up to 65,535 segments",
+            Format::Webp => "one RIFF chunk per message, but the whole file has to stay under 4 GiB",
+            Format::Gif => "any length, in 255-byte sub-blocks of an application extension",
+        }
+    }
+
+    /// Read `bytes` as an image in this format.
+    pub fn parse(self, bytes: &[u8]) -> crate::Result<Box<dyn MetadataCarrier>> {
+        Ok(match self {
+            Format::Png => Box::new(Png::try_from(bytes)?),
+            Format::Jpeg => Box::new(Jpeg::try_from(bytes)?),
+            Format::Webp => Box::new(Webp::try_from(bytes)?),
+            Format::Gif => Box::new(Gif::try_from(bytes)?),
+        })
+    }
+}
+
+impl MetadataCarrier for Png {
+    fn format(&self) -> Format {
+        Format::Png
+    }
+
+    fn blocks(&self) -> crate::Result<Vec<Chunk>> {
+        Ok(self
+            .chunks()
+            .iter()
+            .map(|c| Chunk::new(*c.chunk_type(), c.data().to_vec()))
+            .collect())
+    }
+
+    fn append_block(&mut self, chunk_type: ChunkType, data: &[u8]) -> crate::Result<()> {
+        self.append_chunk(Chunk::new(chunk_type, data.to_vec()));
+        Ok(())
+    }
+
+    fn remove_block(&mut self, chunk_type: ChunkType, n: usize) -> crate::Result<Chunk> {
+        Ok(self.remove_nth_chunk(chunk_type, n)?)
+    }
+
+    fn describe(&self) -> crate::Result<Vec<String>> {
+        Ok(self.chunks().iter().map(Chunk::to_string).collect())
+    }
+
+    fn as_bytes(&self) -> Vec<u8> {
+        Png::as_bytes(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_sniff() {
+        let png = testing::minimal_png().as_bytes();
+        assert_eq!(Format::sniff(&png), Some(Format::Png));
+        assert_eq!(Format::sniff(b"\xff\xd8\xff\xe0"), Some(Format::Jpeg));
+        assert_eq!(Format::sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some(Format::Webp));
+        assert_eq!(Format::sniff(b"GIF89a"), Some(Format::Gif));
+        assert_eq!(Format::sniff(b"%PDF-1.7"), None);
+        assert_eq!(Format::Png.parse(&png).unwrap().format(), Format::Png);
+        assert!(Format::Jpeg.parse(&png).is_err());
+    }
+
+    #[test]
+    fn test_png_blocks() {
+        let mut png: Box<dyn MetadataCarrier> = Box::new(testing::minimal_png());
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        png.append_block(chunk_type, b"hello").unwrap();
+        assert_eq!(png.format(), Format::Png);
+        assert_eq!(png.blocks().unwrap().len(), 4);
+        assert_eq!(png.block(chunk_type, 0).unwrap().data(), b"hello");
+        assert_eq!(png.describe().unwrap().last().unwrap(), "ruSt\thello");
+        assert_eq!(png.remove_block(chunk_type, 0).unwrap().data(), b"hello");
+        assert!(png.block(chunk_type, 0).is_err());
+    }
+}
@@ -1,12 +1,11 @@
 use crate::chunk_type::ChunkType;
+use crc::crc32;
 use std::convert::{TryFrom, TryInto};
 use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
 use std::io::{BufReader, Read};
 
-const MAXIMUM_LENGTH: u32 = (1 << 31) - 1;
-
 /// Each chunk consists of four parts: length, chunk type, chunk data, and CRC.
 pub struct Chunk {
     /// A 4-byte unsigned integer giving the number of bytes in the chunk's data
@@ -31,10 +30,13 @@ pub struct Chunk {
 }
 
 impl Chunk {
+    /// The most data a chunk can hold, in bytes.
+    pub const MAXIMUM_LENGTH: u32 = (1 << 31) - 1;
+
     /// Build a chunk from a [ChunkType](../chunk_type/struct.ChunkType.html) and
     /// chunk data.
     pub fn new(chunk_type: ChunkType, chunk_data: Vec<u8>) -> Self {
-        let crc = crc::crc32::checksum_ieee(&[&chunk_type.bytes(), chunk_data.as_slice()].concat());
+        let crc = checksum(&chunk_type, &chunk_data);
         Chunk {
             length: chunk_data.len() as u32,
             chunk_type,
@@ -57,15 +59,34 @@ impl Chunk {
     }
 
     /// The chunk data.
-    fn data(&self) -> &[u8] {
+    pub fn data(&self) -> &[u8] {
         &self.chunk_data
     }
 
-    /// The pre-calculated CRC (cyclic redundancy check).
-    fn crc(&self) -> u32 {
+    /// Replace the chunk's data, updating its length and CRC to match.
+    pub fn set_data(&mut self, chunk_data: Vec<u8>) {
+        self.length = chunk_data.len() as u32;
+        self.crc = checksum(&self.chunk_type, &chunk_data);
+        self.chunk_data = chunk_data;
+    }
+
+    /// The CRC (cyclic redundancy check) stored with the chunk. A chunk that
+    /// was read without checking CRCs keeps whatever CRC it was stored with,
+    /// which may not match its type and data.
+    pub fn crc(&self) -> u32 {
         self.crc
     }
 
+    /// The CRC that the chunk's type and data add up to.
+    pub fn expected_crc(&self) -> u32 {
+        checksum(&self.chunk_type, &self.chunk_data)
+    }
+
+    /// Does the stored CRC match the chunk's type and data?
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc == self.expected_crc()
+    }
+
     /// Attempt to represent the data a UTF-8 string. Returns `Err` if it could
     /// not decode to a String.
     pub fn data_as_string(&self) -> crate::Result<String> {
@@ -85,6 +106,14 @@ impl Chunk {
     }
 }
 
+/// The CRC of a chunk, which covers its type and data but not its length. The
+/// type and data are fed to the checksum one after the other rather than
+/// copied into one buffer, since the data can be very large.
+fn checksum(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    let crc = crc32::update(0, &crc32::IEEE_TABLE, &chunk_type.bytes());
+    crc32::update(crc, &crc32::IEEE_TABLE, data)
+}
+
 /// Something went wrong while decoding a chunk.
 #[derive(Debug)]
 pub struct ChunkDecodingError {
@@ -104,16 +133,16 @@ impl fmt::Display for ChunkDecodingError {
 }
 impl Error for ChunkDecodingError {}
 
-impl TryFrom<&[u8]> for Chunk {
-    type Error = crate::Error;
-
-    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+impl Chunk {
+    /// Read a chunk from `bytes`, checking its CRC only if `verify_crc` is
+    /// set. An unchecked chunk keeps the CRC it was read with.
+    pub(crate) fn parse(bytes: &[u8], verify_crc: bool) -> crate::Result<Self> {
         let mut reader = BufReader::new(bytes);
         // Store the various 4-byte values in a chunk
         let mut buf: [u8; 4] = [0; 4];
         reader.read_exact(&mut buf)?;
         let length = u32::from_be_bytes(buf);
-        if length > MAXIMUM_LENGTH {
+        if length > Chunk::MAXIMUM_LENGTH {
             return Err(ChunkDecodingError::boxed(format!(
                 "Length is too long ({} > 2^31 - 1)",
                 length
@@ -132,38 +161,69 @@ impl TryFrom<&[u8]> for Chunk {
         }
         reader.read_exact(&mut buf)?;
         let provided_crc = u32::from_be_bytes(buf);
-        let true_crc =
-            crc::crc32::checksum_ieee(&[&chunk_type.bytes(), chunk_data.as_slice()].concat());
-        if provided_crc != true_crc {
-            return Err(ChunkDecodingError::boxed(format!(
-                "Bad CRC (received {}, expected {})",
-                provided_crc, true_crc
-            )));
+        if verify_crc {
+            let true_crc = checksum(&chunk_type, &chunk_data);
+            if provided_crc != true_crc {
+                return Err(ChunkDecodingError::boxed(format!(
+                    "Bad CRC (received {}, expected {})",
+                    provided_crc, true_crc
+                )));
+            }
         }
         Ok(Chunk {
             length,
             chunk_type,
             chunk_data,
-            crc: true_crc,
+            crc: provided_crc,
         })
     }
 }
 
+impl TryFrom<&[u8]> for Chunk {
+    type Error = crate::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        Chunk::parse(bytes, true)
+    }
+}
+
+/// How many bytes of data that isn't text are shown when displaying a chunk.
+const PREVIEW_LENGTH: usize = 16;
+
+/// Shows the chunk type and its data. Text is shown as-is, as long as it has
+/// no control characters other than newlines and tabs. Anything else could mess
+/// up a terminal, so it is shown as its length and a hex preview of the first
+/// few bytes.
 impl Display for Chunk {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}\t{}",
-            self.chunk_type(),
-            self.data_as_string()
-                .unwrap_or_else(|_| "[data]".to_string())
-        )
+        write!(f, "{}\t", self.chunk_type())?;
+        match std::str::from_utf8(self.data()) {
+            Ok(text)
+                if !text
+                    .chars()
+                    .any(|c| c.is_control() && c != '\n' && c != '\t') =>
+            {
+                write!(f, "{}", text)
+            }
+            _ => {
+                let length = self.data().len();
+                write!(f, "[{} byte{}:", length, if length == 1 { "" } else { "s" })?;
+                for byte in self.data().iter().take(PREVIEW_LENGTH) {
+                    write!(f, " {:02x}", byte)?;
+                }
+                if length > PREVIEW_LENGTH {
+                    write!(f, " ...")?;
+                }
+                write!(f, "]")
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
 
     fn testing_chunk() -> Chunk {
         let data_length: u32 = 42;
@@ -209,6 +269,18 @@ mod tests {
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_set_data() {
+        let mut chunk = testing_chunk();
+        chunk.set_data(b"Shorter".to_vec());
+        assert_eq!(chunk.length(), 7);
+        assert_eq!(chunk.data(), b"Shorter");
+        assert!(chunk.has_valid_crc());
+        let parsed = Chunk::try_from(chunk.as_bytes().as_slice()).unwrap();
+        assert_eq!(parsed.crc(), chunk.crc());
+        assert_eq!(parsed.data(), b"Shorter");
+    }
+
     #[test]
     fn test_valid_chunk_from_bytes() {
         let data_length: u32 = 42;
@@ -255,6 +327,55 @@ mod tests {
         let chunk = Chunk::try_from(chunk_data.as_ref());
 
         assert!(chunk.is_err());
+
+        let chunk = Chunk::parse(&chunk_data, false).unwrap();
+        assert_eq!(chunk.crc(), 2882656333);
+        assert_eq!(chunk.expected_crc(), 2882656334);
+        assert!(!chunk.has_valid_crc());
+    }
+
+    #[test]
+    fn test_checksum_matches_whole_buffer() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = b"This is where your secret message will be!";
+        let whole: Vec<u8> = chunk_type
+            .bytes()
+            .iter()
+            .chain(data.iter())
+            .copied()
+            .collect();
+        assert_eq!(checksum(&chunk_type, data), crc32::checksum_ieee(&whole));
+        assert_eq!(checksum(&chunk_type, data), 2882656334);
+    }
+
+    #[test]
+    fn test_display_binary_data() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0xff; 20]);
+        assert_eq!(
+            chunk.to_string(),
+            "RuSt\t[20 bytes: ff ff ff ff ff ff ff ff ff ff ff ff ff ff ff ff ...]"
+        );
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0x89, 0x50]);
+        assert_eq!(chunk.to_string(), "RuSt\t[2 bytes: 89 50]");
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0]);
+        assert_eq!(chunk.to_string(), "RuSt\t[1 byte: 00]");
+    }
+
+    #[test]
+    fn test_display_text() {
+        let chunk = Chunk::new(
+            ChunkType::from_str("RuSt").unwrap(),
+            b"two\tlines\n".to_vec(),
+        );
+        assert_eq!(chunk.to_string(), "RuSt\ttwo\tlines\n");
+        let chunk = Chunk::new(
+            ChunkType::from_str("RuSt").unwrap(),
+            b"red\x1b[31m".to_vec(),
+        );
+        assert_eq!(
+            chunk.to_string(),
+            "RuSt\t[8 bytes: 72 65 64 1b 5b 33 31 6d]"
+        );
     }
 
     #[test]
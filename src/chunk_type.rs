@@ -1,3 +1,4 @@
+use rand::Rng;
 use std::convert::{From, TryFrom};
 use std::error::Error;
 use std::fmt;
@@ -8,7 +9,7 @@ use std::str::FromStr;
 /// spec](http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html).
 /// Type codes are restricted to consist of uppercase and lowercase ASCII letters
 /// (A-Z and a-z, or 65-90 and 97-122 decimal).
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ChunkType {
     bytes: [u8; 4],
 }
@@ -16,9 +17,227 @@ pub struct ChunkType {
 // Many "unused" methods are used in tests
 #[allow(dead_code)]
 impl ChunkType {
+    /// The chunk that records which chunk types were picked at random, one
+    /// per line.
+    pub const TYPE_MANIFEST: ChunkType = ChunkType { bytes: *b"tyPe" };
+
+    /// The chunk that records how a payload was split into fragments. See
+    /// [split::Manifest](../split/struct.Manifest.html).
+    pub const FRAGMENT_MANIFEST: ChunkType = ChunkType { bytes: *b"frAg" };
+
+    /// The chunk that maps labels to the random chunk types their payloads are
+    /// hidden under. See [labels](../labels/index.html).
+    pub const LABEL_INDEX: ChunkType = ChunkType { bytes: *b"inDx" };
+
+    /// The chunk that holds a detached signature over another chunk's payload.
+    pub const SIGNATURE: ChunkType = ChunkType { bytes: *b"siGn" };
+
+    /// Every chunk type that pngme itself gives a meaning to.
+    pub(crate) const PNGME_TYPES: [ChunkType; 4] = [
+        Self::TYPE_MANIFEST,
+        Self::FRAGMENT_MANIFEST,
+        Self::SIGNATURE,
+        Self::LABEL_INDEX,
+    ];
+
+    /// The standard image header chunk, which must come first.
+    pub const IHDR: ChunkType = ChunkType { bytes: *b"IHDR" };
+
+    /// The standard palette chunk, which must come before the image data.
+    pub const PLTE: ChunkType = ChunkType { bytes: *b"PLTE" };
+
+    /// The standard image data chunk. There can be several, but they must all
+    /// be next to each other.
+    pub const IDAT: ChunkType = ChunkType { bytes: *b"IDAT" };
+
+    /// The standard image trailer chunk, which must come last.
+    pub const IEND: ChunkType = ChunkType { bytes: *b"IEND" };
+
+    /// The standard chunk for Latin-1 textual data.
+    pub const TEXT: ChunkType = ChunkType { bytes: *b"tEXt" };
+
+    /// The standard chunk for compressed Latin-1 textual data.
+    pub const ZTXT: ChunkType = ChunkType { bytes: *b"zTXt" };
+
+    /// The standard chunk for UTF-8 textual data, optionally compressed.
+    pub const ITXT: ChunkType = ChunkType { bytes: *b"iTXt" };
+
+    /// The standard chunk for which color, or which palette entries, are
+    /// transparent.
+    pub const TRNS: ChunkType = ChunkType { bytes: *b"tRNS" };
+
+    /// The standard chunk for the color to show the image on.
+    pub const BKGD: ChunkType = ChunkType { bytes: *b"bKGD" };
+
+    /// The standard chunk for an embedded ICC color profile.
+    pub const ICCP: ChunkType = ChunkType { bytes: *b"iCCP" };
+
+    /// The standard chunk saying the image is in the sRGB color space.
+    pub const SRGB: ChunkType = ChunkType { bytes: *b"sRGB" };
+
+    /// The standard chunk for the physical size or aspect ratio of the pixels.
+    pub const PHYS: ChunkType = ChunkType { bytes: *b"pHYs" };
+
+    /// The standard chunk for a suggested palette.
+    pub const SPLT: ChunkType = ChunkType { bytes: *b"sPLT" };
+
+    /// The APNG chunk that says how many frames there are, and how many
+    /// times to play them.
+    pub const ACTL: ChunkType = ChunkType { bytes: *b"acTL" };
+
+    /// The APNG chunk before each frame, with its size, position, and timing.
+    pub const FCTL: ChunkType = ChunkType { bytes: *b"fcTL" };
+
+    /// The APNG chunk for the image data of frames after the first.
+    pub const FDAT: ChunkType = ChunkType { bytes: *b"fdAT" };
+
+    /// The standard chunk for the time the image was last changed.
+    pub const TIME: ChunkType = ChunkType { bytes: *b"tIME" };
+
+    /// The chunk types in the PNG specification (third edition), and the
+    /// registered extensions to it, with what each holds.
+    const STANDARD_TYPES: &'static [([u8; 4], &'static str)] = &[
+        (
+            *b"IHDR",
+            "Image header: width, height, bit depth, color type, and interlacing",
+        ),
+        (*b"PLTE", "Palette of up to 256 colors"),
+        (*b"IDAT", "Compressed image data"),
+        (*b"IEND", "End of the image"),
+        (
+            *b"tRNS",
+            "Transparency, as one color or an alpha for each palette entry",
+        ),
+        (*b"cHRM", "Chromaticities of the primaries and white point"),
+        (*b"gAMA", "Image gamma"),
+        (*b"iCCP", "Embedded ICC color profile"),
+        (*b"sBIT", "Significant bits in each channel"),
+        (
+            *b"sRGB",
+            "The image is in the sRGB color space, with this rendering intent",
+        ),
+        (
+            *b"cICP",
+            "Coding-independent code points for the color space, as in video",
+        ),
+        (*b"mDCV", "Color volume of the mastering display"),
+        (*b"cLLI", "Content light levels"),
+        (*b"tEXt", "Latin-1 text, with a keyword"),
+        (*b"zTXt", "Compressed Latin-1 text, with a keyword"),
+        (
+            *b"iTXt",
+            "UTF-8 text, with a keyword and language, maybe compressed",
+        ),
+        (*b"bKGD", "Background color to show the image on"),
+        (*b"hIST", "How often each palette color is used"),
+        (*b"pHYs", "Physical size or aspect ratio of the pixels"),
+        (*b"sPLT", "Suggested palette"),
+        (*b"eXIf", "Exif metadata"),
+        (*b"tIME", "When the image was last changed"),
+        (
+            *b"acTL",
+            "APNG animation control: how many frames, and how many plays",
+        ),
+        (
+            *b"fcTL",
+            "APNG frame control: a frame's size, position, and timing",
+        ),
+        (*b"fdAT", "APNG frame data"),
+        (*b"oFFs", "Offset of the image, for printing"),
+        (*b"pCAL", "Calibration of pixel values to physical values"),
+        (*b"sCAL", "Physical scale of the image's subject"),
+        (*b"gIFg", "GIF graphic control extension"),
+        (*b"gIFx", "GIF application extension"),
+        (*b"gIFt", "GIF plain text extension (deprecated)"),
+        (*b"sTER", "Stereo image layout"),
+        (*b"dSIG", "Digital signature of the file"),
+        (*b"fRAc", "Fractal image parameters"),
+    ];
+
+    /// What each of [PNGME_TYPES](#associatedconstant.PNGME_TYPES) holds.
+    const PNGME_DESCRIPTIONS: [&'static str; 4] = [
+        "pngme: the chunk types that were picked at random",
+        "pngme: how a payload was split into fragments",
+        "pngme: a signature over another chunk's payload",
+        "pngme: the encrypted index of labels",
+    ];
+
+    /// Pick a random chunk type that is ancillary, private, and safe to copy,
+    /// with a valid reserved bit (shaped like `ruSt`). Decoders ignore these
+    /// chunks and editors keep them, which makes them the best place for
+    /// secret messages.
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        let mut bytes = [0u8; 4];
+        for byte in bytes.iter_mut() {
+            *byte = rng.gen_range(b'a'..=b'z');
+        }
+        bytes[2] = bytes[2].to_ascii_uppercase();
+        ChunkType { bytes }
+    }
+
+    /// Pick a [random](#method.random) chunk type that isn't one pngme gives
+    /// a meaning to itself, like the fragment manifest's, and isn't in
+    /// `present`, so that the payload hidden under it can't be mistaken for
+    /// another one.
+    pub fn random_unused<R: Rng + ?Sized>(rng: &mut R, present: &[ChunkType]) -> Self {
+        loop {
+            let candidate = Self::random(rng);
+            if !Self::PNGME_TYPES.contains(&candidate) && !present.contains(&candidate) {
+                return candidate;
+            }
+        }
+    }
+
+    /// Build a chunk type from four letters, changing the case of each one
+    /// so that the property bits match what was asked for. The reserved bit
+    /// is always made valid.
+    /// Returns `Err` if any of the bytes in `base` is not an ASCII letter.
+    pub fn with_properties(
+        base: [u8; 4],
+        critical: bool,
+        private: bool,
+        safe_to_copy: bool,
+    ) -> crate::Result<Self> {
+        let mut chunk_type = Self::try_from(base)?;
+        let set_case = |b: u8, uppercase: bool| {
+            if uppercase {
+                b.to_ascii_uppercase()
+            } else {
+                b.to_ascii_lowercase()
+            }
+        };
+        chunk_type.bytes[0] = set_case(base[0], critical);
+        chunk_type.bytes[1] = set_case(base[1], !private);
+        chunk_type.bytes[2] = set_case(base[2], true);
+        chunk_type.bytes[3] = set_case(base[3], !safe_to_copy);
+        Ok(chunk_type)
+    }
+
+    /// Start building a chunk type from four letters, like `"rust"`, and
+    /// then pick its properties, which sets the case of the letters:
+    ///
+    /// ```
+    /// # use pngme::ChunkType;
+    /// let chunk_type = ChunkType::builder("rust")
+    ///     .ancillary(true)
+    ///     .private(true)
+    ///     .safe_to_copy(true)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(chunk_type.to_string(), "ruSt");
+    /// ```
+    pub fn builder(name: &str) -> ChunkTypeBuilder {
+        ChunkTypeBuilder {
+            name: name.to_string(),
+            ancillary: None,
+            private: None,
+            safe_to_copy: None,
+        }
+    }
+
     /// Must be in ASCII A-Z or a-z (decimal 65-90 and 97-122).
     fn is_valid_byte(b: u8) -> bool {
-        (65 <= b && b <= 90) || (97 <= b && b <= 122)
+        (65..=90).contains(&b) || (97..=122).contains(&b)
     }
 
     /// All of the bytes that make up this chunk type.
@@ -43,13 +262,15 @@ impl ChunkType {
 
     /// A chunk is critical if the ancillary bit is 0.
     /// The ancillary bit is the (0-indexed) 5th bit of the 0th byte.
-    fn is_critical(&self) -> bool {
+    /// Decoders have to understand every critical chunk to show the image,
+    /// and can skip ancillary ones.
+    pub fn is_critical(&self) -> bool {
         Self::bit_is_zero(self.bytes[0], 5)
     }
 
     /// A chunk is public if the private bit is 0.
     /// The private bit is the (0-indexed) 5th bit of the 1st byte.
-    fn is_public(&self) -> bool {
+    pub fn is_public(&self) -> bool {
         Self::bit_is_zero(self.bytes[1], 5)
     }
 
@@ -58,22 +279,109 @@ impl ChunkType {
     /// spec](http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html):
     /// > Must be 0 (uppercase) in files conforming to this version of PNG.
     /// > The significance of the case of the third letter of the chunk name is reserved for possible future expansion
-    fn is_reserved_bit_valid(&self) -> bool {
+    pub fn is_reserved_bit_valid(&self) -> bool {
         Self::bit_is_zero(self.bytes[2], 5)
     }
 
     /// A chunk is safe to copy if the safe-to-copy bit is 1.
     /// The safe-to-copy bit is the (0-indexed) 5th bit of the 3rd byte.
-    fn is_safe_to_copy(&self) -> bool {
+    /// Editors that don't understand a chunk should only keep it if it's safe
+    /// to copy.
+    pub fn is_safe_to_copy(&self) -> bool {
         !Self::bit_is_zero(self.bytes[3], 5)
     }
 
+    /// Whether `other` has the same letters as this chunk type, whatever their
+    /// case (and so whatever their property bits).
+    pub fn eq_ignore_case(&self, other: &ChunkType) -> bool {
+        self.bytes.eq_ignore_ascii_case(&other.bytes)
+    }
+
+    /// Whether this chunk type is in the PNG specification or one of its
+    /// registered extensions, so that decoders and editors know what it means.
+    pub fn is_standard(&self) -> bool {
+        Self::STANDARD_TYPES
+            .iter()
+            .any(|(bytes, _)| bytes == &self.bytes)
+    }
+
+    /// Every chunk type pngme knows the meaning of: the standard ones, in the
+    /// order the specification lists them, then pngme's own.
+    pub fn known() -> impl Iterator<Item = ChunkType> {
+        Self::STANDARD_TYPES
+            .iter()
+            .map(|(bytes, _)| ChunkType { bytes: *bytes })
+            .chain(Self::PNGME_TYPES)
+    }
+
+    /// A one-line description of what a chunk of this type holds, if it's one
+    /// of the [known](#method.known) types.
+    pub fn description(&self) -> Option<&'static str> {
+        Self::STANDARD_TYPES
+            .iter()
+            .find(|(bytes, _)| bytes == &self.bytes)
+            .map(|(_, description)| *description)
+            .or_else(|| {
+                Self::PNGME_TYPES
+                    .iter()
+                    .position(|t| t == self)
+                    .map(|i| Self::PNGME_DESCRIPTIONS[i])
+            })
+    }
+
     /// Is the chunk type valid?
-    fn is_valid(&self) -> bool {
+    pub(crate) fn is_valid(&self) -> bool {
         self.is_reserved_bit_valid()
     }
 }
 
+/// Builds a [ChunkType](struct.ChunkType.html) with the properties that are
+/// asked for. See [ChunkType::builder](struct.ChunkType.html#method.builder).
+/// Properties that aren't asked for keep whatever the case of the letters
+/// says, and the reserved bit is always made valid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkTypeBuilder {
+    name: String,
+    ancillary: Option<bool>,
+    private: Option<bool>,
+    safe_to_copy: Option<bool>,
+}
+
+impl ChunkTypeBuilder {
+    /// Whether decoders may ignore chunks of this type (the first letter is
+    /// lowercase) or must understand them (uppercase).
+    pub fn ancillary(mut self, ancillary: bool) -> Self {
+        self.ancillary = Some(ancillary);
+        self
+    }
+
+    /// Whether the chunk type is private (the second letter is lowercase) or
+    /// public (uppercase).
+    pub fn private(mut self, private: bool) -> Self {
+        self.private = Some(private);
+        self
+    }
+
+    /// Whether editors that don't understand chunks of this type may copy
+    /// them into a changed image (the fourth letter is lowercase) or not
+    /// (uppercase).
+    pub fn safe_to_copy(mut self, safe_to_copy: bool) -> Self {
+        self.safe_to_copy = Some(safe_to_copy);
+        self
+    }
+
+    /// Returns `Err` if the name isn't four ASCII letters.
+    pub fn build(&self) -> crate::Result<ChunkType> {
+        let base = ChunkType::from_str(&self.name)?;
+        ChunkType::with_properties(
+            base.bytes,
+            self.ancillary.map_or(base.is_critical(), |a| !a),
+            self.private.unwrap_or(!base.is_public()),
+            self.safe_to_copy.unwrap_or(base.is_safe_to_copy()),
+        )
+    }
+}
+
 impl fmt::Display for ChunkType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for b in &self.bytes {
@@ -203,6 +511,37 @@ mod tests {
         assert!(!chunk.is_safe_to_copy());
     }
 
+    #[test]
+    pub fn test_chunk_type_eq_ignore_case() {
+        let chunk = ChunkType::from_str("ruSt").unwrap();
+        assert!(chunk.eq_ignore_case(&ChunkType::from_str("RUST").unwrap()));
+        assert!(chunk.eq_ignore_case(&chunk));
+        assert!(!chunk.eq_ignore_case(&ChunkType::from_str("rest").unwrap()));
+    }
+
+    #[test]
+    pub fn test_known_types() {
+        let known: Vec<ChunkType> = ChunkType::known().collect();
+        assert_eq!(known[0], ChunkType::IHDR);
+        assert!(known.contains(&ChunkType::LABEL_INDEX));
+        assert!(known.iter().all(|t| t.description().is_some()));
+        assert_eq!(ChunkType::IEND.description(), Some("End of the image"));
+        assert!(ChunkType::SIGNATURE
+            .description()
+            .unwrap()
+            .contains("signature"));
+        assert_eq!(ChunkType::from_str("ruSt").unwrap().description(), None);
+    }
+
+    #[test]
+    pub fn test_chunk_type_is_standard() {
+        assert!(ChunkType::IDAT.is_standard());
+        assert!(ChunkType::from_str("tRNS").unwrap().is_standard());
+        assert!(ChunkType::from_str("eXIf").unwrap().is_standard());
+        assert!(!ChunkType::from_str("trns").unwrap().is_standard());
+        assert!(!ChunkType::from_str("ruSt").unwrap().is_standard());
+    }
+
     #[test]
     pub fn test_valid_chunk_is_valid() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -218,6 +557,63 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_random_chunk_type() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let chunk = ChunkType::random(&mut rng);
+            assert!(!chunk.is_critical());
+            assert!(!chunk.is_public());
+            assert!(chunk.is_safe_to_copy());
+            assert!(chunk.is_valid());
+            assert!(ChunkType::from_str(&chunk.to_string()).is_ok());
+        }
+    }
+
+    #[test]
+    pub fn test_random_unused_chunk_type() {
+        use rand::{rngs::StdRng, SeedableRng};
+        // The same seed picks the same chunk type first, which is taken.
+        let taken = ChunkType::random(&mut StdRng::seed_from_u64(7));
+        let chunk_type = ChunkType::random_unused(&mut StdRng::seed_from_u64(7), &[taken]);
+        assert_ne!(chunk_type, taken);
+        assert!(!ChunkType::PNGME_TYPES.contains(&chunk_type));
+    }
+
+    #[test]
+    pub fn test_with_properties() {
+        let chunk = ChunkType::with_properties(*b"rust", false, true, true).unwrap();
+        assert_eq!(&chunk.to_string(), "ruSt");
+
+        let chunk = ChunkType::with_properties(*b"rUsT", true, false, false).unwrap();
+        assert_eq!(&chunk.to_string(), "RUST");
+        assert!(chunk.is_critical());
+        assert!(chunk.is_public());
+        assert!(!chunk.is_safe_to_copy());
+        assert!(chunk.is_valid());
+    }
+
+    #[test]
+    pub fn test_builder() {
+        let chunk = ChunkType::builder("RUST")
+            .ancillary(true)
+            .private(true)
+            .safe_to_copy(true)
+            .build()
+            .unwrap();
+        assert_eq!(&chunk.to_string(), "ruSt");
+        let chunk = ChunkType::builder("rust").ancillary(false).build().unwrap();
+        assert_eq!(&chunk.to_string(), "RuSt");
+        assert!(chunk.is_valid());
+        assert!(ChunkType::builder("ru5t").build().is_err());
+        assert!(ChunkType::builder("rusty").build().is_err());
+    }
+
+    #[test]
+    pub fn test_with_properties_bad_byte() {
+        assert!(ChunkType::with_properties(*b"ru5t", false, true, true).is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
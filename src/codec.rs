@@ -0,0 +1,874 @@
+//! Typed parsers and serializers for the data in particular chunk types.
+//!
+//! Register a [ChunkCodec](trait.ChunkCodec.html) with a
+//! [CodecRegistry](struct.CodecRegistry.html) to have chunks of its type shown
+//! as something more useful than raw bytes.
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::transform::Compress;
+use flate2::read::{ZlibDecoder, ZlibEncoder};
+use flate2::Compression;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+
+/// Knows how to turn the data of one chunk type into a typed value, and back.
+pub trait ChunkCodec {
+    /// The typed value stored in the chunk's data.
+    type Value: fmt::Display;
+
+    /// The chunk type this codec understands.
+    fn chunk_type(&self) -> ChunkType;
+
+    /// Parse a chunk's data into a value.
+    fn decode(&self, data: &[u8]) -> crate::Result<Self::Value>;
+
+    /// Serialize a value into chunk data.
+    fn encode(&self, value: &Self::Value) -> crate::Result<Vec<u8>>;
+
+    /// Build a whole [Chunk](../struct.Chunk.html) holding `value`.
+    fn to_chunk(&self, value: &Self::Value) -> crate::Result<Chunk> {
+        Ok(Chunk::new(self.chunk_type(), self.encode(value)?))
+    }
+}
+
+/// The part of a [ChunkCodec](trait.ChunkCodec.html) that doesn't depend on
+/// its value type, so that codecs for different types can share a registry.
+trait Render {
+    fn render(&self, data: &[u8]) -> crate::Result<String>;
+}
+
+impl<C: ChunkCodec> Render for C {
+    fn render(&self, data: &[u8]) -> crate::Result<String> {
+        Ok(self.decode(data)?.to_string())
+    }
+}
+
+/// A set of [ChunkCodec](trait.ChunkCodec.html)s, at most one per chunk type.
+pub struct CodecRegistry {
+    codecs: HashMap<ChunkType, Box<dyn Render>>,
+}
+
+impl CodecRegistry {
+    /// A registry with no codecs at all.
+    pub fn empty() -> Self {
+        CodecRegistry {
+            codecs: HashMap::new(),
+        }
+    }
+
+    /// Add a codec, replacing any codec already registered for its chunk type.
+    pub fn register<C: ChunkCodec + 'static>(&mut self, codec: C) {
+        self.codecs.insert(codec.chunk_type(), Box::new(codec));
+    }
+
+    /// The chunk types there are codecs for, in no particular order.
+    pub fn chunk_types(&self) -> impl Iterator<Item = &ChunkType> {
+        self.codecs.keys()
+    }
+
+    /// Is there a codec for this chunk type?
+    pub fn is_registered(&self, chunk_type: &ChunkType) -> bool {
+        self.codecs.contains_key(chunk_type)
+    }
+
+    /// Render a chunk's data through its codec. Returns `None` if no codec is
+    /// registered for the chunk's type.
+    pub fn render(&self, chunk: &Chunk) -> Option<crate::Result<String>> {
+        self.codecs
+            .get(chunk.chunk_type())
+            .map(|codec| codec.render(chunk.data()))
+    }
+
+    /// Display a chunk like [Chunk](../struct.Chunk.html)'s `Display` does,
+    /// but with its data rendered through its codec if it has one and the data
+    /// parses.
+    pub fn display<'a>(&'a self, chunk: &'a Chunk) -> impl fmt::Display + 'a {
+        RenderedChunk {
+            registry: self,
+            chunk,
+        }
+    }
+}
+
+impl Default for CodecRegistry {
+    /// A registry with codecs for the standard chunk types pngme understands.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(TextCodec);
+        registry.register(CompressedTextCodec);
+        registry.register(InternationalTextCodec);
+        registry.register(IccProfileCodec);
+        registry.register(PhysicalDimensionsCodec);
+        registry.register(SuggestedPaletteCodec);
+        registry
+    }
+}
+
+struct RenderedChunk<'a> {
+    registry: &'a CodecRegistry,
+    chunk: &'a Chunk,
+}
+
+impl fmt::Display for RenderedChunk<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.registry.render(self.chunk) {
+            Some(Ok(rendered)) => write!(f, "{}\t{}", self.chunk.chunk_type(), rendered),
+            _ => write!(f, "{}", self.chunk),
+        }
+    }
+}
+
+/// A keyword and its text, as stored in a `tEXt` chunk.
+#[derive(Debug, PartialEq)]
+pub struct TextualData {
+    pub keyword: String,
+    pub text: String,
+}
+
+impl fmt::Display for TextualData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.keyword, self.text)
+    }
+}
+
+/// Codec for `tEXt` chunks: a Latin-1 keyword, a null separator, and Latin-1
+/// text.
+pub struct TextCodec;
+
+impl ChunkCodec for TextCodec {
+    type Value = TextualData;
+
+    fn chunk_type(&self) -> ChunkType {
+        ChunkType::TEXT
+    }
+
+    fn decode(&self, data: &[u8]) -> crate::Result<TextualData> {
+        let (keyword, text) = split_at_null(data, "tEXt")?;
+        Ok(TextualData {
+            keyword: latin1_to_string(keyword),
+            text: latin1_to_string(text),
+        })
+    }
+
+    fn encode(&self, value: &TextualData) -> crate::Result<Vec<u8>> {
+        let mut data = keyword_to_latin1(&value.keyword)?;
+        data.push(0);
+        data.extend(string_to_latin1(&value.text)?);
+        Ok(data)
+    }
+}
+
+/// Codec for `zTXt` chunks: a Latin-1 keyword, a null separator, a
+/// compression method (always 0, for zlib), and zlib-compressed Latin-1 text.
+pub struct CompressedTextCodec;
+
+impl ChunkCodec for CompressedTextCodec {
+    type Value = TextualData;
+
+    fn chunk_type(&self) -> ChunkType {
+        ChunkType::ZTXT
+    }
+
+    fn decode(&self, data: &[u8]) -> crate::Result<TextualData> {
+        let (keyword, rest) = split_at_null(data, "zTXt")?;
+        let compressed = match rest {
+            [0, compressed @ ..] => compressed,
+            _ => {
+                return Err(CodecError::boxed(
+                    "zTXt chunk has an unknown compression method".to_string(),
+                ))
+            }
+        };
+        Ok(TextualData {
+            keyword: latin1_to_string(keyword),
+            text: latin1_to_string(&inflate(compressed)?),
+        })
+    }
+
+    fn encode(&self, value: &TextualData) -> crate::Result<Vec<u8>> {
+        let mut data = keyword_to_latin1(&value.keyword)?;
+        data.extend_from_slice(&[0, 0]);
+        data.extend(deflate(&string_to_latin1(&value.text)?)?);
+        Ok(data)
+    }
+}
+
+/// A keyword and its text, as stored in an `iTXt` chunk.
+#[derive(Debug, PartialEq)]
+pub struct InternationalText {
+    /// A Latin-1 keyword, like in `tEXt`.
+    pub keyword: String,
+    /// Whether the text is stored compressed.
+    pub compressed: bool,
+    /// The language the text is in, like `en-US`, or empty if it's not known.
+    pub language: String,
+    /// The keyword, translated into the text's language.
+    pub translated_keyword: String,
+    pub text: String,
+}
+
+impl fmt::Display for InternationalText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.keyword, self.text)
+    }
+}
+
+/// Codec for `iTXt` chunks: a Latin-1 keyword, a null separator, a
+/// compression flag and method, a language tag and translated keyword (each
+/// followed by a null separator), and UTF-8 text that's zlib-compressed if the
+/// flag is 1.
+pub struct InternationalTextCodec;
+
+impl ChunkCodec for InternationalTextCodec {
+    type Value = InternationalText;
+
+    fn chunk_type(&self) -> ChunkType {
+        ChunkType::ITXT
+    }
+
+    fn decode(&self, data: &[u8]) -> crate::Result<InternationalText> {
+        let (keyword, rest) = split_at_null(data, "iTXt")?;
+        let (compressed, rest) = match rest {
+            [0, _, rest @ ..] => (false, rest),
+            [1, 0, rest @ ..] => (true, rest),
+            _ => {
+                return Err(CodecError::boxed(
+                    "iTXt chunk has an unknown compression flag or method".to_string(),
+                ))
+            }
+        };
+        let (language, rest) = split_at_null(rest, "iTXt")?;
+        let (translated_keyword, text) = split_at_null(rest, "iTXt")?;
+        let text = if compressed {
+            inflate(text)?
+        } else {
+            text.to_vec()
+        };
+        Ok(InternationalText {
+            keyword: latin1_to_string(keyword),
+            compressed,
+            language: String::from_utf8(language.to_vec())?,
+            translated_keyword: String::from_utf8(translated_keyword.to_vec())?,
+            text: String::from_utf8(text)?,
+        })
+    }
+
+    fn encode(&self, value: &InternationalText) -> crate::Result<Vec<u8>> {
+        let mut data = keyword_to_latin1(&value.keyword)?;
+        data.extend_from_slice(&[0, u8::from(value.compressed), 0]);
+        data.extend_from_slice(value.language.as_bytes());
+        data.push(0);
+        data.extend_from_slice(value.translated_keyword.as_bytes());
+        data.push(0);
+        if value.compressed {
+            data.extend(deflate(value.text.as_bytes())?);
+        } else {
+            data.extend_from_slice(value.text.as_bytes());
+        }
+        Ok(data)
+    }
+}
+
+/// An ICC color profile, as stored in an `iCCP` chunk.
+#[derive(Debug, PartialEq)]
+pub struct IccProfile {
+    /// A Latin-1 name for the profile, which has the same rules as a text
+    /// chunk's keyword.
+    pub name: String,
+    /// The profile itself, decompressed.
+    pub profile: Vec<u8>,
+}
+
+impl fmt::Display for IccProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({} bytes)", self.name, self.profile.len())
+    }
+}
+
+/// Codec for `iCCP` chunks: a Latin-1 profile name, a null separator, a
+/// compression method (always 0, for zlib), and the zlib-compressed profile.
+pub struct IccProfileCodec;
+
+impl ChunkCodec for IccProfileCodec {
+    type Value = IccProfile;
+
+    fn chunk_type(&self) -> ChunkType {
+        ChunkType::ICCP
+    }
+
+    fn decode(&self, data: &[u8]) -> crate::Result<IccProfile> {
+        let (name, rest) = split_at_null(data, "iCCP")?;
+        let compressed = match rest {
+            [0, compressed @ ..] => compressed,
+            _ => {
+                return Err(CodecError::boxed(
+                    "iCCP chunk has an unknown compression method".to_string(),
+                ))
+            }
+        };
+        Ok(IccProfile {
+            name: latin1_to_string(name),
+            profile: inflate(compressed)?,
+        })
+    }
+
+    fn encode(&self, value: &IccProfile) -> crate::Result<Vec<u8>> {
+        let mut data = keyword_to_latin1(&value.name)?;
+        data.extend_from_slice(&[0, 0]);
+        data.extend(deflate(&value.profile)?);
+        Ok(data)
+    }
+}
+
+/// How many meters there are in an inch.
+const METERS_PER_INCH: f64 = 0.0254;
+
+/// The size of the pixels, as stored in a `pHYs` chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicalDimensions {
+    /// Pixels per unit, horizontally.
+    pub x: u32,
+    /// Pixels per unit, vertically.
+    pub y: u32,
+    /// Whether the unit is the meter. If it isn't, the unit is unknown, and
+    /// `x` and `y` only give the pixels' aspect ratio.
+    pub per_meter: bool,
+}
+
+impl PhysicalDimensions {
+    /// Square pixels at `dpi` dots (pixels) per inch. Returns `Err` if that
+    /// isn't a positive number of pixels per meter that fits in a `u32`.
+    pub fn from_dpi(dpi: f64) -> crate::Result<Self> {
+        Self::from_ppm(dpi / METERS_PER_INCH)
+    }
+
+    /// Square pixels at `ppm` pixels per meter, rounded to the nearest whole
+    /// number. Returns `Err` if that isn't positive, or doesn't fit in a `u32`.
+    pub fn from_ppm(ppm: f64) -> crate::Result<Self> {
+        let rounded = ppm.round();
+        if !(rounded >= 1.0 && rounded <= f64::from(u32::MAX)) {
+            return Err(CodecError::boxed(format!(
+                "{} pixels per meter is out of range",
+                ppm
+            )));
+        }
+        Ok(PhysicalDimensions {
+            x: rounded as u32,
+            y: rounded as u32,
+            per_meter: true,
+        })
+    }
+
+    /// The horizontal and vertical dots per inch, if the unit is known.
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        if self.per_meter {
+            Some((
+                f64::from(self.x) * METERS_PER_INCH,
+                f64::from(self.y) * METERS_PER_INCH,
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for PhysicalDimensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.dpi() {
+            Some((x, _)) if self.x == self.y => {
+                write!(f, "{:.0} DPI ({} pixels per meter)", x, self.x)
+            }
+            Some((x, y)) => write!(
+                f,
+                "{:.0}x{:.0} DPI ({}x{} pixels per meter)",
+                x, y, self.x, self.y
+            ),
+            None => write!(f, "pixel aspect ratio {}:{}", self.x, self.y),
+        }
+    }
+}
+
+/// Codec for `pHYs` chunks: the pixels per unit horizontally and vertically,
+/// as 4-byte big-endian numbers, then the unit (1 for the meter, 0 if it's
+/// unknown).
+pub struct PhysicalDimensionsCodec;
+
+impl ChunkCodec for PhysicalDimensionsCodec {
+    type Value = PhysicalDimensions;
+
+    fn chunk_type(&self) -> ChunkType {
+        ChunkType::PHYS
+    }
+
+    fn decode(&self, data: &[u8]) -> crate::Result<PhysicalDimensions> {
+        if data.len() != 9 {
+            return Err(CodecError::boxed(format!(
+                "pHYs chunk is {} bytes long (expected 9)",
+                data.len()
+            )));
+        }
+        let per_meter = match data[8] {
+            0 => false,
+            1 => true,
+            unit => {
+                return Err(CodecError::boxed(format!(
+                    "pHYs chunk has an unknown unit {}",
+                    unit
+                )))
+            }
+        };
+        Ok(PhysicalDimensions {
+            x: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            y: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            per_meter,
+        })
+    }
+
+    fn encode(&self, value: &PhysicalDimensions) -> crate::Result<Vec<u8>> {
+        let mut data = value.x.to_be_bytes().to_vec();
+        data.extend_from_slice(&value.y.to_be_bytes());
+        data.push(u8::from(value.per_meter));
+        Ok(data)
+    }
+}
+
+/// One color in a suggested palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaletteEntry {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+    pub alpha: u16,
+    /// How often the color is used in the image, relative to the others in
+    /// the palette, or 0 if that isn't known.
+    pub frequency: u16,
+}
+
+/// A suggested palette, as stored in an `sPLT` chunk, for viewers that can
+/// only show a limited number of colors.
+#[derive(Debug, PartialEq)]
+pub struct SuggestedPalette {
+    /// A Latin-1 name for the palette, which has the same rules as a text
+    /// chunk's keyword.
+    pub name: String,
+    /// The bits in each sample of each entry: 8 or 16.
+    pub sample_depth: u8,
+    pub entries: Vec<PaletteEntry>,
+}
+
+impl SuggestedPalette {
+    /// How many entries are shown before the rest are left out.
+    const SHOWN_ENTRIES: usize = 4;
+}
+
+impl fmt::Display for SuggestedPalette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}-bit, {} entries)",
+            self.name,
+            self.sample_depth,
+            self.entries.len()
+        )?;
+        for (i, e) in self.entries.iter().take(Self::SHOWN_ENTRIES).enumerate() {
+            write!(
+                f,
+                "{} rgba({}, {}, {}, {}) x{}",
+                if i == 0 { ":" } else { "," },
+                e.red,
+                e.green,
+                e.blue,
+                e.alpha,
+                e.frequency
+            )?;
+        }
+        if self.entries.len() > Self::SHOWN_ENTRIES {
+            write!(f, ", ...")?;
+        }
+        Ok(())
+    }
+}
+
+/// Codec for `sPLT` chunks: a Latin-1 palette name, a null separator, the
+/// sample depth, then each entry's red, green, blue, and alpha (1 byte each
+/// for a sample depth of 8, and 2 for 16) and 2-byte frequency, all
+/// big-endian.
+pub struct SuggestedPaletteCodec;
+
+impl ChunkCodec for SuggestedPaletteCodec {
+    type Value = SuggestedPalette;
+
+    fn chunk_type(&self) -> ChunkType {
+        ChunkType::SPLT
+    }
+
+    fn decode(&self, data: &[u8]) -> crate::Result<SuggestedPalette> {
+        let (name, rest) = split_at_null(data, "sPLT")?;
+        let (sample_depth, entries) = match rest {
+            [depth @ (8 | 16), entries @ ..] => (*depth, entries),
+            _ => {
+                return Err(CodecError::boxed(
+                    "sPLT chunk has a sample depth other than 8 or 16".to_string(),
+                ))
+            }
+        };
+        let sample_len = usize::from(sample_depth / 8);
+        let entry_len = 4 * sample_len + 2;
+        if entries.len() % entry_len != 0 {
+            return Err(CodecError::boxed(format!(
+                "sPLT chunk's entries are {} bytes long, which isn't a multiple of {}",
+                entries.len(),
+                entry_len
+            )));
+        }
+        let sample = |bytes: &[u8]| match bytes {
+            [b] => u16::from(*b),
+            [high, low] => u16::from_be_bytes([*high, *low]),
+            _ => unreachable!("samples are 1 or 2 bytes"),
+        };
+        let entries = entries
+            .chunks(entry_len)
+            .map(|entry| {
+                let mut samples = entry.chunks(sample_len).map(sample);
+                let mut next = || samples.next().expect("every entry has 4 samples");
+                PaletteEntry {
+                    red: next(),
+                    green: next(),
+                    blue: next(),
+                    alpha: next(),
+                    frequency: u16::from_be_bytes([entry[entry_len - 2], entry[entry_len - 1]]),
+                }
+            })
+            .collect();
+        Ok(SuggestedPalette {
+            name: latin1_to_string(name),
+            sample_depth,
+            entries,
+        })
+    }
+
+    fn encode(&self, value: &SuggestedPalette) -> crate::Result<Vec<u8>> {
+        let mut data = keyword_to_latin1(&value.name)?;
+        data.push(0);
+        data.push(value.sample_depth);
+        for entry in &value.entries {
+            for sample in &[entry.red, entry.green, entry.blue, entry.alpha] {
+                match value.sample_depth {
+                    8 => data.push(u8::try_from(*sample).map_err(|_| {
+                        CodecError::boxed(format!("{} doesn't fit in 8 bits", sample))
+                    })?),
+                    16 => data.extend_from_slice(&sample.to_be_bytes()),
+                    depth => {
+                        return Err(CodecError::boxed(format!(
+                            "sPLT sample depth has to be 8 or 16, not {}",
+                            depth
+                        )))
+                    }
+                }
+            }
+            data.extend_from_slice(&entry.frequency.to_be_bytes());
+        }
+        Ok(data)
+    }
+}
+
+/// The bytes before and after the first null byte in `data`.
+fn split_at_null<'a>(data: &'a [u8], chunk_type: &str) -> crate::Result<(&'a [u8], &'a [u8])> {
+    let separator = data
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| CodecError::boxed(format!("{} chunk has no null separator", chunk_type)))?;
+    Ok((&data[..separator], &data[separator + 1..]))
+}
+
+/// A keyword for a text chunk, which has to be 1 to 79 Latin-1 characters.
+fn keyword_to_latin1(keyword: &str) -> crate::Result<Vec<u8>> {
+    let data = string_to_latin1(keyword)?;
+    if data.is_empty() || data.len() > 79 {
+        return Err(CodecError::boxed(format!(
+            "Keyword must be 1 to 79 characters (got {})",
+            data.len()
+        )));
+    }
+    Ok(data)
+}
+
+/// Decompress the data in a zTXt, iTXt, or iCCP chunk, refusing to go past
+/// the same limit as compressed payloads, so that a small chunk in an
+/// untrusted PNG can't fill up memory.
+fn inflate(compressed: &[u8]) -> crate::Result<Vec<u8>> {
+    inflate_at_most(compressed, Compress::MAX_DECOMPRESSED_LENGTH)
+}
+
+fn inflate_at_most(compressed: &[u8], limit: u64) -> crate::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    ZlibDecoder::new(compressed)
+        .take(limit + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| CodecError::boxed(format!("Couldn't decompress the data: {}", e)))?;
+    if data.len() as u64 > limit {
+        return Err(CodecError::boxed(format!(
+            "Compressed data is too long once decompressed (more than {} bytes)",
+            limit
+        )));
+    }
+    Ok(data)
+}
+
+fn deflate(data: &[u8]) -> crate::Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    ZlibEncoder::new(data, Compression::default()).read_to_end(&mut compressed)?;
+    Ok(compressed)
+}
+
+fn latin1_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| char::from(*b)).collect()
+}
+
+fn string_to_latin1(s: &str) -> crate::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(s.len());
+    for c in s.chars() {
+        if (c as u32) > 255 {
+            return Err(CodecError::boxed(format!(
+                "'{}' is not a Latin-1 character",
+                c
+            )));
+        }
+        bytes.push(c as u8);
+    }
+    Ok(bytes)
+}
+
+/// A codec could not parse or serialize chunk data.
+#[derive(Debug)]
+pub struct CodecError {
+    /// Why the data could not be parsed or serialized.
+    reason: String,
+}
+impl CodecError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad chunk data: {}", self.reason)
+    }
+}
+impl Error for CodecError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    struct ShoutCodec;
+
+    impl ChunkCodec for ShoutCodec {
+        type Value = String;
+
+        fn chunk_type(&self) -> ChunkType {
+            ChunkType::from_str("ruSt").unwrap()
+        }
+
+        fn decode(&self, data: &[u8]) -> crate::Result<String> {
+            Ok(String::from_utf8(data.to_vec())?.to_uppercase())
+        }
+
+        fn encode(&self, value: &String) -> crate::Result<Vec<u8>> {
+            Ok(value.to_lowercase().into_bytes())
+        }
+    }
+
+    #[test]
+    fn test_custom_codec() {
+        let mut registry = CodecRegistry::empty();
+        let chunk = ShoutCodec.to_chunk(&"Hello".to_string()).unwrap();
+        assert!(registry.render(&chunk).is_none());
+        assert_eq!(registry.display(&chunk).to_string(), "ruSt\thello");
+
+        registry.register(ShoutCodec);
+        assert!(registry.is_registered(&ChunkType::from_str("ruSt").unwrap()));
+        assert_eq!(registry.render(&chunk).unwrap().unwrap(), "HELLO");
+        assert_eq!(registry.display(&chunk).to_string(), "ruSt\tHELLO");
+    }
+
+    #[test]
+    fn test_text_codec_round_trip() {
+        let value = TextualData {
+            keyword: "Author".to_string(),
+            text: "Gabe Berke-Williams, café".to_string(),
+        };
+        let chunk = TextCodec.to_chunk(&value).unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "tEXt");
+        assert_eq!(TextCodec.decode(chunk.data()).unwrap(), value);
+        assert_eq!(
+            CodecRegistry::default().display(&chunk).to_string(),
+            "tEXt\tAuthor: Gabe Berke-Williams, café"
+        );
+    }
+
+    #[test]
+    fn test_text_codec_rejects_bad_data() {
+        assert!(TextCodec.decode(b"no separator").is_err());
+        let value = TextualData {
+            keyword: String::new(),
+            text: "text".to_string(),
+        };
+        assert!(TextCodec.encode(&value).is_err());
+        let value = TextualData {
+            keyword: "Title".to_string(),
+            text: "日本".to_string(),
+        };
+        assert!(TextCodec.encode(&value).is_err());
+    }
+
+    #[test]
+    fn test_icc_profile_codec_round_trip() {
+        let value = IccProfile {
+            name: "sRGB IEC61966-2.1".to_string(),
+            profile: vec![7; 3000],
+        };
+        let chunk = IccProfileCodec.to_chunk(&value).unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "iCCP");
+        assert!(chunk.data().starts_with(b"sRGB IEC61966-2.1\0\0"));
+        assert!(chunk.data().len() < 100);
+        assert_eq!(IccProfileCodec.decode(chunk.data()).unwrap(), value);
+        assert_eq!(
+            CodecRegistry::default().display(&chunk).to_string(),
+            "iCCP\tsRGB IEC61966-2.1 (3000 bytes)"
+        );
+        assert!(IccProfileCodec.decode(b"Profile\0\x01data").is_err());
+        assert!(IccProfileCodec.decode(b"Profile\0\0not zlib").is_err());
+    }
+
+    #[test]
+    fn test_physical_dimensions_codec() {
+        let value = PhysicalDimensions::from_dpi(300.0).unwrap();
+        assert_eq!((value.x, value.y), (11811, 11811));
+        let chunk = PhysicalDimensionsCodec.to_chunk(&value).unwrap();
+        assert_eq!(chunk.data(), &[0, 0, 0x2e, 0x23, 0, 0, 0x2e, 0x23, 1]);
+        assert_eq!(PhysicalDimensionsCodec.decode(chunk.data()).unwrap(), value);
+        assert_eq!(
+            CodecRegistry::default().display(&chunk).to_string(),
+            "pHYs\t300 DPI (11811 pixels per meter)"
+        );
+
+        let aspect = PhysicalDimensionsCodec
+            .decode(&[0, 0, 0, 1, 0, 0, 0, 2, 0])
+            .unwrap();
+        assert_eq!(aspect.dpi(), None);
+        assert_eq!(aspect.to_string(), "pixel aspect ratio 1:2");
+
+        assert!(PhysicalDimensionsCodec.decode(&[0; 8]).is_err());
+        assert!(PhysicalDimensionsCodec
+            .decode(&[0, 0, 0, 1, 0, 0, 0, 1, 2])
+            .is_err());
+        assert!(PhysicalDimensions::from_ppm(0.0).is_err());
+        assert!(PhysicalDimensions::from_dpi(f64::NAN).is_err());
+        assert!(PhysicalDimensions::from_dpi(1e12).is_err());
+    }
+
+    #[test]
+    fn test_suggested_palette_codec() {
+        let entry = |value, frequency| PaletteEntry {
+            red: value,
+            green: value,
+            blue: 0,
+            alpha: 255,
+            frequency,
+        };
+        let mut value = SuggestedPalette {
+            name: "Grays".to_string(),
+            sample_depth: 8,
+            entries: (0..6).map(|i| entry(i * 50, 6 - i)).collect(),
+        };
+        let chunk = SuggestedPaletteCodec.to_chunk(&value).unwrap();
+        assert_eq!(chunk.data().len(), 6 + 1 + 6 * 6);
+        assert_eq!(&chunk.data()[7..13], &[0, 0, 0, 255, 0, 6]);
+        assert_eq!(SuggestedPaletteCodec.decode(chunk.data()).unwrap(), value);
+        assert_eq!(
+            CodecRegistry::default().display(&chunk).to_string(),
+            "sPLT\tGrays (8-bit, 6 entries): rgba(0, 0, 0, 255) x6, \
+             rgba(50, 50, 0, 255) x5, rgba(100, 100, 0, 255) x4, \
+             rgba(150, 150, 0, 255) x3, ..."
+        );
+
+        value.sample_depth = 16;
+        value.entries[0].red = 65535;
+        let chunk = SuggestedPaletteCodec.to_chunk(&value).unwrap();
+        assert_eq!(chunk.data().len(), 6 + 1 + 6 * 10);
+        assert_eq!(SuggestedPaletteCodec.decode(chunk.data()).unwrap(), value);
+
+        value.sample_depth = 8;
+        assert!(SuggestedPaletteCodec.encode(&value).is_err());
+        assert!(SuggestedPaletteCodec.decode(b"Grays\0\x04").is_err());
+        assert!(SuggestedPaletteCodec.decode(b"Grays\0\x08\0\0\0").is_err());
+    }
+
+    #[test]
+    fn test_compressed_text_codec_round_trip() {
+        let value = TextualData {
+            keyword: "Comment".to_string(),
+            text: "café ".repeat(100),
+        };
+        let chunk = CompressedTextCodec.to_chunk(&value).unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "zTXt");
+        assert!(chunk.data().len() < value.text.len());
+        assert_eq!(CompressedTextCodec.decode(chunk.data()).unwrap(), value);
+        assert!(CompressedTextCodec.decode(b"Comment\0\x01data").is_err());
+        assert!(CompressedTextCodec.decode(b"Comment\0\0not zlib").is_err());
+    }
+
+    #[test]
+    fn test_compressed_text_codec_limits_decompressed_length() {
+        let value = TextualData {
+            keyword: "Comment".to_string(),
+            text: "\0".repeat(64 * 1024),
+        };
+        let chunk = CompressedTextCodec.to_chunk(&value).unwrap();
+        assert!(chunk.data().len() < 1024);
+        let compressed = &chunk.data()[b"Comment\0\0".len()..];
+        assert_eq!(
+            inflate_at_most(compressed, 64 * 1024).unwrap().len(),
+            64 * 1024
+        );
+        assert!(inflate_at_most(compressed, 64 * 1024 - 1).is_err());
+    }
+
+    #[test]
+    fn test_international_text_codec_round_trip() {
+        for &compressed in &[false, true] {
+            let value = InternationalText {
+                keyword: "Title".to_string(),
+                compressed,
+                language: "ja".to_string(),
+                translated_keyword: "タイトル".to_string(),
+                text: "日本".to_string(),
+            };
+            let chunk = InternationalTextCodec.to_chunk(&value).unwrap();
+            assert_eq!(InternationalTextCodec.decode(chunk.data()).unwrap(), value);
+            assert_eq!(
+                CodecRegistry::default().display(&chunk).to_string(),
+                "iTXt\tTitle: 日本"
+            );
+        }
+        assert!(InternationalTextCodec.decode(b"Title\0\0\0ja").is_err());
+        assert!(InternationalTextCodec
+            .decode(b"Title\0\x02\0\0\0text")
+            .is_err());
+    }
+
+    #[test]
+    fn test_display_falls_back_on_bad_data() {
+        let chunk = Chunk::new(ChunkType::TEXT, b"no separator".to_vec());
+        assert_eq!(
+            CodecRegistry::default().display(&chunk).to_string(),
+            "tEXt\tno separator"
+        );
+    }
+}
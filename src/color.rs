@@ -0,0 +1,335 @@
+//! Chunks whose data depends on the image's color type, like `tRNS` and
+//! `bKGD`, so they can only be read alongside the image header.
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::info::ImageHeader;
+use crate::png::Png;
+use std::error::Error;
+use std::fmt;
+
+/// A color in the image's own terms: the samples of one pixel, without alpha.
+/// Images with an alpha channel use the same colors as those without.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// A gray level, for grayscale images.
+    Gray(u16),
+    /// Red, green, and blue, for truecolor images.
+    Rgb(u16, u16, u16),
+    /// An entry in the palette, for indexed images.
+    Index(u8),
+}
+
+impl Color {
+    /// The color with these samples in `png`: one gray level, red, green, and
+    /// blue, or a palette index, depending on its color type. Returns `Err`
+    /// if there are the wrong number of samples, or one doesn't fit in the
+    /// image's bit depth or palette.
+    pub fn in_png(png: &Png, samples: &[u16]) -> crate::Result<Self> {
+        let header = ImageHeader::from_png(png)?;
+        let max = (1u32 << header.bit_depth) - 1;
+        if let Some(sample) = samples.iter().find(|&&s| u32::from(s) > max) {
+            return Err(ColorError::boxed(format!(
+                "{} doesn't fit in {} bits",
+                sample, header.bit_depth
+            )));
+        }
+        match (header.color_type, samples) {
+            (0, &[gray]) | (4, &[gray]) => Ok(Color::Gray(gray)),
+            (2, &[red, green, blue]) | (6, &[red, green, blue]) => Ok(Color::Rgb(red, green, blue)),
+            (3, &[index]) => {
+                let entries = png
+                    .chunk_by_type(ChunkType::PLTE)
+                    .map_or(0, |c| c.data().len() / 3);
+                if usize::from(index) < entries {
+                    Ok(Color::Index(index as u8))
+                } else {
+                    Err(ColorError::boxed(format!(
+                        "Palette index {} is past the end of the palette ({} entries)",
+                        index, entries
+                    )))
+                }
+            }
+            (color_type, _) => Err(ColorError::boxed(format!(
+                "A {} image needs {} (got {} values)",
+                header.color_type_name(),
+                match color_type {
+                    0 | 4 => "one gray level",
+                    3 => "one palette index",
+                    _ => "a red, green, and blue",
+                },
+                samples.len()
+            ))),
+        }
+    }
+
+    /// Parse the 2-byte gray level, 6-byte red, green, and blue, or 1-byte
+    /// palette index that `tRNS` and `bKGD` store, depending on the color
+    /// type.
+    fn parse(data: &[u8], header: &ImageHeader) -> crate::Result<Self> {
+        let sample = |i: usize| u16::from_be_bytes([data[2 * i], data[2 * i + 1]]);
+        match (header.color_type, data.len()) {
+            (0, 2) | (4, 2) => Ok(Color::Gray(sample(0))),
+            (2, 6) | (6, 6) => Ok(Color::Rgb(sample(0), sample(1), sample(2))),
+            (3, 1) => Ok(Color::Index(data[0])),
+            (color_type, len) => Err(ColorError::boxed(format!(
+                "{} bytes isn't a color for color type {}",
+                len, color_type
+            ))),
+        }
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        match self {
+            Color::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Color::Rgb(red, green, blue) => [red, green, blue]
+                .iter()
+                .flat_map(|s| s.to_be_bytes())
+                .collect(),
+            Color::Index(index) => vec![index],
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Color::Gray(gray) => write!(f, "gray {}", gray),
+            Color::Rgb(red, green, blue) => write!(f, "rgb({}, {}, {})", red, green, blue),
+            Color::Index(index) => write!(f, "palette index {}", index),
+        }
+    }
+}
+
+/// The contents of a `tRNS` chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+    /// Pixels of exactly this color are transparent, in a grayscale or
+    /// truecolor image.
+    Color(Color),
+    /// The alpha of each palette entry, from the first. Entries past the end
+    /// are opaque.
+    Alphas(Vec<u8>),
+}
+
+impl Transparency {
+    /// Make the color with these samples fully transparent in `png`, like
+    /// [of](#method.of). Returns `Err` if `png` already has an alpha channel,
+    /// or [Color::in_png](enum.Color.html#method.in_png) does.
+    pub fn in_png(png: &Png, samples: &[u16]) -> crate::Result<Self> {
+        let header = ImageHeader::from_png(png)?;
+        if header.color_type == 4 || header.color_type == 6 {
+            return Err(ColorError::boxed(format!(
+                "A {} image already has an alpha channel",
+                header.color_type_name()
+            )));
+        }
+        Ok(Self::of(Color::in_png(png, samples)?))
+    }
+
+    /// Make `color` fully transparent. For an indexed image, every palette
+    /// entry before it is left opaque.
+    pub fn of(color: Color) -> Self {
+        match color {
+            Color::Index(index) => {
+                let mut alphas = vec![255; usize::from(index)];
+                alphas.push(0);
+                Transparency::Alphas(alphas)
+            }
+            color => Transparency::Color(color),
+        }
+    }
+
+    /// Parse a `tRNS` chunk's data, for an image with this header.
+    pub fn parse(data: &[u8], header: &ImageHeader) -> crate::Result<Self> {
+        match header.color_type {
+            3 if data.len() <= 256 => Ok(Transparency::Alphas(data.to_vec())),
+            3 => Err(ColorError::boxed(format!(
+                "tRNS has {} alphas, but a palette has at most 256 entries",
+                data.len()
+            ))),
+            0 | 2 => Ok(Transparency::Color(Color::parse(data, header)?)),
+            _ => Err(ColorError::boxed(format!(
+                "A {} image can't have a tRNS chunk",
+                header.color_type_name()
+            ))),
+        }
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let data = match self {
+            Transparency::Color(color) => color.to_bytes(),
+            Transparency::Alphas(alphas) => alphas.clone(),
+        };
+        Chunk::new(ChunkType::TRNS, data)
+    }
+}
+
+impl fmt::Display for Transparency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transparency::Color(color) => write!(f, "{} is transparent", color),
+            Transparency::Alphas(alphas) => {
+                let alphas: Vec<String> = alphas.iter().map(u8::to_string).collect();
+                write!(f, "palette alphas {}", alphas.join(", "))
+            }
+        }
+    }
+}
+
+/// The contents of a `bKGD` chunk: the color to show the image on, if there's
+/// nothing better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Background(pub Color);
+
+impl Background {
+    /// Parse a `bKGD` chunk's data, for an image with this header.
+    pub fn parse(data: &[u8], header: &ImageHeader) -> crate::Result<Self> {
+        Color::parse(data, header).map(Background)
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::BKGD, self.0.to_bytes())
+    }
+}
+
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "background {}", self.0)
+    }
+}
+
+/// Render `chunk`'s data, if it's one of the chunk types whose data depends
+/// on `header`. Returns `None` for other chunk types.
+pub fn render(chunk: &Chunk, header: &ImageHeader) -> Option<crate::Result<String>> {
+    match *chunk.chunk_type() {
+        ChunkType::TRNS => Some(Transparency::parse(chunk.data(), header).map(|t| t.to_string())),
+        ChunkType::BKGD => Some(Background::parse(chunk.data(), header).map(|b| b.to_string())),
+        _ => None,
+    }
+}
+
+/// A color doesn't suit the image, or a chunk that holds one is damaged.
+#[derive(Debug)]
+pub struct ColorError {
+    reason: String,
+}
+impl ColorError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for ColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad color: {}", self.reason)
+    }
+}
+impl Error for ColorError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// [testing::minimal_png], with its IHDR changed to `bit_depth` and
+    /// `color_type`.
+    fn png(bit_depth: u8, color_type: u8) -> Png {
+        let mut png = testing::minimal_png();
+        let mut header = png.chunks()[0].data().to_vec();
+        header[8] = bit_depth;
+        header[9] = color_type;
+        png.chunks_mut()[0] = Chunk::new(ChunkType::IHDR, header);
+        png
+    }
+
+    #[test]
+    fn test_color_in_png() {
+        assert_eq!(Color::in_png(&png(8, 0), &[7]).unwrap(), Color::Gray(7));
+        assert_eq!(
+            Color::in_png(&png(16, 2), &[1, 2, 65535]).unwrap(),
+            Color::Rgb(1, 2, 65535)
+        );
+        assert!(Color::in_png(&png(8, 0), &[256]).is_err());
+        assert!(Color::in_png(&png(8, 2), &[1, 2]).is_err());
+        assert_eq!(
+            Color::in_png(&png(8, 6), &[1, 2, 3]).unwrap(),
+            Color::Rgb(1, 2, 3)
+        );
+        assert!(Color::in_png(&png(8, 4), &[1, 2, 3]).is_err());
+
+        let mut indexed = png(8, 3);
+        indexed.insert_chunk(1, Chunk::new(ChunkType::PLTE, vec![0; 3 * 4]));
+        assert_eq!(Color::in_png(&indexed, &[3]).unwrap(), Color::Index(3));
+        assert!(Color::in_png(&indexed, &[4]).is_err());
+    }
+
+    #[test]
+    fn test_transparency_round_trip() {
+        let header = |color_type| ImageHeader::from_png(&png(8, color_type)).unwrap();
+        for (color, color_type) in &[
+            (Color::Gray(7), 0),
+            (Color::Rgb(1, 2, 3), 2),
+            (Color::Index(2), 3),
+        ] {
+            let transparency = Transparency::of(*color);
+            let chunk = transparency.to_chunk();
+            let parsed = Transparency::parse(chunk.data(), &header(*color_type)).unwrap();
+            assert_eq!(parsed, transparency);
+        }
+        assert_eq!(
+            Transparency::of(Color::Index(2)),
+            Transparency::Alphas(vec![255, 255, 0])
+        );
+        assert!(Transparency::parse(&[0, 7], &header(2)).is_err());
+        assert!(Transparency::parse(&[0, 7], &header(4)).is_err());
+        assert!(Transparency::in_png(&png(8, 6), &[1, 2, 3]).is_err());
+        assert_eq!(
+            Transparency::in_png(&png(8, 0), &[7]).unwrap(),
+            Transparency::Color(Color::Gray(7))
+        );
+    }
+
+    #[test]
+    fn test_background_round_trip() {
+        let header = |color_type| ImageHeader::from_png(&png(16, color_type)).unwrap();
+        for (color, color_type) in &[
+            (Color::Gray(700), 0),
+            (Color::Gray(700), 4),
+            (Color::Rgb(1, 2, 3), 2),
+            (Color::Rgb(1, 2, 3), 6),
+        ] {
+            let chunk = Background(*color).to_chunk();
+            let parsed = Background::parse(chunk.data(), &header(*color_type)).unwrap();
+            assert_eq!(parsed, Background(*color));
+        }
+        let indexed = ImageHeader::from_png(&png(8, 3)).unwrap();
+        assert_eq!(
+            Background::parse(&[5], &indexed).unwrap(),
+            Background(Color::Index(5))
+        );
+        assert!(Background::parse(&[5, 6], &indexed).is_err());
+        assert!(Background::parse(&[0, 5], &header(2)).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let header = ImageHeader::from_png(&png(8, 2)).unwrap();
+        let chunk = Transparency::of(Color::Rgb(255, 0, 0)).to_chunk();
+        assert_eq!(
+            render(&chunk, &header).unwrap().unwrap(),
+            "rgb(255, 0, 0) is transparent"
+        );
+        let indexed = ImageHeader::from_png(&png(8, 3)).unwrap();
+        let chunk = Chunk::new(ChunkType::TRNS, vec![255, 0]);
+        assert_eq!(
+            render(&chunk, &indexed).unwrap().unwrap(),
+            "palette alphas 255, 0"
+        );
+        let chunk = Background(Color::Rgb(0, 0, 255)).to_chunk();
+        assert_eq!(
+            render(&chunk, &header).unwrap().unwrap(),
+            "background rgb(0, 0, 255)"
+        );
+        assert!(render(&testing::text_chunk("Title", "Dice"), &header).is_none());
+    }
+}
@@ -1,56 +1,1751 @@
 use crate::args::*;
-use crate::chunk::Chunk;
-use crate::png::Png;
+use crate::corrupt;
+use crate::explain;
+use crate::extract;
+use crate::filter;
+use crate::gitfilter;
+use crate::keychain;
+use crate::man;
+use crate::meta::MetaJson;
+use crate::output::WriteOptions;
+use crate::schema::PngJson;
+use crate::serve;
+use crate::shell;
+use crate::sniff;
+use crate::storage;
+use crate::watch::{self, StampConfig};
+use clap::CommandFactory;
+use pngme::apng;
+use pngme::capacity::{self, Capacity};
+use pngme::carrier::{Format, MetadataCarrier};
+use pngme::codec::{
+    ChunkCodec, CodecRegistry, IccProfile, PhysicalDimensions, PhysicalDimensionsCodec,
+    SuggestedPaletteCodec,
+};
+use pngme::color::{self, Background, Color, Transparency};
+use pngme::digest;
+use pngme::fields::Fields;
+use pngme::icc;
+use pngme::info::{AncillarySummary, IdatSummary, ImageHeader};
+use pngme::keystore::KeyStore;
+use pngme::labels::LabelIndex;
+use pngme::pixels::{PerceptualHash, PixelDiff};
+use pngme::shamir::{self, Share};
+use pngme::signature::{self, SigningKey, VerifyingKey};
+use pngme::split::{self, Manifest};
+use pngme::spread;
+use pngme::trailer;
+use pngme::transform::{self, Encrypt, Pad, Pipeline};
+use pngme::xmp;
+use pngme::{Chunk, ChunkNotFoundError, ChunkReader, ChunkType, ParseOptions, Png};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use std::convert::TryFrom;
-use std::fs;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
 
-fn encode(args: EncodeArgs) -> crate::Result<()> {
-    let input_bytes = fs::read(&args.input_file_path)?;
-    let output = args.output_file_path.unwrap_or(args.input_file_path);
-    let mut png = Png::try_from(input_bytes.as_slice())?;
-    let chunk = Chunk::new(args.chunk_type, args.message.as_bytes().to_vec());
-    png.append_chunk(chunk);
-    fs::write(output, png.as_bytes())?;
+fn encode(args: EncodeArgs, write_options: WriteOptions) -> crate::Result<()> {
+    args.check_method()?;
+    let (chunk_type, message) = args.chunk_type_and_message()?;
+    // A trailer record's type is just a name, not a chunk.
+    if let (Some(chunk_type), EncodeMethod::Chunk) = (chunk_type, args.method) {
+        check_chunk_type(chunk_type, args.allow_unsafe)?;
+    }
+    if let Some(other) = other_format(&args.input_file_path, args.format)? {
+        return encode_other(&args, other, chunk_type, message, write_options);
+    }
+    // Appending to the input file doesn't need the rest of it, so don't read
+    // it all in just to write it all back out.
+    let png = if args.appends_in_place() {
+        None
+    } else {
+        Some(Png::try_from(
+            storage::read(&args.input_file_path)?.as_slice(),
+        )?)
+    };
+    let (payload, passphrase) = seal(&args, message)?;
+    let mut png = match png {
+        Some(png) => png,
+        None => {
+            let chunk_type = match chunk_type {
+                Some(chunk_type) => chunk_type,
+                None => random_chunk_type(&chunk_types_in(&args.input_file_path)?),
+            };
+            let (mut chunks, extras) = payload_chunks(&args, chunk_type, payload)?;
+            chunks.extend(extras);
+            let path = &args.input_file_path;
+            let kept = write_options.keep(path)?;
+            let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+            if Png::append_chunks_in_place(&mut file, &chunks)? {
+                file.sync_all()?;
+            } else {
+                // Trailing data has to move to make room, so write a new copy
+                // rather than risk losing it partway through.
+                drop(file);
+                write_options.copy(path, path, |reader, writer| {
+                    reader.append_to(writer, &chunks)
+                })?;
+                file = File::open(path)?;
+            }
+            warn_if_conspicuous(AncillarySummary::read(&mut file)?, args.payload_warning);
+            return kept.restore(path);
+        }
+    };
+    if !args.carriers.is_empty() {
+        return encode_across(&args, png, chunk_type, &payload, write_options);
+    }
+    if args.method == EncodeMethod::Spread {
+        let carriers = spread::spread(&mut png, &payload)?;
+        eprintln!(
+            "Spread the message across {} chunk{}",
+            carriers,
+            if carriers == 1 { "" } else { "s" }
+        );
+    } else {
+        let chunk_type = match (chunk_type, &args.label, &passphrase) {
+            (Some(chunk_type), _, _) => chunk_type,
+            (None, Some(label), Some(passphrase)) => {
+                let mut index = LabelIndex::from_png(&png, passphrase)?;
+                let chunk_type = index.assign(label, &png, &mut rand::thread_rng())?;
+                index.write_to(&mut png, passphrase, args.kdf_params())?;
+                chunk_type
+            }
+            _ => random_chunk_type(&types_of(png.chunks())),
+        };
+        if args.method == EncodeMethod::Trailer {
+            trailer::append_record(&mut png, chunk_type, &payload)?;
+        } else {
+            hide_in_chunks(&mut png, &args, chunk_type, payload)?;
+        }
+        if args.record {
+            png.record_chunk_type(chunk_type);
+        }
+    }
+    warn_if_conspicuous(AncillarySummary::of(&png), args.payload_warning);
+    let output = args
+        .output_file_path
+        .as_ref()
+        .unwrap_or(&args.input_file_path);
+    write_options.write(&args.input_file_path, output, &png.as_bytes())?;
+    Ok(())
+}
+
+/// Warn if chunks that aren't in the PNG standard make up more than `percent`
+/// of the file, since a carrier that's mostly payload stands out.
+fn warn_if_conspicuous(summary: AncillarySummary, percent: u8) {
+    if summary.exceeds(percent) {
+        eprintln!(
+            "Warning: chunks that aren't in the PNG standard make up {:.1}% of the file (more than {}%), so it's plain that it's carrying something",
+            summary.percentage(),
+            percent
+        );
+    }
+}
+
+/// The message as it's hidden: filtered, compressed, padded, and encrypted
+/// as asked. The passphrase is given back too, if one was read, so that it
+/// doesn't have to be asked for again.
+fn seal(args: &EncodeArgs, message: &str) -> crate::Result<(Vec<u8>, Option<Zeroizing<String>>)> {
+    let message = Zeroizing::new(match args.fields() {
+        Some(fields) => fields.to_cbor(),
+        None => message.as_bytes().to_vec(),
+    });
+    let mut message = match &args.filter {
+        Some(command) => Zeroizing::new(filter::run(command, &message)?),
+        None => message,
+    };
+    // The label index and the message share a passphrase, so only ask once.
+    let passphrase = if args.encrypt || args.label.is_some() {
+        Some(read_passphrase(&args.passphrase)?)
+    } else {
+        None
+    };
+    let compression = args.compression(&message);
+    if args.compress && compression.is_none() {
+        eprintln!("The message already looks compressed, so it won't be compressed again");
+    }
+    let payload = if compression.is_some() || args.encrypt {
+        let mut pipeline = Pipeline::new();
+        if let Some(compress) = compression {
+            pipeline = pipeline.then(compress);
+        }
+        if args.pad {
+            pipeline = pipeline.then(Pad);
+        }
+        if let (true, Some(passphrase)) = (args.encrypt, &passphrase) {
+            pipeline = pipeline.then(Encrypt::with_kdf(passphrase.clone(), args.kdf_params()));
+        }
+        pipeline.seal(&message)?
+    } else {
+        // Nothing hides it, so there's no point in wiping it.
+        Pipeline::plain(std::mem::take(&mut *message))?
+    };
+    Ok((payload, passphrase))
+}
+
+/// Hide the payload in a JPEG, WebP, or GIF, in blocks of its own. Only the chunk
+/// method works, and nothing that needs chunks besides the payload's.
+fn encode_other(
+    args: &EncodeArgs,
+    mut other: Box<dyn MetadataCarrier>,
+    chunk_type: Option<ChunkType>,
+    message: &str,
+    write_options: WriteOptions,
+) -> crate::Result<()> {
+    args.check_other_format()?;
+    let (payload, _) = seal(args, message)?;
+    let chunk_type = match chunk_type {
+        Some(chunk_type) => chunk_type,
+        None => random_chunk_type(&types_of(&other.blocks()?)),
+    };
+    other.append_block(chunk_type, &payload)?;
+    let output = args
+        .output_file_path
+        .as_ref()
+        .unwrap_or(&args.input_file_path);
+    write_options.write(&args.input_file_path, output, &other.as_bytes())?;
+    Ok(())
+}
+
+/// Hide the payload across the input PNG and the carriers: as Shamir shares
+/// with `--split-shares`, or in fragments with `--stripe`.
+fn encode_across(
+    args: &EncodeArgs,
+    png: Png,
+    chunk_type: Option<ChunkType>,
+    payload: &[u8],
+    write_options: WriteOptions,
+) -> crate::Result<()> {
+    // Read every carrier before writing any, so that a bad one doesn't leave
+    // only some of the payload hidden.
+    let carriers = args
+        .carriers
+        .iter()
+        .map(|path| Png::try_from(storage::read(path)?.as_slice()))
+        .collect::<crate::Result<Vec<Png>>>()?;
+    let chunk_type = chunk_type.unwrap_or_else(|| {
+        let present: Vec<ChunkType> = std::iter::once(&png)
+            .chain(&carriers)
+            .flat_map(|png| types_of(png.chunks()))
+            .collect();
+        random_chunk_type(&present)
+    });
+    let count = carriers.len() + 1;
+    let (pieces, done) = match (args.split_shares, args.threshold) {
+        (Some(shares), Some(threshold)) => {
+            let pieces = shamir::split(payload, shares, threshold, &mut rand::thread_rng())?
+                .iter()
+                .map(|share| payload_chunks(args, chunk_type, share.to_bytes()))
+                .collect::<crate::Result<Vec<_>>>()?;
+            let done = format!(
+                "Hid {} shares, any {} of which give back the message",
+                shares, threshold
+            );
+            (pieces, done)
+        }
+        _ => {
+            let fragments = split::stripe_payload(chunk_type, payload, count)?;
+            let manifest = Manifest::new(&fragments)?;
+            // A short payload leaves some carriers with just the manifest.
+            let pieces = fragments
+                .into_iter()
+                .map(|fragment| vec![fragment])
+                .chain(std::iter::repeat_with(Vec::new))
+                .take(count)
+                .map(|chunks| (chunks, vec![manifest.to_chunk()]))
+                .collect();
+            (pieces, format!("Striped the message across {} PNGs", count))
+        }
+    };
+    let output = args
+        .output_file_path
+        .as_ref()
+        .unwrap_or(&args.input_file_path);
+    let mut targets = vec![(&args.input_file_path, output, png)];
+    targets.extend(
+        args.carriers
+            .iter()
+            .zip(carriers)
+            .map(|(path, png)| (path, path, png)),
+    );
+    for ((_, _, png), (chunks, extras)) in targets.iter_mut().zip(pieces) {
+        insert_chunks(png, args, chunks, extras);
+        if args.record {
+            png.record_chunk_type(chunk_type);
+        }
+    }
+    for (input, output, png) in targets {
+        write_options.write(input, output, &png.as_bytes())?;
+    }
+    eprintln!("{}", done);
     Ok(())
 }
 
-fn decode(args: DecodeArgs) -> crate::Result<()> {
-    let input_bytes = fs::read(&args.file_path)?;
-    let png = Png::try_from(input_bytes.as_slice())?;
-    let chunk = png.chunk_by_type(args.chunk_type);
-    if let Some(c) = chunk {
+/// Pick a chunk type to hide a message under that isn't one of the `present`
+/// ones or pngme's own, and print it so the message can be found again.
+fn random_chunk_type(present: &[ChunkType]) -> ChunkType {
+    let chunk_type = ChunkType::random_unused(&mut rand::thread_rng(), present);
+    println!("{}", chunk_type);
+    chunk_type
+}
+
+/// The chunk type of each of `chunks`.
+fn types_of(chunks: &[Chunk]) -> Vec<ChunkType> {
+    chunks.iter().map(|c| *c.chunk_type()).collect()
+}
+
+/// The chunk type of each chunk in the PNG at `path`, read one chunk at a
+/// time.
+pub(crate) fn chunk_types_in(path: &Path) -> crate::Result<Vec<ChunkType>> {
+    ChunkReader::new(BufReader::new(File::open(path)?))
+        .map(|chunk| Ok(*chunk?.chunk_type()))
+        .collect()
+}
+
+/// Add the payload to the PNG as one or more chunks, signing it if asked to.
+fn hide_in_chunks(
+    png: &mut Png,
+    args: &EncodeArgs,
+    chunk_type: ChunkType,
+    payload: Vec<u8>,
+) -> crate::Result<()> {
+    let (chunks, extras) = payload_chunks(args, chunk_type, payload)?;
+    insert_chunks(png, args, chunks, extras);
+    Ok(())
+}
+
+/// Add the chunks that hold a payload, and then the chunks that go after
+/// them.
+fn insert_chunks(png: &mut Png, args: &EncodeArgs, chunks: Vec<Chunk>, extras: Vec<Chunk>) {
+    let mut rng = rand::thread_rng();
+    for chunk in chunks {
+        if args.randomize_position {
+            png.insert_chunk_randomly(chunk, &mut rng);
+        } else {
+            png.append_chunk(chunk);
+        }
+    }
+    for chunk in extras {
+        png.append_chunk(chunk);
+    }
+}
+
+/// The chunks that hold the payload, and the chunks that go after them: the
+/// manifest of a split payload and the signature, if there are any.
+fn payload_chunks(
+    args: &EncodeArgs,
+    chunk_type: ChunkType,
+    payload: Vec<u8>,
+) -> crate::Result<(Vec<Chunk>, Vec<Chunk>)> {
+    let signature = match &args.sign {
+        Some(path) => {
+            let key = signing_key(path)?;
+            Some(signature::signature_chunk(&key, chunk_type, &payload))
+        }
+        None => None,
+    };
+    // Payloads too big for one chunk are split even if we weren't asked to.
+    let split_size = match args.split_size {
+        Some(size) => Some(size.get()),
+        None if payload.len() > split::MAX_FRAGMENT_LENGTH => Some(split::MAX_FRAGMENT_LENGTH),
+        None => None,
+    };
+    let (chunks, manifest) = match split_size {
+        Some(size) => {
+            let fragments = split::split_payload(chunk_type, &payload, size)?;
+            let manifest = Manifest::new(&fragments)?.to_chunk();
+            (fragments, Some(manifest))
+        }
+        None => (vec![Chunk::new(chunk_type, payload)], None),
+    };
+    Ok((chunks, manifest.into_iter().chain(signature).collect()))
+}
+
+fn decode(args: DecodeArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    if let Some(other) = other_format(&args.file_path, args.format)? {
+        return decode_other(args, other);
+    }
+    if args.spread {
+        return decode_spread(args, parse_options);
+    }
+    if args.raw {
+        return decode_raw(&args, parse_options);
+    }
+    // A labelled payload is usually encrypted with the same passphrase as the
+    // label index, so only ask once.
+    let (chunk_type, passphrase) = match (&args.label, args.chunk_type) {
+        (Some(label), _) => {
+            let passphrase = read_passphrase(&args.passphrase)?;
+            let png = Png::parse_with(&storage::read(&args.file_path)?, parse_options)?;
+            let chunk_type = LabelIndex::from_png(&png, &passphrase)?.require(label)?;
+            (chunk_type, Some(passphrase))
+        }
+        (None, Some(chunk_type)) => (chunk_type, None),
+        (None, None) => unreachable!("clap requires a chunk type or a label"),
+    };
+    let (chunk_type, n) = if args.ignore_case {
+        exact_case(
+            &args.file_path,
+            chunk_type,
+            args.nth.get() - 1,
+            parse_options,
+        )?
+    } else {
+        (chunk_type, args.nth.get() - 1)
+    };
+    if args.resume {
+        return resume_payload(&args, chunk_type, n, parse_options);
+    }
+    let c = if args.combine.is_empty() {
+        find_payload(&args.file_path, chunk_type, n, parse_options)?
+    } else {
+        let shares = std::iter::once(&args.file_path)
+            .chain(&args.combine)
+            .map(|path| {
+                let c = find_payload(path, chunk_type, n, parse_options)?;
+                Share::from_bytes(c.data())
+            })
+            .collect::<crate::Result<Vec<Share>>>()?;
+        Chunk::new(chunk_type, shamir::combine(&shares)?)
+    };
+    show_payload(&c, &args, passphrase.as_ref())
+}
+
+/// Copy the data of a payload's chunk to stdout as it's read from the PNG, so
+/// that it's never all in memory at once. A payload after the end of the PNG
+/// is read in first, like the rest of the trailing data.
+fn decode_raw(args: &DecodeArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    let chunk_type = args
+        .chunk_type
+        .expect("clap requires a chunk type with --raw");
+    let n = args.nth.get() - 1;
+    let mut reader = BufReader::new(storage::open(&args.file_path)?);
+    let mut stdout = io::stdout().lock();
+    if Png::copy_nth_chunk_data_streaming(&mut reader, chunk_type, n, parse_options, &mut stdout)?
+        .is_none()
+    {
+        let c = find_in_trailer(&args.file_path, chunk_type, n, parse_options)?;
+        stdout.write_all(c.data())?;
+    }
+    Ok(())
+}
+
+/// Write a split payload to `--output`, keeping the fragments at the start of
+/// the file that an earlier, interrupted run already wrote and that match the
+/// manifest, and appending the rest. A payload that wasn't split, or that's
+/// sealed and so has to be opened as a whole, is written from the start.
+fn resume_payload(
+    args: &DecodeArgs,
+    chunk_type: ChunkType,
+    n: usize,
+    parse_options: &ParseOptions,
+) -> crate::Result<()> {
+    args.check_resume()?;
+    let path = args
+        .output
+        .as_ref()
+        .expect("clap requires --output with --resume");
+    let mut reader = BufReader::new(storage::open(&args.file_path)?);
+    let manifest = match find_manifest(&mut reader, chunk_type, parse_options)? {
+        Some(manifest) if n == 0 => manifest,
+        _ => {
+            eprintln!("The message isn't split, so there's nothing to resume");
+            let c = find_payload(&args.file_path, chunk_type, n, parse_options)?;
+            return show_payload(&c, args, None);
+        }
+    };
+    let png = Png::parse_with(&storage::read(&args.file_path)?, parse_options)?;
+    let pieces = manifest.pieces(
+        png.chunks()
+            .iter()
+            .filter(|c| c.chunk_type() == &chunk_type),
+    )?;
+    if pieces
+        .first()
+        .is_some_and(|piece| Pipeline::is_envelope(piece))
+    {
+        eprintln!("The message is sealed, so it has to be written from the start");
+        return show_payload(&Chunk::new(chunk_type, pieces.concat()), args, None);
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    let (done, offset) = manifest.verified_prefix(BufReader::new(&mut file))?;
+    file.set_len(offset)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut written = 0;
+    for piece in &pieces[done..] {
+        file.write_all(piece)?;
+        written += piece.len();
+    }
+    file.sync_all()?;
+    eprintln!(
+        "Kept {} of {} pieces already in {}, and wrote {} more bytes",
+        done,
+        pieces.len(),
+        path.display(),
+        written
+    );
+    Ok(())
+}
+
+/// Decode a payload in a JPEG, WebP, or GIF. There's only ever one copy of it, in
+/// blocks of its own.
+fn decode_other(args: DecodeArgs, other: Box<dyn MetadataCarrier>) -> crate::Result<()> {
+    args.check_other_format()?;
+    let chunk_type = args
+        .chunk_type
+        .expect("clap requires a chunk type or a label");
+    let (chunk_type, n) =
+        exact_block_case(&*other, chunk_type, args.nth.get() - 1, args.ignore_case)?;
+    show_payload(&other.block(chunk_type, n)?, &args, None)
+}
+
+/// Print the payload in `c`, or write it where `args` say to, after opening
+/// it if it's sealed.
+fn show_payload(
+    c: &Chunk,
+    args: &DecodeArgs,
+    passphrase: Option<&Zeroizing<String>>,
+) -> crate::Result<()> {
+    if args.raw {
+        io::stdout().write_all(c.data())?;
+        return Ok(());
+    }
+    let raw = args.extract.is_some() || args.output.is_some() || args.shows_fields();
+    if Pipeline::is_envelope(c.data()) || args.filter.is_some() || raw {
+        let mut payload = open_payload(c.data(), || match passphrase {
+            Some(passphrase) => Ok(passphrase.clone()),
+            None => read_passphrase(&args.passphrase),
+        })?;
+        if let Some(command) = &args.filter {
+            payload = Zeroizing::new(filter::run(command, &payload)?);
+        }
+        match (&args.extract, &args.output) {
+            (Some(dir), _) => extract_payload(&payload, dir)?,
+            (None, Some(path)) => write_payload(&payload, path, &c.chunk_type().to_string())?,
+            (None, None) if args.shows_fields() => print_fields(&payload, args.field.as_deref())?,
+            (None, None) => println!("{}\t{}", c.chunk_type(), String::from_utf8_lossy(&payload)),
+        }
+    } else {
         println!("{}", c);
     }
     Ok(())
 }
 
-fn remove(args: RemoveArgs) -> crate::Result<()> {
-    let input_bytes = fs::read(&args.file_path)?;
+/// The exact chunk type and occurrence of the `n`th payload in the PNG at
+/// `path` whose type is `chunk_type` in any case, for `--ignore-case`. Like
+/// [find_payload], payloads after the end of the PNG come after the ones in
+/// chunks. If there aren't that many, `chunk_type` and `n` are given back as
+/// they are, so that looking for them gives the usual error.
+fn exact_case(
+    path: &Path,
+    chunk_type: ChunkType,
+    n: usize,
+    parse_options: &ParseOptions,
+) -> crate::Result<(ChunkType, usize)> {
+    let png = Png::parse_with(&storage::read(path)?, parse_options)?;
+    let matches: Vec<ChunkType> = png
+        .chunks()
+        .iter()
+        .map(|c| *c.chunk_type())
+        .chain(
+            trailer::records(png.trailing_data())
+                .into_iter()
+                .map(|(t, _)| t),
+        )
+        .filter(|t| t.eq_ignore_case(&chunk_type))
+        .collect();
+    Ok(match matches.get(n) {
+        Some(exact) => (*exact, matches[..n].iter().filter(|t| t == &exact).count()),
+        None => (chunk_type, n),
+    })
+}
+
+/// Find the `n`th payload of type `chunk_type` in the PNG at `path`: in a
+/// chunk, in fragments described by a manifest, or after the end of the PNG.
+fn find_payload(
+    path: &Path,
+    chunk_type: ChunkType,
+    n: usize,
+    parse_options: &ParseOptions,
+) -> crate::Result<Chunk> {
+    let mut reader = BufReader::new(storage::open(path)?);
+    if let Some(manifest) = find_manifest(&mut reader, chunk_type, parse_options)? {
+        // A split payload is decoded as a whole.
+        if n > 0 {
+            return Err(Box::new(ChunkNotFoundError::new(chunk_type, n)));
+        }
+        let png = Png::parse_with(&storage::read(path)?, parse_options)?;
+        let fragments = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type() == &chunk_type);
+        return Ok(Chunk::new(chunk_type, manifest.join(fragments)?));
+    }
+    reader.seek(SeekFrom::Start(0))?;
+    match Png::find_nth_chunk_streaming(&mut reader, chunk_type, n, parse_options)? {
+        Some(chunk) => Ok(chunk),
+        None => find_in_trailer(path, chunk_type, n, parse_options),
+    }
+}
+
+fn combine(args: CombineArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    let pngs = png_paths(&args.dir)?
+        .iter()
+        .map(|path| Png::parse_with(&fs::read(path)?, parse_options))
+        .collect::<crate::Result<Vec<Png>>>()?;
+    let payload = split::join_carriers(&pngs, args.chunk_type)?;
+    let payload = open_payload(&payload, || read_passphrase(&args.passphrase))?;
+    match &args.output_file_path {
+        Some(path) => write_payload(&payload, path, &args.chunk_type.to_string())?,
+        None => io::stdout().write_all(&payload)?,
+    }
+    Ok(())
+}
+
+fn plan(args: PlanArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    let paths = png_paths(&args.dir)?;
+    let capacities = paths
+        .iter()
+        .map(|path| Capacity::of(&Png::parse_with(&fs::read(path)?, parse_options)?))
+        .collect::<crate::Result<Vec<Capacity>>>()?;
+    println!("PNG\tchunk\ttrailer\tspread");
+    for (path, capacity) in paths.iter().zip(&capacities) {
+        println!(
+            "{}\t{}\t{}\t{}",
+            path.display(),
+            capacity.chunk,
+            capacity.trailer,
+            capacity.spread
+        );
+    }
+    let chunk_capacities: Vec<u64> = capacities.iter().map(|c| c.chunk).collect();
+    let chosen = capacity::plan_stripe(&chunk_capacities, args.payload_size)?;
+    let lengths = capacity::stripe_fragment_lengths(args.payload_size, chosen.len() as u64);
+    // Only the carriers that end up with a fragment are needed.
+    let chosen = &chosen[..lengths.len().min(chosen.len())];
+    println!();
+    for (n, (&i, length)) in chosen.iter().zip(&lengths).enumerate() {
+        println!(
+            "Fragment {} of {} ({} bytes):\t{}",
+            n + 1,
+            chosen.len(),
+            length,
+            paths[i].display()
+        );
+    }
+    let carriers: Vec<String> = chosen[1..]
+        .iter()
+        .map(|&i| paths[i].display().to_string())
+        .collect();
+    if carriers.is_empty() {
+        eprintln!("One PNG is enough, so there's no need to stripe the message");
+    } else {
+        eprintln!(
+            "To stripe the message this way: pngme encode {} <chunk type> <message> --stripe --carriers {}",
+            paths[chosen[0]].display(),
+            carriers.join(" ")
+        );
+    }
+    Ok(())
+}
+
+/// The PNGs in `dir`, by name.
+fn png_paths(dir: &Path) -> crate::Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    paths.retain(|path| {
+        path.extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+    });
+    paths.sort();
+    Ok(paths)
+}
+
+/// Put back together a message that was spread across the ancillary chunks.
+fn decode_spread(args: DecodeArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    let png = Png::parse_with(&storage::read(&args.file_path)?, parse_options)?;
+    let mut payload = open_payload(&spread::gather(&png)?, || read_passphrase(&args.passphrase))?;
+    if let Some(command) = &args.filter {
+        payload = Zeroizing::new(filter::run(command, &payload)?);
+    }
+    match (&args.extract, &args.output) {
+        (Some(dir), _) => extract_payload(&payload, dir)?,
+        (None, Some(path)) => write_payload(&payload, path, "spread")?,
+        (None, None) if args.shows_fields() => print_fields(&payload, args.field.as_deref())?,
+        (None, None) => println!("{}", String::from_utf8_lossy(&payload)),
+    }
+    Ok(())
+}
+
+/// Show the fields in a message that was encoded with `--field`: all of them,
+/// or just the value of `field`.
+fn print_fields(payload: &[u8], field: Option<&str>) -> crate::Result<()> {
+    let fields = Fields::from_cbor(payload)?;
+    match field {
+        Some(name) => println!("{}", fields.require(name)?),
+        None => print!("{}", fields),
+    }
+    Ok(())
+}
+
+/// Unpack a decoded message that is a tar or zip archive into `dir`.
+fn extract_payload(payload: &[u8], dir: &Path) -> crate::Result<()> {
+    let count = extract::extract(payload, dir)?;
+    eprintln!(
+        "Extracted {} file{} into {}",
+        count,
+        if count == 1 { "" } else { "s" },
+        dir.display()
+    );
+    Ok(())
+}
+
+/// Write a decoded message to `path`. If `path` is "auto", the file is named
+/// `stem` with an extension picked from what the message looks like.
+fn write_payload(payload: &[u8], path: &Path, stem: &str) -> crate::Result<()> {
+    let path = if path == Path::new("auto") {
+        PathBuf::from(format!("{}.{}", stem, sniff::extension(payload)))
+    } else {
+        path.to_path_buf()
+    };
+    storage::write(&path, payload)?;
+    eprintln!("Wrote {} bytes to {}", payload.len(), path.display());
+    Ok(())
+}
+
+/// Look for the manifest of a payload that was split into chunks of type
+/// `chunk_type`, without reading the whole PNG.
+fn find_manifest<R: Read + Seek>(
+    reader: &mut R,
+    chunk_type: ChunkType,
+    parse_options: &ParseOptions,
+) -> crate::Result<Option<Manifest>> {
+    for n in 0.. {
+        reader.seek(SeekFrom::Start(0))?;
+        match Png::find_nth_chunk_streaming(reader, ChunkType::FRAGMENT_MANIFEST, n, parse_options)?
+        {
+            Some(chunk) if Manifest::describes(&chunk, chunk_type) => {
+                return Ok(Some(Manifest::from_chunk(&chunk)?))
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    Ok(None)
+}
+
+/// Look for a payload that was hidden after the end of the PNG, for when
+/// there's no chunk with the type we want. The `n` counts chunks first, then
+/// trailer records.
+fn find_in_trailer(
+    path: &Path,
+    chunk_type: ChunkType,
+    n: usize,
+    parse_options: &ParseOptions,
+) -> crate::Result<Chunk> {
+    let png = Png::parse_with(&storage::read(path)?, parse_options)?;
+    let in_chunks = png
+        .chunks()
+        .iter()
+        .filter(|c| c.chunk_type() == &chunk_type)
+        .count();
+    let records = trailer::records(png.trailing_data());
+    let present: Vec<ChunkType> = png
+        .chunks()
+        .iter()
+        .map(|c| *c.chunk_type())
+        .chain(records.iter().map(|(t, _)| *t))
+        .collect();
+    let (_, payload) = n
+        .checked_sub(in_chunks)
+        .and_then(|i| records.into_iter().filter(|(t, _)| t == &chunk_type).nth(i))
+        .ok_or_else(|| ChunkNotFoundError::new(chunk_type, n).with_present(present))?;
+    Ok(Chunk::new(chunk_type, payload.to_vec()))
+}
+
+fn remove(args: RemoveArgs, write_options: WriteOptions) -> crate::Result<()> {
+    if let Some(other) = other_format(&args.file_path, args.format)? {
+        return remove_other(args, other, write_options);
+    }
+    let n = args.nth.get() - 1;
+    let mut seen = 0;
+    let mut removed = None;
+    let mut present = Vec::new();
+    let chunk = write_options.copy(&args.file_path, &args.file_path, |reader, writer| {
+        reader.copy_to(
+            writer,
+            |chunk| {
+                if !present.contains(chunk.chunk_type()) {
+                    present.push(*chunk.chunk_type());
+                }
+                let matches = if args.ignore_case {
+                    chunk.chunk_type().eq_ignore_case(&args.chunk_type)
+                } else {
+                    chunk.chunk_type() == &args.chunk_type
+                };
+                if !matches {
+                    return true;
+                }
+                seen += 1;
+                if seen != n + 1 {
+                    return true;
+                }
+                removed = Some(chunk.to_string());
+                false
+            },
+            true,
+        )?;
+        match removed.take() {
+            Some(chunk) => Ok(chunk),
+            None => Err(Box::new(
+                ChunkNotFoundError::new(args.chunk_type, n).with_present(present),
+            )),
+        }
+    })?;
+    eprintln!("Removed chunk: {}", chunk);
+    Ok(())
+}
+
+fn remove_other(
+    args: RemoveArgs,
+    mut other: Box<dyn MetadataCarrier>,
+    write_options: WriteOptions,
+) -> crate::Result<()> {
+    let (chunk_type, n) = exact_block_case(
+        &*other,
+        args.chunk_type,
+        args.nth.get() - 1,
+        args.ignore_case,
+    )?;
+    let chunk = other.remove_block(chunk_type, n)?;
+    write_options.write(&args.file_path, &args.file_path, &other.as_bytes())?;
+    eprintln!("Removed chunk: {}", chunk);
+    Ok(())
+}
+
+fn strip(args: StripArgs, write_options: WriteOptions) -> crate::Result<()> {
+    if args.dry_run {
+        return strip_dry_run(&args);
+    }
+    let output = args.output_file_path.as_ref().unwrap_or(&args.file_path);
+    let mut removed = 0;
+    let trailing = write_options.copy(&args.file_path, output, |reader, writer| {
+        let trailing = reader.copy_to(
+            writer,
+            |chunk| {
+                let keep = !args.chunk_types.contains(chunk.chunk_type());
+                removed += usize::from(!keep);
+                keep
+            },
+            !args.trailing,
+        )?;
+        Ok(if args.trailing { trailing } else { 0 })
+    })?;
+    eprintln!(
+        "Removed {} chunk{} and {} byte{} of trailing data",
+        removed,
+        if removed == 1 { "" } else { "s" },
+        trailing,
+        if trailing == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+/// List what `strip` would remove, and how many bytes that would save,
+/// without writing anything. Like `strip` itself, the PNG is read one chunk at
+/// a time.
+fn strip_dry_run(args: &StripArgs) -> crate::Result<()> {
+    let reader = ChunkReader::new(BufReader::new(storage::open(&args.file_path)?));
+    // The PNG signature.
+    let mut total = 8;
+    let mut removed = Vec::new();
+    let trailing = reader.copy_to(
+        &mut io::sink(),
+        |chunk| {
+            let length = 12 + u64::from(chunk.length());
+            total += length;
+            if args.chunk_types.contains(chunk.chunk_type()) {
+                removed.push((*chunk.chunk_type(), length));
+            }
+            true
+        },
+        true,
+    )?;
+    total += trailing;
+    for (chunk_type, length) in &removed {
+        println!("{}\t{} bytes", chunk_type, length);
+    }
+    let trailing = if args.trailing { trailing } else { 0 };
+    if trailing > 0 {
+        println!("Trailing data\t{} bytes", trailing);
+    }
+    let saved = removed.iter().map(|(_, length)| length).sum::<u64>() + trailing;
+    println!(
+        "Would remove {} chunk{} and {} byte{} of trailing data, saving {} of {} bytes ({:.1}%)",
+        removed.len(),
+        if removed.len() == 1 { "" } else { "s" },
+        trailing,
+        if trailing == 1 { "" } else { "s" },
+        saved,
+        total,
+        100.0 * saved as f64 / total as f64
+    );
+    Ok(())
+}
+
+fn dedupe(args: DedupeArgs, write_options: WriteOptions) -> crate::Result<()> {
+    let input_bytes = storage::read(&args.file_path)?;
     let mut png = Png::try_from(input_bytes.as_slice())?;
-    match png.remove_chunk(args.chunk_type) {
-        Ok(chunk) => {
-            fs::write(&args.file_path, png.as_bytes())?;
-            println!("Removed chunk: {}", chunk);
+    let removed = png.remove_duplicate_chunks();
+    let output = args.output_file_path.as_ref().unwrap_or(&args.file_path);
+    write_options.write(&args.file_path, output, &png.as_bytes())?;
+    for chunk in &removed {
+        eprintln!("Removed duplicate chunk: {}", chunk);
+    }
+    eprintln!(
+        "Removed {} duplicate chunk{}",
+        removed.len(),
+        if removed.len() == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+fn set_meta(args: SetMetaArgs, write_options: WriteOptions) -> crate::Result<()> {
+    let input_bytes = storage::read(&args.file_path)?;
+    let mut png = Png::try_from(input_bytes.as_slice())?;
+    for (keyword, text) in args.keywords() {
+        png.set_text(keyword, text)?;
+        eprintln!("Set {}", keyword);
+    }
+    let output = args.output_file_path.as_ref().unwrap_or(&args.file_path);
+    write_options.write(&args.file_path, output, &png.as_bytes())
+}
+
+fn set_dpi(args: SetDpiArgs, write_options: WriteOptions) -> crate::Result<()> {
+    let dimensions = if args.ppm {
+        PhysicalDimensions::from_ppm(args.density)?
+    } else {
+        PhysicalDimensions::from_dpi(args.density)?
+    };
+    let input_bytes = storage::read(&args.file_path)?;
+    let mut png = Png::try_from(input_bytes.as_slice())?;
+    png.set_chunk(PhysicalDimensionsCodec.to_chunk(&dimensions)?);
+    eprintln!("Set the density to {}", dimensions);
+    let output = args.output_file_path.as_ref().unwrap_or(&args.file_path);
+    write_options.write(&args.file_path, output, &png.as_bytes())
+}
+
+fn set_transparent(args: SetTransparentArgs, write_options: WriteOptions) -> crate::Result<()> {
+    let input_bytes = storage::read(&args.file_path)?;
+    let mut png = Png::try_from(input_bytes.as_slice())?;
+    let transparency = Transparency::in_png(&png, &args.color.0)?;
+    png.set_chunk(transparency.to_chunk());
+    eprintln!("Set the transparency: {}", transparency);
+    let output = args.output_file_path.as_ref().unwrap_or(&args.file_path);
+    write_options.write(&args.file_path, output, &png.as_bytes())
+}
+
+fn set_background(args: SetBackgroundArgs, write_options: WriteOptions) -> crate::Result<()> {
+    let input_bytes = storage::read(&args.file_path)?;
+    let mut png = Png::try_from(input_bytes.as_slice())?;
+    let background = Background(Color::in_png(&png, &args.color.0)?);
+    png.set_chunk(background.to_chunk());
+    eprintln!("Set the {}", background);
+    let output = args.output_file_path.as_ref().unwrap_or(&args.file_path);
+    write_options.write(&args.file_path, output, &png.as_bytes())
+}
+
+fn export_meta(args: ExportMetaArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    let png = Png::parse_with(&storage::read(&args.file_path)?, parse_options)?;
+    let json = MetaJson::from_png(&png)?.to_json() + "\n";
+    match args.output_file_path {
+        Some(path) => storage::write(&path, json.as_bytes())?,
+        None => io::stdout().write_all(json.as_bytes())?,
+    }
+    Ok(())
+}
+
+fn import_meta(args: ImportMetaArgs, write_options: WriteOptions) -> crate::Result<()> {
+    let meta = MetaJson::from_json(&String::from_utf8(storage::read(&args.json_path)?)?)?;
+    let mut png = Png::try_from(storage::read(&args.file_path)?.as_slice())?;
+    meta.apply(&mut png)?;
+    let output = args.output_file_path.as_ref().unwrap_or(&args.file_path);
+    write_options.write(&args.file_path, output, &png.as_bytes())?;
+    eprintln!(
+        "Set {} text chunk{}",
+        meta.text.len(),
+        if meta.text.len() == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+fn xmp(subcommand: XmpSubcommand, write_options: WriteOptions) -> crate::Result<()> {
+    match subcommand {
+        XmpSubcommand::Import(args) => {
+            let packet = String::from_utf8(storage::read(&args.xmp_path)?)?;
+            let mut png = Png::try_from(storage::read(&args.file_path)?.as_slice())?;
+            xmp::write(&mut png, &packet)?;
+            let output = args.output_file_path.as_ref().unwrap_or(&args.file_path);
+            write_options.write(&args.file_path, output, &png.as_bytes())
+        }
+        XmpSubcommand::Export(args) => {
+            let png = Png::try_from(storage::read(&args.file_path)?.as_slice())?;
+            let packet = xmp::require(&png)?;
+            match args.output_file_path {
+                Some(path) => storage::write(&path, packet.as_bytes())?,
+                None => io::stdout().write_all(packet.as_bytes())?,
+            }
+            Ok(())
+        }
+    }
+}
+
+fn icc(subcommand: IccSubcommand, write_options: WriteOptions) -> crate::Result<()> {
+    match subcommand {
+        IccSubcommand::Extract(args) => {
+            let png = Png::try_from(storage::read(&args.file_path)?.as_slice())?;
+            let profile = icc::require(&png)?;
+            eprintln!("Profile name: {}", profile.name);
+            match args.output_file_path {
+                Some(path) => storage::write(&path, &profile.profile)?,
+                None => io::stdout().write_all(&profile.profile)?,
+            }
+            Ok(())
+        }
+        IccSubcommand::Embed(args) => {
+            let profile = IccProfile {
+                name: args.name,
+                profile: storage::read(&args.profile_path)?,
+            };
+            let mut png = Png::try_from(storage::read(&args.file_path)?.as_slice())?;
+            if icc::write(&mut png, &profile)? {
+                eprintln!("Removed the sRGB chunk, since a PNG shouldn't have both");
+            }
+            let output = args.output_file_path.as_ref().unwrap_or(&args.file_path);
+            write_options.write(&args.file_path, output, &png.as_bytes())
+        }
+    }
+}
+
+fn apng(
+    subcommand: ApngSubcommand,
+    parse_options: &ParseOptions,
+    write_options: WriteOptions,
+) -> crate::Result<()> {
+    match subcommand {
+        ApngSubcommand::Split(args) => {
+            let png = Png::parse_with(&storage::read(&args.file_path)?, parse_options)?;
+            let frames = apng::split(&png)?;
+            fs::create_dir_all(&args.out_dir)?;
+            for (n, frame) in frames.iter().enumerate() {
+                let path = args.out_dir.join(format!("frame-{:03}.png", n + 1));
+                write_options.write(&args.file_path, &path, &frame.as_bytes())?;
+            }
+            eprintln!(
+                "Wrote {} frames to {}",
+                frames.len(),
+                args.out_dir.display()
+            );
+            Ok(())
+        }
+        ApngSubcommand::Build(args) => {
+            let (paths, output) = args.frames_and_output()?;
+            let frames = paths
+                .iter()
+                .map(|path| Png::parse_with(&storage::read(path)?, parse_options))
+                .collect::<crate::Result<Vec<Png>>>()?;
+            let png = apng::build(&frames, 1, args.fps, args.plays)?;
+            write_options.write(&paths[0], output, &png.as_bytes())?;
+            eprintln!("Built an animation of {} frames", frames.len());
+            Ok(())
+        }
+    }
+}
+
+fn sort(args: SortArgs, write_options: WriteOptions) -> crate::Result<()> {
+    let input_bytes = storage::read(&args.file_path)?;
+    let mut png = Png::try_from(input_bytes.as_slice())?;
+    let moved = png.sort_chunks();
+    let output = args.output_file_path.as_ref().unwrap_or(&args.file_path);
+    write_options.write(&args.file_path, output, &png.as_bytes())?;
+    if moved {
+        eprintln!("Reordered the chunks");
+    } else {
+        eprintln!("The chunks were already in order");
+    }
+    Ok(())
+}
+
+fn move_chunk(args: MoveArgs, write_options: WriteOptions) -> crate::Result<()> {
+    let input_bytes = storage::read(&args.file_path)?;
+    let mut png = Png::try_from(input_bytes.as_slice())?;
+    args.check_index(png.chunks().len())?;
+    let n = args.nth.get() - 1;
+    let from = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.chunk_type() == &args.chunk_type)
+        .map(|(index, _)| index)
+        .nth(n)
+        .ok_or_else(|| ChunkNotFoundError::new(args.chunk_type, n))?;
+    // Positions relative to another chunk are worked out as if the chunk
+    // being moved had already been taken out.
+    let others: Vec<&ChunkType> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != from)
+        .map(|(_, c)| c.chunk_type())
+        .collect();
+    let to = match (args.before, args.after, args.index) {
+        (Some(before), _, _) => others
+            .iter()
+            .position(|t| **t == before)
+            .ok_or_else(|| ChunkNotFoundError::new(before, 0))?,
+        (_, Some(after), _) => {
+            others
+                .iter()
+                .rposition(|t| **t == after)
+                .ok_or_else(|| ChunkNotFoundError::new(after, 0))?
+                + 1
+        }
+        (_, _, Some(index)) => index,
+        (None, None, None) => unreachable!("clap requires a position"),
+    };
+    png.move_chunk(from, to)?;
+    let output = args.output_file_path.as_ref().unwrap_or(&args.file_path);
+    write_options.write(&args.file_path, output, &png.as_bytes())?;
+    eprintln!(
+        "Moved {} from chunk {} to chunk {}",
+        args.chunk_type, from, to
+    );
+    Ok(())
+}
+
+fn corrupt(args: CorruptArgs, write_options: WriteOptions) -> crate::Result<()> {
+    let mut bytes = storage::read(&args.file_path)?;
+    let span = match args.chunk_type {
+        Some(chunk_type) => Some(corrupt::find_chunk(&bytes, chunk_type, args.nth.get() - 1)?),
+        None => None,
+    };
+    if let Some(count) = args.flip_bits {
+        let range = match &span {
+            Some(span) => span.data.clone(),
+            None => 8.min(bytes.len())..bytes.len(),
+        };
+        let mut rng = match args.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        for bit in corrupt::flip_bits(&mut bytes, range, count, &mut rng) {
+            eprintln!("Flipped bit {} of byte {}", bit % 8, bit / 8);
         }
-        Err(e) => println!("Error: {}", e),
     }
+    // Zero the CRC first, since it can't be found once the length is wrong.
+    if let Some(span) = &span {
+        if args.zero_crc {
+            corrupt::zero(&mut bytes, span.crc());
+            eprintln!("Zeroed the CRC at byte {}", span.crc().start);
+        }
+        if args.zero_length {
+            corrupt::zero(&mut bytes, span.length());
+            eprintln!("Zeroed the length at byte {}", span.start);
+        }
+    }
+    let output = args.output_file_path.as_ref().unwrap_or(&args.file_path);
+    write_options.write(&args.file_path, output, &bytes)?;
     Ok(())
 }
 
-fn print(args: PrintArgs) -> crate::Result<()> {
-    let input_bytes = fs::read(&args.file_path)?;
-    let png = Png::try_from(input_bytes.as_slice())?;
+fn print(args: PrintArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    let input_bytes = storage::read(&args.file_path)?;
+    let format = match args.image_format {
+        Some(format) => format.format(),
+        None => Format::sniff(&input_bytes).unwrap_or(Format::Png),
+    };
+    if format != Format::Png {
+        args.check_other_format()?;
+        for line in format.parse(&input_bytes)?.describe()? {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    let png = Png::parse_with(&input_bytes, parse_options)?;
+    match args.format {
+        PrintFormat::Text => {
+            let registry = CodecRegistry::default();
+            // Some chunks can only be read knowing the image's color type.
+            let header = ImageHeader::from_png(&png).ok();
+            for chunk in png.chunks() {
+                match header.and_then(|h| color::render(chunk, &h)) {
+                    Some(Ok(rendered)) => println!("{}\t{}", chunk.chunk_type(), rendered),
+                    _ => println!("{}", registry.display(chunk)),
+                }
+            }
+        }
+        PrintFormat::Json => println!("{}", PngJson::from_png(&png).to_json()),
+    }
+    Ok(())
+}
+
+fn export(args: ExportArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    let png = Png::parse_with(&storage::read(&args.file_path)?, parse_options)?;
+    let json = PngJson::from_png(&png).to_json() + "\n";
+    match args.output_file_path {
+        Some(path) => storage::write(&path, json.as_bytes())?,
+        None => io::stdout().write_all(json.as_bytes())?,
+    }
+    Ok(())
+}
+
+fn import(args: ImportArgs, write_options: WriteOptions) -> crate::Result<()> {
+    let json = String::from_utf8(storage::read(&args.json_path)?)?;
+    let png = PngJson::from_json(&json)?.to_png()?;
+    write_options.write(&args.json_path, &args.output_file_path, &png.as_bytes())
+}
+
+fn info(args: InfoArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    let input_bytes = storage::read(&args.file_path)?;
+    let png = Png::parse_with(&input_bytes, parse_options)?;
+    let header = ImageHeader::from_png(&png)?;
+    let idat = IdatSummary::of(&png);
+    let raw_len = header.raw_data_len();
+    println!("Size:\t{}x{}", header.width, header.height);
+    println!(
+        "Color:\t{} ({}-bit)",
+        header.color_type_name(),
+        header.bit_depth
+    );
+    println!(
+        "Interlaced:\t{}",
+        if header.interlaced { "yes" } else { "no" }
+    );
+    if let Some(Ok(dimensions)) = png
+        .chunk_by_type(ChunkType::PHYS)
+        .map(|c| PhysicalDimensionsCodec.decode(c.data()))
+    {
+        println!("Density:\t{}", dimensions);
+    }
+    if let Some(Ok(transparency)) = png
+        .chunk_by_type(ChunkType::TRNS)
+        .map(|c| Transparency::parse(c.data(), &header))
+    {
+        println!("Transparency:\t{}", transparency);
+    }
+    for palette in png
+        .chunks()
+        .iter()
+        .filter(|c| c.chunk_type() == &ChunkType::SPLT)
+        .filter_map(|c| SuggestedPaletteCodec.decode(c.data()).ok())
+    {
+        println!(
+            "Suggested palette:\t{} ({}-bit, {} entries)",
+            palette.name,
+            palette.sample_depth,
+            palette.entries.len()
+        );
+    }
+    if let Some(Ok(Background(color))) = png
+        .chunk_by_type(ChunkType::BKGD)
+        .map(|c| Background::parse(c.data(), &header))
+    {
+        println!("Background:\t{}", color);
+    }
+    println!("Chunks:\t{}", png.chunk_count());
+    println!("IDAT chunks:\t{}", idat.count);
+    println!("IDAT size:\t{} bytes", idat.compressed_len);
+    println!("Image data size:\t{} bytes when decompressed", raw_len);
+    if raw_len > 0 {
+        println!(
+            "Compression:\t{:.1}%",
+            100.0 * idat.compressed_len as f64 / raw_len as f64
+        );
+    }
+    if !png.trailing_data().is_empty() {
+        println!(
+            "Trailing data:\t{} bytes after IEND",
+            png.trailing_data().len()
+        );
+        eprintln!(
+            "Warning: there is data after the end of the image; see `pngme strip --trailing`"
+        );
+    }
+    let ancillary = AncillarySummary::of(&png);
+    println!(
+        "Non-standard chunks:\t{} bytes ({:.1}% of the file)",
+        ancillary.nonstandard_len,
+        ancillary.percentage()
+    );
+    if idat.is_oversized(raw_len) {
+        eprintln!(
+            "Warning: the IDAT chunks are bigger than the image could need, even uncompressed, so they may be hiding extra data"
+        );
+    }
+    warn_if_conspicuous(ancillary, args.payload_warning);
+    Ok(())
+}
+
+/// Print a digest of each chunk's data, then one of the whole PNG.
+fn hash(args: HashArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    let png = Png::parse_with(&storage::read(&args.file_path)?, parse_options)?;
+    let algorithm = args.algo.algorithm();
     for chunk in png.chunks() {
-        println!("{}", chunk);
+        println!(
+            "{}\t{}",
+            chunk.chunk_type(),
+            digest::to_hex(&digest::chunk_digest(algorithm, chunk))
+        );
+    }
+    println!(
+        "Structure\t{}",
+        digest::to_hex(&digest::structural_digest(algorithm, &png))
+    );
+    Ok(())
+}
+
+/// Print a digest of each PNG's image data, then its path.
+fn pixel_hash(args: PixelHashArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    let algorithm = args.algo.algorithm();
+    for path in &args.file_paths {
+        let png = Png::parse_with(&storage::read(path)?, parse_options)?;
+        println!(
+            "{}\t{}",
+            digest::to_hex(&digest::pixel_data_digest(algorithm, &png)),
+            path.display()
+        );
     }
     Ok(())
 }
 
-pub fn run(subcommand: Subcommand) -> crate::Result<()> {
+/// Print a perceptual hash of each image, then how many bits it differs by
+/// from the first one's (when there's more than one), then its path.
+fn phash(args: PhashArgs) -> crate::Result<()> {
+    let hashes = args
+        .file_paths
+        .iter()
+        .map(|path| PerceptualHash::of(&storage::read(path)?))
+        .collect::<crate::Result<Vec<PerceptualHash>>>()?;
+    for (hash, path) in hashes.iter().zip(&args.file_paths) {
+        if hashes.len() == 1 {
+            println!("{}\t{}", hash, path.display());
+        } else {
+            println!("{}\t{}\t{}", hash, hash.distance(hashes[0]), path.display());
+        }
+    }
+    Ok(())
+}
+
+fn imgdiff(args: ImgdiffArgs) -> crate::Result<()> {
+    let diff = PixelDiff::between(&storage::read(&args.first)?, &storage::read(&args.second)?)?;
+    println!(
+        "Differing pixels:\t{} of {} ({:.2}%)",
+        diff.differing,
+        diff.total,
+        100.0 * diff.differing as f64 / diff.total.max(1) as f64
+    );
+    println!(
+        "Largest channel difference:\t{} of {}",
+        diff.max_delta, diff.max_value
+    );
+    Ok(())
+}
+
+fn cat(args: CatArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    let input_bytes = storage::read(&args.file_path)?;
+    let png = Png::parse_with(&input_bytes, parse_options)?;
+    let fragments = png
+        .chunks()
+        .iter()
+        .filter(|c| c.chunk_type().to_string().starts_with(&args.type_prefix));
+    let payload = open_payload(&split::join_fragments(fragments)?, || {
+        read_passphrase(&args.passphrase)
+    })?;
+    match args.output_file_path {
+        Some(path) => storage::write(&path, &payload)?,
+        None => io::stdout().write_all(&payload)?,
+    }
+    Ok(())
+}
+
+fn type_make(args: TypeMakeArgs) -> crate::Result<()> {
+    let chunk_type = ChunkType::with_properties(
+        args.letters.bytes(),
+        args.critical,
+        !args.public,
+        !args.unsafe_to_copy,
+    )?;
+    println!("{}", chunk_type);
+    Ok(())
+}
+
+fn types() -> crate::Result<()> {
+    let mut chunk_types: Vec<ChunkType> = ChunkType::known().collect();
+    let registry = CodecRegistry::default();
+    let mut custom: Vec<ChunkType> = registry
+        .chunk_types()
+        .filter(|t| !chunk_types.contains(t))
+        .copied()
+        .collect();
+    custom.sort_by_key(|t| t.to_string());
+    chunk_types.extend(custom);
+    for chunk_type in chunk_types {
+        println!(
+            "{}  {:<9}  {:<7}  {:<14}  {}",
+            chunk_type,
+            if chunk_type.is_critical() {
+                "critical"
+            } else {
+                "ancillary"
+            },
+            if chunk_type.is_public() {
+                "public"
+            } else {
+                "private"
+            },
+            if chunk_type.is_safe_to_copy() {
+                "safe to copy"
+            } else {
+                "unsafe to copy"
+            },
+            chunk_type.description().unwrap_or("Has a registered codec"),
+        );
+    }
+    Ok(())
+}
+
+fn formats() -> crate::Result<()> {
+    for format in Format::ALL.iter() {
+        println!(
+            "{:<4}  {:<22}  {}",
+            format.name(),
+            format.methods().join(", "),
+            format.capacity()
+        );
+    }
+    Ok(())
+}
+
+fn explain(args: ExplainArgs) -> crate::Result<()> {
+    println!("{}", explain::explain(args.chunk_type));
+    Ok(())
+}
+
+fn verify(args: VerifyArgs, parse_options: &ParseOptions) -> crate::Result<()> {
+    if args.crc {
+        return verify_crcs(&args.file_path);
+    }
+    let (chunk_type, pubkey) = match (args.chunk_type, &args.pubkey) {
+        (Some(chunk_type), Some(pubkey)) => (chunk_type, pubkey),
+        _ => unreachable!("clap requires a chunk type and a key without --crc"),
+    };
+    let key = verifying_key(pubkey)?;
+    let input_bytes = storage::read(&args.file_path)?;
+    let png = Png::parse_with(&input_bytes, parse_options)?;
+    signature::verify(&png, chunk_type, &key)?;
+    println!("Signature OK");
+    Ok(())
+}
+
+/// Report whether each chunk's CRC is right, rather than stopping at the first
+/// one that isn't.
+fn verify_crcs(path: &Path) -> crate::Result<()> {
+    let input_bytes = storage::read(path)?;
+    let png = Png::parse_with(
+        &input_bytes,
+        &ParseOptions {
+            verify_crc: false,
+            ..ParseOptions::default()
+        },
+    )?;
+    for chunk in png.chunks() {
+        if chunk.has_valid_crc() {
+            println!("{}\tOK", chunk.chunk_type());
+        } else {
+            println!(
+                "{}\tCRC mismatch (stored {:08x}, expected {:08x})",
+                chunk.chunk_type(),
+                chunk.crc(),
+                chunk.expected_crc()
+            );
+        }
+    }
+    png.check_crcs()
+}
+
+fn key(subcommand: KeySubcommand) -> crate::Result<()> {
+    let store = KeyStore::open_default()?;
+    match subcommand {
+        KeySubcommand::Generate(args) => {
+            store.generate(&args.name)?;
+            eprintln!("Saved {} in {}", args.name, store.dir().display());
+        }
+        KeySubcommand::List => {
+            for name in store.names()? {
+                println!("{}", name);
+            }
+        }
+        KeySubcommand::Export(args) => {
+            if args.private {
+                print!("{}", store.private_pem(&args.name)?);
+            } else {
+                print!("{}", store.public_pem(&args.name)?);
+            }
+        }
+        KeySubcommand::Import(args) => {
+            store.import_pem(&args.name, &fs::read_to_string(&args.file_path)?)?;
+            eprintln!("Saved {} in {}", args.name, store.dir().display());
+        }
+    }
+    Ok(())
+}
+
+fn watch(args: WatchArgs, write_options: WriteOptions) -> crate::Result<()> {
+    let config = StampConfig::from_toml(&fs::read_to_string(&args.encode_with)?)?;
+    watch::watch(&args.dir, &config, write_options)
+}
+
+fn git_filter(subcommand: FilterSubcommand) -> crate::Result<()> {
+    let mut input = Vec::new();
+    io::stdin().read_to_end(&mut input)?;
+    let output = match subcommand {
+        FilterSubcommand::Clean(args) => {
+            let local = read_local_chunks(args.local.as_deref())?;
+            gitfilter::clean(&input, &args.strip, local.as_ref())?
+        }
+        FilterSubcommand::Smudge(args) => {
+            let local = read_local_chunks(args.local.as_deref())?;
+            gitfilter::smudge(&input, local.as_ref())?
+        }
+    };
+    io::stdout().write_all(&output)?;
+    Ok(())
+}
+
+fn man(args: ManArgs) -> crate::Result<()> {
+    match args.out_dir {
+        Some(dir) => {
+            fs::create_dir_all(&dir)?;
+            let written = man::write_pages(Cli::command(), &dir)?;
+            eprintln!("Wrote {} man pages to {}", written.len(), dir.display());
+        }
+        None => man::render(Cli::command(), &mut io::stdout())?,
+    }
+    Ok(())
+}
+
+fn read_local_chunks(path: Option<&Path>) -> crate::Result<Option<StampConfig>> {
+    match path {
+        Some(path) => Ok(Some(StampConfig::from_toml(&fs::read_to_string(path)?)?)),
+        None => Ok(None),
+    }
+}
+
+/// A key given on the command line is either a path to a PEM file or the name
+/// of a key in the key store.
+fn signing_key(key: &Path) -> crate::Result<SigningKey> {
+    if key.is_file() {
+        signature::signing_key_from_pem(&fs::read_to_string(key)?)
+    } else {
+        KeyStore::open_default()?.signing_key(key_name(key)?)
+    }
+}
+
+/// Like [signing_key], but for public keys.
+fn verifying_key(key: &Path) -> crate::Result<VerifyingKey> {
+    if key.is_file() {
+        signature::verifying_key_from_pem(&fs::read_to_string(key)?)
+    } else {
+        KeyStore::open_default()?.verifying_key(key_name(key)?)
+    }
+}
+
+/// A key that isn't a file is looked up by name in the key store. Key names
+/// are always UTF-8, so a path that isn't can only have been a missing file.
+fn key_name(key: &Path) -> crate::Result<&str> {
+    key.to_str().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("There is no key file at {}", key.display()),
+        )
+        .into()
+    })
+}
+
+/// The environment variable that supplies the passphrase instead of asking
+/// for it, for scripts.
+const PASSPHRASE_VARIABLE: &str = "PNGME_PASSPHRASE";
+
+/// Get the passphrase from the first line of `--passphrase-file`, from the
+/// keychain, from `$PNGME_PASSPHRASE`, or by asking for it, in that order. With
+/// `--use-keyring`, a passphrase that wasn't in the keychain is stored there
+/// for next time.
+pub fn read_passphrase(args: &PassphraseArgs) -> crate::Result<Zeroizing<String>> {
+    if let Some(path) = &args.passphrase_file {
+        let contents = Zeroizing::new(fs::read_to_string(path)?);
+        let line = contents.lines().next().unwrap_or_default();
+        return Ok(Zeroizing::new(line.to_string()));
+    }
+    if let Some(service) = &args.use_keyring {
+        if let Some(passphrase) = keychain::get(service)? {
+            return Ok(passphrase);
+        }
+        let passphrase = read_unstored_passphrase()?;
+        keychain::store_confirmed(service, &passphrase, &confirm_passphrase(&passphrase)?)?;
+        return Ok(passphrase);
+    }
+    read_unstored_passphrase()
+}
+
+/// Get the passphrase from `$PNGME_PASSPHRASE`, or by asking for it.
+fn read_unstored_passphrase() -> crate::Result<Zeroizing<String>> {
+    match env::var(PASSPHRASE_VARIABLE) {
+        Ok(passphrase) => Ok(Zeroizing::new(passphrase)),
+        Err(env::VarError::NotPresent) => {
+            Ok(Zeroizing::new(rpassword::prompt_password("Passphrase: ")?))
+        }
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+/// Ask again for a passphrase that's about to be stored in the keychain. One
+/// from `$PNGME_PASSPHRASE` wasn't typed, so it isn't asked for again.
+fn confirm_passphrase(passphrase: &str) -> crate::Result<Zeroizing<String>> {
+    if env::var_os(PASSPHRASE_VARIABLE).is_some() {
+        return Ok(Zeroizing::new(passphrase.to_string()));
+    }
+    Ok(Zeroizing::new(rpassword::prompt_password(
+        "Passphrase again, to store it: ",
+    )?))
+}
+
+/// Undo whatever was done to a payload when it was encoded. Payloads that
+/// aren't in an envelope are returned as-is. `passphrase` is only called if
+/// the payload is encrypted. The payload is wiped from memory when it's
+/// dropped.
+pub fn open_payload<P>(data: &[u8], passphrase: P) -> crate::Result<Zeroizing<Vec<u8>>>
+where
+    P: Fn() -> crate::Result<Zeroizing<String>>,
+{
+    if Pipeline::is_envelope(data) {
+        Ok(Zeroizing::new(Pipeline::open(data, |id| {
+            transform::builtin_transform(id, &passphrase)
+        })?))
+    } else {
+        Ok(Zeroizing::new(data.to_vec()))
+    }
+}
+
+/// The format of the image at `path`: the one given with `--format`, or the
+/// one it looks like. Anything that looks like none of them is read as a PNG,
+/// to say what's wrong with it.
+fn image_format(path: &Path, format: Option<ImageFormat>) -> crate::Result<Format> {
+    if let Some(format) = format {
+        return Ok(format.format());
+    }
+    let mut magic = Vec::new();
+    storage::open(path)?
+        .take(Format::SNIFF_LENGTH as u64)
+        .read_to_end(&mut magic)?;
+    Ok(Format::sniff(&magic).unwrap_or(Format::Png))
+}
+
+/// The image at `path`, unless it's a PNG. PNGs are handled on their own,
+/// since most of what pngme does only works on them, and reading them whole
+/// isn't always needed.
+fn other_format(
+    path: &Path,
+    format: Option<ImageFormat>,
+) -> crate::Result<Option<Box<dyn MetadataCarrier>>> {
+    match image_format(path, format)? {
+        Format::Png => Ok(None),
+        format => Ok(Some(format.parse(&storage::read(path)?)?)),
+    }
+}
+
+/// Like [exact_case], but for the blocks of `carrier`, and only if
+/// `ignore_case` is set.
+fn exact_block_case(
+    carrier: &dyn MetadataCarrier,
+    chunk_type: ChunkType,
+    n: usize,
+    ignore_case: bool,
+) -> crate::Result<(ChunkType, usize)> {
+    if !ignore_case {
+        return Ok((chunk_type, n));
+    }
+    let matches: Vec<ChunkType> = carrier
+        .blocks()?
+        .iter()
+        .map(|c| *c.chunk_type())
+        .filter(|t| t.eq_ignore_case(&chunk_type))
+        .collect();
+    Ok(match matches.get(n) {
+        Some(exact) => (*exact, matches[..n].iter().filter(|t| t == &exact).count()),
+        None => (chunk_type, n),
+    })
+}
+
+/// Run a subcommand. `parse_options` are used by subcommands that only read a
+/// PNG; subcommands that change a PNG always check it fully.
+pub fn run(
+    subcommand: Subcommand,
+    parse_options: &ParseOptions,
+    write_options: WriteOptions,
+) -> crate::Result<()> {
     match subcommand {
-        Subcommand::Encode(args) => encode(args),
-        Subcommand::Decode(args) => decode(args),
-        Subcommand::Remove(args) => remove(args),
-        Subcommand::Print(args) => print(args),
+        Subcommand::Encode(args) => encode(args, write_options),
+        Subcommand::Decode(args) => decode(args, parse_options),
+        Subcommand::Remove(args) => remove(args, write_options),
+        Subcommand::Strip(args) => strip(args, write_options),
+        Subcommand::Dedupe(args) => dedupe(args, write_options),
+        Subcommand::SetMeta(args) => set_meta(args, write_options),
+        Subcommand::SetDpi(args) => set_dpi(args, write_options),
+        Subcommand::SetTransparent(args) => set_transparent(args, write_options),
+        Subcommand::SetBackground(args) => set_background(args, write_options),
+        Subcommand::ExportMeta(args) => export_meta(args, parse_options),
+        Subcommand::ImportMeta(args) => import_meta(args, write_options),
+        Subcommand::Xmp(subcommand) => xmp(subcommand, write_options),
+        Subcommand::Icc(subcommand) => icc(subcommand, write_options),
+        Subcommand::Apng(subcommand) => apng(subcommand, parse_options, write_options),
+        Subcommand::Sort(args) => sort(args, write_options),
+        Subcommand::Move(args) => move_chunk(args, write_options),
+        Subcommand::Corrupt(args) => corrupt(args, write_options),
+        Subcommand::Print(args) => print(args, parse_options),
+        Subcommand::Export(args) => export(args, parse_options),
+        Subcommand::Import(args) => import(args, write_options),
+        Subcommand::Info(args) => info(args, parse_options),
+        Subcommand::Hash(args) => hash(args, parse_options),
+        Subcommand::PixelHash(args) => pixel_hash(args, parse_options),
+        Subcommand::Phash(args) => phash(args),
+        Subcommand::Imgdiff(args) => imgdiff(args),
+        Subcommand::Cat(args) => cat(args, parse_options),
+        Subcommand::Combine(args) => combine(args, parse_options),
+        Subcommand::Plan(args) => plan(args, parse_options),
+        Subcommand::Type(TypeSubcommand::Make(args)) => type_make(args),
+        Subcommand::Types => types(),
+        Subcommand::Formats => formats(),
+        Subcommand::Explain(args) => explain(args),
+        Subcommand::Verify(args) => verify(args, parse_options),
+        Subcommand::Key(subcommand) => key(subcommand),
+        Subcommand::Watch(args) => watch(args, write_options),
+        Subcommand::Filter(subcommand) => git_filter(subcommand),
+        Subcommand::Man(args) => man(args),
+        Subcommand::Serve(args) => serve::serve(args.listen, args.allow_unsafe),
+        Subcommand::Shell(args) => shell::run(
+            &args.file_path,
+            write_options,
+            args.passphrase,
+            args.allow_unsafe,
+        ),
     }
 }
@@ -0,0 +1,92 @@
+//! Shell completion, through `COMPLETE=$SHELL pngme` (see the README).
+//!
+//! Completing a chunk type lists the chunk types that are actually in the PNG
+//! named earlier on the command line, so there's no need to `print` it first
+//! to see what's there.
+use crate::commands;
+use crate::storage;
+use clap_complete::CompletionCandidate;
+use pngme::ChunkType;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+
+/// The chunk types in the PNG being completed for that start with `current`,
+/// each once, in the order they first appear. There are none if no PNG has
+/// been named yet, or it can't be read.
+pub fn chunk_types(current: &OsStr) -> Vec<CompletionCandidate> {
+    // The shell passes the whole command line after a `--`.
+    let words: Vec<OsString> = env::args_os().skip_while(|a| a != "--").skip(1).collect();
+    chunk_types_in(&words, &current.to_string_lossy())
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// The chunk types to complete `current` with, given the command line
+/// `words`, with the word being completed last.
+fn chunk_types_in(words: &[OsString], current: &str) -> Vec<String> {
+    let types = match typed_png(words).and_then(|path| commands::chunk_types_in(&path).ok()) {
+        Some(types) => types,
+        None => return Vec::new(),
+    };
+    let mut seen: Vec<ChunkType> = Vec::new();
+    for chunk_type in types {
+        if !seen.contains(&chunk_type) {
+            seen.push(chunk_type);
+        }
+    }
+    seen.into_iter()
+        .map(|t| t.to_string())
+        .filter(|t| t.starts_with(current))
+        .collect()
+}
+
+/// The PNG named in `words`: the first word after the program's name that's
+/// a file.
+fn typed_png(words: &[OsString]) -> Option<PathBuf> {
+    // Leave out the program's name, and the word being completed.
+    let typed = words.get(1..words.len().saturating_sub(1))?;
+    typed
+        .iter()
+        .filter(|word| !word.to_string_lossy().starts_with('-'))
+        .map(PathBuf::from)
+        .find(|path| !storage::is_remote(path) && path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngme::{Chunk, Png};
+    use std::fs;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_chunk_types_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dice.png");
+        let mut png = Png::default();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        png.append_chunk(Chunk::new(chunk_type, b"one".to_vec()));
+        png.append_chunk(Chunk::new(chunk_type, b"two".to_vec()));
+        fs::write(&path, png.as_bytes()).unwrap();
+
+        let words = |current: &str| -> Vec<OsString> {
+            vec![
+                "pngme".into(),
+                "decode".into(),
+                "--quiet".into(),
+                path.clone().into(),
+                current.into(),
+            ]
+        };
+        let all = chunk_types_in(&words(""), "");
+        assert!(all.contains(&"ruSt".to_string()));
+        assert_eq!(all.iter().filter(|t| *t == "ruSt").count(), 1);
+        assert_eq!(chunk_types_in(&words("r"), "r"), vec!["ruSt"]);
+
+        // Nothing to complete from before the PNG is named.
+        let words: Vec<OsString> = vec!["pngme".into(), "decode".into(), "".into()];
+        assert!(chunk_types_in(&words, "").is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+//! Damaging PNGs on purpose, to test how decoders cope.
+//!
+//! Everything here works on the raw bytes rather than a parsed `Png`, so it
+//! keeps working on files that are already damaged.
+use pngme::{ChunkNotFoundError, ChunkType};
+use rand::Rng;
+use std::convert::TryFrom;
+use std::ops::Range;
+
+/// PNGs start with an 8-byte signature before their first chunk.
+const SIGNATURE_LENGTH: usize = 8;
+
+/// Where one chunk is in a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkSpan {
+    /// The offset of the chunk's 4-byte length.
+    pub start: usize,
+    /// The chunk's data.
+    pub data: Range<usize>,
+}
+
+impl ChunkSpan {
+    /// The chunk's 4-byte length.
+    pub fn length(&self) -> Range<usize> {
+        self.start..self.start + 4
+    }
+
+    /// The chunk's 4-byte CRC.
+    pub fn crc(&self) -> Range<usize> {
+        self.data.end..self.data.end + 4
+    }
+}
+
+/// Find the `n`th (counting from 0) chunk of type `chunk_type` by walking the
+/// chunk lengths, without checking anything else. Returns `Err` if the walk
+/// runs off the end of the file first.
+pub fn find_chunk(bytes: &[u8], chunk_type: ChunkType, n: usize) -> crate::Result<ChunkSpan> {
+    let mut start = SIGNATURE_LENGTH;
+    let mut seen = 0;
+    while let Some(header) = bytes.get(start..start + 8) {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let data = start + 8..(start + 8).saturating_add(usize::try_from(length)?);
+        if data.end.saturating_add(4) > bytes.len() {
+            break;
+        }
+        if header[4..] == chunk_type.bytes() {
+            if seen == n {
+                return Ok(ChunkSpan { start, data });
+            }
+            seen += 1;
+        }
+        start = data.end + 4;
+    }
+    Err(Box::new(ChunkNotFoundError::new(chunk_type, n)))
+}
+
+/// Flip `count` randomly chosen bits in `range` of `bytes`. The same bit may
+/// be picked more than once, which flips it back. Does nothing if `range` is
+/// empty.
+pub fn flip_bits<R: Rng + ?Sized>(
+    bytes: &mut [u8],
+    range: Range<usize>,
+    count: usize,
+    rng: &mut R,
+) -> Vec<usize> {
+    if range.is_empty() {
+        return Vec::new();
+    }
+    (0..count)
+        .map(|_| {
+            let bit = rng.gen_range(range.start * 8..range.end * 8);
+            bytes[bit / 8] ^= 0x80 >> (bit % 8);
+            bit
+        })
+        .collect()
+}
+
+/// Set every byte in `range` of `bytes` to 0.
+pub fn zero(bytes: &mut [u8], range: Range<usize>) {
+    bytes[range].iter_mut().for_each(|byte| *byte = 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngme::{Chunk, Png};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::str::FromStr;
+
+    fn bytes() -> Vec<u8> {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"first".to_vec()),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"second".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ])
+        .as_bytes()
+    }
+
+    #[test]
+    fn test_find_chunk() {
+        let bytes = bytes();
+        let rust = ChunkType::from_str("ruSt").unwrap();
+        let span = find_chunk(&bytes, rust, 1).unwrap();
+        assert_eq!(&bytes[span.data.clone()], b"second");
+        assert_eq!(&bytes[span.length()], &[0, 0, 0, 6]);
+        assert!(find_chunk(&bytes, rust, 2).is_err());
+    }
+
+    #[test]
+    fn test_zero_crc() {
+        let mut bytes = bytes();
+        let span = find_chunk(&bytes, ChunkType::from_str("ruSt").unwrap(), 0).unwrap();
+        zero(&mut bytes, span.crc());
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+        // The chunks after it can still be found.
+        assert!(find_chunk(&bytes, ChunkType::from_str("IEND").unwrap(), 0).is_ok());
+    }
+
+    #[test]
+    fn test_flip_bits() {
+        let original = bytes();
+        let mut bytes = original.clone();
+        let span = find_chunk(&bytes, ChunkType::from_str("ruSt").unwrap(), 0).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        let flipped = flip_bits(&mut bytes, span.data.clone(), 1, &mut rng);
+        assert_eq!(flipped.len(), 1);
+        let changed: Vec<usize> = (0..bytes.len())
+            .filter(|&i| bytes[i] != original[i])
+            .collect();
+        assert_eq!(changed, vec![flipped[0] / 8]);
+        assert!(span.data.contains(&changed[0]));
+        assert!(flip_bits(&mut bytes, 0..0, 5, &mut rng).is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use miette::{Diagnostic, GraphicalReportHandler, GraphicalTheme, LabeledSpan, SourceCode};
+use pngme::ChunkParseError;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+/// How many bytes each line of a hexdump shows.
+const BYTES_PER_LINE: usize = 16;
+
+/// A [ChunkParseError], shown with a hexdump of the bytes around the problem
+/// and the bad bytes pointed out.
+#[derive(Debug)]
+struct ChunkDiagnostic {
+    message: String,
+    hexdump: String,
+    /// Where the bad bytes are in `hexdump`.
+    span: Range<usize>,
+    label: String,
+}
+
+impl ChunkDiagnostic {
+    fn new(error: &ChunkParseError) -> Self {
+        let (context_offset, context) = error.context();
+        let (hexdump, span) = hexdump(context_offset, context, error.span());
+        let bad = error.span();
+        ChunkDiagnostic {
+            message: error.to_string(),
+            hexdump,
+            span,
+            label: format!("bytes {:#X} to {:#X}", bad.start, bad.end - 1),
+        }
+    }
+}
+
+impl fmt::Display for ChunkDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+impl Error for ChunkDiagnostic {}
+
+impl Diagnostic for ChunkDiagnostic {
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.hexdump)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        Some(Box::new(std::iter::once(LabeledSpan::new_with_span(
+            Some(self.label.clone()),
+            self.span.clone(),
+        ))))
+    }
+}
+
+/// Lay out `bytes`, which start at `offset` in the file, as a hexdump with
+/// each line starting at a multiple of 16. Returns the hexdump and where the
+/// bytes at the file offsets in `highlight` are in it.
+fn hexdump(offset: usize, bytes: &[u8], highlight: Range<usize>) -> (String, Range<usize>) {
+    let end = offset + bytes.len();
+    let mut text = String::new();
+    let mut span = 0..0;
+    for line_start in (offset - offset % BYTES_PER_LINE..end).step_by(BYTES_PER_LINE) {
+        text.push_str(&format!("{:08x} ", line_start));
+        let mut ascii = String::new();
+        for position in line_start..line_start + BYTES_PER_LINE {
+            text.push(' ');
+            if position == highlight.start {
+                span.start = text.len();
+            }
+            match position.checked_sub(offset).and_then(|i| bytes.get(i)) {
+                Some(byte) => {
+                    text.push_str(&format!("{:02x}", byte));
+                    ascii.push(if byte.is_ascii_graphic() {
+                        *byte as char
+                    } else {
+                        '.'
+                    });
+                }
+                None => {
+                    text.push_str("  ");
+                    ascii.push(' ');
+                }
+            }
+            if position + 1 == highlight.end {
+                span.end = text.len();
+            }
+        }
+        text.push_str(&format!("  |{}|\n", ascii));
+    }
+    (text, span)
+}
+
+/// Render `error` with a hexdump, if it's an error that knows where in the
+/// file it happened.
+pub fn render(error: &crate::Error, theme: GraphicalTheme) -> Option<String> {
+    let error = error.downcast_ref::<ChunkParseError>()?;
+    let mut out = String::new();
+    GraphicalReportHandler::new_themed(theme)
+        .render_report(&mut out, &ChunkDiagnostic::new(error))
+        .ok()?;
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngme::{Chunk, ChunkType, Png};
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_hexdump() {
+        let (text, span) = hexdump(0x12, b"PNG\r\n", 0x13..0x15);
+        assert_eq!(
+            text,
+            "00000010        50 4e 47 0d 0a                             |  PNG..         |\n"
+        );
+        assert_eq!(&text[span], "4e 47");
+    }
+
+    #[test]
+    fn test_render_bad_crc() {
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(
+                ChunkType::from_str("tEXt").unwrap(),
+                b"Comment\0hi".to_vec(),
+            ),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ]);
+        let mut bytes = png.as_bytes();
+        let crc = bytes.len() - 12 - 4;
+        bytes[crc] ^= 0xff;
+        let error = Png::try_from(bytes.as_slice()).err().unwrap();
+        let rendered = render(&error, GraphicalTheme::unicode_nocolor()).unwrap();
+        assert!(rendered.contains("in chunk #1 `tEXt` at offset 0x21"));
+        assert!(rendered.contains("bytes 0x33 to 0x36"));
+        assert!(rendered.contains("00000030 "));
+    }
+
+    #[test]
+    fn test_render_other_errors() {
+        let error: crate::Error = Box::new(std::io::Error::other("gone"));
+        assert!(render(&error, GraphicalTheme::unicode_nocolor()).is_none());
+    }
+}
@@ -0,0 +1,155 @@
+//! Digests of chunks, for telling when images carry the same chunks.
+//!
+//! A chunk's digest covers only its data. A structural digest covers every
+//! chunk's type and data but not the order of the chunks, so two PNGs that
+//! hold the same chunks in a different order have the same structural digest.
+//! A pixel data digest covers only what the image looks like, so it stays the
+//! same however the metadata changes. Data after IEND isn't part of any of
+//! them.
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use sha2::{Digest, Sha256};
+
+/// A hash function to compute digests with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha256,
+    Blake3,
+}
+
+impl Algorithm {
+    /// The digest of `data`. Both algorithms produce 32 bytes.
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::Sha256 => Sha256::digest(data).to_vec(),
+            Algorithm::Blake3 => blake3::hash(data).as_bytes().to_vec(),
+        }
+    }
+}
+
+/// The digest of a chunk's data.
+pub fn chunk_digest(algorithm: Algorithm, chunk: &Chunk) -> Vec<u8> {
+    algorithm.digest(chunk.data())
+}
+
+/// A digest of every chunk in `png` that doesn't depend on their order.
+pub fn structural_digest(algorithm: Algorithm, png: &Png) -> Vec<u8> {
+    let mut digests: Vec<Vec<u8>> = png
+        .chunks()
+        .iter()
+        .map(|chunk| {
+            let mut typed = chunk.chunk_type().bytes().to_vec();
+            typed.extend_from_slice(chunk.data());
+            algorithm.digest(&typed)
+        })
+        .collect();
+    digests.sort();
+    algorithm.digest(&digests.concat())
+}
+
+/// A digest of the chunks that make up the image itself: IHDR's data, then
+/// PLTE's data (if there is one), each after its type and length, then the
+/// data of every IDAT chunk joined together. How the image data is divided
+/// into IDAT chunks doesn't matter, and neither does any ancillary chunk.
+pub fn pixel_data_digest(algorithm: Algorithm, png: &Png) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for chunk_type in [ChunkType::IHDR, ChunkType::PLTE] {
+        if let Some(chunk) = png.chunk_by_type(chunk_type) {
+            bytes.extend_from_slice(&chunk_type.bytes());
+            bytes.extend_from_slice(&chunk.length().to_be_bytes());
+            bytes.extend_from_slice(chunk.data());
+        }
+    }
+    bytes.extend_from_slice(&ChunkType::IDAT.bytes());
+    for chunk in png
+        .chunks()
+        .iter()
+        .filter(|c| c.chunk_type() == &ChunkType::IDAT)
+    {
+        bytes.extend_from_slice(chunk.data());
+    }
+    algorithm.digest(&bytes)
+}
+
+/// Format a digest as lowercase hex.
+pub fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunks() -> Vec<Chunk> {
+        vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"Secret".to_vec()),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]
+    }
+
+    #[test]
+    fn test_chunk_digest() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"abc".to_vec());
+        assert_eq!(
+            to_hex(&chunk_digest(Algorithm::Sha256, &chunk)),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            to_hex(&chunk_digest(Algorithm::Blake3, &chunk)),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+    }
+
+    #[test]
+    fn test_structural_digest_ignores_order() {
+        let png = Png::from_chunks(chunks());
+        let mut reordered = chunks();
+        reordered.swap(0, 1);
+        let reordered = Png::from_chunks(reordered);
+        for algorithm in [Algorithm::Sha256, Algorithm::Blake3] {
+            assert_eq!(
+                structural_digest(algorithm, &png),
+                structural_digest(algorithm, &reordered)
+            );
+        }
+    }
+
+    #[test]
+    fn test_pixel_data_digest_ignores_metadata() {
+        let image = |chunks: Vec<Chunk>| {
+            let mut all = vec![Chunk::new(ChunkType::IHDR, vec![0; 13])];
+            all.extend(chunks);
+            all.push(Chunk::new(ChunkType::IEND, vec![]));
+            Png::from_chunks(all)
+        };
+        let png = image(vec![Chunk::new(ChunkType::IDAT, b"pixels".to_vec())]);
+        let other = image(vec![
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"Secret".to_vec()),
+            Chunk::new(ChunkType::IDAT, b"pix".to_vec()),
+            Chunk::new(ChunkType::IDAT, b"els".to_vec()),
+        ]);
+        assert_eq!(png.pixel_data_digest(), other.pixel_data_digest());
+        let changed = image(vec![Chunk::new(ChunkType::IDAT, b"pixelz".to_vec())]);
+        assert_ne!(png.pixel_data_digest(), changed.pixel_data_digest());
+        let paletted = image(vec![
+            Chunk::new(ChunkType::PLTE, vec![0; 3]),
+            Chunk::new(ChunkType::IDAT, b"pixels".to_vec()),
+        ]);
+        assert_ne!(png.pixel_data_digest(), paletted.pixel_data_digest());
+    }
+
+    #[test]
+    fn test_structural_digest_covers_types() {
+        let png = Png::from_chunks(chunks());
+        let mut retyped = chunks();
+        retyped[1] = Chunk::new(ChunkType::from_str("coDe").unwrap(), b"Secret".to_vec());
+        let retyped = Png::from_chunks(retyped);
+        assert_ne!(
+            structural_digest(Algorithm::Sha256, &png),
+            structural_digest(Algorithm::Sha256, &retyped)
+        );
+    }
+}
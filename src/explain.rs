@@ -0,0 +1,84 @@
+//! Explaining a chunk type letter by letter: what each byte is, and what the
+//! case of each letter says about the chunk.
+//!
+//! The four property bits are bit 5 of each byte, the one that makes a letter
+//! lowercase. See [the PNG
+//! spec](http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html#Chunk-naming-conventions).
+use pngme::ChunkType;
+
+/// What bit 5 of each byte means, for when it's 0 (uppercase) and when it's 1
+/// (lowercase).
+const PROPERTIES: [(&str, &str, &str); 4] = [
+    (
+        "Ancillary bit",
+        "critical, so decoders have to understand it to show the image",
+        "ancillary, so decoders that don't understand it can skip it",
+    ),
+    (
+        "Private bit",
+        "public, so it's in the PNG spec or registered with it",
+        "private, so it's for some application's own use",
+    ),
+    (
+        "Reserved bit",
+        "as the spec requires for now",
+        "which the spec doesn't allow yet",
+    ),
+    (
+        "Safe-to-copy bit",
+        "unsafe to copy, so editors that change critical chunks have to drop it if they don't understand it",
+        "safe to copy, so editors can keep it whatever else they change",
+    ),
+];
+
+/// The explanation of `chunk_type`, one line per byte, then what it holds if
+/// pngme knows.
+pub fn explain(chunk_type: ChunkType) -> String {
+    let mut lines = Vec::new();
+    for (byte, (bit, upper, lower)) in chunk_type.bytes().iter().zip(&PROPERTIES) {
+        let set = byte & (1 << 5) != 0;
+        lines.push(format!(
+            "{}  {:3}  0x{:02x}  {:08b}  {} is {}: {}",
+            *byte as char,
+            byte,
+            byte,
+            byte,
+            bit,
+            u8::from(set),
+            if set { lower } else { upper },
+        ));
+    }
+    if !chunk_type.is_reserved_bit_valid() {
+        lines.push("This isn't a valid chunk type, since its reserved bit is set".to_string());
+    }
+    if let Some(description) = chunk_type.description() {
+        lines.push(format!(
+            "{} is a known chunk type: {}",
+            chunk_type, description
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_explain() {
+        let explanation = explain(ChunkType::from_str("ruSt").unwrap());
+        let lines: Vec<&str> = explanation.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("r  114  0x72  01110010  Ancillary bit is 1: ancillary"));
+        assert!(lines[1].contains("Private bit is 1: private"));
+        assert!(lines[2].contains("Reserved bit is 0"));
+        assert!(lines[3].contains("Safe-to-copy bit is 1: safe to copy"));
+
+        let explanation = explain(ChunkType::IHDR);
+        assert!(explanation.contains("critical"));
+        assert!(explanation.ends_with("IHDR is a known chunk type: Image header: width, height, bit depth, color type, and interlacing"));
+
+        assert!(explain(ChunkType::from_str("rust").unwrap()).contains("isn't a valid chunk type"));
+    }
+}
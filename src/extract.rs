@@ -0,0 +1,228 @@
+//! Unpacking a decoded message that is a tar or zip archive, for
+//! `decode --extract`.
+use flate2::read::GzDecoder;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+/// A kind of archive that a payload can be unpacked from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    Tar,
+    /// A tar archive compressed with gzip.
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    /// Work out what kind of archive `payload` is from its first few bytes,
+    /// or return `None` if it isn't one.
+    pub fn sniff(payload: &[u8]) -> Option<Self> {
+        if payload.starts_with(b"PK\x03\x04") || payload.starts_with(b"PK\x05\x06") {
+            Some(ArchiveKind::Zip)
+        } else if is_tar(payload) {
+            Some(ArchiveKind::Tar)
+        } else if payload.starts_with(&[0x1f, 0x8b]) {
+            // Only the start of the decompressed data is needed to tell.
+            let mut start = Vec::new();
+            GzDecoder::new(payload)
+                .take(512)
+                .read_to_end(&mut start)
+                .ok()?;
+            if is_tar(&start) {
+                Some(ArchiveKind::TarGz)
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    }
+}
+
+/// Tar headers have "ustar" at offset 257.
+fn is_tar(data: &[u8]) -> bool {
+    data.get(257..262) == Some(b"ustar")
+}
+
+/// Unpack the archive in `payload` into `dir`, creating it if needed, and
+/// return how many files were unpacked. Entries that would end up outside
+/// `dir` are skipped.
+pub fn extract(payload: &[u8], dir: &Path) -> crate::Result<usize> {
+    let kind = ArchiveKind::sniff(payload).ok_or(NotAnArchiveError)?;
+    fs::create_dir_all(dir)?;
+    match kind {
+        ArchiveKind::Zip => extract_zip(payload, dir),
+        ArchiveKind::Tar => extract_tar(payload, dir),
+        ArchiveKind::TarGz => extract_tar(GzDecoder::new(payload), dir),
+    }
+}
+
+fn extract_tar<R: Read>(reader: R, dir: &Path) -> crate::Result<usize> {
+    let mut archive = tar::Archive::new(reader);
+    let mut count = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let is_dir = entry.header().entry_type().is_dir();
+        if entry.unpack_in(dir)? && !is_dir {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// `ZipArchive::extract` gives up on the whole archive at the first unsafe
+/// path, so unpack entry by entry to skip them the way `unpack_in` does.
+fn extract_zip(payload: &[u8], dir: &Path) -> crate::Result<usize> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(payload))?;
+    let mut count = 0;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let path = match file.enclosed_name() {
+            Some(name) => dir.join(name),
+            None => continue,
+        };
+        if file.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut file, &mut fs::File::create(&path)?)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// `decode --extract` was given a payload that isn't a tar or zip archive.
+#[derive(Debug)]
+pub struct NotAnArchiveError;
+impl fmt::Display for NotAnArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The message is not a tar or zip archive")
+    }
+}
+impl Error for NotAnArchiveError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn tar_archive() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "notes/secret.txt", &b"secret"[..])
+            .unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    /// A tar archive with `notes/secret.txt` and an entry named `path`.
+    /// `Header::set_path` refuses `..`, so the name is written by hand.
+    fn tar_archive_with(path: &str) -> Vec<u8> {
+        let mut builder = tar::Builder::new(tar_archive());
+        let mut header = tar::Header::new_gnu();
+        header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_size(6);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &b"escape"[..]).unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn zip_archive() -> Vec<u8> {
+        zip_archive_with(&[])
+    }
+
+    /// A zip archive with `secret.txt`, then a file for each of `paths`.
+    fn zip_archive_with(paths: &[&str]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        writer.start_file("secret.txt", options).unwrap();
+        writer.write_all(b"secret").unwrap();
+        for path in paths {
+            writer.start_file(*path, options).unwrap();
+            writer.write_all(b"escape").unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(ArchiveKind::sniff(&tar_archive()), Some(ArchiveKind::Tar));
+        assert_eq!(ArchiveKind::sniff(&zip_archive()), Some(ArchiveKind::Zip));
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&tar_archive()).unwrap();
+        assert_eq!(
+            ArchiveKind::sniff(&gz.finish().unwrap()),
+            Some(ArchiveKind::TarGz)
+        );
+        assert_eq!(ArchiveKind::sniff(b"just a message"), None);
+    }
+
+    #[test]
+    fn test_extract_tar() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(extract(&tar_archive(), dir.path()).unwrap(), 1);
+        let contents = fs::read(dir.path().join("notes/secret.txt")).unwrap();
+        assert_eq!(contents, b"secret");
+    }
+
+    #[test]
+    fn test_extract_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(extract(&zip_archive(), dir.path()).unwrap(), 1);
+        assert_eq!(fs::read(dir.path().join("secret.txt")).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_extract_tar_skips_paths_outside_dir() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("out");
+        let archive = tar_archive_with("../escape.txt");
+        assert_eq!(extract(&archive, &dir).unwrap(), 1);
+        assert!(dir.join("notes/secret.txt").exists());
+        assert!(!root.path().join("escape.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_zip_skips_paths_outside_dir() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("out");
+        let archive = zip_archive_with(&["../escape.txt"]);
+        assert_eq!(extract(&archive, &dir).unwrap(), 1);
+        assert_eq!(fs::read(dir.join("secret.txt")).unwrap(), b"secret");
+        assert!(!root.path().join("escape.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_zip_counts_files_only() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        writer.add_directory("notes/", options).unwrap();
+        writer.start_file("notes/secret.txt", options).unwrap();
+        writer.write_all(b"secret").unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(extract(&archive, dir.path()).unwrap(), 1);
+        assert!(dir.path().join("notes/secret.txt").exists());
+    }
+
+    #[test]
+    fn test_not_an_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        assert!(extract(b"just a message", &out).is_err());
+        assert!(!out.exists());
+    }
+}
@@ -0,0 +1,286 @@
+//! Payloads made of named fields, like `author=gabe` and `build=1234`.
+//!
+//! The fields are stored as a [CBOR](https://www.rfc-editor.org/rfc/rfc8949)
+//! map from text to text, in the deterministic encoding from section 4.2.1 of
+//! the RFC: lengths are as short as they can be, and the keys are sorted by
+//! their encoded bytes. The same fields always make the same bytes, so
+//! payloads can be compared and signed, and any CBOR library can read them.
+//!
+//! Values are always text. `build=1234` stores the text `"1234"`, not a
+//! number, so that a value always reads back exactly as it was given.
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+/// CBOR's major type for text strings.
+const TEXT: u8 = 3;
+/// CBOR's major type for maps.
+const MAP: u8 = 5;
+
+/// A set of named fields, each with a text value.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Fields {
+    fields: BTreeMap<String, String>,
+}
+
+impl Fields {
+    /// No fields at all.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the field `name` to `value`, replacing any value it had.
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.fields.insert(name.to_string(), value.to_string());
+    }
+
+    /// The value of the field `name`, if there is one.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+
+    /// Like [get](#method.get), but returns `Err` if there's no field `name`.
+    pub fn require(&self, name: &str) -> crate::Result<&str> {
+        self.get(name).ok_or_else(|| {
+            FieldsError::boxed(format!("There's no field named '{}'", name)) as crate::Error
+        })
+    }
+
+    /// Every field and its value, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// The fields as deterministically encoded CBOR.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut entries: Vec<(Vec<u8>, Vec<u8>)> = self
+            .fields
+            .iter()
+            .map(|(name, value)| {
+                let (mut key, mut encoded) = (Vec::new(), Vec::new());
+                write_text(&mut key, name);
+                write_text(&mut encoded, value);
+                (key, encoded)
+            })
+            .collect();
+        entries.sort();
+        let mut bytes = Vec::new();
+        write_head(&mut bytes, MAP, entries.len() as u64);
+        for (key, value) in entries {
+            bytes.extend(key);
+            bytes.extend(value);
+        }
+        bytes
+    }
+
+    /// Read fields from CBOR. Any map from text to text is accepted, whatever
+    /// order its keys are in, but not a map with a key in it twice, or
+    /// anything after the map.
+    pub fn from_cbor(bytes: &[u8]) -> crate::Result<Self> {
+        let mut reader = Reader { bytes, position: 0 };
+        let length = reader.head(MAP)?;
+        let mut fields = Fields::new();
+        for _ in 0..length {
+            let name = reader.text()?;
+            let value = reader.text()?;
+            if fields.fields.insert(name.clone(), value).is_some() {
+                return Err(FieldsError::boxed(format!(
+                    "'{}' is in the fields twice",
+                    name
+                )));
+            }
+        }
+        if reader.position != bytes.len() {
+            return Err(FieldsError::boxed(
+                "There's more after the fields".to_string(),
+            ));
+        }
+        Ok(fields)
+    }
+}
+
+impl fmt::Display for Fields {
+    /// One `name: value` line per field.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in self.iter() {
+            writeln!(f, "{}: {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the head of a CBOR item: its major type and its argument (a length,
+/// here), in as few bytes as the argument fits in.
+fn write_head(bytes: &mut Vec<u8>, major: u8, argument: u64) {
+    let major = major << 5;
+    if argument < 24 {
+        bytes.push(major | argument as u8);
+    } else if let Ok(argument) = u8::try_from(argument) {
+        bytes.extend_from_slice(&[major | 24, argument]);
+    } else if let Ok(argument) = u16::try_from(argument) {
+        bytes.push(major | 25);
+        bytes.extend_from_slice(&argument.to_be_bytes());
+    } else if let Ok(argument) = u32::try_from(argument) {
+        bytes.push(major | 26);
+        bytes.extend_from_slice(&argument.to_be_bytes());
+    } else {
+        bytes.push(major | 27);
+        bytes.extend_from_slice(&argument.to_be_bytes());
+    }
+}
+
+fn write_text(bytes: &mut Vec<u8>, text: &str) {
+    write_head(bytes, TEXT, text.len() as u64);
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+/// Reads CBOR items from the start of some bytes.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> crate::Result<&[u8]> {
+        let taken = self
+            .position
+            .checked_add(n)
+            .and_then(|end| self.bytes.get(self.position..end))
+            .ok_or_else(|| FieldsError::boxed("The fields are truncated".to_string()))?;
+        self.position += n;
+        Ok(taken)
+    }
+
+    /// Read the head of an item that has to be of the `major` type, and
+    /// return its argument.
+    fn head(&mut self, major: u8) -> crate::Result<u64> {
+        let initial = self.take(1)?[0];
+        if initial >> 5 != major {
+            return Err(FieldsError::boxed(format!(
+                "Expected a {}, not CBOR major type {}",
+                if major == MAP { "map" } else { "text string" },
+                initial >> 5
+            )));
+        }
+        let argument = match initial & 0x1f {
+            n @ 0..=23 => u64::from(n),
+            24 => u64::from(self.take(1)?[0]),
+            n @ 25..=27 => {
+                let mut buffer = [0; 8];
+                let length = 1 << (n - 24);
+                buffer[8 - length..].copy_from_slice(self.take(length)?);
+                u64::from_be_bytes(buffer)
+            }
+            _ => {
+                return Err(FieldsError::boxed(
+                    "Indefinite-length items aren't supported".to_string(),
+                ))
+            }
+        };
+        Ok(argument)
+    }
+
+    fn text(&mut self) -> crate::Result<String> {
+        let length = usize::try_from(self.head(TEXT)?)?;
+        let text = self.take(length)?;
+        String::from_utf8(text.to_vec())
+            .map_err(|_| FieldsError::boxed("A field isn't valid UTF-8".to_string()).into())
+    }
+}
+
+/// A payload can't be read as fields.
+#[derive(Debug)]
+pub struct FieldsError {
+    /// Why the payload isn't fields.
+    reason: String,
+}
+impl FieldsError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for FieldsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad fields: {}", self.reason)
+    }
+}
+impl Error for FieldsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(pairs: &[(&str, &str)]) -> Fields {
+        let mut fields = Fields::new();
+        for (name, value) in pairs {
+            fields.insert(name, value);
+        }
+        fields
+    }
+
+    #[test]
+    fn test_to_cbor() {
+        let fields = fields(&[("build", "1234"), ("a", "gabe")]);
+        // {"a": "gabe", "build": "1234"}, with the shorter key first.
+        let mut expected = vec![0xa2, 0x61, b'a', 0x64];
+        expected.extend_from_slice(b"gabe");
+        expected.push(0x65);
+        expected.extend_from_slice(b"build");
+        expected.push(0x64);
+        expected.extend_from_slice(b"1234");
+        assert_eq!(fields.to_cbor(), expected);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let long = "x".repeat(300);
+        let fields = fields(&[("author", "gabe"), ("notes", &long), ("empty", "")]);
+        let cbor = fields.to_cbor();
+        assert_eq!(Fields::from_cbor(&cbor).unwrap(), fields);
+        assert_eq!(fields.get("notes"), Some(long.as_str()));
+        assert_eq!(fields.require("author").unwrap(), "gabe");
+        assert!(fields.require("build").is_err());
+        assert_eq!(
+            Fields::from_cbor(&Fields::new().to_cbor()).unwrap().len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let fields = fields(&[("build", "1234"), ("author", "gabe")]);
+        assert_eq!(fields.to_string(), "author: gabe\nbuild: 1234\n");
+    }
+
+    #[test]
+    fn test_bad_cbor() {
+        for bytes in [
+            &b""[..],
+            b"plain text",
+            // A map with a number for a key.
+            &[0xa1, 0x01, 0x61, b'a'],
+            // A key with no value.
+            &[0xa1, 0x61, b'a'],
+            // The same key twice.
+            &[0xa2, 0x61, b'a', 0x60, 0x61, b'a', 0x60],
+            // An indefinite-length map.
+            &[0xbf, 0xff],
+            // Something after the map.
+            &[0xa0, 0x00],
+            // A length far past the end.
+            &[0xa1, 0x7b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+        ] {
+            assert!(Fields::from_cbor(bytes).is_err(), "{:?}", bytes);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Run `command` in the shell, feeding it `input` on stdin, and return what
+/// it printed to stdout. Returns `Err` if the command can't be started or
+/// exits unsuccessfully.
+pub fn run(command: &str, input: &[u8]) -> crate::Result<Vec<u8>> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    // Write stdin on another thread so that a command that produces lots of
+    // output before reading all of its input can't deadlock us.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_vec();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output()?;
+    // A command that exits without reading all its input closes the pipe,
+    // which is fine as long as it succeeded.
+    let _ = writer.join();
+    if !output.status.success() {
+        return Err(Box::new(FilterError {
+            command: command.to_string(),
+            status: output.status.to_string(),
+        }));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// A filter command failed.
+#[derive(Debug)]
+pub struct FilterError {
+    command: String,
+    status: String,
+}
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Filter `{}` failed ({})", self.command, self.status)
+    }
+}
+impl Error for FilterError {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        assert_eq!(run("tr a-z A-Z", b"secret").unwrap(), b"SECRET");
+    }
+
+    #[test]
+    fn test_large_input() {
+        let input = vec![b'a'; 1 << 20];
+        assert_eq!(run("cat", &input).unwrap(), input);
+    }
+
+    #[test]
+    fn test_failing_command() {
+        assert!(run("exit 3", b"secret").is_err());
+    }
+}
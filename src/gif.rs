@@ -0,0 +1,378 @@
+//! Hiding payloads in GIF files instead of PNGs.
+//!
+//! After its header, screen descriptor, and color table, a GIF is a list of
+//! blocks: images, and extensions that hold everything else, like comments
+//! and animation settings. Their data is in sub-blocks of at most 255 bytes.
+//! Payloads are kept in application extensions, which decoders skip when they
+//! don't know the application: the identifier `PNGmeMSG` and authentication
+//! code `1.0`, then the 4-byte chunk type and the payload.
+use crate::carrier::{Format, MetadataCarrier};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::ChunkNotFoundError;
+use std::convert::TryFrom;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+/// The labels of the extensions GIF89a defines.
+pub const PLAIN_TEXT: u8 = 0x01;
+pub const GRAPHIC_CONTROL: u8 = 0xf9;
+pub const COMMENT: u8 = 0xfe;
+pub const APPLICATION: u8 = 0xff;
+
+/// What an extension starts with.
+const EXTENSION_INTRODUCER: u8 = 0x21;
+/// What an image starts with.
+const IMAGE_SEPARATOR: u8 = 0x2c;
+/// What the file ends with.
+const TRAILER: u8 = 0x3b;
+
+/// The application identifier and authentication code of a payload's
+/// extension.
+const APPLICATION_ID: &[u8; 11] = b"PNGmeMSG1.0";
+
+/// Whether `bytes` look like the start of a GIF.
+pub fn is_gif(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a")
+}
+
+/// An image or extension in a GIF.
+pub enum Block {
+    Extension {
+        label: u8,
+        sub_blocks: Vec<Vec<u8>>,
+    },
+    Image {
+        /// The image's position, size, and flags, and its own color table if
+        /// it has one.
+        descriptor: Vec<u8>,
+        minimum_code_size: u8,
+        sub_blocks: Vec<Vec<u8>>,
+    },
+}
+
+impl Block {
+    /// The chunk type and data of the payload this block holds, if it holds
+    /// one.
+    pub fn payload(&self) -> Option<Chunk> {
+        let sub_blocks = match self {
+            Block::Extension {
+                label: APPLICATION,
+                sub_blocks,
+            } if sub_blocks.first().map(Vec::as_slice) == Some(APPLICATION_ID) => sub_blocks,
+            _ => return None,
+        };
+        let data: Vec<u8> = sub_blocks[1..].concat();
+        let bytes: [u8; 4] = data.get(..4)?.try_into().unwrap();
+        let chunk_type = ChunkType::try_from(bytes).ok()?;
+        Some(Chunk::new(chunk_type, data[4..].to_vec()))
+    }
+
+    /// The name of this kind of block, like `Comment` or `Image`.
+    pub fn name(&self) -> String {
+        match self {
+            Block::Image { .. } => "Image".to_string(),
+            Block::Extension { label, .. } => match *label {
+                PLAIN_TEXT => "Plain text".to_string(),
+                GRAPHIC_CONTROL => "Graphic control".to_string(),
+                COMMENT => "Comment".to_string(),
+                APPLICATION => "Application".to_string(),
+                label => format!("Extension 0x{:02x}", label),
+            },
+        }
+    }
+
+    fn sub_blocks(&self) -> &[Vec<u8>] {
+        match self {
+            Block::Extension { sub_blocks, .. } | Block::Image { sub_blocks, .. } => sub_blocks,
+        }
+    }
+
+    fn write_to(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Block::Extension { label, .. } => {
+                bytes.extend_from_slice(&[EXTENSION_INTRODUCER, *label])
+            }
+            Block::Image {
+                descriptor,
+                minimum_code_size,
+                ..
+            } => {
+                bytes.push(IMAGE_SEPARATOR);
+                bytes.extend_from_slice(descriptor);
+                bytes.push(*minimum_code_size);
+            }
+        }
+        for sub_block in self.sub_blocks() {
+            bytes.push(sub_block.len() as u8);
+            bytes.extend_from_slice(sub_block);
+        }
+        bytes.push(0);
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let length: usize = self.sub_blocks().iter().map(Vec::len).sum();
+        let bytes = format!("{} byte{}", length, if length == 1 { "" } else { "s" });
+        match self {
+            Block::Image { descriptor, .. } => {
+                let width = u16::from_le_bytes([descriptor[4], descriptor[5]]);
+                let height = u16::from_le_bytes([descriptor[6], descriptor[7]]);
+                write!(f, "{}\t{}x{} ({})", self.name(), width, height, bytes)
+            }
+            Block::Extension {
+                label: COMMENT,
+                sub_blocks,
+            } => write!(
+                f,
+                "{}\t{}",
+                self.name(),
+                String::from_utf8_lossy(&sub_blocks.concat())
+            ),
+            Block::Extension {
+                label: APPLICATION,
+                sub_blocks,
+            } if sub_blocks.first().map(Vec::len) == Some(11) => write!(
+                f,
+                "{}\t{} ({})",
+                self.name(),
+                String::from_utf8_lossy(&sub_blocks[0]),
+                bytes
+            ),
+            _ => write!(f, "{}\t[{}]", self.name(), bytes),
+        }
+    }
+}
+
+/// A GIF, as its blocks.
+pub struct Gif {
+    /// The header, the logical screen descriptor, and the global color table,
+    /// if there is one.
+    header: Vec<u8>,
+    blocks: Vec<Block>,
+    /// Anything after the trailer.
+    trailing_data: Vec<u8>,
+}
+
+impl TryFrom<&[u8]> for Gif {
+    type Error = crate::Error;
+
+    fn try_from(bytes: &[u8]) -> crate::Result<Self> {
+        if !is_gif(bytes) {
+            return Err(GifError::boxed(
+                "It doesn't start with a GIF87a or GIF89a header".to_string(),
+            ));
+        }
+        let cut_off = || GifError::boxed("It ends before the trailer".to_string()) as crate::Error;
+        let flags = *bytes.get(10).ok_or_else(cut_off)?;
+        let mut i = 13 + color_table_length(flags);
+        let header = bytes.get(..i).ok_or_else(cut_off)?.to_vec();
+        let mut blocks = Vec::new();
+        loop {
+            match *bytes.get(i).ok_or_else(cut_off)? {
+                TRAILER => {
+                    return Ok(Gif {
+                        header,
+                        blocks,
+                        trailing_data: bytes[i + 1..].to_vec(),
+                    })
+                }
+                EXTENSION_INTRODUCER => {
+                    let label = *bytes.get(i + 1).ok_or_else(cut_off)?;
+                    let (sub_blocks, end) = read_sub_blocks(bytes, i + 2)?;
+                    blocks.push(Block::Extension { label, sub_blocks });
+                    i = end;
+                }
+                IMAGE_SEPARATOR => {
+                    let flags = *bytes.get(i + 9).ok_or_else(cut_off)?;
+                    let end = i + 10 + color_table_length(flags);
+                    let descriptor = bytes.get(i + 1..end).ok_or_else(cut_off)?.to_vec();
+                    let minimum_code_size = *bytes.get(end).ok_or_else(cut_off)?;
+                    let (sub_blocks, end) = read_sub_blocks(bytes, end + 1)?;
+                    blocks.push(Block::Image {
+                        descriptor,
+                        minimum_code_size,
+                        sub_blocks,
+                    });
+                    i = end;
+                }
+                byte => {
+                    return Err(GifError::boxed(format!(
+                        "Expected a block at byte {}, not 0x{:02x}",
+                        i, byte
+                    )))
+                }
+            }
+        }
+    }
+}
+
+/// The length of the color table that a screen or image descriptor with
+/// `flags` says follows it.
+fn color_table_length(flags: u8) -> usize {
+    if flags & 0x80 == 0 {
+        0
+    } else {
+        3 << ((flags & 0x07) + 1)
+    }
+}
+
+/// The sub-blocks starting at `start`, and where the block after them
+/// starts.
+fn read_sub_blocks(bytes: &[u8], start: usize) -> crate::Result<(Vec<Vec<u8>>, usize)> {
+    let mut sub_blocks = Vec::new();
+    let mut i = start;
+    loop {
+        let length = match bytes.get(i) {
+            Some(0) => return Ok((sub_blocks, i + 1)),
+            Some(&length) => usize::from(length),
+            None => return Err(GifError::boxed("It ends before the trailer".to_string())),
+        };
+        let sub_block = bytes.get(i + 1..i + 1 + length).ok_or_else(|| {
+            GifError::boxed(format!("The sub-block at byte {} is cut off", i)) as crate::Error
+        })?;
+        sub_blocks.push(sub_block.to_vec());
+        i += 1 + length;
+    }
+}
+
+impl Gif {
+    /// Every image and extension, in order.
+    pub fn contents(&self) -> &[Block] {
+        &self.blocks
+    }
+}
+
+impl MetadataCarrier for Gif {
+    fn format(&self) -> Format {
+        Format::Gif
+    }
+
+    /// Every payload, in order.
+    fn blocks(&self) -> crate::Result<Vec<Chunk>> {
+        Ok(self.blocks.iter().filter_map(Block::payload).collect())
+    }
+
+    /// Add a payload of type `chunk_type` after every other block. A GIF87a
+    /// becomes a GIF89a, since that's the version with extensions.
+    fn append_block(&mut self, chunk_type: ChunkType, data: &[u8]) -> crate::Result<()> {
+        let mut sub_blocks = vec![APPLICATION_ID.to_vec()];
+        let mut payload = chunk_type.bytes().to_vec();
+        payload.extend_from_slice(data);
+        sub_blocks.extend(payload.chunks(255).map(<[u8]>::to_vec));
+        self.blocks.push(Block::Extension {
+            label: APPLICATION,
+            sub_blocks,
+        });
+        self.header[..6].copy_from_slice(b"GIF89a");
+        Ok(())
+    }
+
+    /// Remove the `n`th (counting from 0) payload of type `chunk_type`, and
+    /// return it.
+    fn remove_block(&mut self, chunk_type: ChunkType, n: usize) -> crate::Result<Chunk> {
+        let present: Vec<ChunkType> = self.blocks()?.iter().map(|c| *c.chunk_type()).collect();
+        let index = self
+            .blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.payload().is_some_and(|c| c.chunk_type() == &chunk_type))
+            .map(|(i, _)| i)
+            .nth(n)
+            .ok_or_else(|| ChunkNotFoundError::new(chunk_type, n).with_present(present))?;
+        let removed = self.blocks.remove(index);
+        Ok(removed.payload().expect("it was found as a payload"))
+    }
+
+    /// The images and extensions that aren't payloads, and then the
+    /// payloads.
+    fn describe(&self) -> crate::Result<Vec<String>> {
+        let blocks = self.blocks.iter().filter(|b| b.payload().is_none());
+        let payloads = self.blocks()?;
+        Ok(blocks
+            .map(Block::to_string)
+            .chain(payloads.iter().map(Chunk::to_string))
+            .collect())
+    }
+
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header.clone();
+        for block in &self.blocks {
+            block.write_to(&mut bytes);
+        }
+        bytes.push(TRAILER);
+        bytes.extend_from_slice(&self.trailing_data);
+        bytes
+    }
+}
+
+/// A GIF can't be read.
+#[derive(Debug)]
+pub struct GifError {
+    reason: String,
+}
+impl GifError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for GifError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad GIF: {}", self.reason)
+    }
+}
+impl Error for GifError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// A 1x1 GIF87a with a two-color global color table and a comment.
+    fn gif_bytes() -> Vec<u8> {
+        let mut bytes = b"GIF87a\x01\0\x01\0\x80\0\0".to_vec();
+        bytes.extend_from_slice(&[0, 0, 0, 255, 255, 255]);
+        bytes.extend_from_slice(b"\x21\xfe\x05hello\0");
+        bytes.extend_from_slice(b"\x2c\0\0\0\0\x01\0\x01\0\0\x02\x02\x44\x01\0");
+        bytes.push(TRAILER);
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let gif = Gif::try_from(gif_bytes().as_slice()).unwrap();
+        let names: Vec<String> = gif.contents().iter().map(Block::name).collect();
+        assert_eq!(names, vec!["Comment", "Image"]);
+        assert_eq!(gif.contents()[0].to_string(), "Comment\thello");
+        assert_eq!(gif.contents()[1].to_string(), "Image\t1x1 (2 bytes)");
+        assert_eq!(gif.as_bytes(), gif_bytes());
+    }
+
+    #[test]
+    fn test_payloads() {
+        let mut gif = Gif::try_from(gif_bytes().as_slice()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let long = vec![7; 600];
+        gif.append_block(chunk_type, b"first").unwrap();
+        gif.append_block(chunk_type, &long).unwrap();
+        let bytes = gif.as_bytes();
+        assert!(bytes.starts_with(b"GIF89a"));
+
+        let mut gif = Gif::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(gif.block(chunk_type, 1).unwrap().data(), long.as_slice());
+        assert_eq!(gif.remove_block(chunk_type, 0).unwrap().data(), b"first");
+        assert_eq!(gif.blocks().unwrap().len(), 1);
+        assert!(gif.block(chunk_type, 1).is_err());
+    }
+
+    #[test]
+    fn test_not_a_gif() {
+        assert!(Gif::try_from(&b"\x89PNG\r\n\x1a\n"[..]).is_err());
+        let mut truncated = gif_bytes();
+        truncated.pop();
+        assert!(Gif::try_from(truncated.as_slice()).is_err());
+    }
+}
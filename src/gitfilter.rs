@@ -0,0 +1,97 @@
+use crate::watch::StampConfig;
+use pngme::{ChunkType, Png};
+use std::convert::TryFrom;
+
+/// Chunks that change whenever an image is saved, even if the image itself
+/// didn't change.
+const VOLATILE_CHUNK_TYPES: [ChunkType; 1] = [ChunkType::TIME];
+
+/// Prepare a PNG to be committed: remove volatile chunks, the chunks in
+/// `strip`, and this machine's local chunks, so that saving an image again
+/// doesn't show up as a change.
+///
+/// Git hands every file matched by the filter to us, so anything that isn't a
+/// PNG is passed through untouched.
+pub fn clean(
+    input: &[u8],
+    strip: &[ChunkType],
+    local: Option<&StampConfig>,
+) -> crate::Result<Vec<u8>> {
+    let mut png = match Png::try_from(input) {
+        Ok(png) => png,
+        Err(_) => return Ok(input.to_vec()),
+    };
+    let mut unwanted: Vec<ChunkType> = VOLATILE_CHUNK_TYPES.to_vec();
+    unwanted.extend_from_slice(strip);
+    if let Some(local) = local {
+        unwanted.extend(local.chunk_types()?);
+    }
+    png.retain_chunks(|c| !unwanted.contains(c.chunk_type()));
+    Ok(png.as_bytes())
+}
+
+/// Prepare a committed PNG to be checked out: add this machine's local chunks
+/// back. Like [clean], anything that isn't a PNG is passed through untouched.
+pub fn smudge(input: &[u8], local: Option<&StampConfig>) -> crate::Result<Vec<u8>> {
+    let (mut png, local) = match (Png::try_from(input), local) {
+        (Ok(png), Some(local)) => (png, local),
+        _ => return Ok(input.to_vec()),
+    };
+    local.stamp(&mut png)?;
+    Ok(png.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngme::Chunk;
+    use std::str::FromStr;
+
+    const LOCAL: &str = r#"
+        [[chunk]]
+        type = "loCl"
+        message = "Checked out on my laptop"
+    "#;
+
+    fn png_with(types: &[&str]) -> Vec<u8> {
+        let chunks = types
+            .iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), b"data".to_vec()))
+            .collect();
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    fn types_in(bytes: &[u8]) -> Vec<String> {
+        Png::try_from(bytes)
+            .unwrap()
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_clean() {
+        let local = StampConfig::from_toml(LOCAL).unwrap();
+        let input = png_with(&["ruSt", "tIME", "loCl", "biLd"]);
+        let strip = [ChunkType::from_str("biLd").unwrap()];
+        let cleaned = clean(&input, &strip, Some(&local)).unwrap();
+        assert_eq!(types_in(&cleaned), vec!["ruSt"]);
+    }
+
+    #[test]
+    fn test_smudge_then_clean() {
+        let local = StampConfig::from_toml(LOCAL).unwrap();
+        let committed = png_with(&["ruSt"]);
+        let checked_out = smudge(&committed, Some(&local)).unwrap();
+        assert_eq!(types_in(&checked_out), vec!["ruSt", "loCl"]);
+        assert_eq!(clean(&checked_out, &[], Some(&local)).unwrap(), committed);
+    }
+
+    #[test]
+    fn test_not_a_png() {
+        let local = StampConfig::from_toml(LOCAL).unwrap();
+        assert_eq!(clean(b"hello", &[], Some(&local)).unwrap(), b"hello");
+        assert_eq!(smudge(b"hello", Some(&local)).unwrap(), b"hello");
+    }
+}
@@ -0,0 +1,143 @@
+//! ICC color profiles, embedded in an `iCCP` chunk.
+//!
+//! A PNG has at most one `iCCP` chunk, and it has to come before the palette
+//! and the image data. The profile replaces any `sRGB` chunk, since the spec
+//! says a PNG shouldn't have both.
+use crate::chunk_type::ChunkType;
+use crate::codec::{ChunkCodec, IccProfile, IccProfileCodec};
+use crate::png::Png;
+use std::error::Error;
+use std::fmt;
+
+/// Every ICC profile has a 128-byte header, with this signature at byte 36.
+const SIGNATURE: &[u8; 4] = b"acsp";
+const HEADER_LENGTH: usize = 128;
+
+/// The ICC profile in `png`, if it has one. Returns `Err` if the chunk it's
+/// in is damaged.
+pub fn read(png: &Png) -> crate::Result<Option<IccProfile>> {
+    match png.chunk_by_type(ChunkType::ICCP) {
+        Some(chunk) => Ok(Some(IccProfileCodec.decode(chunk.data())?)),
+        None => Ok(None),
+    }
+}
+
+/// Like [read](fn.read.html), but returns `Err` if there's no ICC profile.
+pub fn require(png: &Png) -> crate::Result<IccProfile> {
+    read(png)?.ok_or_else(|| IccError::boxed("There's no iCCP chunk".to_string()) as crate::Error)
+}
+
+/// Put `profile` in `png`, replacing any ICC profile already there. A new
+/// profile goes just before the palette or image data. Returns `Err` if the
+/// profile doesn't have an ICC header, or its name isn't 1 to 79 Latin-1
+/// characters.
+///
+/// Returns whether an `sRGB` chunk was removed to make way for it.
+pub fn write(png: &mut Png, profile: &IccProfile) -> crate::Result<bool> {
+    if profile.profile.len() < HEADER_LENGTH || &profile.profile[36..40] != SIGNATURE {
+        return Err(IccError::boxed(
+            "That isn't an ICC profile: it doesn't have an ICC header".to_string(),
+        ));
+    }
+    let chunk = IccProfileCodec.to_chunk(profile)?;
+    let replaced = |t: &ChunkType| t == &ChunkType::ICCP || t == &ChunkType::SRGB;
+    let had_srgb = png
+        .chunks()
+        .iter()
+        .any(|c| c.chunk_type() == &ChunkType::SRGB);
+    let chunks = png.chunks();
+    let index = chunks
+        .iter()
+        .position(|c| {
+            replaced(c.chunk_type())
+                || c.chunk_type() == &ChunkType::PLTE
+                || c.chunk_type() == &ChunkType::IDAT
+        })
+        .or_else(|| {
+            chunks
+                .iter()
+                .rposition(|c| c.chunk_type() == &ChunkType::IEND)
+        })
+        .unwrap_or(chunks.len());
+    // Nothing before `index` is replaced, so removing the chunks that are
+    // leaves it pointing at the same place.
+    png.retain_chunks(|c| !replaced(c.chunk_type()));
+    png.insert_chunk(index, chunk);
+    Ok(had_srgb)
+}
+
+/// A PNG doesn't have the ICC profile it should, or a profile isn't one.
+#[derive(Debug)]
+pub struct IccError {
+    reason: String,
+}
+impl IccError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for IccError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad ICC profile: {}", self.reason)
+    }
+}
+impl Error for IccError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::testing;
+
+    fn profile(name: &str) -> IccProfile {
+        let mut profile = vec![0; 500];
+        profile[36..40].copy_from_slice(SIGNATURE);
+        IccProfile {
+            name: name.to_string(),
+            profile,
+        }
+    }
+
+    #[test]
+    fn test_write_then_read() {
+        let mut png = testing::minimal_png();
+        assert_eq!(read(&png).unwrap(), None);
+        assert!(require(&png).is_err());
+        assert!(!write(&mut png, &profile("Display P3")).unwrap());
+        assert_eq!(require(&png).unwrap(), profile("Display P3"));
+        assert_eq!(png.chunks()[1].chunk_type(), &ChunkType::ICCP);
+        assert_eq!(png.chunks()[2].chunk_type(), &ChunkType::IDAT);
+    }
+
+    #[test]
+    fn test_write_replaces() {
+        let mut png = testing::minimal_png();
+        png.insert_chunk(1, Chunk::new(ChunkType::SRGB, vec![0]));
+        assert!(write(&mut png, &profile("first")).unwrap());
+        assert!(!write(&mut png, &profile("second")).unwrap());
+        let types: Vec<ChunkType> = png.chunks().iter().map(|c| *c.chunk_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                ChunkType::IHDR,
+                ChunkType::ICCP,
+                ChunkType::IDAT,
+                ChunkType::IEND
+            ]
+        );
+        assert_eq!(require(&png).unwrap().name, "second");
+    }
+
+    #[test]
+    fn test_write_rejects_non_profiles() {
+        let mut png = testing::minimal_png();
+        let not_a_profile = IccProfile {
+            name: "Profile".to_string(),
+            profile: b"not a profile".to_vec(),
+        };
+        assert!(write(&mut png, &not_a_profile).is_err());
+        assert!(write(&mut png, &profile("")).is_err());
+        assert_eq!(read(&png).unwrap(), None);
+    }
+}
@@ -0,0 +1,374 @@
+//! Facts about an image that can be worked out from its chunks, without
+//! decompressing its pixels.
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
+
+/// The contents of the IHDR chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHeader {
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// Bits per sample (or per palette index).
+    pub bit_depth: u8,
+    /// 0 (grayscale), 2 (truecolor), 3 (indexed), 4 (grayscale with alpha),
+    /// or 6 (truecolor with alpha).
+    pub color_type: u8,
+    /// Whether the image uses Adam7 interlacing.
+    pub interlaced: bool,
+}
+
+/// The start and step, in pixels, of each Adam7 pass: (x0, y0, dx, dy).
+const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+impl ImageHeader {
+    /// Read the header from a PNG's IHDR chunk.
+    pub fn from_png(png: &Png) -> crate::Result<Self> {
+        let chunk = png
+            .chunk_by_type(ChunkType::IHDR)
+            .ok_or_else(|| InfoError::boxed("There is no IHDR chunk".to_string()))?;
+        Self::parse(chunk.data())
+    }
+
+    /// Parse the data of an IHDR chunk.
+    pub fn parse(data: &[u8]) -> crate::Result<Self> {
+        if data.len() != 13 {
+            return Err(InfoError::boxed(format!(
+                "IHDR is {} bytes long (expected 13)",
+                data.len()
+            )));
+        }
+        let header = ImageHeader {
+            width: u32::from_be_bytes(data[0..4].try_into()?),
+            height: u32::from_be_bytes(data[4..8].try_into()?),
+            bit_depth: data[8],
+            color_type: data[9],
+            interlaced: data[12] == 1,
+        };
+        if header.channels().is_none() {
+            return Err(InfoError::boxed(format!(
+                "Unknown color type {}",
+                header.color_type
+            )));
+        }
+        Ok(header)
+    }
+
+    /// The number of samples in each pixel, or `None` for an unknown color
+    /// type.
+    pub fn channels(&self) -> Option<u8> {
+        match self.color_type {
+            0 | 3 => Some(1),
+            4 => Some(2),
+            2 => Some(3),
+            6 => Some(4),
+            _ => None,
+        }
+    }
+
+    /// A human-readable name for the color type.
+    pub fn color_type_name(&self) -> &'static str {
+        match self.color_type {
+            0 => "grayscale",
+            2 => "truecolor",
+            3 => "indexed",
+            4 => "grayscale with alpha",
+            6 => "truecolor with alpha",
+            _ => "unknown",
+        }
+    }
+
+    /// How many bits each pixel takes up.
+    pub fn bits_per_pixel(&self) -> u64 {
+        u64::from(self.channels().unwrap_or(0)) * u64::from(self.bit_depth)
+    }
+
+    /// How many bytes the image data takes up once it's decompressed: each
+    /// scanline is one filter-type byte followed by its pixels. Interlaced
+    /// images have a set of scanlines for each of the seven passes.
+    pub fn raw_data_len(&self) -> u64 {
+        if self.interlaced {
+            ADAM7_PASSES
+                .iter()
+                .map(|&(x0, y0, dx, dy)| {
+                    self.scanlines_len(
+                        pass_length(self.width, x0, dx),
+                        pass_length(self.height, y0, dy),
+                    )
+                })
+                .sum()
+        } else {
+            self.scanlines_len(self.width, self.height)
+        }
+    }
+
+    fn scanlines_len(&self, width: u32, height: u32) -> u64 {
+        if width == 0 || height == 0 {
+            return 0;
+        }
+        let row = (u64::from(width) * self.bits_per_pixel()).div_ceil(8);
+        u64::from(height) * (1 + row)
+    }
+}
+
+/// How many pixels of a row (or column) of `length` pixels are in an Adam7
+/// pass that starts at `start` and takes every `step`th pixel.
+fn pass_length(length: u32, start: u32, step: u32) -> u32 {
+    if length > start {
+        (length - start).div_ceil(step)
+    } else {
+        0
+    }
+}
+
+/// A summary of a PNG's image data (IDAT) chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdatSummary {
+    /// How many IDAT chunks there are.
+    pub count: usize,
+    /// The total size of their (compressed) data.
+    pub compressed_len: u64,
+}
+
+impl IdatSummary {
+    /// Summarize the IDAT chunks in `png`.
+    pub fn of(png: &Png) -> Self {
+        let idats = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type() == &ChunkType::IDAT);
+        IdatSummary {
+            count: idats.clone().count(),
+            compressed_len: idats.map(|c| u64::from(c.length())).sum(),
+        }
+    }
+
+    /// Is there more image data than zlib could ever need to store
+    /// `raw_data_len` bytes? Even when zlib can't compress the data at all, it
+    /// only adds a 2-byte header, a 4-byte checksum, and 5 bytes per block of
+    /// at most 65535 bytes, so anything bigger than that is carrying something
+    /// besides the image.
+    pub fn is_oversized(&self, raw_data_len: u64) -> bool {
+        let blocks = raw_data_len.div_ceil(65535).max(1);
+        self.compressed_len > raw_data_len + 2 + 4 + 5 * blocks
+    }
+}
+
+/// How much of a PNG is taken up by ancillary chunks that aren't in the PNG
+/// standard, which is where payloads are hidden. A file that's mostly those
+/// gives away that it's carrying something.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AncillarySummary {
+    /// The total size of the non-standard ancillary chunks, counting each
+    /// one's length, type, and CRC as well as its data.
+    pub nonstandard_len: u64,
+    /// The size of the whole file.
+    pub file_len: u64,
+}
+
+impl AncillarySummary {
+    /// The percentage of a file that non-standard ancillary chunks can take
+    /// up before it's worth a warning.
+    pub const DEFAULT_WARNING_PERCENT: u8 = 30;
+
+    /// Summarize the chunks in `png`.
+    pub fn of(png: &Png) -> Self {
+        let chunks_len = |nonstandard_only: bool| -> u64 {
+            png.chunks()
+                .iter()
+                .filter(|c| !nonstandard_only || is_nonstandard_ancillary(c.chunk_type()))
+                .map(|c| 12 + u64::from(c.length()))
+                .sum()
+        };
+        AncillarySummary {
+            nonstandard_len: chunks_len(true),
+            file_len: Png::STANDARD_HEADER.len() as u64
+                + chunks_len(false)
+                + png.trailing_data().len() as u64,
+        }
+    }
+
+    /// Like [of](#method.of), but reading the PNG from `reader`, and skipping
+    /// over each chunk's data using its length rather than reading it. After
+    /// IEND, anything that isn't a chunk type, or is longer than what's left,
+    /// is taken to be trailing data.
+    pub fn read<R: Read + Seek>(reader: &mut R) -> crate::Result<Self> {
+        let file_len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        Png::read_header(reader)?;
+        let mut position = Png::STANDARD_HEADER.len() as u64;
+        let mut after_iend = false;
+        let mut nonstandard_len = 0;
+        // The length and chunk type of each chunk.
+        let mut prefix = [0u8; 8];
+        while let Ok(()) = reader.read_exact(&mut prefix) {
+            let length = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+            let chunk_len = 12 + u64::from(length);
+            let chunk_type = ChunkType::try_from([prefix[4], prefix[5], prefix[6], prefix[7]]);
+            if after_iend && (chunk_type.is_err() || position + chunk_len > file_len) {
+                break;
+            }
+            let chunk_type = chunk_type?;
+            if is_nonstandard_ancillary(&chunk_type) {
+                nonstandard_len += chunk_len;
+            }
+            after_iend |= chunk_type == ChunkType::IEND;
+            position += chunk_len;
+            reader.seek(SeekFrom::Start(position))?;
+        }
+        Ok(AncillarySummary {
+            nonstandard_len,
+            file_len,
+        })
+    }
+
+    /// What percentage of the file the non-standard ancillary chunks take up.
+    pub fn percentage(&self) -> f64 {
+        if self.file_len == 0 {
+            return 0.0;
+        }
+        100.0 * self.nonstandard_len as f64 / self.file_len as f64
+    }
+
+    /// Do the non-standard ancillary chunks take up more than `percent` of
+    /// the file?
+    pub fn exceeds(&self, percent: u8) -> bool {
+        self.percentage() > f64::from(percent)
+    }
+}
+
+fn is_nonstandard_ancillary(chunk_type: &ChunkType) -> bool {
+    !chunk_type.is_critical() && !chunk_type.is_standard()
+}
+
+/// Something went wrong while reading an image's details.
+#[derive(Debug)]
+pub struct InfoError {
+    /// Why the details could not be read.
+    reason: String,
+}
+impl InfoError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for InfoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad image header: {}", self.reason)
+    }
+}
+impl Error for InfoError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use std::str::FromStr;
+
+    fn header_data(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: u8,
+        interlace: u8,
+    ) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(&width.to_be_bytes());
+        data.extend(&height.to_be_bytes());
+        data.extend(&[bit_depth, color_type, 0, 0, interlace]);
+        data
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let header = ImageHeader::parse(&header_data(100, 50, 8, 6, 0)).unwrap();
+        assert_eq!(header.width, 100);
+        assert_eq!(header.height, 50);
+        assert_eq!(header.channels(), Some(4));
+        assert_eq!(header.color_type_name(), "truecolor with alpha");
+        assert!(!header.interlaced);
+        assert!(ImageHeader::parse(&header_data(1, 1, 8, 5, 0)).is_err());
+        assert!(ImageHeader::parse(b"short").is_err());
+    }
+
+    #[test]
+    fn test_raw_data_len() {
+        // Each row is a filter byte plus 100 RGB pixels.
+        let header = ImageHeader::parse(&header_data(100, 100, 8, 2, 0)).unwrap();
+        assert_eq!(header.raw_data_len(), 100 * (1 + 300));
+        // 1-bit pixels are packed, and rows are rounded up to whole bytes.
+        let header = ImageHeader::parse(&header_data(10, 3, 1, 0, 0)).unwrap();
+        assert_eq!(header.raw_data_len(), 3 * (1 + 2));
+    }
+
+    #[test]
+    fn test_interlaced_raw_data_len() {
+        // A 1x1 image only has a pixel in the first pass.
+        let header = ImageHeader::parse(&header_data(1, 1, 8, 0, 1)).unwrap();
+        assert_eq!(header.raw_data_len(), 2);
+        // An 8x8 grayscale image has 64 pixels spread over 15 scanlines.
+        let header = ImageHeader::parse(&header_data(8, 8, 8, 0, 1)).unwrap();
+        assert_eq!(header.raw_data_len(), 64 + 15);
+    }
+
+    #[test]
+    fn test_idat_summary() {
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, header_data(2, 2, 8, 0, 0)),
+            Chunk::new(ChunkType::IDAT, vec![0; 10]),
+            Chunk::new(ChunkType::IDAT, vec![0; 5]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]);
+        let summary = IdatSummary::of(&png);
+        assert_eq!(summary.count, 2);
+        assert_eq!(summary.compressed_len, 15);
+        let raw = ImageHeader::from_png(&png).unwrap().raw_data_len();
+        assert_eq!(raw, 6);
+        // 6 bytes stored uncompressed take at most 6 + 11 bytes.
+        assert!(!summary.is_oversized(raw));
+        assert!(summary.is_oversized(2));
+    }
+
+    #[test]
+    fn test_ancillary_summary() {
+        let mut png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, header_data(2, 2, 8, 0, 0)),
+            Chunk::new(ChunkType::IDAT, vec![0; 28]),
+            Chunk::new(ChunkType::TEXT, vec![0; 20]),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![0; 28]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]);
+        png.append_trailing_data(&[0; 4]);
+        let summary = AncillarySummary::of(&png);
+        // 8 + 25 + 40 + 32 + 40 + 12 + 4
+        assert_eq!(summary.file_len, 161);
+        assert_eq!(summary.nonstandard_len, 40);
+        assert!(summary.exceeds(20));
+        assert!(!summary.exceeds(30));
+        let read = AncillarySummary::read(&mut std::io::Cursor::new(png.as_bytes())).unwrap();
+        assert_eq!(read, summary);
+
+        // Chunks after IEND count too.
+        let mut bytes = png.as_bytes();
+        bytes.truncate(bytes.len() - 4);
+        bytes.extend(Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![0; 8]).as_bytes());
+        bytes.extend(b"more");
+        let read = AncillarySummary::read(&mut std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(read.nonstandard_len, 60);
+        assert_eq!(read.file_len, 181);
+    }
+}
@@ -0,0 +1,440 @@
+//! Hiding payloads in JPEG files instead of PNGs.
+//!
+//! A JPEG is a list of segments, each a marker and usually a body with a
+//! 2-byte length, up to the compressed image data. Payloads are kept in
+//! `APP15` segments, which no common format uses: the identifier `pngme\0`,
+//! the 4-byte chunk type, the part's number and the number of parts as 2-byte
+//! big-endian numbers, then that part of the payload. A segment can't hold
+//! more than 65,533 bytes, so longer payloads are split across several.
+//!
+//! Everything from the start of the image data on is kept as it is.
+use crate::carrier::{Format, MetadataCarrier};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::ChunkNotFoundError;
+use std::convert::TryFrom;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+/// The bytes every JPEG starts with: the start-of-image marker, and the first
+/// byte of the next one.
+pub const MAGIC: [u8; 3] = [0xff, 0xd8, 0xff];
+
+/// The segment marker payloads are kept in.
+pub const APP15: u8 = 0xef;
+
+/// The marker of a comment segment.
+pub const COM: u8 = 0xfe;
+
+/// The marker of the segment that starts the image data.
+const SOS: u8 = 0xda;
+
+/// What a payload segment's body starts with.
+const IDENTIFIER: &[u8; 6] = b"pngme\0";
+
+/// The identifier, chunk type, part number, and number of parts.
+const PART_HEADER_LENGTH: usize = IDENTIFIER.len() + 4 + 2 + 2;
+
+/// The most payload one segment can hold, after its length and header.
+const MAXIMUM_PART_LENGTH: usize = u16::MAX as usize - 2 - PART_HEADER_LENGTH;
+
+/// Whether `bytes` look like the start of a JPEG.
+pub fn is_jpeg(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// One segment before the image data.
+pub struct Segment {
+    marker: u8,
+    data: Vec<u8>,
+}
+
+impl Segment {
+    /// A segment with marker `marker` (the byte after the `0xff`) and body
+    /// `data`, which has to fit in a segment.
+    fn new(marker: u8, data: Vec<u8>) -> Self {
+        Segment { marker, data }
+    }
+
+    pub fn marker(&self) -> u8 {
+        self.marker
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The marker's usual name, like `APP0` or `DQT`.
+    pub fn name(&self) -> String {
+        match self.marker {
+            0xc0..=0xcf if ![0xc4, 0xc8, 0xcc].contains(&self.marker) => {
+                format!("SOF{}", self.marker - 0xc0)
+            }
+            0xc4 => "DHT".to_string(),
+            0xcc => "DAC".to_string(),
+            0xd0..=0xd7 => format!("RST{}", self.marker - 0xd0),
+            0xdb => "DQT".to_string(),
+            0xdd => "DRI".to_string(),
+            0xe0..=0xef => format!("APP{}", self.marker - 0xe0),
+            COM => "COM".to_string(),
+            marker => format!("0x{:02x}", marker),
+        }
+    }
+
+    /// Whether this segment holds part of a payload.
+    pub fn is_payload(&self) -> bool {
+        self.part().is_some()
+    }
+
+    /// The chunk type, part number, number of parts, and data of the payload
+    /// part this segment holds, if it holds one.
+    fn part(&self) -> Option<(ChunkType, u16, u16, &[u8])> {
+        if self.marker != APP15
+            || self.data.len() < PART_HEADER_LENGTH
+            || !self.data.starts_with(IDENTIFIER)
+        {
+            return None;
+        }
+        let header = &self.data[IDENTIFIER.len()..PART_HEADER_LENGTH];
+        let bytes: [u8; 4] = header[..4].try_into().unwrap();
+        let chunk_type = ChunkType::try_from(bytes).ok()?;
+        let part = u16::from_be_bytes([header[4], header[5]]);
+        let parts = u16::from_be_bytes([header[6], header[7]]);
+        Some((chunk_type, part, parts, &self.data[PART_HEADER_LENGTH..]))
+    }
+
+    /// The identifier an `APPn` segment starts with, like `JFIF` or `Exif`,
+    /// if it has a printable one.
+    fn identifier(&self) -> Option<&str> {
+        let end = self.data.iter().position(|&b| b == 0)?;
+        let identifier = std::str::from_utf8(&self.data[..end]).ok()?;
+        if identifier.is_empty() || !identifier.chars().all(|c| c.is_ascii_graphic()) {
+            return None;
+        }
+        Some(identifier)
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let length = self.data.len();
+        let bytes = format!("{} byte{}", length, if length == 1 { "" } else { "s" });
+        if let Some((chunk_type, part, parts, _)) = self.part() {
+            return write!(
+                f,
+                "{}\tpngme {}, part {} of {} ({})",
+                self.name(),
+                chunk_type,
+                part + 1,
+                parts,
+                bytes
+            );
+        }
+        match (self.marker, self.identifier()) {
+            (COM, _) => write!(f, "COM\t{}", String::from_utf8_lossy(&self.data)),
+            (0xe0..=0xef, Some(identifier)) => {
+                write!(f, "{}\t{} ({})", self.name(), identifier, bytes)
+            }
+            _ => write!(f, "{}\t[{}]", self.name(), bytes),
+        }
+    }
+}
+
+/// A JPEG, as its segments and the image data after them.
+pub struct Jpeg {
+    segments: Vec<Segment>,
+    /// Everything from the start-of-scan marker on.
+    image_data: Vec<u8>,
+}
+
+impl TryFrom<&[u8]> for Jpeg {
+    type Error = crate::Error;
+
+    fn try_from(bytes: &[u8]) -> crate::Result<Self> {
+        if !is_jpeg(bytes) {
+            return Err(JpegError::boxed(
+                "It doesn't start with a JPEG start-of-image marker".to_string(),
+            ));
+        }
+        let mut segments = Vec::new();
+        let mut i = 2;
+        loop {
+            let start = i;
+            if bytes.get(i) != Some(&0xff) {
+                return Err(JpegError::boxed(format!(
+                    "Expected a segment marker at byte {}",
+                    i
+                )));
+            }
+            // Any number of 0xff bytes can pad out the space between segments.
+            while bytes.get(i) == Some(&0xff) {
+                i += 1;
+            }
+            let marker = *bytes.get(i).ok_or_else(|| {
+                JpegError::boxed("It ends before the image data".to_string()) as crate::Error
+            })?;
+            i += 1;
+            // The end-of-image marker, if there's no image data at all, and
+            // the scan itself are kept as they are.
+            if marker == SOS || marker == 0xd9 {
+                return Ok(Jpeg {
+                    segments,
+                    image_data: bytes[start..].to_vec(),
+                });
+            }
+            // These markers stand on their own, without a body.
+            if marker == 0x01 || (0xd0..=0xd7).contains(&marker) {
+                segments.push(Segment::new(marker, Vec::new()));
+                continue;
+            }
+            let length = match bytes.get(i..i + 2) {
+                Some(length) => usize::from(u16::from_be_bytes([length[0], length[1]])),
+                None => {
+                    return Err(JpegError::boxed(
+                        "It ends before the image data".to_string(),
+                    ))
+                }
+            };
+            let data = length
+                .checked_sub(2)
+                .and_then(|length| bytes.get(i + 2..i + 2 + length))
+                .ok_or_else(|| {
+                    JpegError::boxed(format!(
+                        "The {} segment at byte {} has a bad length ({})",
+                        Segment::new(marker, Vec::new()).name(),
+                        start,
+                        length
+                    )) as crate::Error
+                })?;
+            segments.push(Segment::new(marker, data.to_vec()));
+            i += length;
+        }
+    }
+}
+
+impl Jpeg {
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+}
+
+impl MetadataCarrier for Jpeg {
+    fn format(&self) -> Format {
+        Format::Jpeg
+    }
+
+    /// Every payload, in order, as a chunk holding the whole of it.
+    fn blocks(&self) -> crate::Result<Vec<Chunk>> {
+        Ok(self
+            .payload_segments()?
+            .into_iter()
+            .map(|(chunk_type, range)| Chunk::new(chunk_type, self.join(range)))
+            .collect())
+    }
+
+    /// Add a payload of type `chunk_type`, after the `APPn` and comment
+    /// segments at the start, where other metadata is.
+    fn append_block(&mut self, chunk_type: ChunkType, data: &[u8]) -> crate::Result<()> {
+        let parts = data.chunks(MAXIMUM_PART_LENGTH).count().max(1);
+        let parts = u16::try_from(parts).map_err(|_| {
+            JpegError::boxed(format!(
+                "Payload is too long ({} bytes, at most {})",
+                data.len(),
+                usize::from(u16::MAX) * MAXIMUM_PART_LENGTH
+            ))
+        })?;
+        let index = self
+            .segments
+            .iter()
+            .position(|s| !(0xe0..=0xef).contains(&s.marker) && s.marker != COM)
+            .unwrap_or(self.segments.len());
+        let segments = (0..parts).map(|part| {
+            let start = usize::from(part) * MAXIMUM_PART_LENGTH;
+            let end = (start + MAXIMUM_PART_LENGTH).min(data.len());
+            let mut body = IDENTIFIER.to_vec();
+            body.extend_from_slice(&chunk_type.bytes());
+            body.extend_from_slice(&part.to_be_bytes());
+            body.extend_from_slice(&parts.to_be_bytes());
+            body.extend_from_slice(&data[start..end]);
+            Segment::new(APP15, body)
+        });
+        self.segments.splice(index..index, segments);
+        Ok(())
+    }
+
+    /// Remove the `n`th (counting from 0) payload of type `chunk_type`, and
+    /// return it.
+    fn remove_block(&mut self, chunk_type: ChunkType, n: usize) -> crate::Result<Chunk> {
+        let payloads = self.payload_segments()?;
+        let present: Vec<ChunkType> = payloads.iter().map(|(t, _)| *t).collect();
+        let (_, range) = payloads
+            .into_iter()
+            .filter(|(t, _)| t == &chunk_type)
+            .nth(n)
+            .ok_or_else(|| ChunkNotFoundError::new(chunk_type, n).with_present(present))?;
+        let chunk = Chunk::new(chunk_type, self.join(range.clone()));
+        self.segments.drain(range);
+        Ok(chunk)
+    }
+
+    /// The segments that aren't payloads, and then the payloads.
+    fn describe(&self) -> crate::Result<Vec<String>> {
+        let segments = self.segments.iter().filter(|s| !s.is_payload());
+        let payloads = self.blocks()?;
+        Ok(segments
+            .map(Segment::to_string)
+            .chain(payloads.iter().map(Chunk::to_string))
+            .collect())
+    }
+
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0xff, 0xd8];
+        for segment in &self.segments {
+            bytes.extend_from_slice(&[0xff, segment.marker]);
+            if segment.marker != 0x01 && !(0xd0..=0xd7).contains(&segment.marker) {
+                // Only segments that were read or made to fit have bodies.
+                bytes.extend_from_slice(&(segment.data.len() as u16 + 2).to_be_bytes());
+                bytes.extend_from_slice(&segment.data);
+            }
+        }
+        bytes.extend_from_slice(&self.image_data);
+        bytes
+    }
+}
+
+impl Jpeg {
+    /// The chunk type of each payload, and the segments it's in. Returns
+    /// `Err` if a payload's parts are missing or out of order.
+    fn payload_segments(&self) -> crate::Result<Vec<(ChunkType, Range<usize>)>> {
+        let mut payloads = Vec::new();
+        // The payload being read, its number of parts, and where it starts.
+        let mut current: Option<(ChunkType, u16, usize)> = None;
+        for (i, segment) in self.segments.iter().enumerate() {
+            let (chunk_type, part, parts, _) = match segment.part() {
+                Some(part) => part,
+                None => continue,
+            };
+            let start = match current.take() {
+                Some((t, n, start)) if t == chunk_type && n == parts => start,
+                Some((t, _, _)) => return Err(missing_parts(t)),
+                None => i,
+            };
+            if usize::from(part) != i - start || part >= parts {
+                return Err(JpegError::boxed(format!(
+                    "Part {} of {} of the {} payload is out of place",
+                    part + 1,
+                    parts,
+                    chunk_type
+                )));
+            }
+            if part + 1 == parts {
+                payloads.push((chunk_type, start..i + 1));
+                current = None;
+            } else {
+                current = Some((chunk_type, parts, start));
+            }
+        }
+        match current {
+            Some((chunk_type, _, _)) => Err(missing_parts(chunk_type)),
+            None => Ok(payloads),
+        }
+    }
+
+    fn join(&self, range: Range<usize>) -> Vec<u8> {
+        self.segments[range]
+            .iter()
+            .filter_map(|s| s.part())
+            .flat_map(|(_, _, _, data)| data.iter().copied())
+            .collect()
+    }
+}
+
+fn missing_parts(chunk_type: ChunkType) -> crate::Error {
+    JpegError::boxed(format!("The {} payload is missing parts", chunk_type))
+}
+
+/// A JPEG can't be read, or a payload can't be put in it.
+#[derive(Debug)]
+pub struct JpegError {
+    reason: String,
+}
+impl JpegError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for JpegError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad JPEG: {}", self.reason)
+    }
+}
+impl Error for JpegError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// A JPEG with a JFIF header, a comment, a quantization table, and some
+    /// stand-in image data.
+    fn jpeg_bytes() -> Vec<u8> {
+        let mut bytes = vec![0xff, 0xd8];
+        bytes.extend_from_slice(&[0xff, 0xe0, 0, 7]);
+        bytes.extend_from_slice(b"JFIF\0");
+        bytes.extend_from_slice(&[0xff, COM, 0, 7]);
+        bytes.extend_from_slice(b"hello");
+        bytes.extend_from_slice(&[0xff, 0xdb, 0, 4, 1, 2]);
+        bytes.extend_from_slice(&[0xff, SOS, 0, 2, 0x12, 0xff, 0, 0x34, 0xff, 0xd9]);
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let jpeg = Jpeg::try_from(jpeg_bytes().as_slice()).unwrap();
+        let names: Vec<String> = jpeg.segments().iter().map(|s| s.name()).collect();
+        assert_eq!(names, vec!["APP0", "COM", "DQT"]);
+        assert_eq!(jpeg.segments()[1].to_string(), "COM\thello");
+        assert_eq!(jpeg.segments()[0].to_string(), "APP0\tJFIF (5 bytes)");
+        assert_eq!(jpeg.as_bytes(), jpeg_bytes());
+    }
+
+    #[test]
+    fn test_payloads() {
+        let mut jpeg = Jpeg::try_from(jpeg_bytes().as_slice()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        jpeg.append_block(chunk_type, b"first").unwrap();
+        jpeg.append_block(chunk_type, b"second").unwrap();
+        // They go after the comment, before the quantization table.
+        assert_eq!(jpeg.segments()[3].name(), "APP15");
+        assert_eq!(jpeg.segments()[4].name(), "DQT");
+
+        let jpeg = Jpeg::try_from(jpeg.as_bytes().as_slice()).unwrap();
+        assert_eq!(jpeg.block(chunk_type, 1).unwrap().data(), b"second");
+        let mut jpeg = jpeg;
+        assert_eq!(jpeg.remove_block(chunk_type, 0).unwrap().data(), b"first");
+        assert_eq!(jpeg.blocks().unwrap().len(), 1);
+        assert!(jpeg.block(chunk_type, 1).is_err());
+    }
+
+    #[test]
+    fn test_long_payloads_are_split() {
+        let mut jpeg = Jpeg::try_from(jpeg_bytes().as_slice()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let data = vec![7; MAXIMUM_PART_LENGTH * 2 + 1];
+        jpeg.append_block(chunk_type, &data).unwrap();
+        let jpeg = Jpeg::try_from(jpeg.as_bytes().as_slice()).unwrap();
+        assert_eq!(jpeg.segments().iter().filter(|s| s.is_payload()).count(), 3);
+        assert_eq!(jpeg.block(chunk_type, 0).unwrap().data(), data.as_slice());
+    }
+
+    #[test]
+    fn test_not_a_jpeg() {
+        assert!(Jpeg::try_from(&b"\x89PNG\r\n\x1a\n"[..]).is_err());
+        let mut truncated = jpeg_bytes();
+        truncated.truncate(12);
+        assert!(Jpeg::try_from(truncated.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Passphrases kept in the platform's keychain, if pngme was built with the
+//! `keyring` feature: the Keychain on macOS, the Credential Manager on
+//! Windows, and the Secret Service (GNOME Keyring, KWallet) on Linux.
+//!
+//! Each passphrase is stored under the service name given to
+//! `--use-keyring`, for the account `pngme`, so different images can use
+//! different passphrases by using different service names.
+use std::error::Error;
+use std::fmt;
+use zeroize::Zeroizing;
+
+#[cfg(feature = "keyring")]
+mod platform {
+    use super::KeychainError;
+    use keyring::Entry;
+    use zeroize::Zeroizing;
+
+    /// The account that passphrases are stored for.
+    const ACCOUNT: &str = "pngme";
+
+    fn entry(service: &str) -> crate::Result<Entry> {
+        Entry::new(service, ACCOUNT).map_err(|e| error(service, e))
+    }
+
+    fn error(service: &str, error: keyring::Error) -> crate::Error {
+        KeychainError::boxed(format!("{} ({})", error, service))
+    }
+
+    pub fn get(service: &str) -> crate::Result<Option<Zeroizing<String>>> {
+        match entry(service)?.get_password() {
+            Ok(passphrase) => Ok(Some(Zeroizing::new(passphrase))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(error(service, e)),
+        }
+    }
+
+    pub fn store(service: &str, passphrase: &str) -> crate::Result<()> {
+        entry(service)?
+            .set_password(passphrase)
+            .map_err(|e| error(service, e))
+    }
+}
+
+/// Without the `keyring` feature, there's no keychain to use.
+#[cfg(not(feature = "keyring"))]
+mod platform {
+    use super::KeychainError;
+    use zeroize::Zeroizing;
+
+    fn unsupported() -> crate::Error {
+        KeychainError::boxed(
+            "Can't use the keychain, because pngme was built without the keyring feature"
+                .to_string(),
+        )
+    }
+
+    pub fn get(_service: &str) -> crate::Result<Option<Zeroizing<String>>> {
+        Err(unsupported())
+    }
+
+    pub fn store(_service: &str, _passphrase: &str) -> crate::Result<()> {
+        Err(unsupported())
+    }
+}
+
+/// The passphrase stored for `service`, or `None` if there isn't one yet.
+pub fn get(service: &str) -> crate::Result<Option<Zeroizing<String>>> {
+    platform::get(service)
+}
+
+/// Store `passphrase` for `service`, replacing any passphrase already there.
+pub fn store(service: &str, passphrase: &str) -> crate::Result<()> {
+    platform::store(service, passphrase)
+}
+
+/// Store `passphrase` for `service`, like [store](fn.store.html), but only
+/// if `confirmation` (the passphrase typed a second time) matches it, so that
+/// a typo isn't stored and used from then on.
+pub fn store_confirmed(service: &str, passphrase: &str, confirmation: &str) -> crate::Result<()> {
+    if passphrase != confirmation {
+        return Err(KeychainError::boxed(format!(
+            "The passphrases don't match, so neither was stored ({})",
+            service
+        )));
+    }
+    store(service, passphrase)
+}
+
+/// The keychain can't be read or written.
+#[derive(Debug)]
+pub struct KeychainError {
+    reason: String,
+}
+
+impl KeychainError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for KeychainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad keychain: {}", self.reason)
+    }
+}
+
+impl Error for KeychainError {}
+
+#[cfg(all(test, not(feature = "keyring")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_without_feature() {
+        let error = get("pngme-test").unwrap_err();
+        assert!(error.to_string().contains("keyring feature"));
+        assert!(store("pngme-test", "hunter2").is_err());
+    }
+
+    #[test]
+    fn test_store_unconfirmed() {
+        let error = store_confirmed("pngme-test", "hunter2", "hunter3").unwrap_err();
+        assert!(error.to_string().contains("don't match"));
+    }
+}
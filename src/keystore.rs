@@ -0,0 +1,289 @@
+//! A directory of named Ed25519 keypairs, so that signing and verifying don't
+//! need keys to be managed with a separate tool.
+//!
+//! Each key is stored as `<name>.pem` (the PKCS#8 private key, if we have it)
+//! and `<name>.pub.pem` (the public key).
+use crate::signature::{self, SigningKey, VerifyingKey};
+use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
+use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey};
+use rand::RngCore;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The environment variable that overrides where keys are stored.
+pub const KEY_DIR_VARIABLE: &str = "PNGME_KEY_DIR";
+
+/// A directory of named keys.
+pub struct KeyStore {
+    dir: PathBuf,
+}
+
+impl KeyStore {
+    /// Use `dir` as the key store. It is created when the first key is saved.
+    pub fn open<P: Into<PathBuf>>(dir: P) -> Self {
+        KeyStore { dir: dir.into() }
+    }
+
+    /// The key store in `$PNGME_KEY_DIR`, or in `pngme/keys` inside the
+    /// user's configuration directory.
+    pub fn open_default() -> crate::Result<Self> {
+        if let Some(dir) = std::env::var_os(KEY_DIR_VARIABLE) {
+            return Ok(Self::open(dir));
+        }
+        let config = dirs::config_dir().ok_or_else(|| {
+            KeyStoreError::boxed("Could not find a configuration directory".to_string())
+        })?;
+        Ok(Self::open(config.join("pngme").join("keys")))
+    }
+
+    /// Where the keys are stored.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Make a new random keypair and save it under `name`.
+    /// Returns `Err` if there is already a key with that name.
+    pub fn generate(&self, name: &str) -> crate::Result<SigningKey> {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let key = SigningKey::from_bytes(&secret);
+        self.save_signing_key(name, &key)?;
+        Ok(key)
+    }
+
+    /// Save a PEM-encoded key under `name`. A private key is saved along with
+    /// its public key; a public key is saved on its own, which is enough to
+    /// verify signatures.
+    pub fn import_pem(&self, name: &str, pem: &str) -> crate::Result<()> {
+        if let Ok(key) = signature::signing_key_from_pem(pem) {
+            self.save_signing_key(name, &key)
+        } else {
+            let key = signature::verifying_key_from_pem(pem)?;
+            self.check_new(name)?;
+            self.write(
+                &self.public_path(name)?,
+                public_pem(&key)?.as_bytes(),
+                false,
+            )
+        }
+    }
+
+    /// The private key saved under `name`.
+    pub fn signing_key(&self, name: &str) -> crate::Result<SigningKey> {
+        signature::signing_key_from_pem(&self.private_pem(name)?)
+    }
+
+    /// The public key saved under `name`.
+    pub fn verifying_key(&self, name: &str) -> crate::Result<VerifyingKey> {
+        signature::verifying_key_from_pem(&self.public_pem(name)?)
+    }
+
+    /// The PEM-encoded private key saved under `name`.
+    pub fn private_pem(&self, name: &str) -> crate::Result<String> {
+        self.read(&self.private_path(name)?, name, "private key")
+    }
+
+    /// The PEM-encoded public key saved under `name`.
+    pub fn public_pem(&self, name: &str) -> crate::Result<String> {
+        self.read(&self.public_path(name)?, name, "public key")
+    }
+
+    /// The name of every saved key, sorted.
+    pub fn names(&self) -> crate::Result<Vec<String>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut names: Vec<String> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|f| f.strip_suffix(".pub.pem"))
+                    .map(String::from)
+            })
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    fn save_signing_key(&self, name: &str, key: &SigningKey) -> crate::Result<()> {
+        self.check_new(name)?;
+        let private = key
+            .to_pkcs8_pem(LineEnding::LF)
+            .map_err(|e| KeyStoreError::boxed(format!("Could not encode private key: {}", e)))?;
+        self.write(&self.private_path(name)?, private.as_bytes(), true)?;
+        self.write(
+            &self.public_path(name)?,
+            public_pem(&key.verifying_key())?.as_bytes(),
+            false,
+        )
+    }
+
+    fn check_new(&self, name: &str) -> crate::Result<()> {
+        if self.public_path(name)?.exists() || self.private_path(name)?.exists() {
+            Err(KeyStoreError::boxed(format!(
+                "There is already a key named {}",
+                name
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn private_path(&self, name: &str) -> crate::Result<PathBuf> {
+        Ok(self.dir.join(format!("{}.pem", checked_name(name)?)))
+    }
+
+    fn public_path(&self, name: &str) -> crate::Result<PathBuf> {
+        Ok(self.dir.join(format!("{}.pub.pem", checked_name(name)?)))
+    }
+
+    fn read(&self, path: &Path, name: &str, what: &str) -> crate::Result<String> {
+        if !path.exists() {
+            return Err(KeyStoreError::boxed(format!(
+                "No {} named {} in {}",
+                what,
+                name,
+                self.dir.display()
+            )));
+        }
+        Ok(fs::read_to_string(path)?)
+    }
+
+    /// Write a key file. Private keys are only readable by their owner.
+    fn write(&self, path: &Path, contents: &[u8], private: bool) -> crate::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(if private { 0o600 } else { 0o644 });
+        }
+        #[cfg(not(unix))]
+        let _ = private;
+        options.open(path)?.write_all(contents)?;
+        Ok(())
+    }
+}
+
+fn public_pem(key: &VerifyingKey) -> crate::Result<String> {
+    key.to_public_key_pem(LineEnding::LF).map_err(|e| {
+        KeyStoreError::boxed(format!("Could not encode public key: {}", e)) as crate::Error
+    })
+}
+
+/// Key names become file names, so keep them to letters, digits, `-`, and `_`.
+fn checked_name(name: &str) -> crate::Result<&str> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(name)
+    } else {
+        Err(KeyStoreError::boxed(format!(
+            "'{}' is not a valid key name (use letters, digits, '-', and '_')",
+            name
+        )))
+    }
+}
+
+/// Something went wrong while reading or writing the key store.
+#[derive(Debug)]
+pub struct KeyStoreError {
+    /// Why the key store could not be used.
+    reason: String,
+}
+impl KeyStoreError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for KeyStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Key store: {}", self.reason)
+    }
+}
+impl Error for KeyStoreError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyStore::open(dir.path());
+        let key = store.generate("mine").unwrap();
+        assert_eq!(store.signing_key("mine").unwrap(), key);
+        assert_eq!(store.verifying_key("mine").unwrap(), key.verifying_key());
+        assert_eq!(store.names().unwrap(), vec!["mine".to_string()]);
+        assert!(store.generate("mine").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_private_key_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyStore::open(dir.path());
+        store.generate("mine").unwrap();
+        let mode = fs::metadata(dir.path().join("mine.pem"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyStore::open(dir.path().join("first"));
+        let other = KeyStore::open(dir.path().join("second"));
+        let key = store.generate("mine").unwrap();
+
+        other
+            .import_pem("theirs", &store.public_pem("mine").unwrap())
+            .unwrap();
+        assert_eq!(other.verifying_key("theirs").unwrap(), key.verifying_key());
+        assert!(other.signing_key("theirs").is_err());
+
+        other
+            .import_pem("copy", &store.private_pem("mine").unwrap())
+            .unwrap();
+        assert_eq!(other.signing_key("copy").unwrap(), key);
+        assert_eq!(
+            other.names().unwrap(),
+            vec!["copy".to_string(), "theirs".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_import_garbage() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyStore::open(dir.path());
+        assert!(store.import_pem("mine", "not a key").is_err());
+    }
+
+    #[test]
+    fn test_bad_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyStore::open(dir.path());
+        assert!(store.generate("../escape").is_err());
+        assert!(store.generate("").is_err());
+        assert!(store.signing_key("missing").is_err());
+    }
+
+    #[test]
+    fn test_empty_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KeyStore::open(dir.path().join("nothing-here"));
+        assert!(store.names().unwrap().is_empty());
+    }
+}
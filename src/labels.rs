@@ -0,0 +1,234 @@
+//! Hiding payloads under random chunk types, found again by a label.
+//!
+//! Scanning for well-known chunk types like `ruSt` is the first thing anyone
+//! looking for hidden data does. A labelled payload is stored under a randomly
+//! picked chunk type instead, and the mapping from each label to its chunk
+//! type is kept in a single
+//! [LABEL_INDEX](../struct.ChunkType.html#associatedconstant.LABEL_INDEX)
+//! chunk, encrypted with a passphrase.
+//!
+//! Once decrypted, the index is a list of entries, each made of the 4-byte
+//! chunk type, the length of the label as a 2-byte big-endian number, and
+//! then the label itself in UTF-8.
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::transform::{self, Encrypt, KdfParams, Pipeline};
+use rand::Rng;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use zeroize::Zeroizing;
+
+/// The decrypted contents of a label index chunk.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LabelIndex {
+    entries: Vec<(String, ChunkType)>,
+}
+
+impl LabelIndex {
+    /// An index with no labels.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decrypt the index stored in `png`, or return an empty index if there
+    /// isn't one. Returns `Err` if the passphrase is wrong or the index is
+    /// damaged.
+    pub fn from_png(png: &Png, passphrase: &str) -> crate::Result<Self> {
+        match png.chunk_by_type(ChunkType::LABEL_INDEX) {
+            Some(chunk) => Self::from_chunk(chunk, passphrase),
+            None => Ok(Self::new()),
+        }
+    }
+
+    /// Decrypt an index chunk.
+    pub fn from_chunk(chunk: &Chunk, passphrase: &str) -> crate::Result<Self> {
+        let data = Zeroizing::new(Pipeline::open(chunk.data(), |id| {
+            transform::builtin_transform(id, || Ok(passphrase.to_string()))
+        })?);
+        let mut entries = Vec::new();
+        let mut rest = data.as_slice();
+        while !rest.is_empty() {
+            if rest.len() < 6 {
+                return Err(LabelError::boxed("Index entry is truncated".to_string()));
+            }
+            let chunk_type = ChunkType::try_from([rest[0], rest[1], rest[2], rest[3]])?;
+            let length = usize::from(u16::from_be_bytes([rest[4], rest[5]]));
+            let label = rest
+                .get(6..6 + length)
+                .ok_or_else(|| LabelError::boxed("Index entry is truncated".to_string()))?;
+            let label = String::from_utf8(label.to_vec())
+                .map_err(|_| LabelError::boxed("Label is not valid UTF-8".to_string()))?;
+            entries.push((label, chunk_type));
+            rest = &rest[6 + length..];
+        }
+        Ok(LabelIndex { entries })
+    }
+
+    /// The chunk type that `label` is stored under, if it's in the index.
+    pub fn get(&self, label: &str) -> Option<ChunkType> {
+        self.entries
+            .iter()
+            .find(|(l, _)| l == label)
+            .map(|(_, chunk_type)| *chunk_type)
+    }
+
+    /// Like [get](#method.get), but returns `Err` if `label` isn't in the
+    /// index.
+    pub fn require(&self, label: &str) -> crate::Result<ChunkType> {
+        self.get(label).ok_or_else(|| {
+            LabelError::boxed(format!("Nothing is labelled '{}'", label)) as crate::Error
+        })
+    }
+
+    /// Every label in the index, in the order they were added.
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(label, _)| label.as_str())
+    }
+
+    /// The chunk type to store `label` under. A label that's already in the
+    /// index keeps its chunk type; a new one gets a random chunk type that
+    /// isn't used anywhere in `png` or the index.
+    pub fn assign<R: Rng + ?Sized>(
+        &mut self,
+        label: &str,
+        png: &Png,
+        rng: &mut R,
+    ) -> crate::Result<ChunkType> {
+        if let Some(chunk_type) = self.get(label) {
+            return Ok(chunk_type);
+        }
+        if u16::try_from(label.len()).is_err() {
+            return Err(LabelError::boxed(format!(
+                "Label is too long ({} bytes, at most {})",
+                label.len(),
+                u16::MAX
+            )));
+        }
+        let present: Vec<ChunkType> = self
+            .entries
+            .iter()
+            .map(|(_, t)| *t)
+            .chain(png.chunks().iter().map(|c| *c.chunk_type()))
+            .collect();
+        let chunk_type = ChunkType::random_unused(rng, &present);
+        self.entries.push((label.to_string(), chunk_type));
+        Ok(chunk_type)
+    }
+
+    /// Encrypt the index into a chunk.
+    pub fn to_chunk(&self, passphrase: &str, kdf: KdfParams) -> crate::Result<Chunk> {
+        let mut data = Zeroizing::new(Vec::new());
+        for (label, chunk_type) in &self.entries {
+            data.extend_from_slice(&chunk_type.bytes());
+            data.extend_from_slice(&(label.len() as u16).to_be_bytes());
+            data.extend_from_slice(label.as_bytes());
+        }
+        let sealed = Pipeline::new()
+            .then(Encrypt::with_kdf(passphrase.to_string(), kdf))
+            .seal(&data)?;
+        Ok(Chunk::new(ChunkType::LABEL_INDEX, sealed))
+    }
+
+    /// Replace the index stored in `png` (if any) with this one.
+    pub fn write_to(&self, png: &mut Png, passphrase: &str, kdf: KdfParams) -> crate::Result<()> {
+        let chunk = self.to_chunk(passphrase, kdf)?;
+        png.retain_chunks(|c| c.chunk_type() != &ChunkType::LABEL_INDEX);
+        png.append_chunk(chunk);
+        Ok(())
+    }
+}
+
+/// A label index could not be read or changed.
+#[derive(Debug)]
+pub struct LabelError {
+    /// Why the index could not be used.
+    reason: String,
+}
+impl LabelError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for LabelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad label index: {}", self.reason)
+    }
+}
+impl Error for LabelError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The default KDF settings are deliberately slow, so use cheap ones.
+    const KDF: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+    };
+
+    fn png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut png = png();
+        let mut rng = rand::thread_rng();
+        let mut index = LabelIndex::new();
+        let diary = index.assign("diary", &png, &mut rng).unwrap();
+        let keys = index.assign("keys", &png, &mut rng).unwrap();
+        assert_ne!(diary, keys);
+        assert_eq!(index.assign("diary", &png, &mut rng).unwrap(), diary);
+        index.write_to(&mut png, "hunter2", KDF).unwrap();
+
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        let index = LabelIndex::from_png(&png, "hunter2").unwrap();
+        assert_eq!(index.get("diary"), Some(diary));
+        assert_eq!(index.get("keys"), Some(keys));
+        assert_eq!(index.get("other"), None);
+        assert!(index.require("other").is_err());
+        assert_eq!(index.labels().collect::<Vec<_>>(), vec!["diary", "keys"]);
+    }
+
+    #[test]
+    fn test_index_is_replaced() {
+        let mut png = png();
+        let mut index = LabelIndex::new();
+        index.write_to(&mut png, "hunter2", KDF).unwrap();
+        index
+            .assign("diary", &png, &mut rand::thread_rng())
+            .unwrap();
+        index.write_to(&mut png, "hunter2", KDF).unwrap();
+        let indexes = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type() == &ChunkType::LABEL_INDEX)
+            .count();
+        assert_eq!(indexes, 1);
+    }
+
+    #[test]
+    fn test_label_is_not_visible() {
+        let mut png = png();
+        let mut index = LabelIndex::new();
+        index
+            .assign("diary", &png, &mut rand::thread_rng())
+            .unwrap();
+        let chunk = index.to_chunk("hunter2", KDF).unwrap();
+        assert!(!chunk.data().windows(5).any(|w| w == b"diary"));
+        png.append_chunk(chunk);
+        assert!(LabelIndex::from_png(&png, "wrong").is_err());
+    }
+
+    #[test]
+    fn test_no_index() {
+        let index = LabelIndex::from_png(&png(), "hunter2").unwrap();
+        assert_eq!(index, LabelIndex::new());
+    }
+}
@@ -1,16 +1,43 @@
-#[doc(inline)]
+pub mod apng;
+pub mod capacity;
+pub mod carrier;
 mod chunk;
-#[doc(inline)]
 mod chunk_type;
-#[doc(inline)]
+pub mod codec;
+pub mod color;
+pub mod digest;
+pub mod fields;
+pub mod gif;
+pub mod icc;
+pub mod info;
+pub mod jpeg;
+pub mod keystore;
+pub mod labels;
+pub mod pixels;
 mod png;
+mod reader;
+pub mod shamir;
+pub mod signature;
+pub mod split;
+pub mod spread;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+pub mod trailer;
+pub mod transform;
+pub mod webp;
+pub mod xmp;
 
 #[doc(inline)]
 pub use chunk::Chunk;
 #[doc(inline)]
-pub use chunk_type::ChunkType;
+pub use chunk_type::{ChunkType, ChunkTypeBuilder};
+#[doc(inline)]
+pub use png::{
+    ChunkNotFoundError, ChunkOrderError, ChunkParseError, ParseOptions, Png, PngDecodeError,
+    Strictness,
+};
 #[doc(inline)]
-pub use png::Png;
+pub use reader::ChunkReader;
 
 /// Holds any kind of error.
 pub type Error = Box<dyn std::error::Error>;
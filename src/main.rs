@@ -1,10 +1,29 @@
 mod args;
-mod chunk;
-mod chunk_type;
 mod commands;
-mod png;
+mod completion;
+mod corrupt;
+mod diagnostic;
+mod explain;
+mod extract;
+mod filter;
+mod gitfilter;
+mod keychain;
+mod man;
+mod meta;
+mod output;
+mod paths;
+mod report;
+mod schema;
+mod serve;
+mod shell;
+mod sniff;
+mod storage;
+mod watch;
 
-use structopt::StructOpt;
+use clap::{CommandFactory, Parser};
+use clap_complete::CompleteEnv;
+use pngme::ParseOptions;
+use std::process;
 
 /// Holds any kind of error.
 pub type Error = Box<dyn std::error::Error>;
@@ -12,7 +31,20 @@ pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[doc(hidden)]
-fn main() -> Result<()> {
-    let cli = args::Cli::from_args();
-    commands::run(cli.subcommand)
+fn main() {
+    // Answer the shell, if it's asking for completions.
+    CompleteEnv::with_factory(args::Cli::command).complete();
+    let cli = args::Cli::parse();
+    let (errors, quiet) = (cli.errors, cli.quiet);
+    let parse_options = ParseOptions {
+        verify_crc: !cli.no_verify_crc,
+        ..ParseOptions::default()
+    };
+    let write_options = output::WriteOptions {
+        preserve: cli.preserve,
+        force: cli.force,
+    };
+    if let Err(e) = commands::run(cli.subcommand, &parse_options, write_options) {
+        process::exit(report::report(&e, errors, quiet));
+    }
 }
@@ -0,0 +1,61 @@
+use clap::Command;
+use clap_mangen::Man;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Write the man page for `command` alone (without its subcommands).
+pub fn render<W: Write>(command: Command, out: &mut W) -> crate::Result<()> {
+    Man::new(command).render(out)?;
+    Ok(())
+}
+
+/// Write a man page for `command` and one for each of its subcommands, at any
+/// depth, into `dir`. Subcommand pages are named after the whole command line,
+/// like `pngme-key-generate.1`. Returns the paths that were written.
+pub fn write_pages(command: Command, dir: &Path) -> crate::Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    write_page(command, dir, &mut written)?;
+    Ok(written)
+}
+
+fn write_page(command: Command, dir: &Path, written: &mut Vec<PathBuf>) -> crate::Result<()> {
+    let name = command.get_name().to_string();
+    for subcommand in command.get_subcommands() {
+        let full_name = format!("{}-{}", name, subcommand.get_name());
+        write_page(subcommand.clone().name(full_name), dir, written)?;
+    }
+    let path = dir.join(format!("{}.1", name));
+    let mut out = BufWriter::new(File::create(&path)?);
+    render(command, &mut out)?;
+    out.flush()?;
+    written.push(path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Cli;
+    use clap::CommandFactory;
+    use std::fs;
+
+    #[test]
+    fn test_render() {
+        let mut out = Vec::new();
+        render(Cli::command(), &mut out).unwrap();
+        let page = String::from_utf8(out).unwrap();
+        assert!(page.contains(".TH pngme"));
+        assert!(page.contains("encode"));
+    }
+
+    #[test]
+    fn test_write_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let written = write_pages(Cli::command(), dir.path()).unwrap();
+        assert!(written.contains(&dir.path().join("pngme.1")));
+        assert!(written.contains(&dir.path().join("pngme-key-generate.1")));
+        let encode = fs::read_to_string(dir.path().join("pngme-encode.1")).unwrap();
+        assert!(encode.contains("\\-\\-compress"));
+    }
+}
@@ -0,0 +1,301 @@
+//! A JSON sidecar holding every bit of text metadata in a PNG, so that it can
+//! be moved onto another image.
+//!
+//! `export-meta` writes it and `import-meta` applies it. It looks like this:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "text": [
+//!     {
+//!       "keyword": "Title",
+//!       "language": "",
+//!       "value": "Dice",
+//!       "type": "tEXt"
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! - `version` is [VERSION], and changes the same way the version of the PNG's
+//!   JSON form does.
+//! - `language` is the language tag of an `iTXt` chunk, like `en-US`, and is
+//!   empty for `tEXt` and `zTXt` chunks.
+//! - `value` is the text, decompressed.
+//! - `type` is the chunk type the text was in. It's optional: without it,
+//!   `import-meta` writes `tEXt` if it can, like `set-meta` does.
+//! - `translated_keyword` is only there for an `iTXt` chunk that has one.
+use pngme::codec::{
+    ChunkCodec, CompressedTextCodec, InternationalText, InternationalTextCodec, TextCodec,
+    TextualData,
+};
+use pngme::{Chunk, ChunkType, Png};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// The version of the sidecar that this pngme writes and reads.
+pub const VERSION: u32 = 1;
+
+/// All of a PNG's text metadata.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct MetaJson {
+    pub version: u32,
+    pub text: Vec<TextJson>,
+}
+
+/// The text in one `tEXt`, `zTXt`, or `iTXt` chunk.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct TextJson {
+    pub keyword: String,
+    #[serde(default)]
+    pub language: String,
+    pub value: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub chunk_type: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub translated_keyword: String,
+}
+
+impl TextJson {
+    /// The text in `chunk`, or `None` if it isn't a text chunk. Returns `Err`
+    /// if it is one, but can't be parsed.
+    fn from_chunk(chunk: &Chunk) -> Option<crate::Result<Self>> {
+        let chunk_type = *chunk.chunk_type();
+        let plain = |t: TextualData| TextJson {
+            keyword: t.keyword,
+            language: String::new(),
+            value: t.text,
+            chunk_type: Some(chunk_type.to_string()),
+            translated_keyword: String::new(),
+        };
+        match chunk_type {
+            ChunkType::TEXT => Some(TextCodec.decode(chunk.data()).map(plain)),
+            ChunkType::ZTXT => Some(CompressedTextCodec.decode(chunk.data()).map(plain)),
+            ChunkType::ITXT => {
+                Some(
+                    InternationalTextCodec
+                        .decode(chunk.data())
+                        .map(|t| TextJson {
+                            keyword: t.keyword,
+                            language: t.language,
+                            value: t.text,
+                            chunk_type: Some(chunk_type.to_string()),
+                            translated_keyword: t.translated_keyword,
+                        }),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    /// A chunk holding this text. Returns `Err` if the keyword isn't 1 to 79
+    /// Latin-1 characters, or the text doesn't fit in the type it asks for.
+    fn to_chunk(&self) -> crate::Result<Chunk> {
+        let plain = || TextualData {
+            keyword: self.keyword.clone(),
+            text: self.value.clone(),
+        };
+        let international = || InternationalText {
+            keyword: self.keyword.clone(),
+            compressed: false,
+            language: self.language.clone(),
+            translated_keyword: self.translated_keyword.clone(),
+            text: self.value.clone(),
+        };
+        let is_plain = self.language.is_empty() && self.translated_keyword.is_empty();
+        let chunk_type = match &self.chunk_type {
+            Some(chunk_type) => Some(ChunkType::from_str(chunk_type)?),
+            None => None,
+        };
+        match chunk_type {
+            Some(ChunkType::TEXT) | Some(ChunkType::ZTXT) if !is_plain => {
+                Err(MetaError::boxed(format!(
+                    "'{}' has a language or translated keyword, which only iTXt can hold",
+                    self.keyword
+                )))
+            }
+            Some(ChunkType::TEXT) => TextCodec.to_chunk(&plain()),
+            Some(ChunkType::ZTXT) => CompressedTextCodec.to_chunk(&plain()),
+            Some(ChunkType::ITXT) => InternationalTextCodec.to_chunk(&international()),
+            Some(other) => Err(MetaError::boxed(format!(
+                "'{}' is in a {} chunk, which isn't a text chunk type",
+                self.keyword, other
+            ))),
+            None if is_plain => TextCodec
+                .to_chunk(&plain())
+                .or_else(|_| InternationalTextCodec.to_chunk(&international())),
+            None => InternationalTextCodec.to_chunk(&international()),
+        }
+    }
+}
+
+/// The keyword of `chunk`, if it's a text chunk that can be parsed.
+fn keyword(chunk: &Chunk) -> Option<String> {
+    TextJson::from_chunk(chunk)?.ok().map(|t| t.keyword)
+}
+
+impl MetaJson {
+    /// Every text chunk in `png`, in order. Returns `Err` if any of them
+    /// can't be parsed.
+    pub fn from_png(png: &Png) -> crate::Result<Self> {
+        Ok(MetaJson {
+            version: VERSION,
+            text: png
+                .chunks()
+                .iter()
+                .filter_map(TextJson::from_chunk)
+                .collect::<crate::Result<Vec<TextJson>>>()?,
+        })
+    }
+
+    /// Put this text in `png`. Every text chunk already there with one of
+    /// these keywords is removed, and the new chunks go where the first of
+    /// those was, or just before IEND if there weren't any. Text with other
+    /// keywords is left alone.
+    pub fn apply(&self, png: &mut Png) -> crate::Result<()> {
+        if self.version != VERSION {
+            return Err(MetaError::boxed(format!(
+                "Version {} isn't supported (expected {})",
+                self.version, VERSION
+            )));
+        }
+        let chunks = self
+            .text
+            .iter()
+            .map(TextJson::to_chunk)
+            .collect::<crate::Result<Vec<Chunk>>>()?;
+        let keywords: HashSet<&str> = self.text.iter().map(|t| t.keyword.as_str()).collect();
+        let replaced = |c: &Chunk| keyword(c).is_some_and(|k| keywords.contains(k.as_str()));
+        let index = match png.chunks().iter().position(&replaced) {
+            Some(index) => index,
+            None => png
+                .chunks()
+                .iter()
+                .rposition(|c| c.chunk_type() == &ChunkType::IEND)
+                .unwrap_or_else(|| png.chunk_count()),
+        };
+        // Nothing before `index` is replaced, so removing the chunks that are
+        // leaves it pointing at the same place.
+        png.retain_chunks(|c| !replaced(c));
+        for (offset, chunk) in chunks.into_iter().enumerate() {
+            png.insert_chunk(index + offset, chunk);
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("text metadata is always valid JSON")
+    }
+
+    pub fn from_json(json: &str) -> crate::Result<Self> {
+        serde_json::from_str(json).map_err(|e| MetaError::boxed(e.to_string()).into())
+    }
+}
+
+/// A metadata sidecar can't be read or applied.
+#[derive(Debug)]
+pub struct MetaError {
+    reason: String,
+}
+
+impl MetaError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for MetaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad metadata JSON: {}", self.reason)
+    }
+}
+
+impl Error for MetaError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png() -> Png {
+        let mut png = Png::default();
+        png.set_text("Title", "Dice").unwrap();
+        png.set_text("Author", "Gabe").unwrap();
+        let comment = InternationalText {
+            keyword: "Comment".to_string(),
+            compressed: true,
+            language: "fr".to_string(),
+            translated_keyword: "Commentaire".to_string(),
+            text: "Bonjour ✓".to_string(),
+        };
+        png.append_chunk(InternationalTextCodec.to_chunk(&comment).unwrap());
+        png
+    }
+
+    #[test]
+    fn test_from_png() {
+        let meta = MetaJson::from_png(&png()).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&meta.to_json()).unwrap();
+        assert_eq!(
+            value["text"][0],
+            serde_json::json!({
+                "keyword": "Title",
+                "language": "",
+                "value": "Dice",
+                "type": "tEXt",
+            })
+        );
+        assert_eq!(value["text"][2]["language"], "fr");
+        assert_eq!(value["text"][2]["translated_keyword"], "Commentaire");
+        assert_eq!(value["text"][2]["value"], "Bonjour ✓");
+    }
+
+    #[test]
+    fn test_apply() {
+        let meta = MetaJson::from_png(&png()).unwrap();
+        let mut other = Png::default();
+        other.set_text("Author", "Someone else").unwrap();
+        other.set_text("Software", "pngme").unwrap();
+        meta.apply(&mut other).unwrap();
+
+        let back = MetaJson::from_png(&other).unwrap();
+        let keywords: Vec<&str> = back.text.iter().map(|t| t.keyword.as_str()).collect();
+        // Author is replaced, and Software is left alone.
+        assert_eq!(keywords, vec!["Title", "Author", "Comment", "Software"]);
+        assert_eq!(back.text[..3], meta.text[..]);
+    }
+
+    #[test]
+    fn test_apply_without_types() {
+        let json = r#"{"version": 1, "text": [
+            {"keyword": "Title", "value": "Dice"},
+            {"keyword": "Title", "language": "de", "value": "Würfel"}
+        ]}"#;
+        let mut png = Png::default();
+        MetaJson::from_json(json).unwrap().apply(&mut png).unwrap();
+        let types: Vec<String> = MetaJson::from_png(&png)
+            .unwrap()
+            .text
+            .into_iter()
+            .map(|t| t.chunk_type.unwrap())
+            .collect();
+        assert_eq!(types, vec!["tEXt", "iTXt"]);
+    }
+
+    #[test]
+    fn test_bad_json() {
+        for json in &[
+            r#"{"version": 2, "text": []}"#,
+            r#"{"version": 1, "text": [{"keyword": "", "value": "x"}]}"#,
+            r#"{"version": 1, "text": [{"keyword": "Title", "value": "x", "type": "IDAT"}]}"#,
+            r#"{"version": 1, "text": [{"keyword": "Title", "language": "de", "value": "x", "type": "tEXt"}]}"#,
+            r#"{"version": 1, "text": [{"keyword": "Title", "value": "✓", "type": "tEXt"}]}"#,
+            r#"{"text": []}"#,
+        ] {
+            let result = MetaJson::from_json(json).and_then(|meta| meta.apply(&mut Png::default()));
+            assert!(result.is_err(), "{}", json);
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! Writing PNGs back out.
+//!
+//! An output path of `-` means stdout, so pngme can sit in the middle of a
+//! pipeline. Object store URIs are written with [storage].
+use crate::storage::{self, Input, Upload};
+use pngme::ChunkReader;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+/// Whether `path` is `-`, which means stdout.
+fn is_stdout(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Stdout, for writing a PNG to. Returns `Err` if it's a terminal, which
+/// would just fill up with garbage.
+fn stdout() -> crate::Result<BufWriter<io::StdoutLock<'static>>> {
+    let stdout = io::stdout();
+    if stdout.is_terminal() {
+        return Err(Box::new(TerminalOutputError));
+    }
+    Ok(BufWriter::new(stdout.lock()))
+}
+
+/// How to write a file made from an input file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions {
+    /// Give the file the input file's modification time and (on Unix)
+    /// permissions, even if the file is the input file.
+    pub preserve: bool,
+    /// Replace an output file that already exists, even though it isn't the
+    /// input file.
+    pub force: bool,
+}
+
+impl WriteOptions {
+    /// Note down what to keep of `input`, before anything overwrites it.
+    /// Objects in an object store have nothing to keep.
+    pub fn keep(self, input: &Path) -> crate::Result<Kept> {
+        if self.preserve && !storage::is_remote(input) {
+            Ok(Kept(Some(fs::metadata(input)?)))
+        } else {
+            Ok(Kept(None))
+        }
+    }
+
+    /// Check that writing to `output` doesn't replace some other file than
+    /// `input`, unless `force` is set. A typo in an output path shouldn't cost
+    /// an unrelated file.
+    fn check_clobber(self, input: &Path, output: &Path) -> crate::Result<()> {
+        if self.force || output == input || !output.exists() {
+            return Ok(());
+        }
+        // The same file by another name, like `./dice.png` for `dice.png`.
+        if let (Ok(input), Ok(output)) = (fs::canonicalize(input), fs::canonicalize(output)) {
+            if input == output {
+                return Ok(());
+            }
+        }
+        Err(Box::new(OutputExistsError(output.to_path_buf())))
+    }
+
+    /// Write `bytes`, made from the file at `input`, to `output`. Returns
+    /// `Err` if `output` is some other file that already exists, unless
+    /// `force` is set.
+    pub fn write(self, input: &Path, output: &Path, bytes: &[u8]) -> crate::Result<()> {
+        if is_stdout(output) {
+            let mut stdout = stdout()?;
+            stdout.write_all(bytes)?;
+            stdout.flush()?;
+            return Ok(());
+        }
+        if storage::is_remote(output) {
+            return storage::write(output, bytes);
+        }
+        self.check_clobber(input, output)?;
+        let kept = self.keep(input)?;
+        fs::write(output, bytes)?;
+        kept.restore(output)
+    }
+
+    /// Copy the PNG at `input` to `output` through `copy`, one chunk at a
+    /// time. The copy is written to a temporary file next to `output`, which
+    /// replaces `output` only if `copy` succeeds, so `output` can be `input`,
+    /// and a failure leaves it alone. Stdout is written to as the copy goes,
+    /// and an object is uploaded as the copy goes but only replaced at the end.
+    /// Like [write](#method.write), it won't replace some other file.
+    pub fn copy<T, F>(self, input: &Path, output: &Path, copy: F) -> crate::Result<T>
+    where
+        F: FnOnce(ChunkReader<BufReader<Box<dyn Input>>>, &mut dyn Write) -> crate::Result<T>,
+    {
+        let reader = ChunkReader::new(BufReader::new(storage::open(input)?));
+        if is_stdout(output) {
+            return copy(reader, &mut stdout()?);
+        }
+        if storage::is_remote(output) {
+            let mut upload = Upload::create(output)?;
+            return match copy(reader, &mut upload) {
+                Ok(value) => {
+                    upload.finish()?;
+                    Ok(value)
+                }
+                Err(e) => {
+                    upload.abort();
+                    Err(e)
+                }
+            };
+        }
+        self.check_clobber(input, output)?;
+        let kept = self.keep(input)?;
+        let name = output.file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a file", output.display()),
+            )
+        })?;
+        let mut temporary_name = OsString::from(".");
+        temporary_name.push(name);
+        temporary_name.push(".pngme");
+        let temporary = output.with_file_name(temporary_name);
+        let file = File::create(&temporary)?;
+        // Keep the permissions of the file being replaced.
+        if let Ok(metadata) = fs::metadata(output) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        let mut writer = BufWriter::new(file);
+        match copy(reader, &mut writer) {
+            Ok(value) => {
+                // Make sure the copy is on disk before it replaces anything.
+                writer
+                    .into_inner()
+                    .map_err(|e| e.into_error())?
+                    .sync_all()?;
+                fs::rename(&temporary, output)?;
+                kept.restore(output)?;
+                Ok(value)
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&temporary);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// What [WriteOptions::keep] noted down about a file, if anything.
+#[must_use = "the metadata is only kept if it's restored"]
+pub struct Kept(Option<Metadata>);
+
+impl Kept {
+    /// Give `output` the modification time and permissions that were kept.
+    pub fn restore(self, output: &Path) -> crate::Result<()> {
+        let metadata = match self.0 {
+            Some(metadata) => metadata,
+            None => return Ok(()),
+        };
+        let file = OpenOptions::new().write(true).open(output)?;
+        file.set_modified(metadata.modified()?)?;
+        // Permissions are only a read-only flag elsewhere, and a read-only
+        // file could never have been rewritten.
+        #[cfg(unix)]
+        file.set_permissions(metadata.permissions())?;
+        Ok(())
+    }
+}
+
+/// The output was stdout, but stdout is a terminal.
+#[derive(Debug)]
+pub struct TerminalOutputError;
+impl fmt::Display for TerminalOutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Refusing to write a PNG to a terminal (redirect stdout, or give a file to write to)"
+        )
+    }
+}
+impl Error for TerminalOutputError {}
+
+/// The output path is a file that already exists, and isn't the input file.
+#[derive(Debug)]
+pub struct OutputExistsError(PathBuf);
+impl fmt::Display for OutputExistsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Refusing to overwrite {}, which already exists (give --force to overwrite it anyway)",
+            self.0.display()
+        )
+    }
+}
+impl Error for OutputExistsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    /// A file last modified a day ago.
+    fn old_file(path: &Path) -> SystemTime {
+        fs::write(path, b"old").unwrap();
+        let yesterday = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(yesterday)
+            .unwrap();
+        fs::metadata(path).unwrap().modified().unwrap()
+    }
+
+    #[test]
+    fn test_is_stdout() {
+        assert!(is_stdout(Path::new("-")));
+        assert!(!is_stdout(Path::new("./-")));
+        assert!(!is_stdout(Path::new("dice.png")));
+    }
+
+    #[test]
+    fn test_write_without_preserve() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dice.png");
+        let modified = old_file(&path);
+        WriteOptions::default().write(&path, &path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_ne!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+    }
+
+    #[test]
+    fn test_write_with_preserve() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("dice.png");
+        let modified = old_file(&input);
+        let options = WriteOptions {
+            preserve: true,
+            ..WriteOptions::default()
+        };
+        options.write(&input, &input, b"new").unwrap();
+        assert_eq!(fs::read(&input).unwrap(), b"new");
+        assert_eq!(fs::metadata(&input).unwrap().modified().unwrap(), modified);
+
+        // A new file made from the input gets the input's metadata too.
+        let output = dir.path().join("copy.png");
+        options.write(&input, &output, b"copy").unwrap();
+        assert_eq!(fs::metadata(&output).unwrap().modified().unwrap(), modified);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserve_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("dice.png");
+        old_file(&input);
+        fs::set_permissions(&input, fs::Permissions::from_mode(0o640)).unwrap();
+        let output = dir.path().join("copy.png");
+        WriteOptions {
+            preserve: true,
+            ..WriteOptions::default()
+        }
+        .write(&input, &output, b"copy")
+        .unwrap();
+        let mode = fs::metadata(&output).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[test]
+    fn test_refuses_to_clobber() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("dice.png");
+        let output = dir.path().join("notes.txt");
+        fs::write(&input, b"png").unwrap();
+        fs::write(&output, b"important").unwrap();
+        let error = WriteOptions::default()
+            .write(&input, &output, b"new")
+            .unwrap_err();
+        assert!(error.is::<OutputExistsError>());
+        let copied = WriteOptions::default().copy(&input, &output, |_, _| Ok(()));
+        assert!(copied.is_err());
+        assert_eq!(fs::read(&output).unwrap(), b"important");
+
+        // The input file itself, by any name, is fine to overwrite.
+        let same = dir.path().join(".").join("dice.png");
+        WriteOptions::default()
+            .write(&input, &same, b"new")
+            .unwrap();
+
+        let force = WriteOptions {
+            force: true,
+            ..WriteOptions::default()
+        };
+        force.write(&input, &output, b"new").unwrap();
+        assert_eq!(fs::read(&output).unwrap(), b"new");
+    }
+
+    #[test]
+    fn test_failed_copy_leaves_the_file_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dice.png");
+        fs::write(&path, b"not a png").unwrap();
+        let result = WriteOptions::default().copy(&path, &path, |reader, writer| {
+            reader.copy_to(writer, |_| true, true)
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"not a png");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
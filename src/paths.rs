@@ -0,0 +1,88 @@
+//! Paths that work however they're spelled.
+//!
+//! Paths stay `Path`s and `OsString`s from the command line to the file
+//! system and are never turned into `String`s (except to show them), so
+//! filenames that aren't valid UTF-8 work like any other.
+//!
+//! Windows won't open a path longer than `MAX_PATH` unless it has a `\\?\`
+//! prefix. The standard library adds the prefix itself in its own file
+//! functions, but other libraries that hand paths straight to Windows (like
+//! the one `watch` uses) need [long] first.
+use std::borrow::Cow;
+use std::path::Path;
+
+/// The longest path Windows accepts without a `\\?\` prefix, counting the
+/// terminating NUL.
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// `path` in a form Windows accepts however long it is: a path that's too long
+/// is made absolute and given the `\\?\` prefix (or `\\?\UNC\` for a path on
+/// a network share). Short paths, paths that already have a prefix, and every
+/// path on other systems are left alone.
+#[cfg(windows)]
+pub fn long(path: &Path) -> Cow<'_, Path> {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::PathBuf;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().collect();
+    let verbatim: Vec<u16> = r"\\?\".encode_utf16().collect();
+    if wide.len() < MAX_PATH || wide.starts_with(&verbatim) {
+        return Cow::Borrowed(path);
+    }
+    let absolute = match std::path::absolute(path) {
+        Ok(absolute) => absolute,
+        Err(_) => return Cow::Borrowed(path),
+    };
+    let wide: Vec<u16> = absolute.as_os_str().encode_wide().collect();
+    let mut prefixed = verbatim;
+    match wide.strip_prefix(&[u16::from(b'\\'), u16::from(b'\\')][..]) {
+        Some(share) => {
+            prefixed.extend(r"UNC\".encode_utf16());
+            prefixed.extend_from_slice(share);
+        }
+        None => prefixed.extend_from_slice(&wide),
+    }
+    Cow::Owned(PathBuf::from(OsString::from_wide(&prefixed)))
+}
+
+/// `path` in a form Windows accepts however long it is. This isn't Windows,
+/// so that's just `path`.
+#[cfg(not(windows))]
+pub fn long(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_paths_are_unchanged() {
+        let path = Path::new("dice.png");
+        assert_eq!(long(path), path);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_long_paths_are_unchanged() {
+        let path = "a/".repeat(200) + "dice.png";
+        assert_eq!(long(Path::new(&path)), Path::new(&path));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_long_paths_are_prefixed() {
+        let path = r"C:\".to_string() + &r"a\".repeat(200) + "dice.png";
+        assert_eq!(
+            long(Path::new(&path)),
+            Path::new(&(r"\\?\".to_string() + &path))
+        );
+        let share = r"\\server\share\".to_string() + &r"a\".repeat(200) + "dice.png";
+        assert_eq!(
+            long(Path::new(&share)),
+            Path::new(&(r"\\?\UNC\".to_string() + &share[2..]))
+        );
+    }
+}
@@ -0,0 +1,331 @@
+//! Comparing what images look like, rather than how they're stored.
+//!
+//! That means decoding their pixels, which needs pngme to be built with the
+//! `image` feature. Without it, reading an image here is always an error.
+use std::error::Error;
+use std::fmt;
+
+/// A 64-bit perceptual hash (pHash) of an image. Images that look alike have
+/// hashes that differ in only a few bits, even after being re-encoded, resized,
+/// or lightly edited, so compare them with [distance](#method.distance) rather
+/// than for equality.
+///
+/// The image is turned grayscale and shrunk to 32x32, then each of the lowest
+/// 8x8 frequencies of its discrete cosine transform gives one bit: whether it's
+/// above the median of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerceptualHash(u64);
+
+/// The side of the square an image is shrunk to before it's hashed.
+const HASH_SIZE: usize = 32;
+
+/// The side of the square of low frequencies that make up the hash.
+const LOW_FREQUENCIES: usize = 8;
+
+impl PerceptualHash {
+    /// The hash of the image in `bytes`, which can be a PNG, JPEG, WebP, or
+    /// GIF (whose first frame is hashed).
+    pub fn of(bytes: &[u8]) -> crate::Result<Self> {
+        let pixels = decoder::grayscale(bytes, HASH_SIZE as u32)?;
+        Ok(Self::from_grayscale(&pixels))
+    }
+
+    /// The hash of a `HASH_SIZE` by `HASH_SIZE` grayscale image, one value
+    /// per pixel, row by row.
+    fn from_grayscale(pixels: &[f64]) -> Self {
+        let frequencies = dct(pixels, HASH_SIZE);
+        let low: Vec<f64> = (0..LOW_FREQUENCIES)
+            .flat_map(|v| (0..LOW_FREQUENCIES).map(move |u| (u, v)))
+            .map(|(u, v)| frequencies[v * HASH_SIZE + u])
+            .collect();
+        // The first frequency is just how bright the image is overall, which
+        // would throw the median off.
+        let mut sorted = low[1..].to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let median = sorted[sorted.len() / 2];
+        PerceptualHash(low.iter().fold(0, |hash, &frequency| {
+            (hash << 1) | u64::from(frequency > median)
+        }))
+    }
+
+    pub fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// How many bits differ between this hash and `other`, from 0 (the
+    /// images look the same) to 64. Unrelated images differ in about half.
+    pub fn distance(self, other: PerceptualHash) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+/// The hash as 16 hex digits.
+impl fmt::Display for PerceptualHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// How two images of the same size differ, pixel by pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelDiff {
+    /// How many pixels differ in any channel.
+    pub differing: u64,
+    /// How many pixels each image has.
+    pub total: u64,
+    /// The most any one channel of any pixel differs by.
+    pub max_delta: u16,
+    /// The most a channel can hold: 255, or 65535 if either image has 16
+    /// bits per channel.
+    pub max_value: u16,
+}
+
+impl PixelDiff {
+    /// Compare the images in `a` and `b`, which can each be a PNG, JPEG,
+    /// WebP, or GIF, after turning both into RGBA. Returns `Err` if they're
+    /// not the same size.
+    pub fn between(a: &[u8], b: &[u8]) -> crate::Result<Self> {
+        let (a, b) = (decoder::rgba(a)?, decoder::rgba(b)?);
+        if (a.width, a.height) != (b.width, b.height) {
+            return Err(PixelsError::boxed(format!(
+                "The images are different sizes ({}x{} and {}x{})",
+                a.width, a.height, b.width, b.height
+            )));
+        }
+        Ok(Self::of_samples(&a, &b))
+    }
+
+    fn of_samples(a: &Rgba, b: &Rgba) -> Self {
+        let deep = a.deep || b.deep;
+        // An 8-bit sample scaled up to 16 bits, if the other image has them.
+        let scale = |image: &Rgba| if deep && !image.deep { 257 } else { 1 };
+        let (scale_a, scale_b) = (scale(a), scale(b));
+        let mut differing = 0;
+        let mut max_delta = 0;
+        for (pixel_a, pixel_b) in a.samples.chunks(4).zip(b.samples.chunks(4)) {
+            let delta = pixel_a
+                .iter()
+                .zip(pixel_b)
+                .map(|(x, y)| (x * scale_a).abs_diff(y * scale_b))
+                .max()
+                .unwrap_or(0);
+            differing += u64::from(delta > 0);
+            max_delta = max_delta.max(delta);
+        }
+        PixelDiff {
+            differing,
+            total: u64::from(a.width) * u64::from(a.height),
+            max_delta,
+            max_value: if deep { u16::MAX } else { u16::from(u8::MAX) },
+        }
+    }
+}
+
+/// A decoded image, as its red, green, blue, and alpha samples, row by row.
+struct Rgba {
+    width: u32,
+    height: u32,
+    samples: Vec<u16>,
+    /// Whether the image has 16 bits per channel, rather than 8.
+    deep: bool,
+}
+
+/// The two-dimensional discrete cosine transform (DCT-II) of a `size` by
+/// `size` square of values, done a row and then a column at a time.
+fn dct(values: &[f64], size: usize) -> Vec<f64> {
+    let cosines: Vec<f64> = (0..size * size)
+        .map(|i| {
+            let (k, n) = (i / size, i % size);
+            (std::f64::consts::PI / size as f64 * (n as f64 + 0.5) * k as f64).cos()
+        })
+        .collect();
+    let transform = |line: &[f64]| -> Vec<f64> {
+        (0..size)
+            .map(|k| (0..size).map(|n| line[n] * cosines[k * size + n]).sum())
+            .collect()
+    };
+    let rows: Vec<f64> = values.chunks(size).flat_map(transform).collect();
+    let mut result = vec![0.0; size * size];
+    for x in 0..size {
+        let column: Vec<f64> = (0..size).map(|y| rows[y * size + x]).collect();
+        for (y, value) in transform(&column).into_iter().enumerate() {
+            result[y * size + x] = value;
+        }
+    }
+    result
+}
+
+#[cfg(feature = "image")]
+mod decoder {
+    use super::{PixelsError, Rgba};
+    use image::imageops::FilterType;
+
+    fn decode(bytes: &[u8]) -> crate::Result<image::DynamicImage> {
+        image::load_from_memory(bytes)
+            .map_err(|e| PixelsError::boxed(e.to_string()) as crate::Error)
+    }
+
+    /// The image in `bytes`, in grayscale and shrunk (or stretched) to a
+    /// `size` by `size` square, one value from 0 to 255 per pixel.
+    pub fn grayscale(bytes: &[u8], size: u32) -> crate::Result<Vec<f64>> {
+        Ok(decode(bytes)?
+            .resize_exact(size, size, FilterType::Triangle)
+            .to_luma8()
+            .pixels()
+            .map(|pixel| f64::from(pixel.0[0]))
+            .collect())
+    }
+
+    /// The image in `bytes`, in RGBA, keeping 16 bits per channel if it has
+    /// them.
+    pub fn rgba(bytes: &[u8]) -> crate::Result<Rgba> {
+        let image = decode(bytes)?;
+        let color = image.color();
+        let deep = color.bytes_per_pixel() > color.channel_count();
+        let samples = if deep {
+            image.to_rgba16().into_raw()
+        } else {
+            image
+                .to_rgba8()
+                .into_raw()
+                .into_iter()
+                .map(u16::from)
+                .collect()
+        };
+        Ok(Rgba {
+            width: image.width(),
+            height: image.height(),
+            samples,
+            deep,
+        })
+    }
+}
+
+/// Without the `image` feature, there's nothing to decode images with.
+#[cfg(not(feature = "image"))]
+mod decoder {
+    use super::{PixelsError, Rgba};
+
+    fn unsupported() -> crate::Error {
+        PixelsError::boxed(
+            "Can't decode the image, because pngme was built without the image feature".to_string(),
+        )
+    }
+
+    pub fn grayscale(_bytes: &[u8], _size: u32) -> crate::Result<Vec<f64>> {
+        Err(unsupported())
+    }
+
+    pub fn rgba(_bytes: &[u8]) -> crate::Result<Rgba> {
+        Err(unsupported())
+    }
+}
+
+/// An image's pixels can't be decoded.
+#[derive(Debug)]
+pub struct PixelsError {
+    reason: String,
+}
+impl PixelsError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for PixelsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad image: {}", self.reason)
+    }
+}
+impl Error for PixelsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// A square with a pattern of light and dark, made brighter by
+    /// `brightness`, and turned on its side if `turned` is set.
+    fn pattern(turned: bool, brightness: f64) -> Vec<f64> {
+        (0..HASH_SIZE * HASH_SIZE)
+            .map(|i| {
+                let (mut x, mut y) = (i % HASH_SIZE, i / HASH_SIZE);
+                if turned {
+                    std::mem::swap(&mut x, &mut y);
+                }
+                brightness + ((x * x + 3 * y * y + x * y) % 64) as f64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_similar_images_hash_alike() {
+        let hash = PerceptualHash::from_grayscale(&pattern(false, 0.0));
+        let brighter = PerceptualHash::from_grayscale(&pattern(false, 40.0));
+        let turned = PerceptualHash::from_grayscale(&pattern(true, 0.0));
+        assert_eq!(hash.distance(brighter), 0);
+        assert!(hash.distance(turned) > 8);
+        assert_eq!(hash.to_string().len(), 16);
+    }
+
+    #[test]
+    fn test_dct() {
+        // A flat square has nothing but the first frequency.
+        let frequencies = dct(&[1.0; 16], 4);
+        assert!((frequencies[0] - 16.0).abs() < 1e-9);
+        assert!(frequencies[1..].iter().all(|f| f.abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_pixel_diff() {
+        let image = |samples: Vec<u16>, deep: bool| Rgba {
+            width: 2,
+            height: 1,
+            samples,
+            deep,
+        };
+        let a = image(vec![0, 0, 0, 255, 10, 20, 30, 255], false);
+        let b = image(vec![0, 0, 0, 255, 10, 21, 27, 255], false);
+        assert_eq!(
+            PixelDiff::of_samples(&a, &b),
+            PixelDiff {
+                differing: 1,
+                total: 2,
+                max_delta: 3,
+                max_value: 255
+            }
+        );
+        // 8-bit samples are scaled up to compare them with 16-bit ones.
+        let deep = image(vec![0, 0, 0, 65535, 2570, 5140, 7710, 65535], true);
+        let diff = PixelDiff::of_samples(&a, &deep);
+        assert_eq!((diff.differing, diff.max_value), (0, 65535));
+    }
+
+    #[test]
+    fn test_hash_image() {
+        let hash = PerceptualHash::of(&testing::minimal_png().as_bytes());
+        if cfg!(feature = "image") {
+            let with_payload = testing::png_with(vec![testing::chunk("ruSt", b"Secret")]);
+            assert_eq!(
+                hash.unwrap(),
+                PerceptualHash::of(&with_payload.as_bytes()).unwrap()
+            );
+            assert!(PerceptualHash::of(b"not an image").is_err());
+        } else {
+            assert!(hash.is_err());
+        }
+    }
+
+    #[test]
+    fn test_diff_images() {
+        let png = testing::minimal_png().as_bytes();
+        let with_payload = testing::png_with(vec![testing::chunk("ruSt", b"Secret")]).as_bytes();
+        let diff = PixelDiff::between(&png, &with_payload);
+        if cfg!(feature = "image") {
+            let diff = diff.unwrap();
+            assert_eq!((diff.differing, diff.total, diff.max_delta), (0, 1, 0));
+        } else {
+            assert!(diff.is_err());
+        }
+    }
+}
@@ -1,22 +1,39 @@
 use crate::chunk::Chunk;
 use crate::chunk_type::ChunkType;
+use crate::codec::{
+    ChunkCodec, CompressedTextCodec, InternationalText, InternationalTextCodec, TextCodec,
+    TextualData,
+};
+use crate::digest;
+use crc::crc32;
+use flate2::read::ZlibEncoder;
+use flate2::Compression;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
-use std::io::{BufReader, Read};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
 
 /// A full and valid PNG composed of Chunks.
 pub struct Png {
     chunks: Vec<Chunk>,
+    /// Bytes after IEND that aren't chunks.
+    trailing_data: Vec<u8>,
 }
 
 impl Png {
     /// The standard PNG header: b"\x89 P N G \r \n \x1a \n".
-    const STANDARD_HEADER: [u8; 8] = *b"\x89PNG\r\n\x1a\n";
+    pub(crate) const STANDARD_HEADER: [u8; 8] = *b"\x89PNG\r\n\x1a\n";
 
     /// Create a PNG from already-built [Chunk](../chunk/struct.Chunk.html)s.
     pub fn from_chunks(chunks: Vec<Chunk>) -> Self {
-        Png { chunks }
+        Png {
+            chunks,
+            trailing_data: Vec::new(),
+        }
     }
 
     /// Add a [Chunk](../chunk/struct.Chunk.html) to this PNG.
@@ -24,52 +41,774 @@ impl Png {
         self.chunks.push(chunk);
     }
 
+    /// Insert a [Chunk](../chunk/struct.Chunk.html) at `index`, shifting every
+    /// chunk after it along by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of chunks.
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) {
+        self.chunks.insert(index, chunk);
+    }
+
+    /// Put `chunk` in place of the chunk at `index`, returning the old one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub(crate) fn replace_chunk(&mut self, index: usize, chunk: Chunk) -> Chunk {
+        std::mem::replace(&mut self.chunks[index], chunk)
+    }
+
+    /// Put `chunk` in place of every chunk of its type, for the chunk types
+    /// that a PNG can have at most one of, like `pHYs`. It goes where the
+    /// first of those was, or where the spec says it belongs if there weren't
+    /// any: before the first chunk that has to come after it.
+    pub fn set_chunk(&mut self, chunk: Chunk) {
+        let chunk_type = *chunk.chunk_type();
+        let rank = order_rank(&chunk_type, true);
+        let index = match self
+            .chunks
+            .iter()
+            .position(|c| c.chunk_type() == &chunk_type)
+        {
+            Some(index) => index,
+            None => self
+                .order_ranks()
+                .iter()
+                .position(|&r| r > rank)
+                .unwrap_or(self.chunks.len()),
+        };
+        // Nothing before `index` has the chunk's type, so removing the chunks
+        // that do leaves it pointing at the same place.
+        self.chunks.retain(|c| c.chunk_type() != &chunk_type);
+        self.chunks.insert(index, chunk);
+    }
+
+    /// Every index where an ancillary chunk could be inserted while keeping
+    /// the PNG valid: after IHDR, no later than IEND, and never in between two
+    /// IDAT chunks (which must be consecutive).
+    pub fn legal_insert_positions(&self) -> Vec<usize> {
+        let first = self
+            .chunks
+            .iter()
+            .position(|c| c.chunk_type() == &ChunkType::IHDR)
+            .map_or(0, |pos| pos + 1);
+        let last = self
+            .chunks
+            .iter()
+            .rposition(|c| c.chunk_type() == &ChunkType::IEND)
+            .unwrap_or(self.chunks.len());
+        let is_idat = |index: usize| self.chunks[index].chunk_type() == &ChunkType::IDAT;
+        (first..=last.max(first))
+            .filter(|&index| {
+                index == 0 || index == self.chunks.len() || !(is_idat(index - 1) && is_idat(index))
+            })
+            .collect()
+    }
+
+    /// Reorder the chunks so they're allowed where they are: IHDR first, the
+    /// chunks that must come before PLTE, PLTE, the chunks that must come
+    /// between PLTE and IDAT, every IDAT chunk together, then IEND last.
+    /// Chunks that the spec puts no limits on stay on the same side of the
+    /// image data as they were. Chunks of the same kind keep their order, and
+    /// no chunk is changed. Returns whether anything moved.
+    pub fn sort_chunks(&mut self) -> bool {
+        if self.first_misordered_chunk().is_none() {
+            return false;
+        }
+        let ranks = self.order_ranks();
+        let mut ranked: Vec<(u8, Chunk)> = ranks.into_iter().zip(self.chunks.drain(..)).collect();
+        ranked.sort_by_key(|(rank, _)| *rank);
+        self.chunks = ranked.into_iter().map(|(_, c)| c).collect();
+        true
+    }
+
+    /// Move the chunk at index `from` so that it ends up at index `to`.
+    /// Returns `Err`, and leaves the chunks where they were, if moving it
+    /// would put a chunk out of order that wasn't already, in a way
+    /// [sort_chunks](#method.sort_chunks) would have to fix. Chunks that were
+    /// already out of order, like a payload after IEND, don't stop other
+    /// chunks from being moved.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either index is out of bounds.
+    pub fn move_chunk(&mut self, from: usize, to: usize) -> Result<(), ChunkOrderError> {
+        let len = self.chunks.len();
+        assert!(
+            from < len && to < len,
+            "Can't move chunk {} to {}: there are only {} chunks",
+            from,
+            to,
+            len
+        );
+        let misordered = self.misordered_chunks();
+        // Where each chunk was before the move.
+        let mut before: Vec<usize> = (0..len).collect();
+        let index = before.remove(from);
+        before.insert(to, index);
+        let chunk = self.chunks.remove(from);
+        self.chunks.insert(to, chunk);
+        let newly_misordered = self
+            .misordered_chunks()
+            .into_iter()
+            .find(|&i| !misordered.contains(&before[i]));
+        if let Some(i) = newly_misordered {
+            let error = ChunkOrderError {
+                moved: *self.chunks[to].chunk_type(),
+                misordered: *self.chunks[i].chunk_type(),
+                index: i,
+            };
+            let chunk = self.chunks.remove(to);
+            self.chunks.insert(from, chunk);
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// The index of the first chunk that isn't allowed where it is, if any.
+    fn first_misordered_chunk(&self) -> Option<usize> {
+        self.misordered_chunks().into_iter().next()
+    }
+
+    /// The indexes of every chunk that isn't allowed where it is. Each chunk
+    /// is given the earliest [OrderPhase] it's allowed in that isn't before
+    /// the previous chunk's phase; a chunk that isn't allowed is skipped over,
+    /// so that it doesn't make the chunks after it look out of order too.
+    fn misordered_chunks(&self) -> Vec<usize> {
+        let has_plte = self.chunk_by_type(ChunkType::PLTE).is_some();
+        let mut phase = OrderPhase::Header;
+        let mut misordered = Vec::new();
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            let is_idat = chunk.chunk_type() == &ChunkType::IDAT;
+            let (earliest, latest) = allowed_phases(chunk.chunk_type(), has_plte);
+            let mut next = phase.max(earliest);
+            // The IDAT chunks have to be consecutive, so anything else ends them.
+            if next == OrderPhase::ImageData && !is_idat {
+                next = OrderPhase::AfterImageData;
+            }
+            if next > latest {
+                misordered.push(index);
+            } else {
+                phase = next;
+            }
+        }
+        misordered
+    }
+
+    /// The [order_rank] of every chunk.
+    fn order_ranks(&self) -> Vec<u8> {
+        let first_idat = self
+            .chunks
+            .iter()
+            .position(|c| c.chunk_type() == &ChunkType::IDAT)
+            .unwrap_or(self.chunks.len());
+        self.chunks
+            .iter()
+            .enumerate()
+            .map(|(index, c)| order_rank(c.chunk_type(), index < first_idat))
+            .collect()
+    }
+
+    /// Insert a [Chunk](../chunk/struct.Chunk.html) at a random one of the
+    /// [legal positions](#method.legal_insert_positions), so that it isn't
+    /// always the last chunk before IEND.
+    pub fn insert_chunk_randomly<R: Rng + ?Sized>(&mut self, chunk: Chunk, rng: &mut R) {
+        let index = *self
+            .legal_insert_positions()
+            .choose(rng)
+            .expect("There is always at least one legal position");
+        self.insert_chunk(index, chunk);
+    }
+
     /// Remove the first [Chunk](../chunk/struct.Chunk.html) with thechunk
     /// given [ChunkType](../chunk_type/struct.ChunkType.html), if any.
     /// If it found the chunk, returns `Ok(removed_chunk)`.
     /// If it could not find the chunk, returns `Err`.
     pub fn remove_chunk(&mut self, chunk_type: ChunkType) -> Result<Chunk, ChunkNotFoundError> {
+        self.remove_nth_chunk(chunk_type, 0)
+    }
+
+    /// Remove the `n`th (counting from 0) [Chunk](../chunk/struct.Chunk.html)
+    /// with the given [ChunkType](../chunk_type/struct.ChunkType.html), if any.
+    /// Returns `Err` if there are fewer than `n + 1` chunks of that type.
+    pub fn remove_nth_chunk(
+        &mut self,
+        chunk_type: ChunkType,
+        n: usize,
+    ) -> Result<Chunk, ChunkNotFoundError> {
         if let Some(pos) = self
             .chunks
             .iter()
-            .position(|c| c.chunk_type() == &chunk_type)
+            .enumerate()
+            .filter(|(_, c)| c.chunk_type() == &chunk_type)
+            .map(|(pos, _)| pos)
+            .nth(n)
         {
             Ok(self.chunks.remove(pos))
         } else {
-            Err(ChunkNotFoundError {
-                chunk_type: chunk_type.to_string(),
-            })
+            Err(ChunkNotFoundError::new(chunk_type, n)
+                .with_present(self.chunks.iter().map(|c| *c.chunk_type())))
         }
     }
 
+    /// Every chunk type recorded in the
+    /// [type manifest](../chunk_type/struct.ChunkType.html#associatedconstant.TYPE_MANIFEST).
+    pub fn recorded_chunk_types(&self) -> Vec<ChunkType> {
+        self.chunk_by_type(ChunkType::TYPE_MANIFEST)
+            .and_then(|c| c.data_as_string().ok())
+            .map(|text| text.lines().filter_map(|line| line.parse().ok()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Add a chunk type to the
+    /// [type manifest](../chunk_type/struct.ChunkType.html#associatedconstant.TYPE_MANIFEST),
+    /// creating the manifest if there isn't one yet.
+    pub fn record_chunk_type(&mut self, chunk_type: ChunkType) {
+        let mut recorded = self.recorded_chunk_types();
+        if recorded.contains(&chunk_type) {
+            return;
+        }
+        recorded.push(chunk_type);
+        let text: String = recorded.iter().map(|t| format!("{}\n", t)).collect();
+        let _ = self.remove_chunk(ChunkType::TYPE_MANIFEST);
+        self.append_chunk(Chunk::new(ChunkType::TYPE_MANIFEST, text.into_bytes()));
+    }
+
     /// The standard PNG header.
     #[allow(dead_code)]
     fn header(&self) -> &[u8; 8] {
         &Self::STANDARD_HEADER
     }
 
+    /// Keep only the [Chunk](../chunk/struct.Chunk.html)s for which `keep`
+    /// returns `true`, removing the rest.
+    pub fn retain_chunks<F: FnMut(&Chunk) -> bool>(&mut self, keep: F) {
+        self.chunks.retain(keep);
+    }
+
+    /// Remove every ancillary [Chunk](../chunk/struct.Chunk.html) with the
+    /// same type and data as one before it, and return the removed chunks in
+    /// the order they were in. Critical chunks are never removed, since
+    /// repeated image data is still part of the image.
+    pub fn remove_duplicate_chunks(&mut self) -> Vec<Chunk> {
+        let mut seen = HashSet::new();
+        let duplicate: Vec<bool> = self
+            .chunks
+            .iter()
+            .map(|c| !c.chunk_type().is_critical() && !seen.insert((*c.chunk_type(), c.data())))
+            .collect();
+        let (removed, kept) = self
+            .chunks
+            .drain(..)
+            .zip(duplicate)
+            .partition::<Vec<_>, _>(|(_, duplicate)| *duplicate);
+        self.chunks = kept.into_iter().map(|(c, _)| c).collect();
+        removed.into_iter().map(|(c, _)| c).collect()
+    }
+
     /// Every [Chunk](../chunk/struct.Chunk.html) in this PNG.
     pub fn chunks(&self) -> &[Chunk] {
         self.chunks.as_slice()
     }
 
+    /// A SHA-256 digest of the chunks that make up the image itself, which
+    /// doesn't change when the metadata does. See
+    /// [digest::pixel_data_digest](../digest/fn.pixel_data_digest.html).
+    pub fn pixel_data_digest(&self) -> Vec<u8> {
+        digest::pixel_data_digest(digest::Algorithm::Sha256, self)
+    }
+
+    /// Every [Chunk](../chunk/struct.Chunk.html) in this PNG, to change (or
+    /// replace outright) in bulk. Chunks can't be added or removed this way,
+    /// so the order of the rest stays as it was.
+    pub fn chunks_mut(&mut self) -> &mut [Chunk] {
+        self.chunks.as_mut_slice()
+    }
+
+    /// Every critical [Chunk](../chunk/struct.Chunk.html), in order: the ones
+    /// needed to show the image.
+    pub fn critical_chunks(&self) -> impl Iterator<Item = &Chunk> + '_ {
+        self.chunks.iter().filter(|c| c.chunk_type().is_critical())
+    }
+
+    /// Every ancillary [Chunk](../chunk/struct.Chunk.html), in order: the
+    /// ones a decoder can skip, which is where metadata and messages go.
+    pub fn ancillary_chunks(&self) -> impl Iterator<Item = &Chunk> + '_ {
+        self.chunks.iter().filter(|c| !c.chunk_type().is_critical())
+    }
+
+    /// The keyword and text of every `tEXt`, `zTXt`, and `iTXt` chunk, in
+    /// order, with any compressed text decompressed. Returns `Err` if any of
+    /// them can't be parsed.
+    pub fn text_metadata(&self) -> crate::Result<Vec<(String, String)>> {
+        self.chunks
+            .iter()
+            .filter_map(|c| match *c.chunk_type() {
+                ChunkType::TEXT => Some(TextCodec.decode(c.data()).map(|t| (t.keyword, t.text))),
+                ChunkType::ZTXT => Some(
+                    CompressedTextCodec
+                        .decode(c.data())
+                        .map(|t| (t.keyword, t.text)),
+                ),
+                ChunkType::ITXT => Some(
+                    InternationalTextCodec
+                        .decode(c.data())
+                        .map(|t| (t.keyword, t.text)),
+                ),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Set the text for `keyword`, replacing every `tEXt`, `zTXt`, and `iTXt`
+    /// chunk that already has it. The new chunk goes where the first of those
+    /// was, or just before IEND if there weren't any. It's a `tEXt` chunk if the
+    /// text is all Latin-1, and an `iTXt` chunk if it isn't. Returns `Err` if
+    /// the keyword isn't 1 to 79 Latin-1 characters.
+    pub fn set_text(&mut self, keyword: &str, text: &str) -> crate::Result<()> {
+        let value = TextualData {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        };
+        let chunk = TextCodec.to_chunk(&value).or_else(|_| {
+            InternationalTextCodec.to_chunk(&InternationalText {
+                keyword: value.keyword,
+                compressed: false,
+                language: String::new(),
+                translated_keyword: String::new(),
+                text: value.text,
+            })
+        })?;
+        let has_keyword = |c: &Chunk| {
+            let found = match *c.chunk_type() {
+                ChunkType::TEXT => TextCodec.decode(c.data()).map(|t| t.keyword).ok(),
+                ChunkType::ZTXT => CompressedTextCodec.decode(c.data()).map(|t| t.keyword).ok(),
+                ChunkType::ITXT => InternationalTextCodec
+                    .decode(c.data())
+                    .map(|t| t.keyword)
+                    .ok(),
+                _ => None,
+            };
+            found.as_deref() == Some(keyword)
+        };
+        let index = match self.chunks.iter().position(&has_keyword) {
+            Some(index) => index,
+            None => self
+                .chunks
+                .iter()
+                .rposition(|c| c.chunk_type() == &ChunkType::IEND)
+                .unwrap_or(self.chunks.len()),
+        };
+        // Nothing before `index` has the keyword, so removing the chunks that
+        // do leaves it pointing at the same place.
+        self.chunks.retain(|c| !has_keyword(c));
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    /// How many [Chunk](../chunk/struct.Chunk.html)s this PNG has.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// How many bytes [as_bytes](#method.as_bytes) would return, worked out
+    /// without building them.
+    pub fn total_size(&self) -> usize {
+        // Each chunk has a length, a type, and a CRC as well as its data.
+        self.header().len()
+            + self
+                .chunks
+                .iter()
+                .map(|c| 12 + c.data().len())
+                .sum::<usize>()
+            + self.trailing_data.len()
+    }
+
+    /// How many bytes of data there are in every
+    /// [Chunk](../chunk/struct.Chunk.html) with the given
+    /// [ChunkType](../chunk_type/struct.ChunkType.html), not counting their
+    /// lengths, types, and CRCs.
+    pub fn payload_bytes(&self, chunk_type: ChunkType) -> usize {
+        self.chunks
+            .iter()
+            .filter(|c| c.chunk_type() == &chunk_type)
+            .map(|c| c.data().len())
+            .sum()
+    }
+
     /// Find the first [Chunk](../chunk/struct.Chunk.html) with the given
     /// [ChunkType](../chunk_type/struct.ChunkType.html).
     pub fn chunk_by_type(&self, chunk_type: ChunkType) -> Option<&Chunk> {
-        self.chunks.iter().find(|c| c.chunk_type() == &chunk_type)
+        self.nth_chunk_by_type(chunk_type, 0)
+    }
+
+    /// The first [Chunk](../chunk/struct.Chunk.html) with the given
+    /// [ChunkType](../chunk_type/struct.ChunkType.html). The same as
+    /// [chunk_by_type](#method.chunk_by_type).
+    pub fn get(&self, chunk_type: ChunkType) -> Option<&Chunk> {
+        self.chunk_by_type(chunk_type)
+    }
+
+    /// Like [get](#method.get), but the chunk can be changed (or replaced
+    /// outright) where it is, instead of being removed and appended again.
+    pub fn get_mut(&mut self, chunk_type: ChunkType) -> Option<&mut Chunk> {
+        self.chunks
+            .iter_mut()
+            .find(|c| c.chunk_type() == &chunk_type)
+    }
+
+    /// Find the `n`th (counting from 0) [Chunk](../chunk/struct.Chunk.html)
+    /// with the given [ChunkType](../chunk_type/struct.ChunkType.html).
+    pub fn nth_chunk_by_type(&self, chunk_type: ChunkType, n: usize) -> Option<&Chunk> {
+        self.chunks
+            .iter()
+            .filter(|c| c.chunk_type() == &chunk_type)
+            .nth(n)
+    }
+
+    /// Find the first [Chunk](../chunk/struct.Chunk.html) with the given
+    /// [ChunkType](../chunk_type/struct.ChunkType.html) by reading a PNG from
+    /// `reader`, without reading the whole PNG into memory.
+    pub fn find_chunk_streaming<R: Read + Seek>(
+        reader: &mut R,
+        chunk_type: ChunkType,
+    ) -> crate::Result<Option<Chunk>> {
+        Self::find_nth_chunk_streaming(reader, chunk_type, 0, &ParseOptions::default())
+    }
+
+    /// Like [find_chunk_streaming](#method.find_chunk_streaming), but finds
+    /// the `n`th (counting from 0) chunk with the given type, using the given
+    /// [ParseOptions](struct.ParseOptions.html).
+    ///
+    /// Reading stops as soon as that chunk has been read (and its CRC checked,
+    /// if the options say to). Every chunk before it is skipped over using its
+    /// length field, so (unlike parsing the whole PNG) their data is never read
+    /// or checked. A PNG that's cut off before the chunk is found is an error,
+    /// not a PNG without it.
+    pub fn find_nth_chunk_streaming<R: Read + Seek>(
+        reader: &mut R,
+        chunk_type: ChunkType,
+        n: usize,
+        options: &ParseOptions,
+    ) -> crate::Result<Option<Chunk>> {
+        let length = match Png::seek_to_nth_chunk(reader, chunk_type, n, options)? {
+            Some(length) => length,
+            None => return Ok(None),
+        };
+        // The data and the 4-byte CRC.
+        let mut rest = vec![0; usize::try_from(length)? + 4];
+        reader.read_exact(&mut rest)?;
+        let all_bytes: Vec<u8> = length
+            .to_be_bytes()
+            .iter()
+            .chain(chunk_type.bytes().iter())
+            .copied()
+            .chain(rest)
+            .collect();
+        Ok(Some(Chunk::parse(
+            all_bytes.as_slice(),
+            options.verify_crc,
+        )?))
+    }
+
+    /// Like [find_nth_chunk_streaming](#method.find_nth_chunk_streaming), but
+    /// copies the chunk's data to `writer` as it's read rather than keeping
+    /// it, so however long the chunk is, only a small buffer is ever in
+    /// memory. Returns how many bytes were copied, or `None` if there's no
+    /// such chunk.
+    ///
+    /// The CRC can only be checked once all of the data has been read, so if
+    /// it's bad, the error comes after `writer` has been given the data.
+    pub fn copy_nth_chunk_data_streaming<R: Read + Seek, W: Write + ?Sized>(
+        reader: &mut R,
+        chunk_type: ChunkType,
+        n: usize,
+        options: &ParseOptions,
+        writer: &mut W,
+    ) -> crate::Result<Option<u64>> {
+        let length = match Png::seek_to_nth_chunk(reader, chunk_type, n, options)? {
+            Some(length) => length,
+            None => return Ok(None),
+        };
+        let mut crc = crc32::update(0, &crc32::IEEE_TABLE, &chunk_type.bytes());
+        let mut buffer = vec![0; 64 * 1024];
+        let mut left = usize::try_from(length)?;
+        while left > 0 {
+            let read = reader.read(&mut buffer[..left.min(64 * 1024)])?;
+            if read == 0 {
+                return Err(PngDecodeError::boxed(format!(
+                    "The {} chunk is truncated (its length is {}, but only {} bytes are left)",
+                    chunk_type,
+                    length,
+                    length as usize - left
+                )));
+            }
+            writer.write_all(&buffer[..read])?;
+            crc = crc32::update(crc, &crc32::IEEE_TABLE, &buffer[..read]);
+            left -= read;
+        }
+        writer.flush()?;
+        let mut provided = [0u8; 4];
+        reader.read_exact(&mut provided)?;
+        let provided = u32::from_be_bytes(provided);
+        if options.verify_crc && provided != crc {
+            return Err(PngDecodeError::boxed(format!(
+                "The {} chunk has a bad CRC (received {}, expected {})",
+                chunk_type, provided, crc
+            )));
+        }
+        Ok(Some(u64::from(length)))
+    }
+
+    /// Skip to the data of the `n`th (counting from 0) chunk with the given
+    /// type, and return its length, or `None` if there's no such chunk.
+    ///
+    /// Chunks that are cut off are an error, unless they come after IEND,
+    /// where they're taken for trailing data, the same as
+    /// [parse_with](#method.parse_with) does.
+    fn seek_to_nth_chunk<R: Read + Seek>(
+        reader: &mut R,
+        chunk_type: ChunkType,
+        mut n: usize,
+        options: &ParseOptions,
+    ) -> crate::Result<Option<u32>> {
+        Png::read_header(reader)?;
+        let mut position = reader.stream_position()?;
+        let file_length = reader.seek(SeekFrom::End(0))?;
+        let mut after_iend = false;
+        let mut count = 0;
+        while position < file_length {
+            let left = file_length - position;
+            if after_iend && left < 12 {
+                break;
+            }
+            if left < 12 {
+                return Err(PngDecodeError::boxed(format!(
+                    "Chunk #{} is truncated (only {} bytes are left)",
+                    count, left
+                )));
+            }
+            // The length and chunk type of each chunk.
+            let mut prefix = [0u8; 8];
+            reader.seek(SeekFrom::Start(position))?;
+            reader.read_exact(&mut prefix)?;
+            let length = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+            let chunk_length = u64::from(length) + 12;
+            if chunk_length > left {
+                if after_iend {
+                    break;
+                }
+                return Err(PngDecodeError::boxed(format!(
+                    "Chunk #{} is truncated (its length is {}, but only {} bytes are left)",
+                    count,
+                    length,
+                    left - 12
+                )));
+            }
+            if prefix[4..] == chunk_type.bytes() {
+                if options.max_chunk_len.is_some_and(|max| length > max) {
+                    return Err(PngDecodeError::boxed(format!(
+                        "A {} chunk is {} bytes long (at most {} allowed)",
+                        chunk_type,
+                        length,
+                        options.max_chunk_len.unwrap_or_default()
+                    )));
+                }
+                if n == 0 {
+                    return Ok(Some(length));
+                }
+                n -= 1;
+            }
+            after_iend |= prefix[4..] == ChunkType::IEND.bytes();
+            position += chunk_length;
+            count += 1;
+        }
+        Ok(None)
+    }
+
+    /// Add `chunks` to the PNG in `file` without rewriting the rest of it.
+    /// The file ends up the same as parsing it, calling
+    /// [append_chunk](#method.append_chunk) with each chunk, and writing it
+    /// back out, but only the new chunks are written.
+    ///
+    /// Every chunk already there is checked the way parsing it would, CRC
+    /// included, but read a piece at a time rather than all at once. If
+    /// there's [trailing data](#method.trailing_data), it would have to be
+    /// moved to make room, and a write cut off partway through would lose it,
+    /// so `file` is left alone and `Ok(false)` returned; the PNG has to be
+    /// rewritten instead.
+    pub fn append_chunks_in_place<F: Read + Write + Seek>(
+        file: &mut F,
+        chunks: &[Chunk],
+    ) -> crate::Result<bool> {
+        let end = Png::end_of_chunks(file)?;
+        if file.seek(SeekFrom::End(0))? != end {
+            return Ok(false);
+        }
+        for chunk in chunks {
+            file.write_all(&chunk.as_bytes())?;
+        }
+        file.flush()?;
+        Ok(true)
+    }
+
+    /// The offset just past the last chunk in `reader`, which is where any
+    /// trailing data starts. This follows the same rules as
+    /// [parse_with](#method.parse_with) for what counts as a chunk after IEND,
+    /// and checks every chunk's CRC.
+    fn end_of_chunks<R: Read + Seek>(reader: &mut R) -> crate::Result<u64> {
+        Png::read_header(reader)?;
+        let file_length = reader.seek(SeekFrom::End(0))?;
+        let mut position = Png::STANDARD_HEADER.len() as u64;
+        let mut after_iend = false;
+        let mut count = 0;
+        while position < file_length {
+            let left = file_length - position;
+            if left < 4 || (after_iend && left < 12) {
+                break;
+            }
+            if left < 12 {
+                return Err(PngDecodeError::boxed(format!(
+                    "Chunk #{} is truncated (only {} bytes are left)",
+                    count, left
+                )));
+            }
+            let mut prefix = [0u8; 8];
+            reader.seek(SeekFrom::Start(position))?;
+            reader.read_exact(&mut prefix)?;
+            let length = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+            let chunk_length = u64::from(length) + 12;
+            if chunk_length > left {
+                if after_iend {
+                    break;
+                }
+                return Err(PngDecodeError::boxed(format!(
+                    "Chunk #{} is truncated (its length is {}, but only {} bytes are left)",
+                    count,
+                    length,
+                    left - 12
+                )));
+            }
+            if !Png::crc_matches(reader, &prefix, length)? {
+                // A chunk after IEND with a bad CRC is trailing data.
+                if after_iend {
+                    break;
+                }
+                return Err(PngDecodeError::boxed(format!(
+                    "Chunk #{} ({}) has a bad CRC",
+                    count,
+                    String::from_utf8_lossy(&prefix[4..])
+                )));
+            }
+            after_iend |= prefix[4..] == ChunkType::IEND.bytes();
+            position += chunk_length;
+            count += 1;
+        }
+        Ok(position)
     }
 
-    /// Every byte in this PNG, including the header and each
-    /// [Chunk](../chunk/struct.Chunk.html).
+    /// Whether the CRC of the chunk with the length and chunk type in `prefix`
+    /// matches its data, which `reader` is at the start of. The data is read
+    /// a piece at a time, so it's never all in memory.
+    fn crc_matches<R: Read>(reader: &mut R, prefix: &[u8; 8], length: u32) -> crate::Result<bool> {
+        let mut crc = crc32::update(0, &crc32::IEEE_TABLE, &prefix[4..]);
+        let mut buffer = vec![0; 64 * 1024];
+        let mut left = usize::try_from(length)?;
+        while left > 0 {
+            let piece = &mut buffer[..left.min(64 * 1024)];
+            reader.read_exact(piece)?;
+            crc = crc32::update(crc, &crc32::IEEE_TABLE, piece);
+            left -= piece.len();
+        }
+        let mut provided = [0u8; 4];
+        reader.read_exact(&mut provided)?;
+        Ok(u32::from_be_bytes(provided) == crc)
+    }
+
+    /// Read the standard PNG header, returning `Err` if it's wrong.
+    pub(crate) fn read_header<R: Read>(reader: &mut R) -> crate::Result<()> {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        if header != Png::STANDARD_HEADER {
+            return Err(PngDecodeError::boxed(format!(
+                "Bad header (received {:?}, expected {:?})",
+                header,
+                Png::STANDARD_HEADER
+            )));
+        }
+        Ok(())
+    }
+
+    /// Every byte in this PNG, including the header, each
+    /// [Chunk](../chunk/struct.Chunk.html), and any trailing data.
     pub fn as_bytes(&self) -> Vec<u8> {
-        let chunk_iterators: Vec<u8> = self.chunks.iter().map(|c| c.as_bytes()).flatten().collect();
+        let chunk_iterators: Vec<u8> = self.chunks.iter().flat_map(|c| c.as_bytes()).collect();
         self.header()
             .iter()
             .chain(chunk_iterators.iter())
+            .chain(self.trailing_data.iter())
             .copied()
             .collect()
     }
+
+    /// Any bytes after the IEND chunk that aren't chunks themselves. Some
+    /// tools hide data here, since image viewers ignore it.
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing_data
+    }
+
+    /// Add bytes to the end of the [trailing data](#method.trailing_data).
+    pub fn append_trailing_data(&mut self, bytes: &[u8]) {
+        self.trailing_data.extend_from_slice(bytes);
+    }
+
+    /// Remove and return any [trailing data](#method.trailing_data).
+    pub fn strip_trailing_data(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.trailing_data)
+    }
+
+    /// Check every chunk's CRC. This is only useful for a PNG that was parsed
+    /// with [verify_crc](struct.ParseOptions.html#structfield.verify_crc) off,
+    /// since otherwise a bad CRC stops parsing. Returns `Err` saying how many
+    /// chunks have a bad CRC, if any do.
+    pub fn check_crcs(&self) -> crate::Result<()> {
+        let bad = self.chunks.iter().filter(|c| !c.has_valid_crc()).count();
+        if bad > 0 {
+            return Err(PngDecodeError::boxed(format!(
+                "{} chunk{} a bad CRC",
+                bad,
+                if bad == 1 { " has" } else { "s have" }
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The smallest useful image: one transparent pixel, in 8-bit RGBA. It's a
+/// valid carrier for a message without having an image to hand.
+impl Default for Png {
+    fn default() -> Self {
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&1u32.to_be_bytes());
+        header.extend_from_slice(&1u32.to_be_bytes());
+        // Bit depth 8, RGBA, and the only compression, filter, and interlace
+        // methods there are.
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        // A single scanline: filter type 0, then a pixel with no alpha.
+        let mut image_data = Vec::new();
+        ZlibEncoder::new(&[0u8, 0, 0, 0, 0][..], Compression::default())
+            .read_to_end(&mut image_data)
+            .expect("compressing a slice can't fail");
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, header),
+            Chunk::new(ChunkType::IDAT, image_data),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ])
+    }
 }
+
 impl fmt::Display for Png {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for chunk in &self.chunks {
@@ -78,44 +817,229 @@ impl fmt::Display for Png {
         Ok(())
     }
 }
+
+/// The parts of a PNG, in order, that the spec says each kind of chunk has to
+/// be in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum OrderPhase {
+    Header,
+    BeforePalette,
+    Palette,
+    AfterPalette,
+    ImageData,
+    AfterImageData,
+    End,
+}
+
+/// The earliest and latest [OrderPhase] a chunk of type `chunk_type` is
+/// allowed in. The palette only splits up the chunks before the image data if
+/// there is one (`has_plte`).
+fn allowed_phases(chunk_type: &ChunkType, has_plte: bool) -> (OrderPhase, OrderPhase) {
+    use OrderPhase::*;
+    match &chunk_type.bytes() {
+        b"IHDR" => (Header, Header),
+        b"cHRM" | b"cICP" | b"gAMA" | b"iCCP" | b"mDCV" | b"cLLI" | b"sBIT" | b"sRGB"
+            if has_plte =>
+        {
+            (BeforePalette, BeforePalette)
+        }
+        b"PLTE" => (Palette, Palette),
+        b"tRNS" | b"hIST" | b"bKGD" if has_plte => (AfterPalette, AfterPalette),
+        b"cHRM" | b"cICP" | b"gAMA" | b"iCCP" | b"mDCV" | b"cLLI" | b"sBIT" | b"sRGB" | b"tRNS"
+        | b"hIST" | b"bKGD" | b"pHYs" | b"sPLT" | b"oFFs" | b"pCAL" | b"sCAL" | b"acTL" => {
+            (BeforePalette, AfterPalette)
+        }
+        b"IDAT" => (ImageData, ImageData),
+        b"IEND" => (End, End),
+        _ => (BeforePalette, AfterImageData),
+    }
+}
+
+/// Where a chunk of type `chunk_type` belongs, as a number that's smaller the
+/// nearer the start it belongs. `before_idat` is whether it's currently before
+/// the image data, for chunks that are allowed on either side of it.
+fn order_rank(chunk_type: &ChunkType, before_idat: bool) -> u8 {
+    match &chunk_type.bytes() {
+        b"IHDR" => 0,
+        b"cHRM" | b"cICP" | b"gAMA" | b"iCCP" | b"mDCV" | b"cLLI" | b"sBIT" | b"sRGB" => 1,
+        b"PLTE" => 2,
+        b"tRNS" | b"hIST" | b"bKGD" | b"pHYs" | b"sPLT" | b"oFFs" | b"pCAL" | b"sCAL" | b"acTL" => {
+            3
+        }
+        b"IDAT" => 5,
+        b"IEND" => 7,
+        _ if before_idat => 4,
+        _ => 6,
+    }
+}
+
 impl TryFrom<&[u8]> for Png {
     type Error = crate::Error;
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
-        let mut reader = BufReader::new(bytes);
-        let mut header = [0u8; 8];
-        reader.read_exact(&mut header)?;
-        if header != Png::STANDARD_HEADER {
-            return Err(PngDecodeError::boxed(format!(
-                "Bad header (received {:?}, expected {:?})",
-                header,
-                Png::STANDARD_HEADER
-            )));
+        Png::parse_with(bytes, &ParseOptions::default())
+    }
+}
+
+/// Settings for how a PNG is read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseOptions {
+    /// How closely the PNG has to follow the spec.
+    pub strictness: Strictness,
+    /// Check each chunk's CRC, and fail if it doesn't match. Turning this off
+    /// is faster, but damaged chunks go unnoticed, so only do it when the PNG
+    /// is only being read and its CRCs were already checked elsewhere.
+    pub verify_crc: bool,
+    /// Fail on any chunk with more data than this, instead of reading it into
+    /// memory. `None` allows up to
+    /// [Chunk::MAXIMUM_LENGTH](../struct.Chunk.html#associatedconstant.MAXIMUM_LENGTH).
+    pub max_chunk_len: Option<u32>,
+    /// Fail if there are more chunks than this.
+    pub max_chunks: Option<usize>,
+    /// Keep any data after IEND that isn't a chunk as
+    /// [trailing data](struct.Png.html#method.trailing_data). Without this,
+    /// it's an error.
+    pub allow_trailing: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            strictness: Strictness::default(),
+            verify_crc: true,
+            max_chunk_len: None,
+            max_chunks: None,
+            allow_trailing: true,
         }
-        // Continually read the 4 bytes for length, then try to build a chunk.
-        // Every other field is 4 bytes, so once we read the length, we can
-        // infer everything else.
+    }
+}
+
+/// How closely a PNG has to follow the spec to be read. Chunks always have to
+/// be well-formed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    /// Read the chunks in whatever order they are in, including chunks after
+    /// IEND.
+    #[default]
+    Lenient,
+    /// The PNG must start with IHDR and end with IEND, its chunks must be in
+    /// an order the spec allows (see
+    /// [sort_chunks](struct.Png.html#method.sort_chunks)), and every chunk
+    /// type's reserved bit must be valid.
+    Strict,
+}
+
+impl Png {
+    /// Read a PNG from `bytes` using the given
+    /// [ParseOptions](struct.ParseOptions.html). `Png::try_from` uses the
+    /// default options.
+    pub fn parse_with(bytes: &[u8], options: &ParseOptions) -> crate::Result<Self> {
+        Png::read_header(&mut &bytes[..])?;
         let mut chunks = Vec::new();
-        let mut length_buffer = [0u8; 4];
-        while let Ok(()) = reader.read_exact(&mut length_buffer) {
-            // The final position is where a valid chunk ends, counting from the
-            // current position of the reader. We determine its size like so:
-            //   4 bytes of length (already read, so not reading again)
-            //   -----------------------
-            //   4 bytes of chunk type
-            // + `length` bytes of data
-            // + 4 bytes of CRC
-            let final_position = 4 + u32::from_be_bytes(length_buffer) + 4;
-            let mut buffer = vec![0; usize::try_from(final_position)?];
-            reader.read_exact(&mut buffer)?;
-            let all_bytes: Vec<u8> = length_buffer
-                .iter()
-                .copied()
-                .chain(buffer.into_iter())
-                .collect();
-            let chunk = Chunk::try_from(all_bytes.as_slice())?;
+        let mut trailing_data = Vec::new();
+        let mut position = Png::STANDARD_HEADER.len();
+        let mut after_iend = false;
+        while position < bytes.len() {
+            let rest = &bytes[position..];
+            if let (Some(max), [a, b, c, d, ..]) = (options.max_chunk_len, rest) {
+                let length = u32::from_be_bytes([*a, *b, *c, *d]);
+                // After IEND, something too long might just be trailing data.
+                if length > max && !after_iend {
+                    return Err(PngDecodeError::boxed(format!(
+                        "Chunk #{} at offset {:#X} is {} bytes long (at most {} allowed)",
+                        chunks.len(),
+                        position,
+                        length,
+                        max
+                    )));
+                }
+            }
+            if options.max_chunks == Some(chunks.len()) {
+                return Err(PngDecodeError::boxed(format!(
+                    "There are more than {} chunks",
+                    chunks.len()
+                )));
+            }
+            // Chunks after IEND are allowed (pngme itself used to put them
+            // there), but anything after IEND that isn't a whole chunk with a
+            // good CRC is trailing data. So are a few stray bytes at the end,
+            // too short to even hold a chunk's length.
+            let chunk = if after_iend || rest.len() < 4 {
+                match Png::next_chunk(rest, true) {
+                    Ok(chunk) => chunk,
+                    Err(_) => {
+                        trailing_data = rest.to_vec();
+                        break;
+                    }
+                }
+            } else {
+                Png::next_chunk(rest, options.verify_crc)
+                    .map_err(|e| ChunkParseError::new(bytes, position, chunks.len(), e))?
+            };
+            // 4 bytes each of length, chunk type, and CRC, plus the data.
+            position += 12 + chunk.data().len();
+            after_iend |= chunk.chunk_type() == &ChunkType::IEND;
             chunks.push(chunk);
         }
-        Ok(Png::from_chunks(chunks))
+        if !options.allow_trailing && !trailing_data.is_empty() {
+            return Err(PngDecodeError::boxed(format!(
+                "There are {} bytes of data after IEND",
+                trailing_data.len()
+            )));
+        }
+        let mut png = Png::from_chunks(chunks);
+        png.trailing_data = trailing_data;
+        if options.strictness == Strictness::Strict {
+            png.check_strict()?;
+        }
+        Ok(png)
+    }
+
+    /// Check the things that [Strictness::Strict](enum.Strictness.html)
+    /// requires.
+    fn check_strict(&self) -> crate::Result<()> {
+        let first = self.chunks.first().map(|c| *c.chunk_type());
+        let last = self.chunks.last().map(|c| *c.chunk_type());
+        if first != Some(ChunkType::IHDR) {
+            return Err(PngDecodeError::boxed(
+                "The first chunk isn't IHDR".to_string(),
+            ));
+        }
+        if last != Some(ChunkType::IEND) {
+            return Err(PngDecodeError::boxed(
+                "The last chunk isn't IEND".to_string(),
+            ));
+        }
+        if let Some(index) = self.first_misordered_chunk() {
+            return Err(PngDecodeError::boxed(format!(
+                "Chunk #{} ({}) is out of order",
+                index,
+                self.chunks[index].chunk_type()
+            )));
+        }
+        if let Some(chunk) = self.chunks.iter().find(|c| !c.chunk_type().is_valid()) {
+            return Err(PngDecodeError::boxed(format!(
+                "Chunk type {} has its reserved bit set",
+                chunk.chunk_type()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Read the chunk at the start of `bytes`.
+    fn next_chunk(bytes: &[u8], verify_crc: bool) -> crate::Result<Chunk> {
+        let length = match bytes {
+            [a, b, c, d, ..] => u32::from_be_bytes([*a, *b, *c, *d]),
+            _ => return Err(PngDecodeError::boxed("Chunk is truncated".to_string())),
+        };
+        let end = usize::try_from(length)?.saturating_add(12);
+        if bytes.len() < end {
+            return Err(PngDecodeError::boxed(format!(
+                "Chunk is truncated (its length is {}, but only {} bytes are left)",
+                length,
+                bytes.len().saturating_sub(12)
+            )));
+        }
+        Chunk::parse(&bytes[..end], verify_crc)
     }
 }
 
@@ -126,7 +1050,7 @@ pub struct PngDecodeError {
     reason: String,
 }
 impl PngDecodeError {
-    fn boxed(reason: String) -> Box<Self> {
+    pub(crate) fn boxed(reason: String) -> Box<Self> {
         Box::new(Self { reason })
     }
 }
@@ -138,14 +1062,211 @@ impl fmt::Display for PngDecodeError {
 }
 impl Error for PngDecodeError {}
 
+/// A chunk in a PNG could not be read, along with where it is in the file.
+#[derive(Debug)]
+pub struct ChunkParseError {
+    /// Which chunk (counting from 0) could not be read.
+    index: usize,
+    /// The offset of the start of the chunk.
+    offset: usize,
+    /// The chunk's type, if it has a valid one.
+    chunk_type: Option<ChunkType>,
+    /// The bytes that are wrong: the CRC if that's the only problem,
+    /// otherwise the chunk's length and type.
+    span: Range<usize>,
+    /// Up to [CONTEXT_LENGTH](#associatedconstant.CONTEXT_LENGTH) bytes on
+    /// either side of `span`.
+    context: Vec<u8>,
+    /// The offset of the start of `context`.
+    context_offset: usize,
+    source: crate::Error,
+}
+
+impl ChunkParseError {
+    /// How many bytes before and after the bad bytes are kept, to show them
+    /// in context.
+    pub const CONTEXT_LENGTH: usize = 32;
+
+    fn new(bytes: &[u8], offset: usize, index: usize, source: crate::Error) -> Self {
+        let rest = &bytes[offset..];
+        let chunk_type = rest
+            .get(4..8)
+            .and_then(|b| ChunkType::try_from([b[0], b[1], b[2], b[3]]).ok());
+        // If the chunk reads fine without checking its CRC, the CRC is what's
+        // wrong.
+        let span = match Png::next_chunk(rest, false) {
+            Ok(chunk) => {
+                let crc = offset + 8 + chunk.data().len();
+                crc..crc + 4
+            }
+            Err(_) => offset..(offset + 8).min(bytes.len()),
+        };
+        let context_offset = span.start.saturating_sub(Self::CONTEXT_LENGTH);
+        let context_end = (span.end + Self::CONTEXT_LENGTH).min(bytes.len());
+        ChunkParseError {
+            index,
+            offset,
+            chunk_type,
+            span,
+            context: bytes[context_offset..context_end].to_vec(),
+            context_offset,
+            source,
+        }
+    }
+
+    /// Which chunk (counting from 0) could not be read.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The offset in the file of the start of the chunk.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The offsets in the file of the bytes that are wrong.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// The bytes around [span](#method.span), and the offset in the file
+    /// that they start at.
+    pub fn context(&self) -> (usize, &[u8]) {
+        (self.context_offset, &self.context)
+    }
+}
+
+impl fmt::Display for ChunkParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in chunk #{}", self.source, self.index)?;
+        if let Some(chunk_type) = &self.chunk_type {
+            write!(f, " `{}`", chunk_type)?;
+        }
+        write!(f, " at offset {:#X}", self.offset)
+    }
+}
+impl Error for ChunkParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Moving a chunk would have put a chunk out of order.
+#[derive(Debug)]
+pub struct ChunkOrderError {
+    moved: ChunkType,
+    misordered: ChunkType,
+    /// Where the chunk left out of order would have ended up.
+    index: usize,
+}
+
+impl fmt::Display for ChunkOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Moving {} there would leave {} (chunk {}) out of order",
+            self.moved, self.misordered, self.index
+        )
+    }
+}
+impl Error for ChunkOrderError {}
+
+/// There is no chunk of the type that was asked for.
 #[derive(Debug)]
 pub struct ChunkNotFoundError {
     chunk_type: String,
+    /// Which occurrence (counting from 0) of the chunk type was requested.
+    n: usize,
+    /// The chunk types that are there instead, each once, in the order they
+    /// first appear. Empty if they aren't known.
+    present: Vec<ChunkType>,
 }
+impl ChunkNotFoundError {
+    /// The `n`th (counting from 0) chunk of type `chunk_type` is missing.
+    pub fn new(chunk_type: ChunkType, n: usize) -> Self {
+        ChunkNotFoundError {
+            chunk_type: chunk_type.to_string(),
+            n,
+            present: Vec::new(),
+        }
+    }
+
+    /// Note the chunk types that are there instead, so that the error can
+    /// list them and suggest the one that was probably meant.
+    pub fn with_present<I: IntoIterator<Item = ChunkType>>(mut self, chunk_types: I) -> Self {
+        for chunk_type in chunk_types {
+            if !self.present.contains(&chunk_type) {
+                self.present.push(chunk_type);
+            }
+        }
+        self
+    }
+
+    /// The chunk type that's there which was most likely meant: one that only
+    /// differs in case, or failing that, the nearest one that's at most two
+    /// letters off. Case typos are the most common reason a message seems to
+    /// have vanished. There's no suggestion if the type that was asked for is
+    /// there, and it was just a later occurrence that's missing.
+    pub fn suggestion(&self) -> Option<ChunkType> {
+        if self
+            .present
+            .iter()
+            .any(|t| t.to_string() == self.chunk_type)
+        {
+            return None;
+        }
+        let wanted = self.chunk_type.to_ascii_lowercase();
+        self.present
+            .iter()
+            .map(|t| {
+                let letters = t.to_string();
+                let distance = edit_distance(&wanted, &letters.to_ascii_lowercase());
+                // Break ties by how many letters differ in case too.
+                ((distance, edit_distance(&self.chunk_type, &letters)), *t)
+            })
+            .filter(|((distance, _), _)| *distance <= 2)
+            .min_by_key(|(distances, _)| *distances)
+            .map(|(_, t)| t)
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`: how many letters have to be
+/// inserted, deleted, or changed to turn one into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, &x) in a.as_bytes().iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &y) in b.iter().enumerate() {
+            let changed = previous[j] + usize::from(x != y);
+            current.push(changed.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 impl Error for ChunkNotFoundError {}
 impl fmt::Display for ChunkNotFoundError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Chunk not found with type {}", self.chunk_type)
+        if self.n == 0 {
+            write!(f, "Chunk not found with type {}", self.chunk_type)?;
+        } else {
+            write!(
+                f,
+                "Chunk not found with type {} (occurrence {})",
+                self.chunk_type,
+                self.n + 1
+            )?;
+        }
+        if !self.present.is_empty() {
+            let present: Vec<String> = self.present.iter().map(|t| t.to_string()).collect();
+            write!(f, " (there are {})", present.join(", "))?;
+        }
+        if let Some(suggestion) = self.suggestion() {
+            write!(f, ". Did you mean {}?", suggestion)?;
+        }
+        Ok(())
     }
 }
 
@@ -155,16 +1276,15 @@ mod tests {
     use crate::chunk::Chunk;
     use crate::chunk_type::ChunkType;
     use std::convert::TryFrom;
+    use std::io::Cursor;
     use std::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -245,6 +1365,24 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_default() {
+        use crate::info::ImageHeader;
+        use flate2::read::ZlibDecoder;
+
+        let png = Png::try_from(Png::default().as_bytes().as_slice()).unwrap();
+        let header = ImageHeader::from_png(&png).unwrap();
+        assert_eq!((header.width, header.height), (1, 1));
+        assert_eq!(header.channels(), Some(4));
+        let mut pixels = Vec::new();
+        ZlibDecoder::new(png.chunk_by_type(ChunkType::IDAT).unwrap().data())
+            .read_to_end(&mut pixels)
+            .unwrap();
+        assert_eq!(pixels.len() as u64, header.raw_data_len());
+        assert_eq!(pixels, [0; 5]);
+        assert!(!Png::default().sort_chunks());
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -256,7 +1394,7 @@ mod tests {
     fn test_chunk_by_type() {
         let png = testing_png();
         let chunk = png
-            .chunk_by_type(ChunkType::from_str(&"FrSt").unwrap())
+            .chunk_by_type(ChunkType::from_str("FrSt").unwrap())
             .unwrap();
         assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
@@ -267,7 +1405,7 @@ mod tests {
         let mut png = testing_png();
         png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
         let chunk = png
-            .chunk_by_type(ChunkType::from_str(&"TeSt").unwrap())
+            .chunk_by_type(ChunkType::from_str("TeSt").unwrap())
             .unwrap();
         assert_eq!(&chunk.chunk_type().to_string(), "TeSt");
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
@@ -279,10 +1417,609 @@ mod tests {
         png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
         png.remove_chunk(ChunkType::from_str("TeSt").unwrap())
             .unwrap();
-        let chunk = png.chunk_by_type(ChunkType::from_str(&"TeSt").unwrap());
+        let chunk = png.chunk_by_type(ChunkType::from_str("TeSt").unwrap());
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_chunk_not_found_suggestion() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+        let error = match png.remove_chunk(ChunkType::from_str("rust").unwrap()) {
+            Err(error) => error,
+            Ok(_) => panic!("There's no rust chunk"),
+        };
+        assert_eq!(
+            error.suggestion(),
+            Some(ChunkType::from_str("ruSt").unwrap())
+        );
+        assert!(error.to_string().ends_with(", ruSt). Did you mean ruSt?"));
+
+        let present = ["FrSt", "ruSt", "miDl", "ruSt"].map(|t| ChunkType::from_str(t).unwrap());
+        let suggest = |wanted: &str| {
+            ChunkNotFoundError::new(ChunkType::from_str(wanted).unwrap(), 0)
+                .with_present(present)
+                .suggestion()
+                .map(|t| t.to_string())
+        };
+        assert_eq!(suggest("rsUt").as_deref(), Some("ruSt"));
+        assert_eq!(suggest("RUST").as_deref(), Some("ruSt"));
+        assert_eq!(suggest("abcd"), None);
+        // The type that was asked for isn't suggested for a missing occurrence.
+        assert_eq!(suggest("ruSt"), None);
+        assert_eq!(
+            ChunkNotFoundError::new(ChunkType::from_str("abcd").unwrap(), 0)
+                .with_present(present)
+                .to_string(),
+            "Chunk not found with type abcd (there are FrSt, ruSt, miDl)"
+        );
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("rust", "rust"), 0);
+        assert_eq!(edit_distance("rust", "rsut"), 2);
+        assert_eq!(edit_distance("rust", "rest"), 1);
+        assert_eq!(edit_distance("", "rust"), 4);
+    }
+
+    #[test]
+    fn test_retain_chunks() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "First").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "Second").unwrap());
+        let before = png.chunks().len();
+        png.retain_chunks(|c| c.chunk_type() != &ChunkType::from_str("TeSt").unwrap());
+        assert_eq!(png.chunks().len(), before - 2);
+        assert!(png
+            .chunk_by_type(ChunkType::from_str("TeSt").unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn test_get() {
+        let mut png = image_png();
+        let gama = ChunkType::from_str("gAMA").unwrap();
+        assert_eq!(&png.get(gama).unwrap().data_as_string().unwrap(), "gamma");
+        assert!(png.get(ChunkType::from_str("ruSt").unwrap()).is_none());
+
+        *png.get_mut(gama).unwrap() = chunk_from_strings("gAMA", "changed").unwrap();
+        assert_eq!(&png.chunks()[1].data_as_string().unwrap(), "changed");
+        assert!(png.get_mut(ChunkType::from_str("ruSt").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_chunks_mut() {
+        let mut png = testing_png();
+        let last = ChunkType::from_str("LASt").unwrap();
+        for chunk in png.chunks_mut() {
+            if chunk.chunk_type() != &last {
+                let data = chunk.data().to_ascii_uppercase();
+                chunk.set_data(data);
+            }
+        }
+        png.chunks_mut()[2] = chunk_from_strings("laSt", "Replaced").unwrap();
+        let chunks: Vec<String> = png.chunks().iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            chunks,
+            [
+                "FrSt\tI AM THE FIRST CHUNK",
+                "miDl\tI AM ANOTHER CHUNK",
+                "laSt\tReplaced"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_duplicate_chunks() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("teSt", "First").unwrap());
+        png.append_chunk(chunk_from_strings("teSt", "First").unwrap());
+        png.append_chunk(chunk_from_strings("teSt", "Second").unwrap());
+        png.append_chunk(chunk_from_strings("ruSt", "First").unwrap());
+        png.append_chunk(chunk_from_strings("IDAT", "pixels").unwrap());
+        png.append_chunk(chunk_from_strings("IDAT", "pixels").unwrap());
+        png.append_chunk(chunk_from_strings("teSt", "First").unwrap());
+        let before = png.chunks().len();
+        let removed = png.remove_duplicate_chunks();
+        assert_eq!(removed.len(), 2);
+        assert!(removed
+            .iter()
+            .all(|c| c.chunk_type().to_string() == "teSt" && c.data() == b"First"));
+        assert_eq!(png.chunks().len(), before - 2);
+        assert!(png.remove_duplicate_chunks().is_empty());
+    }
+
+    #[test]
+    fn test_nth_chunk_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "First").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "Second").unwrap());
+        let chunk = png
+            .nth_chunk_by_type(ChunkType::from_str("TeSt").unwrap(), 1)
+            .unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "Second");
+        assert!(png
+            .nth_chunk_by_type(ChunkType::from_str("TeSt").unwrap(), 2)
+            .is_none());
+    }
+
+    #[test]
+    fn test_remove_nth_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "First").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "Second").unwrap());
+        let removed = png
+            .remove_nth_chunk(ChunkType::from_str("TeSt").unwrap(), 1)
+            .unwrap();
+        assert_eq!(&removed.data_as_string().unwrap(), "Second");
+        let chunk = png
+            .chunk_by_type(ChunkType::from_str("TeSt").unwrap())
+            .unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "First");
+        assert!(png
+            .remove_nth_chunk(ChunkType::from_str("TeSt").unwrap(), 1)
+            .is_err());
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect()
+    }
+
+    fn image_png() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("gAMA", "gamma").unwrap(),
+            chunk_from_strings("IDAT", "data 1").unwrap(),
+            chunk_from_strings("IDAT", "data 2").unwrap(),
+            chunk_from_strings("tEXt", "text").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let bytes = image_png().as_bytes();
+        let limited = |options: ParseOptions| Png::parse_with(&bytes, &options).is_ok();
+        assert!(limited(ParseOptions {
+            max_chunk_len: Some(6),
+            max_chunks: Some(6),
+            ..ParseOptions::default()
+        }));
+        assert!(!limited(ParseOptions {
+            max_chunk_len: Some(5),
+            ..ParseOptions::default()
+        }));
+        assert!(!limited(ParseOptions {
+            max_chunks: Some(5),
+            ..ParseOptions::default()
+        }));
+        let mut streaming = Cursor::new(&bytes);
+        assert!(Png::find_nth_chunk_streaming(
+            &mut streaming,
+            ChunkType::IDAT,
+            0,
+            &ParseOptions {
+                max_chunk_len: Some(5),
+                ..ParseOptions::default()
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_trailing_data() {
+        let mut png = image_png();
+        png.append_trailing_data(b"extra");
+        let bytes = png.as_bytes();
+        assert!(Png::try_from(bytes.as_slice()).is_ok());
+        let options = ParseOptions {
+            allow_trailing: false,
+            ..ParseOptions::default()
+        };
+        assert!(Png::parse_with(&bytes, &options).is_err());
+        assert!(Png::parse_with(&image_png().as_bytes(), &options).is_ok());
+    }
+
+    #[test]
+    fn test_parse_strict() {
+        let strict = ParseOptions {
+            strictness: Strictness::Strict,
+            ..ParseOptions::default()
+        };
+        assert!(Png::parse_with(&image_png().as_bytes(), &strict).is_ok());
+
+        // gAMA after the image data.
+        let mut misordered = image_png();
+        let gama = misordered.remove_chunk(ChunkType::from_str("gAMA").unwrap());
+        misordered.insert_chunk(4, gama.unwrap());
+        let bytes = misordered.as_bytes();
+        assert!(Png::try_from(bytes.as_slice()).is_ok());
+        assert!(Png::parse_with(&bytes, &strict).is_err());
+
+        let mut after_iend = image_png();
+        after_iend.append_chunk(chunk_from_strings("ruSt", "Secret").unwrap());
+        assert!(Png::parse_with(&after_iend.as_bytes(), &strict).is_err());
+
+        let mut reserved = image_png();
+        reserved.insert_chunk(1, chunk_from_strings("rust", "Secret").unwrap());
+        assert!(Png::parse_with(&reserved.as_bytes(), &strict).is_err());
+
+        assert!(Png::parse_with(&testing_png().as_bytes(), &strict).is_err());
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let mut bytes = image_png().as_bytes();
+        // Damage the CRC of the tEXt chunk, which is 10 bytes from the end.
+        let crc = bytes.len() - 12 - 4;
+        bytes[crc] ^= 1;
+        let error = Png::try_from(bytes.as_slice()).err().unwrap();
+        let error = error.downcast_ref::<ChunkParseError>().unwrap();
+        assert_eq!(error.index(), 4);
+        assert_eq!(error.span(), crc..crc + 4);
+        assert_eq!(error.offset(), crc - 4 - 8);
+        assert!(error
+            .to_string()
+            .ends_with(&format!("in chunk #4 `tEXt` at offset {:#X}", crc - 12)));
+        let (start, context) = error.context();
+        assert_eq!(start, crc - ChunkParseError::CONTEXT_LENGTH);
+        assert_eq!(context, &bytes[start..]);
+
+        // A chunk whose length runs off the end points at its length and type.
+        let mut bytes = image_png().as_bytes();
+        let length = bytes.len() - 12 - 16;
+        bytes[length] = 0x7f;
+        let error = Png::try_from(bytes.as_slice()).err().unwrap();
+        let error = error.downcast_ref::<ChunkParseError>().unwrap();
+        assert_eq!(error.span(), length..length + 8);
+    }
+
+    #[test]
+    fn test_parse_without_verifying_crc() {
+        let mut bytes = image_png().as_bytes();
+        let end = bytes.len() - 12;
+        bytes[end - 1] ^= 1;
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+        let png = Png::parse_with(
+            &bytes,
+            &ParseOptions {
+                verify_crc: false,
+                ..ParseOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(png.chunks().len(), 6);
+        // The damaged CRC is kept as-is.
+        assert_eq!(png.as_bytes(), bytes);
+        assert!(png.check_crcs().is_err());
+        assert!(!png.chunks()[4].has_valid_crc());
+        assert!(image_png().check_crcs().is_ok());
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = image_png().as_bytes();
+        bytes.extend(b"PK\x03\x04 hidden zip file");
+        let mut png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.chunks().len(), 6);
+        assert_eq!(png.trailing_data(), b"PK\x03\x04 hidden zip file");
+        assert_eq!(png.as_bytes(), bytes);
+        assert_eq!(png.strip_trailing_data(), b"PK\x03\x04 hidden zip file");
+        assert_eq!(png.as_bytes(), image_png().as_bytes());
+    }
+
+    #[test]
+    fn test_chunks_after_iend_are_not_trailing_data() {
+        let mut png = image_png();
+        png.append_chunk(chunk_from_strings("ruSt", "after the end").unwrap());
+        let bytes = png.as_bytes();
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.chunks().len(), 7);
+        assert!(png.trailing_data().is_empty());
+    }
+
+    #[test]
+    fn test_truncated_chunk() {
+        let bytes = image_png().as_bytes();
+        let truncated = &bytes[..bytes.len() - 20];
+        assert!(Png::try_from(truncated).is_err());
+    }
+
+    #[test]
+    fn test_find_chunk_streaming() {
+        let bytes = image_png().as_bytes();
+        let mut reader = Cursor::new(&bytes);
+        let chunk = Png::find_chunk_streaming(&mut reader, ChunkType::TEXT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunk.data(), b"text");
+        // It stopped right after the tEXt chunk, before IEND.
+        assert_eq!(reader.position() as usize, bytes.len() - 12);
+
+        let mut reader = Cursor::new(&bytes);
+        let chunk = Png::find_nth_chunk_streaming(
+            &mut reader,
+            ChunkType::IDAT,
+            1,
+            &ParseOptions::default(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(chunk.data(), b"data 2");
+    }
+
+    #[test]
+    fn test_find_chunk_streaming_missing() {
+        let bytes = image_png().as_bytes();
+        let result = Png::find_chunk_streaming(&mut Cursor::new(&bytes), ChunkType::SIGNATURE);
+        assert!(result.unwrap().is_none());
+        let result = Png::find_nth_chunk_streaming(
+            &mut Cursor::new(&bytes),
+            ChunkType::TEXT,
+            1,
+            &ParseOptions::default(),
+        );
+        assert!(result.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_find_chunk_streaming_truncated() {
+        let bytes = image_png().as_bytes();
+        // Cut off partway through the second IDAT chunk's data, and then
+        // partway through the length and type of the tEXt chunk after it.
+        let in_data = &bytes[..bytes.len() - 12 - 16 - 10];
+        let in_prefix = &bytes[..bytes.len() - 12 - 16 + 3];
+        for truncated in [in_data, in_prefix] {
+            let result = Png::find_nth_chunk_streaming(
+                &mut Cursor::new(truncated),
+                ChunkType::SIGNATURE,
+                0,
+                &ParseOptions::default(),
+            );
+            assert!(result.is_err());
+            let mut copied = Vec::new();
+            let result = Png::copy_nth_chunk_data_streaming(
+                &mut Cursor::new(truncated),
+                ChunkType::TEXT,
+                0,
+                &ParseOptions::default(),
+                &mut copied,
+            );
+            assert!(result.is_err());
+        }
+        // Trailing data after IEND isn't a chunk, cut off or not.
+        let mut trailing = bytes.clone();
+        trailing.extend_from_slice(b"\0\0\x01\0tail");
+        let result = Png::find_chunk_streaming(&mut Cursor::new(&trailing), ChunkType::SIGNATURE);
+        assert!(result.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_find_chunk_streaming_checks_crc() {
+        let mut bytes = image_png().as_bytes();
+        let end = bytes.len() - 12;
+        // The last byte of the tEXt chunk's CRC.
+        bytes[end - 1] ^= 1;
+        assert!(Png::find_chunk_streaming(&mut Cursor::new(&bytes), ChunkType::TEXT).is_err());
+        let unverified = ParseOptions {
+            verify_crc: false,
+            ..ParseOptions::default()
+        };
+        let chunk = Png::find_nth_chunk_streaming(
+            &mut Cursor::new(&bytes),
+            ChunkType::TEXT,
+            0,
+            &unverified,
+        );
+        assert_eq!(chunk.unwrap().unwrap().data(), b"text");
+        assert!(
+            Png::find_chunk_streaming(&mut Cursor::new(b"not a png"), ChunkType::TEXT).is_err()
+        );
+    }
+
+    #[test]
+    fn test_copy_chunk_data_streaming() {
+        let bytes = image_png().as_bytes();
+        let options = ParseOptions::default();
+        let mut copied = Vec::new();
+        let length = Png::copy_nth_chunk_data_streaming(
+            &mut Cursor::new(&bytes),
+            ChunkType::IDAT,
+            1,
+            &options,
+            &mut copied,
+        )
+        .unwrap();
+        assert_eq!(length, Some(6));
+        assert_eq!(copied, b"data 2");
+        assert_eq!(
+            Png::copy_nth_chunk_data_streaming(
+                &mut Cursor::new(&bytes),
+                ChunkType::SIGNATURE,
+                0,
+                &options,
+                &mut copied,
+            )
+            .unwrap(),
+            None
+        );
+
+        let mut bytes = bytes;
+        let end = bytes.len() - 12;
+        bytes[end - 1] ^= 1;
+        let mut copied = Vec::new();
+        assert!(Png::copy_nth_chunk_data_streaming(
+            &mut Cursor::new(&bytes),
+            ChunkType::TEXT,
+            0,
+            &options,
+            &mut copied,
+        )
+        .is_err());
+        assert_eq!(copied, b"text");
+    }
+
+    #[test]
+    fn test_append_chunks_in_place() {
+        let chunks = || {
+            vec![
+                Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"first".to_vec()),
+                Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"second".to_vec()),
+            ]
+        };
+        let mut png = image_png();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"after IEND".to_vec(),
+        ));
+        let mut file = Cursor::new(png.as_bytes());
+        assert!(Png::append_chunks_in_place(&mut file, &chunks()).unwrap());
+        for chunk in chunks() {
+            png.append_chunk(chunk);
+        }
+        assert_eq!(file.into_inner(), png.as_bytes());
+
+        // Trailing data would have to move, so it's left for a rewrite.
+        let mut with_trailing_data = image_png();
+        with_trailing_data.append_trailing_data(b"trailing data");
+        let mut file = Cursor::new(with_trailing_data.as_bytes());
+        assert!(!Png::append_chunks_in_place(&mut file, &chunks()).unwrap());
+        assert_eq!(file.into_inner(), with_trailing_data.as_bytes());
+    }
+
+    #[test]
+    fn test_append_chunks_in_place_truncated() {
+        let bytes = image_png().as_bytes();
+        let mut file = Cursor::new(bytes[..bytes.len() - 20].to_vec());
+        assert!(Png::append_chunks_in_place(&mut file, &[]).is_err());
+        let mut file = Cursor::new(b"not a png".to_vec());
+        assert!(Png::append_chunks_in_place(&mut file, &[]).is_err());
+        // The data of every chunk is checked, like parsing the PNG would.
+        let mut damaged = image_png().as_bytes();
+        damaged[40] ^= 1;
+        assert!(Png::try_from(damaged.as_slice()).is_err());
+        let mut file = Cursor::new(damaged);
+        assert!(Png::append_chunks_in_place(&mut file, &[]).is_err());
+    }
+
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(chunk_types(&png), vec!["FrSt", "TeSt", "miDl", "LASt"]);
+    }
+
+    #[test]
+    fn test_legal_insert_positions() {
+        assert_eq!(image_png().legal_insert_positions(), vec![1, 2, 4, 5]);
+        assert_eq!(testing_png().legal_insert_positions(), vec![0, 1, 2, 3]);
+        assert_eq!(Png::from_chunks(vec![]).legal_insert_positions(), vec![0]);
+    }
+
+    #[test]
+    fn test_sort_chunks() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("tEXt", "before").unwrap(),
+            chunk_from_strings("IDAT", "data 1").unwrap(),
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+            chunk_from_strings("tRNS", "alpha").unwrap(),
+            chunk_from_strings("IDAT", "data 2").unwrap(),
+            chunk_from_strings("PLTE", "palette").unwrap(),
+            chunk_from_strings("gAMA", "gamma").unwrap(),
+            chunk_from_strings("tIME", "after").unwrap(),
+        ]);
+        assert!(png.sort_chunks());
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "gAMA", "PLTE", "tRNS", "tEXt", "IDAT", "IDAT", "tIME", "IEND"]
+        );
+        assert_eq!(&png.chunks()[5].data_as_string().unwrap(), "data 1");
+        assert_eq!(&png.chunks()[6].data_as_string().unwrap(), "data 2");
+        assert!(!png.sort_chunks());
+        let mut png = image_png();
+        assert!(!png.sort_chunks());
+    }
+
+    #[test]
+    fn test_move_chunk() {
+        let mut png = image_png();
+        // tEXt can go anywhere outside the image data.
+        png.move_chunk(4, 1).unwrap();
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "tEXt", "gAMA", "IDAT", "IDAT", "IEND"]
+        );
+        // gAMA can't come after the image data.
+        assert!(png.move_chunk(2, 4).is_err());
+        // Ancillary chunks only have to be in the right place relative to
+        // the chunks that the spec names.
+        png.move_chunk(1, 2).unwrap();
+        png.move_chunk(2, 1).unwrap();
+        // Nothing can come between two IDAT chunks, or before IHDR.
+        assert!(png.move_chunk(1, 3).is_err());
+        let error = png.move_chunk(1, 0).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Moving tEXt there would leave IHDR (chunk 1) out of order"
+        );
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "tEXt", "gAMA", "IDAT", "IDAT", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_move_chunk_beside_misordered_chunk() {
+        // A payload after IEND, where pngme puts it, is already out of order.
+        let mut png = image_png();
+        png.append_chunk(chunk_from_strings("ruSt", "Secret").unwrap());
+        png.move_chunk(4, 1).unwrap();
+        png.move_chunk(1, 4).unwrap();
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "gAMA", "IDAT", "IDAT", "tEXt", "IEND", "ruSt"]
+        );
+        // Chunks that weren't out of order still can't be put out of order.
+        let error = png.move_chunk(1, 4).unwrap_err();
+        assert!(error.to_string().contains("gAMA"));
+    }
+
+    #[test]
+    fn test_insert_chunk_randomly() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let mut png = image_png();
+            png.insert_chunk_randomly(chunk_from_strings("ruSt", "Secret").unwrap(), &mut rng);
+            let types = chunk_types(&png);
+            assert_eq!(types.first().unwrap(), "IHDR");
+            assert_eq!(types.last().unwrap(), "IEND");
+            let idat = types.iter().position(|t| t == "IDAT").unwrap();
+            assert_eq!(types[idat + 1], "IDAT");
+        }
+    }
+
+    #[test]
+    fn test_record_chunk_type() {
+        let mut png = testing_png();
+        assert!(png.recorded_chunk_types().is_empty());
+        png.record_chunk_type(ChunkType::from_str("ruSt").unwrap());
+        png.record_chunk_type(ChunkType::from_str("abCd").unwrap());
+        png.record_chunk_type(ChunkType::from_str("ruSt").unwrap());
+        assert_eq!(
+            png.recorded_chunk_types(),
+            vec![
+                ChunkType::from_str("ruSt").unwrap(),
+                ChunkType::from_str("abCd").unwrap()
+            ]
+        );
+        assert_eq!(png.chunks().len(), 4);
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -293,10 +2030,137 @@ mod tests {
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
-        let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_critical_and_ancillary_chunks() {
+        let png = image_png();
+        let types = |chunks: Vec<&Chunk>| -> Vec<String> {
+            chunks.iter().map(|c| c.chunk_type().to_string()).collect()
+        };
+        assert_eq!(
+            types(png.critical_chunks().collect()),
+            ["IHDR", "IDAT", "IDAT", "IEND"]
+        );
+        assert_eq!(types(png.ancillary_chunks().collect()), ["gAMA", "tEXt"]);
+    }
+
+    #[test]
+    fn test_text_metadata() {
+        use crate::codec::{InternationalText, TextualData};
+
+        let text = |keyword: &str, text: &str| TextualData {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        };
+        let mut png = image_png();
+        png.retain_chunks(|c| c.chunk_type() != &ChunkType::TEXT);
+        assert!(png.text_metadata().unwrap().is_empty());
+        png.append_chunk(TextCodec.to_chunk(&text("Title", "Dice")).unwrap());
+        png.append_chunk(
+            CompressedTextCodec
+                .to_chunk(&text("Comment", "Rolled twice"))
+                .unwrap(),
+        );
+        png.append_chunk(
+            InternationalTextCodec
+                .to_chunk(&InternationalText {
+                    keyword: "Author".to_string(),
+                    compressed: true,
+                    language: "fr".to_string(),
+                    translated_keyword: "Auteur".to_string(),
+                    text: "Gabe Berke-Williams, café".to_string(),
+                })
+                .unwrap(),
+        );
+        let metadata = png.text_metadata().unwrap();
+        let metadata: Vec<(&str, &str)> = metadata
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(
+            metadata,
+            [
+                ("Title", "Dice"),
+                ("Comment", "Rolled twice"),
+                ("Author", "Gabe Berke-Williams, café")
+            ]
+        );
+        png.append_chunk(chunk_from_strings("zTXt", "no separator").unwrap());
+        assert!(png.text_metadata().is_err());
+    }
+
+    #[test]
+    fn test_set_text() {
+        let mut png = image_png();
+        png.retain_chunks(|c| c.chunk_type() != &ChunkType::TEXT);
+        png.set_text("Title", "Dice").unwrap();
+        png.set_text("Author", "Gabe").unwrap();
+        png.insert_chunk(
+            1,
+            CompressedTextCodec
+                .to_chunk(&TextualData {
+                    keyword: "Author".to_string(),
+                    text: "Someone else".to_string(),
+                })
+                .unwrap(),
+        );
+        png.set_text("Author", "日本").unwrap();
+        png.set_text("Title", "Dice, again").unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            types,
+            ["IHDR", "iTXt", "gAMA", "IDAT", "IDAT", "tEXt", "IEND"]
+        );
+        let metadata = png.text_metadata().unwrap();
+        assert_eq!(
+            metadata,
+            [
+                ("Author".to_string(), "日本".to_string()),
+                ("Title".to_string(), "Dice, again".to_string())
+            ]
+        );
+        assert!(png.set_text("", "empty keyword").is_err());
+    }
+
+    #[test]
+    fn test_set_chunk() {
+        let mut png = image_png();
+        png.set_chunk(chunk_from_strings("pHYs", "first").unwrap());
+        png.set_chunk(chunk_from_strings("cHRM", "chromaticities").unwrap());
+        png.append_chunk(chunk_from_strings("pHYs", "duplicate").unwrap());
+        png.set_chunk(chunk_from_strings("pHYs", "second").unwrap());
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            types,
+            ["IHDR", "gAMA", "cHRM", "pHYs", "IDAT", "IDAT", "tEXt", "IEND"]
+        );
+        assert_eq!(png.chunks()[3].data(), b"second");
+    }
+
+    #[test]
+    fn test_sizes() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("ruSt", "one").unwrap());
+        png.append_chunk(chunk_from_strings("ruSt", "three").unwrap());
+        png.append_trailing_data(b"extra");
+        assert_eq!(png.chunk_count(), png.chunks().len());
+        assert_eq!(png.total_size(), png.as_bytes().len());
+        assert_eq!(png.payload_bytes(ChunkType::from_str("ruSt").unwrap()), 8);
+        assert_eq!(png.payload_bytes(ChunkType::from_str("seCr").unwrap()), 0);
+        assert_eq!(Png::from_chunks(vec![]).total_size(), 8);
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
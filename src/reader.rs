@@ -0,0 +1,333 @@
+//! Reading a PNG one chunk at a time.
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::{ParseOptions, Png, PngDecodeError};
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read, Write};
+
+/// Reads the chunks of a PNG from any reader one at a time, so only one
+/// chunk is ever in memory.
+///
+/// Of the [ParseOptions](struct.ParseOptions.html), `verify_crc`,
+/// `max_chunk_len`, `max_chunks`, and `allow_trailing` are followed.
+/// `strictness` is not, since checking it needs every chunk at once.
+///
+/// Like [Png::parse_with](struct.Png.html#method.parse_with), anything after
+/// IEND that isn't a whole chunk is trailing data, which ends the chunks
+/// (or, without `allow_trailing`, is an error). After the first error, there
+/// are no more chunks.
+pub struct ChunkReader<R> {
+    reader: R,
+    options: ParseOptions,
+    read_header: bool,
+    after_iend: bool,
+    count: usize,
+    done: bool,
+    /// The start of the trailing data, which was read looking for a chunk.
+    trailing_data: Vec<u8>,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Read chunks from `reader` with the default options. The PNG signature
+    /// is checked before the first chunk.
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, ParseOptions::default())
+    }
+
+    /// Read chunks from `reader` with the given options.
+    pub fn with_options(reader: R, options: ParseOptions) -> Self {
+        ChunkReader {
+            reader,
+            options,
+            read_header: false,
+            after_iend: false,
+            count: 0,
+            done: false,
+            trailing_data: Vec::new(),
+        }
+    }
+
+    /// Stop reading chunks, and get the reader back.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Stop reading chunks, and get a reader for everything after the last
+    /// chunk that was read. Once the chunks have run out, that's the
+    /// trailing data.
+    pub fn into_rest(self) -> io::Chain<Cursor<Vec<u8>>, R> {
+        Cursor::new(self.trailing_data).chain(self.reader)
+    }
+
+    /// Copy the PNG to `writer` one chunk at a time, leaving out the chunks
+    /// that `keep` returns `false` for, and leaving out the trailing data
+    /// unless `keep_trailing` is set. Like reading the chunks, only one is
+    /// ever in memory, and the trailing data is copied without reading it
+    /// all in first. Returns how many bytes of trailing data there were.
+    ///
+    /// This must be called before reading any chunks, since the PNG
+    /// signature is written first.
+    pub fn copy_to<W: Write + ?Sized, F: FnMut(&Chunk) -> bool>(
+        self,
+        writer: &mut W,
+        keep: F,
+        keep_trailing: bool,
+    ) -> crate::Result<u64> {
+        self.copy_with(writer, keep, &[], keep_trailing)
+    }
+
+    /// Copy the PNG to `writer` like [copy_to](#method.copy_to) does, keeping
+    /// every chunk and the trailing data, but with `chunks` added after the
+    /// last chunk, the way [Png::append_chunk](struct.Png.html#method.append_chunk)
+    /// adds them. Returns how many bytes of trailing data there were.
+    pub fn append_to<W: Write + ?Sized>(
+        self,
+        writer: &mut W,
+        chunks: &[Chunk],
+    ) -> crate::Result<u64> {
+        self.copy_with(writer, |_| true, chunks, true)
+    }
+
+    fn copy_with<W: Write + ?Sized, F: FnMut(&Chunk) -> bool>(
+        mut self,
+        writer: &mut W,
+        mut keep: F,
+        appended: &[Chunk],
+        keep_trailing: bool,
+    ) -> crate::Result<u64> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        for chunk in self.by_ref() {
+            let chunk = chunk?;
+            if keep(&chunk) {
+                writer.write_all(&chunk.as_bytes())?;
+            }
+        }
+        for chunk in appended {
+            writer.write_all(&chunk.as_bytes())?;
+        }
+        let mut rest = self.into_rest();
+        let trailing = if keep_trailing {
+            io::copy(&mut rest, writer)?
+        } else {
+            io::copy(&mut rest, &mut io::sink())?
+        };
+        writer.flush()?;
+        Ok(trailing)
+    }
+
+    /// Read the next chunk into `bytes`, or `None` if the reader ends between
+    /// chunks.
+    fn read_chunk(&mut self, bytes: &mut Vec<u8>) -> crate::Result<Option<Chunk>> {
+        if !self.read_header {
+            Png::read_header(&mut self.reader)?;
+            self.read_header = true;
+        }
+        // The length and chunk type.
+        (&mut self.reader).take(8).read_to_end(bytes)?;
+        match bytes.len() {
+            0 => return Ok(None),
+            8 => {}
+            n => {
+                return Err(PngDecodeError::boxed(format!(
+                    "Chunk #{} is truncated (only {} bytes are left)",
+                    self.count, n
+                )))
+            }
+        }
+        let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        // After IEND, something that isn't even a chunk type is trailing
+        // data, and reading however much its "length" says would be a waste.
+        if self.after_iend {
+            ChunkType::try_from([bytes[4], bytes[5], bytes[6], bytes[7]])?;
+        }
+        if let Some(max) = self.options.max_chunk_len.filter(|max| length > *max) {
+            return Err(PngDecodeError::boxed(format!(
+                "Chunk #{} is {} bytes long (at most {} allowed)",
+                self.count, length, max
+            )));
+        }
+        if self.options.max_chunks == Some(self.count) {
+            return Err(PngDecodeError::boxed(format!(
+                "There are more than {} chunks",
+                self.count
+            )));
+        }
+        // Read the data and CRC without trusting the length to say how much
+        // memory to set aside, since the reader may end long before that.
+        let rest = u64::from(length) + 4;
+        (&mut self.reader).take(rest).read_to_end(bytes)?;
+        if bytes.len() < 8 + usize::try_from(rest)? {
+            return Err(PngDecodeError::boxed(format!(
+                "Chunk #{} is truncated (its length is {}, but only {} bytes are left)",
+                self.count,
+                length,
+                bytes.len().saturating_sub(12)
+            )));
+        }
+        let verify_crc = self.options.verify_crc || self.after_iend;
+        let chunk = Chunk::parse(bytes, verify_crc)?;
+        self.after_iend |= chunk.chunk_type() == &ChunkType::IEND;
+        self.count += 1;
+        Ok(Some(chunk))
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = crate::Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut bytes = Vec::with_capacity(8);
+        match self.read_chunk(&mut bytes) {
+            Ok(Some(chunk)) => Some(Ok(chunk)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                if !self.after_iend || e.is::<io::Error>() {
+                    return Some(Err(e));
+                }
+                self.trailing_data = bytes;
+                if self.options.allow_trailing {
+                    None
+                } else {
+                    Some(Err(PngDecodeError::boxed(
+                        "There is data after IEND".to_string(),
+                    )))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::io::Cursor;
+
+    fn png() -> Png {
+        testing::png_with(vec![
+            testing::chunk("ruSt", b"Secret"),
+            testing::text_chunk("Comment", "hi"),
+        ])
+    }
+
+    fn types(chunks: &[Chunk]) -> Vec<String> {
+        chunks.iter().map(|c| c.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let chunks: Vec<Chunk> = ChunkReader::new(Cursor::new(png().as_bytes()))
+            .collect::<crate::Result<_>>()
+            .unwrap();
+        assert_eq!(types(&chunks), vec!["IHDR", "IDAT", "ruSt", "tEXt", "IEND"]);
+        assert_eq!(chunks[2].data(), b"Secret");
+    }
+
+    #[test]
+    fn test_bad_input() {
+        let bytes = png().as_bytes();
+        let mut reader = ChunkReader::new(&bytes[..bytes.len() - 3]);
+        assert_eq!(reader.by_ref().take_while(|c| c.is_ok()).count(), 4);
+        assert!(reader.next().is_none());
+
+        let mut reader = ChunkReader::new(&b"not a png at all"[..]);
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+
+        let bad_crc = testing::with_bad_crc(&png(), 2);
+        let results: Vec<_> = ChunkReader::new(bad_crc.as_slice()).collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[2].is_err());
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut png = png();
+        png.append_trailing_data(b"extra bytes");
+        let bytes = png.as_bytes();
+        assert_eq!(ChunkReader::new(bytes.as_slice()).count(), 5);
+        let options = ParseOptions {
+            allow_trailing: false,
+            ..ParseOptions::default()
+        };
+        let results: Vec<_> = ChunkReader::with_options(bytes.as_slice(), options).collect();
+        assert_eq!(results.len(), 6);
+        assert!(results[5].is_err());
+    }
+
+    #[test]
+    fn test_copy_to() {
+        let mut png = png();
+        png.append_trailing_data(b"PK\x03\x04 hidden zip file");
+        let bytes = png.as_bytes();
+
+        let mut copy = Vec::new();
+        let trailing = ChunkReader::new(bytes.as_slice())
+            .copy_to(&mut copy, |_| true, true)
+            .unwrap();
+        assert_eq!(copy, bytes);
+        assert_eq!(trailing, 20);
+
+        let mut copy = Vec::new();
+        ChunkReader::new(bytes.as_slice())
+            .copy_to(&mut copy, |c| c.chunk_type() != &ChunkType::TEXT, false)
+            .unwrap();
+        png.retain_chunks(|c| c.chunk_type() != &ChunkType::TEXT);
+        png.strip_trailing_data();
+        assert_eq!(copy, png.as_bytes());
+
+        let bad_crc = testing::with_bad_crc(&png, 2);
+        let result = ChunkReader::new(bad_crc.as_slice()).copy_to(&mut Vec::new(), |_| true, true);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_append_to() {
+        let mut png = png();
+        png.append_trailing_data(b"trailing data");
+        let chunk = testing::chunk("ruSt", b"Secret");
+        let mut copy = Vec::new();
+        let trailing = ChunkReader::new(png.as_bytes().as_slice())
+            .append_to(&mut copy, std::slice::from_ref(&chunk))
+            .unwrap();
+        assert_eq!(trailing, 13);
+        png.append_chunk(chunk);
+        assert_eq!(copy, png.as_bytes());
+    }
+
+    #[test]
+    fn test_into_rest() {
+        let mut png = png();
+        png.append_trailing_data(b"extra bytes");
+        let bytes = png.as_bytes();
+        let mut reader = ChunkReader::new(bytes.as_slice());
+        assert_eq!(reader.by_ref().count(), 5);
+        let mut rest = Vec::new();
+        reader.into_rest().read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"extra bytes");
+    }
+
+    #[test]
+    fn test_limits() {
+        let bytes = png().as_bytes();
+        let options = ParseOptions {
+            max_chunks: Some(2),
+            ..ParseOptions::default()
+        };
+        let results: Vec<_> = ChunkReader::with_options(bytes.as_slice(), options).collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[2].is_err());
+        let options = ParseOptions {
+            max_chunk_len: Some(5),
+            ..ParseOptions::default()
+        };
+        assert!(ChunkReader::with_options(bytes.as_slice(), options).any(|c| c.is_err()));
+    }
+}
@@ -0,0 +1,197 @@
+use crate::args::UsageError;
+use crate::diagnostic;
+use crate::extract::NotAnArchiveError;
+use crate::filter::FilterError;
+use crate::keychain::KeychainError;
+use crate::meta::MetaError;
+use crate::output::{OutputExistsError, TerminalOutputError};
+use crate::schema::SchemaError;
+use crate::storage::ObjectStoreError;
+use clap::ValueEnum;
+use miette::GraphicalTheme;
+use pngme::apng::ApngError;
+use pngme::capacity::CapacityError;
+use pngme::codec::CodecError;
+use pngme::color::ColorError;
+use pngme::fields::FieldsError;
+use pngme::gif::GifError;
+use pngme::icc::IccError;
+use pngme::info::InfoError;
+use pngme::jpeg::JpegError;
+use pngme::keystore::KeyStoreError;
+use pngme::labels::LabelError;
+use pngme::pixels::PixelsError;
+use pngme::shamir::ShamirError;
+use pngme::signature::SignatureError;
+use pngme::split::FragmentError;
+use pngme::spread::SpreadError;
+use pngme::trailer::TrailerError;
+use pngme::transform::EnvelopeError;
+use pngme::webp::WebpError;
+use pngme::xmp::XmpError;
+use pngme::{ChunkNotFoundError, ChunkOrderError, ChunkParseError, PngDecodeError};
+use serde::Serialize;
+use std::io;
+
+/// The exit code when the chunk that was asked for isn't in the PNG, so that
+/// scripts can tell "no message" apart from an empty message or other errors.
+pub const EXIT_CHUNK_NOT_FOUND: i32 = 3;
+
+/// The exit code for every other error.
+pub const EXIT_FAILURE: i32 = 1;
+
+/// How errors are written to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ErrorFormat {
+    /// `Error: <message>`, for people.
+    Text,
+    /// One JSON object per error, for other programs.
+    Json,
+}
+
+/// What `--errors json` prints.
+#[derive(Serialize, Debug, PartialEq)]
+struct ErrorReport {
+    /// A stable name for what went wrong, like `chunk_not_found`.
+    kind: &'static str,
+    message: String,
+    exit_code: i32,
+}
+
+impl ErrorReport {
+    fn new(error: &crate::Error) -> Self {
+        let kind = kind(error);
+        ErrorReport {
+            kind,
+            message: error.to_string(),
+            exit_code: if kind == "chunk_not_found" {
+                EXIT_CHUNK_NOT_FOUND
+            } else {
+                EXIT_FAILURE
+            },
+        }
+    }
+}
+
+/// A stable name for what kind of error `error` is.
+pub fn kind(error: &crate::Error) -> &'static str {
+    if error.is::<ChunkNotFoundError>() {
+        "chunk_not_found"
+    } else if error.is::<PngDecodeError>()
+        || error.is::<ChunkParseError>()
+        || error.is::<InfoError>()
+    {
+        "bad_png"
+    } else if error.is::<io::Error>() {
+        "io"
+    } else if error.is::<FragmentError>() {
+        "fragment"
+    } else if error.is::<ShamirError>() {
+        "shamir"
+    } else if error.is::<CapacityError>() {
+        "capacity"
+    } else if error.is::<EnvelopeError>() {
+        "envelope"
+    } else if error.is::<SignatureError>() {
+        "signature"
+    } else if error.is::<KeyStoreError>() {
+        "key_store"
+    } else if error.is::<CodecError>() {
+        "codec"
+    } else if error.is::<TrailerError>() {
+        "trailer"
+    } else if error.is::<LabelError>() {
+        "label"
+    } else if error.is::<SpreadError>() {
+        "spread"
+    } else if error.is::<UsageError>()
+        || error.is::<TerminalOutputError>()
+        || error.is::<ChunkOrderError>()
+    {
+        "usage"
+    } else if error.is::<OutputExistsError>() {
+        "output_exists"
+    } else if error.is::<FilterError>() {
+        "filter"
+    } else if error.is::<NotAnArchiveError>() {
+        "not_an_archive"
+    } else if error.is::<FieldsError>() {
+        "fields"
+    } else if error.is::<XmpError>() {
+        "xmp"
+    } else if error.is::<ApngError>() {
+        "apng"
+    } else if error.is::<ColorError>() {
+        "color"
+    } else if error.is::<IccError>() {
+        "icc"
+    } else if error.is::<JpegError>() {
+        "jpeg"
+    } else if error.is::<WebpError>() {
+        "webp"
+    } else if error.is::<GifError>() {
+        "gif"
+    } else if error.is::<PixelsError>() {
+        "pixels"
+    } else if error.is::<SchemaError>() {
+        "schema"
+    } else if error.is::<MetaError>() {
+        "meta"
+    } else if error.is::<KeychainError>() {
+        "keychain"
+    } else if error.is::<ObjectStoreError>() {
+        "object_store"
+    } else {
+        "other"
+    }
+}
+
+/// Write `error` to stderr, unless it's a missing chunk and `quiet` is set,
+/// and return the exit code to use.
+pub fn report(error: &crate::Error, format: ErrorFormat, quiet: bool) -> i32 {
+    let report = ErrorReport::new(error);
+    if !(quiet && report.kind == "chunk_not_found") {
+        match format {
+            // Errors that know where they are in the file show a hexdump.
+            ErrorFormat::Text => match diagnostic::render(error, GraphicalTheme::default()) {
+                Some(rendered) => eprint!("{}", rendered),
+                None => eprintln!("Error: {}", report.message),
+            },
+            ErrorFormat::Json => eprintln!(
+                "{}",
+                serde_json::to_string(&report).expect("reports are always valid JSON")
+            ),
+        }
+    }
+    report.exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngme::ChunkType;
+
+    #[test]
+    fn test_not_found_report() {
+        let error: crate::Error = Box::new(ChunkNotFoundError::new(ChunkType::TEXT, 0));
+        let report = ErrorReport::new(&error);
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            r#"{"kind":"chunk_not_found","message":"Chunk not found with type tEXt","exit_code":3}"#
+        );
+    }
+
+    #[test]
+    fn test_other_report() {
+        let error: crate::Error = Box::new(io::Error::new(io::ErrorKind::NotFound, "gone"));
+        let report = ErrorReport::new(&error);
+        assert_eq!(report.kind, "io");
+        assert_eq!(report.exit_code, EXIT_FAILURE);
+    }
+
+    #[test]
+    fn test_error_format() {
+        assert_eq!(ErrorFormat::from_str("json", false), Ok(ErrorFormat::Json));
+        assert!(ErrorFormat::from_str("xml", false).is_err());
+    }
+}
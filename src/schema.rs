@@ -0,0 +1,209 @@
+//! The JSON form of a PNG, for other programs to read and write.
+//!
+//! `print --format json` and `export` write it, and `import` reads it back, so
+//! a PNG survives the round trip byte for byte. It looks like this:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "chunks": [
+//!     {
+//!       "type": "IHDR",
+//!       "data": "AAAAAQAAAAEIBgAAAA==",
+//!       "critical": true,
+//!       "public": true,
+//!       "safe_to_copy": false
+//!     }
+//!   ],
+//!   "trailing_data": ""
+//! }
+//! ```
+//!
+//! - `version` is [VERSION]. It only changes when a change would break a
+//!   program that reads this form; adding a field doesn't count, so readers
+//!   should ignore fields they don't know.
+//! - `type` is the four letters of the chunk type.
+//! - `data` and `trailing_data` are standard base64 with padding.
+//! - `critical`, `public`, and `safe_to_copy` are the properties encoded in the
+//!   case of the chunk type's letters. They're there for convenience: `import`
+//!   ignores them, since the type already says what they are.
+//!
+//! Lengths and CRCs are left out because they follow from the data, and
+//! `import` works them out again.
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use pngme::{Chunk, ChunkType, Png};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// The version of the JSON form that this pngme writes and reads.
+pub const VERSION: u32 = 1;
+
+/// A whole PNG.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PngJson {
+    pub version: u32,
+    pub chunks: Vec<ChunkJson>,
+    #[serde(default)]
+    pub trailing_data: String,
+}
+
+/// One chunk of a PNG.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ChunkJson {
+    #[serde(rename = "type")]
+    pub chunk_type: String,
+    pub data: String,
+    #[serde(default)]
+    pub critical: bool,
+    #[serde(default)]
+    pub public: bool,
+    #[serde(default)]
+    pub safe_to_copy: bool,
+}
+
+impl ChunkJson {
+    pub fn from_chunk(chunk: &Chunk) -> Self {
+        let chunk_type = chunk.chunk_type();
+        ChunkJson {
+            chunk_type: chunk_type.to_string(),
+            data: STANDARD.encode(chunk.data()),
+            critical: chunk_type.is_critical(),
+            public: chunk_type.is_public(),
+            safe_to_copy: chunk_type.is_safe_to_copy(),
+        }
+    }
+
+    pub fn to_chunk(&self) -> crate::Result<Chunk> {
+        let chunk_type = ChunkType::from_str(&self.chunk_type)?;
+        let data = STANDARD.decode(&self.data).map_err(|e| {
+            SchemaError::boxed(format!("{} chunk's data isn't base64: {}", chunk_type, e))
+        })?;
+        Ok(Chunk::new(chunk_type, data))
+    }
+}
+
+impl PngJson {
+    pub fn from_png(png: &Png) -> Self {
+        PngJson {
+            version: VERSION,
+            chunks: png.chunks().iter().map(ChunkJson::from_chunk).collect(),
+            trailing_data: STANDARD.encode(png.trailing_data()),
+        }
+    }
+
+    /// Build the PNG back up. Returns `Err` for a version this pngme doesn't
+    /// know, or data that isn't base64.
+    pub fn to_png(&self) -> crate::Result<Png> {
+        if self.version != VERSION {
+            return Err(SchemaError::boxed(format!(
+                "Version {} isn't supported (expected {})",
+                self.version, VERSION
+            )));
+        }
+        let chunks = self
+            .chunks
+            .iter()
+            .map(ChunkJson::to_chunk)
+            .collect::<crate::Result<Vec<Chunk>>>()?;
+        let mut png = Png::from_chunks(chunks);
+        let trailing_data = STANDARD
+            .decode(&self.trailing_data)
+            .map_err(|e| SchemaError::boxed(format!("Trailing data isn't base64: {}", e)))?;
+        png.append_trailing_data(&trailing_data);
+        Ok(png)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("PNGs are always valid JSON")
+    }
+
+    pub fn from_json(json: &str) -> crate::Result<Self> {
+        serde_json::from_str(json).map_err(|e| SchemaError::boxed(e.to_string()).into())
+    }
+}
+
+/// JSON that should describe a PNG doesn't.
+#[derive(Debug)]
+pub struct SchemaError {
+    reason: String,
+}
+
+impl SchemaError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad PNG JSON: {}", self.reason)
+    }
+}
+
+impl Error for SchemaError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png() -> Png {
+        let mut png = Png::default();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"secret".to_vec(),
+        ));
+        png.append_trailing_data(b"extra");
+        png
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let png = png();
+        let json = PngJson::from_png(&png).to_json();
+        let back = PngJson::from_json(&json).unwrap().to_png().unwrap();
+        assert_eq!(back.as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_form() {
+        let value: serde_json::Value =
+            serde_json::from_str(&PngJson::from_png(&png()).to_json()).unwrap();
+        assert_eq!(value["version"], 1);
+        assert_eq!(value["trailing_data"], "ZXh0cmE=");
+        assert_eq!(
+            value["chunks"][3],
+            serde_json::json!({
+                "type": "ruSt",
+                "data": "c2VjcmV0",
+                "critical": false,
+                "public": false,
+                "safe_to_copy": true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_reading_is_lenient() {
+        // Unknown fields and missing properties are fine.
+        let json = r#"{"version": 1, "chunks": [{"type": "IEND", "data": "", "new": 1}]}"#;
+        let png = PngJson::from_json(json).unwrap().to_png().unwrap();
+        assert_eq!(png.chunks().len(), 1);
+    }
+
+    #[test]
+    fn test_bad_json() {
+        for json in &[
+            r#"{"version": 2, "chunks": []}"#,
+            r#"{"version": 1, "chunks": [{"type": "IEND", "data": "!"}]}"#,
+            r#"{"version": 1, "chunks": [{"type": "IE1D", "data": ""}]}"#,
+            r#"{"version": 1, "chunks": [], "trailing_data": "!"}"#,
+            r#"{"chunks": []}"#,
+        ] {
+            let result = PngJson::from_json(json).and_then(|json| json.to_png());
+            assert!(result.is_err(), "{}", json);
+        }
+    }
+}
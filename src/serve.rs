@@ -0,0 +1,335 @@
+//! `pngme serve`: encoding, decoding, and stripping over HTTP.
+//!
+//! Every endpoint takes a PNG as the body of a `POST`, and options in the
+//! query string:
+//!
+//! - `/encode?type=ruSt&message=...` returns the PNG with the message added.
+//! - `/decode?type=ruSt&nth=1` returns the message in a chunk.
+//! - `/strip?type=tEXt&type=tIME&trailing=true` returns the PNG without
+//!   chunks of those types, and without trailing data if `trailing` is set.
+use crate::args::check_chunk_type;
+use crate::commands::open_payload;
+use crate::report;
+use pngme::transform::Pipeline;
+use pngme::{Chunk, ChunkNotFoundError, ChunkType, Png};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use tiny_http::{Header, Method, Request, Server};
+
+/// The biggest PNG anyone can upload.
+const MAX_UPLOAD_LENGTH: u64 = 64 * 1024 * 1024;
+
+/// What to send back for a request.
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn png(png: &Png) -> Self {
+        Response {
+            status: 200,
+            content_type: "image/png",
+            body: png.as_bytes(),
+        }
+    }
+
+    fn bytes(body: Vec<u8>) -> Self {
+        Response {
+            status: 200,
+            content_type: "application/octet-stream",
+            body,
+        }
+    }
+
+    fn text(status: u16, text: &str) -> Self {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", text).into_bytes(),
+        }
+    }
+
+    /// A response for an error: 404 if the chunk that was asked for isn't
+    /// there, and 400 for anything else wrong with the request.
+    fn error(error: &crate::Error) -> Self {
+        // Everything is read from the request, so any other error (even one
+        // reading the PNG) means there was something wrong with it.
+        let status = match report::kind(error) {
+            "chunk_not_found" => 404,
+            _ => 400,
+        };
+        Response::text(status, &error.to_string())
+    }
+}
+
+/// Answer one request, refusing to encode into chunk types that could damage
+/// the image unless `allow_unsafe` is set. This is kept apart from the server
+/// so that it can be tested without one.
+pub fn handle(method: &Method, url: &str, body: &[u8], allow_unsafe: bool) -> Response {
+    let (path, query) = match url.split_once('?') {
+        Some((path, query)) => (path, query_pairs(query)),
+        None => (url, Vec::new()),
+    };
+    if !matches!(path, "/encode" | "/decode" | "/strip") {
+        return Response::text(404, "Not found (try /encode, /decode, or /strip)");
+    }
+    if method != &Method::Post {
+        return Response::text(405, "POST a PNG to this endpoint");
+    }
+    let result = Png::try_from(body).and_then(|png| match path {
+        "/encode" => encode(png, &query, allow_unsafe),
+        "/decode" => decode(&png, &query),
+        _ => strip(png, &query),
+    });
+    result.unwrap_or_else(|e| Response::error(&e))
+}
+
+fn encode(mut png: Png, query: &[(String, String)], allow_unsafe: bool) -> crate::Result<Response> {
+    let chunk_type = ChunkType::from_str(required(query, "type")?)?;
+    check_chunk_type(chunk_type, allow_unsafe)?;
+    let message = required(query, "message")?;
+    let payload = Pipeline::plain(message.as_bytes().to_vec())?;
+    png.append_chunk(Chunk::new(chunk_type, payload));
+    Ok(Response::png(&png))
+}
+
+fn decode(png: &Png, query: &[(String, String)]) -> crate::Result<Response> {
+    let chunk_type = ChunkType::from_str(required(query, "type")?)?;
+    let n = match optional(query, "nth") {
+        Some(nth) => NonZeroUsize::from_str(nth)?.get() - 1,
+        None => 0,
+    };
+    let chunk = png
+        .nth_chunk_by_type(chunk_type, n)
+        .ok_or_else(|| ChunkNotFoundError::new(chunk_type, n))?;
+    let mut payload = open_payload(chunk.data(), || {
+        Err(ServeError::boxed(
+            "The message is encrypted, and there's no way to give a passphrase here".to_string(),
+        ))
+    })?;
+    // The response holds the only copy from here on.
+    Ok(Response::bytes(std::mem::take(&mut *payload)))
+}
+
+fn strip(mut png: Png, query: &[(String, String)]) -> crate::Result<Response> {
+    let chunk_types = query
+        .iter()
+        .filter(|(name, _)| name == "type")
+        .map(|(_, value)| ChunkType::from_str(value))
+        .collect::<crate::Result<Vec<ChunkType>>>()?;
+    png.retain_chunks(|c| !chunk_types.contains(c.chunk_type()));
+    if optional(query, "trailing") == Some("true") {
+        png.strip_trailing_data();
+    }
+    Ok(Response::png(&png))
+}
+
+/// The first value of the query parameter `name`, if there is one.
+fn optional<'a>(query: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    query
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Like [optional], but returns `Err` if there's no such parameter.
+fn required<'a>(query: &'a [(String, String)], name: &str) -> crate::Result<&'a str> {
+    optional(query, name)
+        .ok_or_else(|| ServeError::boxed(format!("Missing the '{}' query parameter", name)).into())
+}
+
+/// Split a query string into its names and values, decoding `+` and `%XX`.
+fn query_pairs(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // `from_str_radix` would also take a sign, as in `%+1`.
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Read the body of `request`, and answer it.
+fn respond(mut request: Request, allow_unsafe: bool) -> crate::Result<()> {
+    let too_long = request
+        .body_length()
+        .is_some_and(|length| length as u64 > MAX_UPLOAD_LENGTH);
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_UPLOAD_LENGTH + 1)
+        .read_to_end(&mut body)?;
+    let response = if too_long || body.len() as u64 > MAX_UPLOAD_LENGTH {
+        Response::text(413, "The PNG is too big")
+    } else {
+        handle(request.method(), request.url(), &body, allow_unsafe)
+    };
+    eprintln!("{} {} {}", request.method(), request.url(), response.status);
+    let content_type = Header::from_bytes("Content-Type", response.content_type)
+        .expect("content types are valid headers");
+    request.respond(
+        tiny_http::Response::from_data(response.body)
+            .with_status_code(response.status)
+            .with_header(content_type),
+    )?;
+    Ok(())
+}
+
+/// Answer requests on `address` forever. `allow_unsafe` is passed on to
+/// [handle].
+pub fn serve(address: SocketAddr, allow_unsafe: bool) -> crate::Result<()> {
+    let server = Server::http(address).map_err(|e| -> crate::Error { e })?;
+    eprintln!("Listening on http://{}", address);
+    for request in server.incoming_requests() {
+        if let Err(e) = respond(request, allow_unsafe) {
+            eprintln!("Couldn't answer a request: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// A request to `pngme serve` was missing something.
+#[derive(Debug)]
+pub struct ServeError {
+    reason: String,
+}
+
+impl ServeError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for ServeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad request: {}", self.reason)
+    }
+}
+
+impl Error for ServeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngme::testing;
+
+    fn png() -> Png {
+        testing::png_with(vec![testing::text_chunk("Comment", "hi")])
+    }
+
+    #[test]
+    fn test_encode_then_decode() {
+        let response = handle(
+            &Method::Post,
+            "/encode?type=ruSt&message=a+secret%21",
+            &png().as_bytes(),
+            false,
+        );
+        assert_eq!(response.status, 200);
+        assert_eq!(response.content_type, "image/png");
+        let response = handle(&Method::Post, "/decode?type=ruSt", &response.body, false);
+        assert_eq!(response, Response::bytes(b"a secret!".to_vec()));
+    }
+
+    #[test]
+    fn test_encode_refuses_unsafe_chunk_types() {
+        let bytes = png().as_bytes();
+        for url in [
+            "/encode?type=IDAT&message=hi",
+            "/encode?type=tRNS&message=hi",
+        ] {
+            let response = handle(&Method::Post, url, &bytes, false);
+            assert_eq!(response.status, 400);
+            assert!(String::from_utf8_lossy(&response.body).contains("Refusing"));
+            assert_eq!(handle(&Method::Post, url, &bytes, true).status, 200);
+        }
+    }
+
+    #[test]
+    fn test_strip() {
+        let mut png = png();
+        png.append_trailing_data(b"extra");
+        let response = handle(
+            &Method::Post,
+            "/strip?type=tEXt&trailing=true",
+            &png.as_bytes(),
+            false,
+        );
+        let stripped = Png::try_from(response.body.as_slice()).unwrap();
+        assert_eq!(stripped.chunks().len(), 3);
+        assert!(stripped.trailing_data().is_empty());
+    }
+
+    #[test]
+    fn test_errors() {
+        let bytes = png().as_bytes();
+        let status =
+            |method: Method, url: &str, body: &[u8]| handle(&method, url, body, false).status;
+        assert_eq!(status(Method::Post, "/decode?type=ruSt", &bytes), 404);
+        assert_eq!(status(Method::Post, "/decode?type=tEXt&nth=2", &bytes), 404);
+        assert_eq!(status(Method::Post, "/decode", &bytes), 400);
+        assert_eq!(status(Method::Post, "/encode?type=ruSt", &bytes), 400);
+        assert_eq!(status(Method::Post, "/decode?type=tEXt", b"not a png"), 400);
+        assert_eq!(status(Method::Get, "/decode?type=tEXt", &bytes), 405);
+        assert_eq!(status(Method::Post, "/", &bytes), 404);
+    }
+
+    #[test]
+    fn test_query_pairs() {
+        assert_eq!(
+            query_pairs("type=ruSt&message=caf%C3%A9+au+lait&&flag&bad=%zz"),
+            vec![
+                ("type".to_string(), "ruSt".to_string()),
+                ("message".to_string(), "café au lait".to_string()),
+                ("flag".to_string(), String::new()),
+                ("bad".to_string(), "%zz".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_percent_decode_needs_two_hex_digits() {
+        assert_eq!(percent_decode("%41%2f"), "A/");
+        assert_eq!(percent_decode("%+1"), "% 1");
+        assert_eq!(percent_decode("%-1"), "%-1");
+        assert_eq!(percent_decode("%4"), "%4");
+    }
+}
@@ -0,0 +1,281 @@
+//! Splitting a payload into Shamir shares, so that it can be hidden across
+//! several images and read back from only some of them.
+//!
+//! Each byte of the payload is the constant term of its own random polynomial
+//! of degree `threshold - 1` over GF(2^8), and share `x` holds every
+//! polynomial's value at `x`. Any `threshold` shares pin the polynomials down
+//! and give back the payload; fewer say nothing at all about it.
+//!
+//! A share's bytes start with a 10-byte header: an 8-byte ID that all the
+//! shares of one payload have in common, the threshold, and the share's `x`
+//! (from 1 to 255). The rest is one value per byte of the payload. The ID
+//! only keeps shares of different payloads from being mixed up; shares carry
+//! no checksum, so damaged ones give back a damaged payload.
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+/// The number of bytes at the start of each share that hold its ID,
+/// threshold, and `x`.
+pub const HEADER_LENGTH: usize = 8 + 1 + 1;
+
+/// One share of a payload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Share {
+    id: [u8; 8],
+    threshold: u8,
+    x: u8,
+    y: Vec<u8>,
+}
+
+impl Share {
+    /// How many shares it takes to get the payload back.
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Which share this is, counting from 1.
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.id.to_vec();
+        bytes.push(self.threshold);
+        bytes.push(self.x);
+        bytes.extend_from_slice(&self.y);
+        bytes
+    }
+
+    /// Read a share written by [to_bytes](#method.to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> crate::Result<Self> {
+        if bytes.len() < HEADER_LENGTH {
+            return Err(ShamirError::boxed("The share is truncated".to_string()));
+        }
+        let (header, y) = bytes.split_at(HEADER_LENGTH);
+        let mut id = [0; 8];
+        id.copy_from_slice(&header[..8]);
+        let (threshold, x) = (header[8], header[9]);
+        if threshold == 0 || x == 0 {
+            return Err(ShamirError::boxed(
+                "The share's threshold and number can't be 0".to_string(),
+            ));
+        }
+        Ok(Share {
+            id,
+            threshold,
+            x,
+            y: y.to_vec(),
+        })
+    }
+}
+
+/// Split `payload` into `count` shares, any `threshold` of which can be
+/// [combined](fn.combine.html) to get it back. Returns `Err` unless
+/// `1 <= threshold <= count`.
+pub fn split<R: Rng + ?Sized>(
+    payload: &[u8],
+    count: u8,
+    threshold: u8,
+    rng: &mut R,
+) -> crate::Result<Vec<Share>> {
+    if threshold == 0 || threshold > count {
+        return Err(ShamirError::boxed(format!(
+            "The threshold has to be from 1 to the number of shares ({}), not {}",
+            count, threshold
+        )));
+    }
+    let id: [u8; 8] = rng.gen();
+    let mut shares: Vec<Share> = (1..=count)
+        .map(|x| Share {
+            id,
+            threshold,
+            x,
+            y: Vec::with_capacity(payload.len()),
+        })
+        .collect();
+    let mut coefficients = vec![0; usize::from(threshold)];
+    for &byte in payload {
+        coefficients[0] = byte;
+        rng.fill(&mut coefficients[1..]);
+        for share in &mut shares {
+            // Horner's rule, from the highest coefficient down.
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(0, |y, &c| add(multiply(y, share.x), c));
+            share.y.push(y);
+        }
+    }
+    Ok(shares)
+}
+
+/// Get a payload back from its shares, in any order. Returns `Err` if there
+/// are fewer distinct shares than the threshold, or the shares aren't all of
+/// the same payload.
+pub fn combine(shares: &[Share]) -> crate::Result<Vec<u8>> {
+    let first = shares
+        .first()
+        .ok_or_else(|| ShamirError::boxed("There are no shares".to_string()))?;
+    let mut by_x = BTreeMap::new();
+    for share in shares {
+        if share.id != first.id
+            || share.threshold != first.threshold
+            || share.y.len() != first.y.len()
+        {
+            return Err(ShamirError::boxed(
+                "The shares aren't all shares of the same payload".to_string(),
+            ));
+        }
+        by_x.insert(share.x, share);
+    }
+    if by_x.len() < usize::from(first.threshold) {
+        return Err(ShamirError::boxed(format!(
+            "{} shares are needed, but there {} only {}",
+            first.threshold,
+            if by_x.len() == 1 { "is" } else { "are" },
+            by_x.len()
+        )));
+    }
+    let used: Vec<&Share> = by_x
+        .into_values()
+        .take(usize::from(first.threshold))
+        .collect();
+    // Each share's Lagrange basis polynomial, evaluated at 0. In GF(2^8),
+    // subtraction is the same as addition.
+    let weights: Vec<u8> = used
+        .iter()
+        .map(|i| {
+            used.iter()
+                .filter(|j| j.x != i.x)
+                .fold(1, |weight, j| multiply(weight, divide(j.x, add(j.x, i.x))))
+        })
+        .collect();
+    Ok((0..first.y.len())
+        .map(|n| {
+            used.iter().zip(&weights).fold(0, |byte, (share, &weight)| {
+                add(byte, multiply(share.y[n], weight))
+            })
+        })
+        .collect())
+}
+
+fn add(a: u8, b: u8) -> u8 {
+    a ^ b
+}
+
+/// Multiply in GF(2^8), modulo the AES polynomial x^8 + x^4 + x^3 + x + 1.
+fn multiply(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Divide in GF(2^8). `b` is never 0, since the `x`s of distinct shares
+/// always differ.
+fn divide(a: u8, b: u8) -> u8 {
+    // b^254 is b's inverse, since b^255 = 1.
+    let mut inverse = 1;
+    for _ in 0..254 {
+        inverse = multiply(inverse, b);
+    }
+    multiply(a, inverse)
+}
+
+/// Shares can't be made or combined.
+#[derive(Debug)]
+pub struct ShamirError {
+    reason: String,
+}
+
+impl ShamirError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for ShamirError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad shares: {}", self.reason)
+    }
+}
+
+impl Error for ShamirError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_any_threshold_subset_combines() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let payload = b"This is a secret message!";
+        let shares = split(payload, 3, 2, &mut rng).unwrap();
+        for (a, b) in &[(0, 1), (0, 2), (2, 1)] {
+            let subset = [shares[*a].clone(), shares[*b].clone()];
+            assert_eq!(combine(&subset).unwrap(), payload);
+        }
+        assert_eq!(combine(&shares).unwrap(), payload);
+        // One share on its own isn't the payload.
+        assert_ne!(shares[0].y, payload);
+    }
+
+    #[test]
+    fn test_too_few_shares() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let shares = split(b"secret", 5, 3, &mut rng).unwrap();
+        let same_twice = [shares[0].clone(), shares[1].clone(), shares[1].clone()];
+        assert!(combine(&same_twice).is_err());
+        assert!(combine(&[]).is_err());
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let shares = split(b"secret", 2, 2, &mut rng).unwrap();
+        let bytes = shares[1].to_bytes();
+        assert_eq!(bytes.len(), HEADER_LENGTH + 6);
+        let share = Share::from_bytes(&bytes).unwrap();
+        assert_eq!(share, shares[1]);
+        assert_eq!((share.threshold(), share.x()), (2, 2));
+        assert!(Share::from_bytes(&bytes[..HEADER_LENGTH - 1]).is_err());
+    }
+
+    #[test]
+    fn test_mixed_payloads() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let one = split(b"secret", 2, 2, &mut rng).unwrap();
+        let other = split(b"secret", 2, 2, &mut rng).unwrap();
+        assert!(combine(&[one[0].clone(), other[1].clone()]).is_err());
+    }
+
+    #[test]
+    fn test_bad_threshold() {
+        let mut rng = StdRng::seed_from_u64(7);
+        assert!(split(b"secret", 2, 3, &mut rng).is_err());
+        assert!(split(b"secret", 2, 0, &mut rng).is_err());
+        assert_eq!(split(b"secret", 1, 1, &mut rng).unwrap()[0].y, b"secret");
+    }
+
+    #[test]
+    fn test_field_arithmetic() {
+        // From FIPS 197, section 4.2.
+        assert_eq!(multiply(0x57, 0x83), 0xc1);
+        for b in 1..=255 {
+            assert_eq!(multiply(divide(1, b), b), 1);
+        }
+    }
+}
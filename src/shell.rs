@@ -0,0 +1,332 @@
+//! `pngme shell`: load a PNG once, then work on it with commands typed in one
+//! at a time, without parsing and writing the file for each one.
+use crate::args::{check_chunk_type, PassphraseArgs};
+use crate::commands::{open_payload, read_passphrase};
+use crate::output::WriteOptions;
+use crate::storage;
+use pngme::codec::CodecRegistry;
+use pngme::{Chunk, ChunkNotFoundError, ChunkType, Png};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const HELP: &str = "\
+list                      Show every chunk, numbered from 0
+decode TYPE [N]           Show the Nth chunk of a type (counting from 1)
+encode TYPE MESSAGE...    Add a chunk with the rest of the line as its data
+remove TYPE [N]           Remove the Nth chunk of a type (counting from 1)
+save [PATH]               Write the PNG back out, or to PATH
+help                      Show this help
+quit                      Stop (asks again if there are unsaved changes)";
+
+/// Whether to keep reading commands.
+#[derive(Debug, PartialEq)]
+enum Flow {
+    Continue,
+    Quit,
+}
+
+/// A PNG that's been loaded, and what's been done to it.
+struct Shell {
+    png: Png,
+    path: PathBuf,
+    write_options: WriteOptions,
+    passphrase: PassphraseArgs,
+    /// Whether `encode` may use chunk types that could damage the image.
+    allow_unsafe: bool,
+    /// Whether the PNG has changed since it was loaded or last saved.
+    changed: bool,
+    /// Whether `quit` already said there were unsaved changes.
+    warned: bool,
+}
+
+impl Shell {
+    fn new(
+        png: Png,
+        path: &Path,
+        write_options: WriteOptions,
+        passphrase: PassphraseArgs,
+        allow_unsafe: bool,
+    ) -> Self {
+        Shell {
+            png,
+            path: path.to_path_buf(),
+            write_options,
+            passphrase,
+            allow_unsafe,
+            changed: false,
+            warned: false,
+        }
+    }
+
+    /// Run one line of input, writing anything it shows to `out`.
+    fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> crate::Result<Flow> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(Flow::Continue),
+        };
+        let args: Vec<&str> = words.collect();
+        match (command, args.as_slice()) {
+            ("list", []) => self.list(out)?,
+            ("decode", [chunk_type, rest @ ..]) => {
+                let (chunk_type, n) = chunk_type_and_n(chunk_type, rest)?;
+                self.decode(chunk_type, n, out)?
+            }
+            ("encode", [chunk_type, _, ..]) => {
+                // The message is everything after the chunk type, spaces and all.
+                let message = line
+                    .trim_start()
+                    .trim_start_matches("encode")
+                    .trim_start()
+                    .trim_start_matches(chunk_type)
+                    .trim();
+                let chunk_type = ChunkType::from_str(chunk_type)?;
+                check_chunk_type(chunk_type, self.allow_unsafe)?;
+                self.png
+                    .append_chunk(Chunk::new(chunk_type, message.as_bytes().to_vec()));
+                self.changed = true;
+            }
+            ("remove", [chunk_type, rest @ ..]) => {
+                let (chunk_type, n) = chunk_type_and_n(chunk_type, rest)?;
+                let chunk = self.png.remove_nth_chunk(chunk_type, n)?;
+                self.changed = true;
+                writeln!(out, "Removed chunk: {}", chunk)?;
+            }
+            ("save", []) => {
+                let path = self.path.clone();
+                self.save(&path, out)?
+            }
+            ("save", [path]) => self.save(Path::new(path), out)?,
+            ("help", []) => writeln!(out, "{}", HELP)?,
+            ("quit", []) | ("exit", []) => {
+                if self.changed && !self.warned {
+                    self.warned = true;
+                    writeln!(
+                        out,
+                        "There are unsaved changes. Save them, or quit again to throw them away."
+                    )?;
+                } else {
+                    return Ok(Flow::Quit);
+                }
+            }
+            _ => {
+                return Err(ShellError::boxed(format!(
+                    "Don't know how to '{}' (try 'help')",
+                    line.trim()
+                )))
+            }
+        }
+        Ok(Flow::Continue)
+    }
+
+    fn list<W: Write>(&self, out: &mut W) -> crate::Result<()> {
+        let registry = CodecRegistry::default();
+        for (index, chunk) in self.png.chunks().iter().enumerate() {
+            writeln!(out, "{}\t{}", index, registry.display(chunk))?;
+        }
+        Ok(())
+    }
+
+    fn decode<W: Write>(&self, chunk_type: ChunkType, n: usize, out: &mut W) -> crate::Result<()> {
+        let chunk = self
+            .png
+            .nth_chunk_by_type(chunk_type, n)
+            .ok_or_else(|| ChunkNotFoundError::new(chunk_type, n))?;
+        let payload = open_payload(chunk.data(), || read_passphrase(&self.passphrase))?;
+        writeln!(
+            out,
+            "{}\t{}",
+            chunk.chunk_type(),
+            String::from_utf8_lossy(&payload)
+        )?;
+        Ok(())
+    }
+
+    fn save<W: Write>(&mut self, path: &Path, out: &mut W) -> crate::Result<()> {
+        self.write_options
+            .write(&self.path, path, &self.png.as_bytes())?;
+        self.changed = false;
+        self.warned = false;
+        writeln!(out, "Saved {}", path.display())?;
+        Ok(())
+    }
+}
+
+/// A chunk type, and which of the chunks with that type to use (counting from
+/// 0) from an optional number that counts from 1.
+fn chunk_type_and_n(chunk_type: &str, rest: &[&str]) -> crate::Result<(ChunkType, usize)> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    match rest {
+        [] => Ok((chunk_type, 0)),
+        [n] => Ok((chunk_type, NonZeroUsize::from_str(n)?.get() - 1)),
+        _ => Err(ShellError::boxed(
+            "Give a chunk type and at most one number".to_string(),
+        )),
+    }
+}
+
+/// Load the PNG at `path` and run commands from stdin until `quit` or the end
+/// of input. A prompt is only shown if stdin is a terminal.
+pub fn run(
+    path: &Path,
+    write_options: WriteOptions,
+    passphrase: PassphraseArgs,
+    allow_unsafe: bool,
+) -> crate::Result<()> {
+    let png = Png::try_from(storage::read(path)?.as_slice())?;
+    let mut shell = Shell::new(png, path, write_options, passphrase, allow_unsafe);
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    let mut stdout = io::stdout();
+    let mut lines = stdin.lock().lines();
+    loop {
+        if interactive {
+            print!("pngme> ");
+            stdout.flush()?;
+        }
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        match shell.execute(&line, &mut stdout) {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Quit) => return Ok(()),
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+    if shell.changed {
+        eprintln!("Quitting without saving changes");
+    }
+    Ok(())
+}
+
+/// A line typed into the shell doesn't make sense.
+#[derive(Debug)]
+pub struct ShellError {
+    reason: String,
+}
+
+impl ShellError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad command: {}", self.reason)
+    }
+}
+
+impl Error for ShellError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngme::testing;
+    use std::fs;
+
+    fn shell(path: &Path) -> Shell {
+        let png = testing::png_with(vec![
+            testing::chunk("tEXt", b"one"),
+            testing::chunk("tEXt", b"two"),
+        ]);
+        Shell::new(
+            png,
+            path,
+            WriteOptions::default(),
+            PassphraseArgs::default(),
+            false,
+        )
+    }
+
+    /// Run `line`, returning what it wrote.
+    fn run_line(shell: &mut Shell, line: &str) -> String {
+        let mut out = Vec::new();
+        shell.execute(line, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_list_and_decode() {
+        let mut shell = shell(Path::new("dice.png"));
+        let listed = run_line(&mut shell, "list");
+        assert_eq!(listed.lines().count(), 5);
+        assert!(listed.starts_with("0\tIHDR"));
+        assert_eq!(run_line(&mut shell, "decode tEXt"), "tEXt\tone\n");
+        assert_eq!(run_line(&mut shell, "  decode tEXt 2 "), "tEXt\ttwo\n");
+        assert!(shell.execute("decode tEXt 3", &mut Vec::new()).is_err());
+        assert!(shell.execute("decode tEXt 0", &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_encode_and_remove() {
+        let mut shell = shell(Path::new("dice.png"));
+        run_line(&mut shell, "encode ruSt a secret  message");
+        assert_eq!(
+            run_line(&mut shell, "decode ruSt"),
+            "ruSt\ta secret  message\n"
+        );
+        assert_eq!(
+            run_line(&mut shell, "remove tEXt 2"),
+            "Removed chunk: tEXt\ttwo\n"
+        );
+        assert_eq!(shell.png.chunks().len(), 5);
+        assert!(shell.execute("remove tEXt 2", &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_encode_refuses_unsafe_chunk_types() {
+        let mut shell = shell(Path::new("dice.png"));
+        for line in &["encode IDAT hi", "encode tRNS hi"] {
+            let error = shell.execute(line, &mut Vec::new()).unwrap_err();
+            assert!(error.to_string().contains("Refusing"), "{}", error);
+        }
+        assert_eq!(shell.png.chunks().len(), 5);
+        shell.allow_unsafe = true;
+        run_line(&mut shell, "encode tRNS hi");
+        assert_eq!(shell.png.chunks().len(), 6);
+    }
+
+    #[test]
+    fn test_save_and_quit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dice.png");
+        let mut shell = shell(&path);
+        assert_eq!(shell.execute("quit", &mut Vec::new()).unwrap(), Flow::Quit);
+
+        run_line(&mut shell, "remove tEXt");
+        // Quitting with unsaved changes asks again first.
+        assert_eq!(
+            shell.execute("quit", &mut Vec::new()).unwrap(),
+            Flow::Continue
+        );
+        run_line(&mut shell, "save");
+        assert_eq!(shell.execute("exit", &mut Vec::new()).unwrap(), Flow::Quit);
+        let saved = Png::try_from(fs::read(&path).unwrap().as_slice()).unwrap();
+        assert_eq!(saved.chunks().len(), 4);
+    }
+
+    #[test]
+    fn test_bad_commands() {
+        let mut shell = shell(Path::new("dice.png"));
+        assert_eq!(
+            shell.execute("   ", &mut Vec::new()).unwrap(),
+            Flow::Continue
+        );
+        for line in &[
+            "frobnicate",
+            "list everything",
+            "decode",
+            "encode ruSt",
+            "decode 1234",
+        ] {
+            assert!(shell.execute(line, &mut Vec::new()).is_err(), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::extract::ArchiveKind;
+
+/// Magic bytes at the start of a file, and the extension files starting with
+/// them usually have.
+const MAGIC: [(&[u8], &str); 12] = [
+    (b"\x89PNG\r\n\x1a\n", "png"),
+    (b"\xff\xd8\xff", "jpg"),
+    (b"GIF87a", "gif"),
+    (b"GIF89a", "gif"),
+    (b"%PDF-", "pdf"),
+    (b"%!PS", "ps"),
+    (b"BZh", "bz2"),
+    (b"\xfd7zXZ\x00", "xz"),
+    (b"7z\xbc\xaf\x27\x1c", "7z"),
+    (b"\x28\xb5\x2f\xfd", "zst"),
+    (b"\x1f\x8b", "gz"),
+    (b"\x7fELF", "elf"),
+];
+
+/// Pick a file extension (without the dot) for `payload` from what its
+/// contents look like. Text that isn't anything more specific is "txt", and
+/// anything unrecognised is "bin".
+pub fn extension(payload: &[u8]) -> &'static str {
+    match ArchiveKind::sniff(payload) {
+        Some(ArchiveKind::Zip) => return "zip",
+        Some(ArchiveKind::Tar) => return "tar",
+        Some(ArchiveKind::TarGz) => return "tar.gz",
+        None => {}
+    }
+    if payload.len() >= 12 && &payload[..4] == b"RIFF" && &payload[8..12] == b"WEBP" {
+        return "webp";
+    }
+    if let Some((_, extension)) = MAGIC.iter().find(|(magic, _)| payload.starts_with(magic)) {
+        return extension;
+    }
+    match std::str::from_utf8(payload) {
+        Ok(text) if !text.is_empty() && !text.contains('\0') => "txt",
+        _ => "bin",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_images() {
+        assert_eq!(extension(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR"), "png");
+        assert_eq!(extension(b"\xff\xd8\xff\xe0\0\x10JFIF"), "jpg");
+        assert_eq!(extension(b"RIFF\0\0\0\0WEBPVP8 "), "webp");
+        assert_eq!(extension(b"GIF89a\x01\0\x01\0"), "gif");
+    }
+
+    #[test]
+    fn test_documents_and_archives() {
+        assert_eq!(extension(b"%PDF-1.7\n"), "pdf");
+        assert_eq!(extension(b"PK\x03\x04\x14\0"), "zip");
+        assert_eq!(extension(b"\x1f\x8b\x08\0\0\0\0\0"), "gz");
+    }
+
+    #[test]
+    fn test_text_and_unknown() {
+        assert_eq!(extension("Notes for later ✓\n".as_bytes()), "txt");
+        assert_eq!(extension(b"\x00\x01\x02\xff"), "bin");
+        assert_eq!(extension(b"nul\0in the middle"), "bin");
+        assert_eq!(extension(b""), "bin");
+    }
+}
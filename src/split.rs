@@ -0,0 +1,710 @@
+//! Splitting a payload across several chunks, and joining it back together.
+//!
+//! Each fragment is an ordinary [Chunk](../struct.Chunk.html) whose data
+//! starts with an 8-byte header: the fragment's sequence number (counting from
+//! 0), then the total number of fragments, both as big-endian `u32`s. The rest
+//! of the data is that fragment's slice of the payload.
+//!
+//! A [Manifest](struct.Manifest.html) can be stored alongside the fragments to
+//! record their sizes and digests, and a digest of the whole payload, so that
+//! missing and damaged fragments are named when they are joined back together.
+//!
+//! A payload can also be striped across several PNGs, with one fragment and a
+//! copy of the manifest in each, when it's more than one image can plausibly
+//! hold. See [stripe_payload](fn.stripe_payload.html) and
+//! [join_carriers](fn.join_carriers.html).
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+
+/// The number of bytes at the start of each fragment's data that hold its
+/// sequence number and the total number of fragments.
+pub const HEADER_LENGTH: usize = 8;
+
+/// The most payload bytes one fragment can hold, since a chunk can hold at
+/// most [Chunk::MAXIMUM_LENGTH](../struct.Chunk.html#associatedconstant.MAXIMUM_LENGTH)
+/// bytes. Payloads bigger than this have to be split.
+pub const MAX_FRAGMENT_LENGTH: usize = Chunk::MAXIMUM_LENGTH as usize - HEADER_LENGTH;
+
+/// The length of the SHA-256 digests in a manifest.
+const DIGEST_LENGTH: usize = 32;
+
+/// Split `payload` into fragments of at most `max_fragment_len` payload bytes
+/// each (not counting the header), all with the same chunk type.
+/// An empty payload still produces a single, empty fragment.
+pub fn split_payload(
+    chunk_type: ChunkType,
+    payload: &[u8],
+    max_fragment_len: usize,
+) -> crate::Result<Vec<Chunk>> {
+    if max_fragment_len == 0 {
+        return Err(Box::new(FragmentError::Malformed(
+            "Fragment length must be at least 1".to_string(),
+        )));
+    }
+    let pieces: Vec<&[u8]> = if payload.is_empty() {
+        vec![payload]
+    } else {
+        payload.chunks(max_fragment_len).collect()
+    };
+    let count = u32::try_from(pieces.len())?;
+    Ok(pieces
+        .into_iter()
+        .enumerate()
+        .map(|(index, piece)| {
+            let data: Vec<u8> = (index as u32)
+                .to_be_bytes()
+                .iter()
+                .chain(count.to_be_bytes().iter())
+                .chain(piece.iter())
+                .copied()
+                .collect();
+            Chunk::new(chunk_type, data)
+        })
+        .collect())
+}
+
+/// Split `payload` into at most `carriers` fragments of about the same size,
+/// one for each PNG it's striped across. A payload shorter than `carriers`
+/// bytes makes fewer fragments, one byte each.
+pub fn stripe_payload(
+    chunk_type: ChunkType,
+    payload: &[u8],
+    carriers: usize,
+) -> crate::Result<Vec<Chunk>> {
+    if carriers == 0 {
+        return Err(Box::new(FragmentError::Malformed(
+            "There has to be at least 1 carrier".to_string(),
+        )));
+    }
+    let size = payload.len().div_ceil(carriers).max(1);
+    if size > MAX_FRAGMENT_LENGTH {
+        return Err(Box::new(FragmentError::Malformed(format!(
+            "{} carriers can't hold {} bytes",
+            carriers,
+            payload.len()
+        ))));
+    }
+    split_payload(chunk_type, payload, size)
+}
+
+/// Put back together a payload that was striped across `pngs`, using the
+/// manifest in them to check every fragment. PNGs that don't carry part of it
+/// are skipped, but every PNG that does has to carry the same manifest.
+pub fn join_carriers<'a, I>(pngs: I, chunk_type: ChunkType) -> Result<Vec<u8>, FragmentError>
+where
+    I: IntoIterator<Item = &'a Png>,
+{
+    let mut manifest = None;
+    let mut fragments = Vec::new();
+    for png in pngs {
+        let chunk = match png
+            .chunks()
+            .iter()
+            .find(|c| Manifest::describes(c, chunk_type))
+        {
+            Some(chunk) => chunk,
+            None => continue,
+        };
+        let found = Manifest::from_chunk(chunk)?;
+        match &manifest {
+            Some(manifest) if manifest != &found => {
+                return Err(FragmentError::Malformed(format!(
+                    "The carriers hold more than one {} payload",
+                    chunk_type
+                )))
+            }
+            Some(_) => {}
+            None => manifest = Some(found),
+        }
+        fragments.extend(
+            png.chunks()
+                .iter()
+                .filter(|c| c.chunk_type() == &chunk_type),
+        );
+    }
+    manifest.ok_or(FragmentError::NoFragments)?.join(fragments)
+}
+
+/// Put fragments back together in sequence order, regardless of the order
+/// they are given in. Every fragment from 0 to the total count must be present
+/// exactly once.
+pub fn join_fragments<'a, I>(fragments: I) -> Result<Vec<u8>, FragmentError>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    Ok(ordered_pieces(fragments)?.concat())
+}
+
+/// Each fragment's slice of the payload, in sequence order, for
+/// [join_fragments](fn.join_fragments.html).
+fn ordered_pieces<'a, I>(fragments: I) -> Result<Vec<&'a [u8]>, FragmentError>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    let mut expected_count = None;
+    let mut by_index = BTreeMap::new();
+    for fragment in fragments {
+        let (index, count, piece) = read_header(fragment)?;
+        match expected_count {
+            None => expected_count = Some(count),
+            Some(expected) if expected != count => {
+                return Err(FragmentError::CountMismatch(expected, count))
+            }
+            Some(_) => {}
+        }
+        if index >= count {
+            return Err(FragmentError::Malformed(format!(
+                "Fragment {} is out of range (only {} fragments)",
+                index, count
+            )));
+        }
+        if by_index.insert(index, piece).is_some() {
+            return Err(FragmentError::Duplicate(index));
+        }
+    }
+    let count = expected_count.ok_or(FragmentError::NoFragments)?;
+    if let Some(missing) = (0..count).find(|i| !by_index.contains_key(i)) {
+        return Err(FragmentError::Missing(missing));
+    }
+    Ok(by_index.into_values().collect())
+}
+
+/// A record of how a payload was split: the chunk type of its fragments, the
+/// SHA-256 digest of the whole payload, and, in sequence order, the length and
+/// SHA-256 digest of each fragment's slice of the payload.
+///
+/// A manifest is stored in a
+/// [FRAGMENT_MANIFEST](../struct.ChunkType.html#associatedconstant.FRAGMENT_MANIFEST)
+/// chunk, whose data is the fragments' chunk type, then the payload's digest,
+/// then for each fragment its length as a big-endian `u32` followed by its
+/// digest. Manifests written before the payload's digest was added leave it
+/// out; each entry is 36 bytes long, so the two can't be mistaken for each
+/// other.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    chunk_type: ChunkType,
+    payload_digest: Option<[u8; DIGEST_LENGTH]>,
+    fragments: Vec<(u32, [u8; DIGEST_LENGTH])>,
+}
+
+impl Manifest {
+    /// Describe fragments made by [split_payload](fn.split_payload.html).
+    /// Every fragment has to be there.
+    pub fn new(fragments: &[Chunk]) -> Result<Self, FragmentError> {
+        let first = fragments.first().ok_or(FragmentError::NoFragments)?;
+        let payload = join_fragments(fragments)?;
+        let mut by_index = BTreeMap::new();
+        for fragment in fragments {
+            let (index, _, piece) = read_header(fragment)?;
+            let length = u32::try_from(piece.len())
+                .map_err(|_| FragmentError::Malformed(format!("Fragment {} is too long", index)))?;
+            by_index.insert(index, (length, digest(piece)));
+        }
+        Ok(Manifest {
+            chunk_type: *first.chunk_type(),
+            payload_digest: Some(digest(&payload)),
+            fragments: by_index.into_values().collect(),
+        })
+    }
+
+    /// The chunk type of the fragments this manifest describes.
+    pub fn chunk_type(&self) -> ChunkType {
+        self.chunk_type
+    }
+
+    /// The SHA-256 digest of the whole payload, unless this manifest was
+    /// written before that was recorded.
+    pub fn payload_digest(&self) -> Option<[u8; DIGEST_LENGTH]> {
+        self.payload_digest
+    }
+
+    /// Does this chunk hold a manifest for fragments of `chunk_type`?
+    pub fn describes(chunk: &Chunk, chunk_type: ChunkType) -> bool {
+        chunk.chunk_type() == &ChunkType::FRAGMENT_MANIFEST
+            && chunk.data().starts_with(&chunk_type.bytes())
+    }
+
+    /// Store this manifest in a chunk.
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.chunk_type.bytes().to_vec();
+        if let Some(digest) = &self.payload_digest {
+            data.extend_from_slice(digest);
+        }
+        for (length, digest) in &self.fragments {
+            data.extend_from_slice(&length.to_be_bytes());
+            data.extend_from_slice(digest);
+        }
+        Chunk::new(ChunkType::FRAGMENT_MANIFEST, data)
+    }
+
+    /// Read a manifest from a chunk made by [to_chunk](#method.to_chunk).
+    pub fn from_chunk(chunk: &Chunk) -> Result<Self, FragmentError> {
+        let data = chunk.data();
+        let entry_length = 4 + DIGEST_LENGTH;
+        if chunk.chunk_type() != &ChunkType::FRAGMENT_MANIFEST || data.len() < 4 {
+            return Err(FragmentError::Malformed("Bad manifest".to_string()));
+        }
+        let (payload_digest, entries) = match (data.len() - 4) % entry_length {
+            0 => (None, &data[4..]),
+            DIGEST_LENGTH => {
+                let mut digest = [0u8; DIGEST_LENGTH];
+                digest.copy_from_slice(&data[4..4 + DIGEST_LENGTH]);
+                (Some(digest), &data[4 + DIGEST_LENGTH..])
+            }
+            _ => return Err(FragmentError::Malformed("Bad manifest".to_string())),
+        };
+        let chunk_type = ChunkType::try_from([data[0], data[1], data[2], data[3]])
+            .map_err(|e| FragmentError::Malformed(format!("Bad manifest: {}", e)))?;
+        let fragments = entries
+            .chunks(entry_length)
+            .map(|entry| {
+                let length = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+                let mut digest = [0u8; DIGEST_LENGTH];
+                digest.copy_from_slice(&entry[4..]);
+                (length, digest)
+            })
+            .collect();
+        Ok(Manifest {
+            chunk_type,
+            payload_digest,
+            fragments,
+        })
+    }
+
+    /// Like [join_fragments](fn.join_fragments.html), but also check that
+    /// there are as many fragments as the manifest says, that each one has
+    /// the length and digest it should, and that the payload they make up
+    /// has the digest it should. Every missing and damaged fragment is named,
+    /// not just the first.
+    pub fn join<'a, I>(&self, fragments: I) -> Result<Vec<u8>, FragmentError>
+    where
+        I: IntoIterator<Item = &'a Chunk>,
+    {
+        Ok(self.pieces(fragments)?.concat())
+    }
+
+    /// Check fragments like [join](#method.join) does, but give back each
+    /// one's slice of the payload, in sequence order, rather than copying
+    /// them all into one.
+    pub fn pieces<'a, I>(&self, fragments: I) -> Result<Vec<&'a [u8]>, FragmentError>
+    where
+        I: IntoIterator<Item = &'a Chunk>,
+    {
+        let fragments: Vec<&Chunk> = fragments.into_iter().collect();
+        let expected_count = self.fragments.len() as u32;
+        let mut found = BTreeSet::new();
+        let mut damaged = BTreeSet::new();
+        for fragment in &fragments {
+            let (index, count, piece) = read_header(fragment)?;
+            if count != expected_count {
+                return Err(FragmentError::CountMismatch(expected_count, count));
+            }
+            match self.fragments.get(index as usize) {
+                Some((length, expected))
+                    if *length as usize == piece.len() && *expected == digest(piece) =>
+                {
+                    found.insert(index);
+                }
+                _ => {
+                    damaged.insert(index);
+                }
+            }
+        }
+        let missing: Vec<u32> = (0..expected_count)
+            .filter(|i| !found.contains(i) && !damaged.contains(i))
+            .collect();
+        let damaged: Vec<u32> = damaged.into_iter().collect();
+        match (missing.as_slice(), damaged.as_slice()) {
+            ([], []) => {}
+            ([index], []) => return Err(FragmentError::Missing(*index)),
+            ([], [index]) => return Err(FragmentError::Damaged(*index)),
+            _ => return Err(FragmentError::Incomplete { missing, damaged }),
+        }
+        let pieces = ordered_pieces(fragments)?;
+        if let Some(expected) = self.payload_digest {
+            let mut hasher = Sha256::new();
+            for piece in &pieces {
+                hasher.update(piece);
+            }
+            if <[u8; DIGEST_LENGTH]>::from(hasher.finalize()) != expected {
+                return Err(FragmentError::PayloadDamaged);
+            }
+        }
+        Ok(pieces)
+    }
+
+    /// The fragments among `chunks` that this manifest lists, whole and with
+    /// the digest they should have, keeping the first copy of each. Other
+    /// chunks of the same type, like a separate payload, are left out.
+    pub fn select<'a, I>(&self, chunks: I) -> Vec<&'a Chunk>
+    where
+        I: IntoIterator<Item = &'a Chunk>,
+    {
+        let mut by_index = BTreeMap::new();
+        for chunk in chunks {
+            if chunk.chunk_type() != &self.chunk_type {
+                continue;
+            }
+            let (index, count, piece) = match read_header(chunk) {
+                Ok(header) => header,
+                Err(_) => continue,
+            };
+            let listed = count as usize == self.fragments.len()
+                && self
+                    .fragments
+                    .get(index as usize)
+                    .is_some_and(|(length, expected)| {
+                        *length as usize == piece.len() && *expected == digest(piece)
+                    });
+            if listed {
+                by_index.entry(index).or_insert(chunk);
+            }
+        }
+        by_index.into_values().collect()
+    }
+
+    /// How many fragments at the start of `extracted`, a copy of the payload
+    /// that may have been cut off, are whole and have the digest they should,
+    /// and how many bytes they take up. Reads one fragment at a time.
+    pub fn verified_prefix<R: Read>(&self, mut extracted: R) -> io::Result<(usize, u64)> {
+        let mut offset = 0;
+        for (i, (length, expected)) in self.fragments.iter().enumerate() {
+            let mut piece = Vec::new();
+            (&mut extracted)
+                .take(u64::from(*length))
+                .read_to_end(&mut piece)?;
+            if piece.len() != *length as usize || digest(&piece) != *expected {
+                return Ok((i, offset));
+            }
+            offset += u64::from(*length);
+        }
+        Ok((self.fragments.len(), offset))
+    }
+
+    /// How many fragments the payload was split into.
+    pub fn len(&self) -> usize {
+        self.fragments.len()
+    }
+
+    /// Whether the manifest lists no fragments at all.
+    pub fn is_empty(&self) -> bool {
+        self.fragments.is_empty()
+    }
+}
+
+fn digest(piece: &[u8]) -> [u8; DIGEST_LENGTH] {
+    Sha256::digest(piece).into()
+}
+
+/// Read the sequence number, total count, and payload slice of a fragment.
+fn read_header(fragment: &Chunk) -> Result<(u32, u32, &[u8]), FragmentError> {
+    let data = fragment.data();
+    if data.len() < HEADER_LENGTH {
+        return Err(FragmentError::Malformed(format!(
+            "{} chunk is too short to be a fragment ({} bytes)",
+            fragment.chunk_type(),
+            data.len()
+        )));
+    }
+    let mut index = [0u8; 4];
+    let mut count = [0u8; 4];
+    index.copy_from_slice(&data[0..4]);
+    count.copy_from_slice(&data[4..8]);
+    Ok((
+        u32::from_be_bytes(index),
+        u32::from_be_bytes(count),
+        &data[HEADER_LENGTH..],
+    ))
+}
+
+/// Something went wrong while joining fragments back together.
+#[derive(Debug, PartialEq)]
+pub enum FragmentError {
+    /// There were no fragments at all.
+    NoFragments,
+    /// The fragment with this sequence number was never found.
+    Missing(u32),
+    /// More than one fragment had this sequence number.
+    Duplicate(u32),
+    /// Fragments disagreed about the total count (first seen, then found).
+    CountMismatch(u32, u32),
+    /// A fragment's header could not be read.
+    Malformed(String),
+    /// The fragment with this sequence number doesn't match its manifest.
+    Damaged(u32),
+    /// More than one fragment is missing or doesn't match its manifest.
+    Incomplete {
+        missing: Vec<u32>,
+        damaged: Vec<u32>,
+    },
+    /// Every fragment matches the manifest, but the payload they make up
+    /// doesn't.
+    PayloadDamaged,
+}
+impl fmt::Display for FragmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoFragments => write!(f, "No fragments found"),
+            Self::Missing(index) => write!(f, "Fragment {} is missing", index),
+            Self::Duplicate(index) => write!(f, "Fragment {} appears more than once", index),
+            Self::CountMismatch(expected, found) => write!(
+                f,
+                "Fragments disagree on the total count ({} vs. {})",
+                expected, found
+            ),
+            Self::Malformed(reason) => write!(f, "Bad fragment: {}", reason),
+            Self::Damaged(index) => write!(f, "Fragment {} doesn't match the manifest", index),
+            Self::Incomplete { missing, damaged } => {
+                let list = |indices: &[u32]| {
+                    indices
+                        .iter()
+                        .map(u32::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                let mut problems = Vec::new();
+                if !missing.is_empty() {
+                    problems.push(format!("missing: {}", list(missing)));
+                }
+                if !damaged.is_empty() {
+                    problems.push(format!("don't match the manifest: {}", list(damaged)));
+                }
+                write!(f, "Fragments {}", problems.join("; "))
+            }
+            Self::PayloadDamaged => write!(
+                f,
+                "Every fragment matches the manifest, but the message they make up doesn't"
+            ),
+        }
+    }
+}
+impl Error for FragmentError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk_type() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    #[test]
+    fn test_split_payload() {
+        let fragments = split_payload(chunk_type(), b"abcdefg", 3).unwrap();
+        assert_eq!(fragments.len(), 3);
+        assert_eq!(
+            fragments[0].data(),
+            &[0, 0, 0, 0, 0, 0, 0, 3, b'a', b'b', b'c']
+        );
+        assert_eq!(fragments[2].data(), &[0, 0, 0, 2, 0, 0, 0, 3, b'g']);
+    }
+
+    #[test]
+    fn test_split_empty_payload() {
+        let fragments = split_payload(chunk_type(), b"", 3).unwrap();
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(join_fragments(&fragments).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_split_zero_length() {
+        assert!(split_payload(chunk_type(), b"abc", 0).is_err());
+    }
+
+    #[test]
+    fn test_join_out_of_order() {
+        let mut fragments = split_payload(chunk_type(), b"abcdefg", 2).unwrap();
+        fragments.reverse();
+        assert_eq!(join_fragments(&fragments).unwrap(), b"abcdefg");
+    }
+
+    #[test]
+    fn test_join_missing_fragment() {
+        let mut fragments = split_payload(chunk_type(), b"abcdefg", 2).unwrap();
+        fragments.remove(1);
+        assert_eq!(join_fragments(&fragments), Err(FragmentError::Missing(1)));
+    }
+
+    #[test]
+    fn test_join_duplicate_fragment() {
+        let mut fragments = split_payload(chunk_type(), b"abcdefg", 2).unwrap();
+        fragments.push(Chunk::new(chunk_type(), fragments[0].data().to_vec()));
+        assert_eq!(join_fragments(&fragments), Err(FragmentError::Duplicate(0)));
+    }
+
+    #[test]
+    fn test_join_nothing() {
+        assert_eq!(join_fragments(&[]), Err(FragmentError::NoFragments));
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let fragments = split_payload(chunk_type(), b"abcdefg", 3).unwrap();
+        let manifest = Manifest::new(&fragments).unwrap();
+        assert_eq!(manifest.chunk_type(), chunk_type());
+        let chunk = manifest.to_chunk();
+        assert!(Manifest::describes(&chunk, chunk_type()));
+        assert!(!Manifest::describes(&chunk, ChunkType::TEXT));
+        assert_eq!(chunk.data().len(), 4 + 32 + 3 * 36);
+        let manifest = Manifest::from_chunk(&chunk).unwrap();
+        assert_eq!(manifest.join(fragments.iter().rev()).unwrap(), b"abcdefg");
+    }
+
+    #[test]
+    fn test_manifest_catches_damage() {
+        let mut fragments = split_payload(chunk_type(), b"abcdefg", 3).unwrap();
+        let manifest = Manifest::new(&fragments).unwrap();
+        let mut data = fragments[1].data().to_vec();
+        data[HEADER_LENGTH] ^= 1;
+        fragments[1] = Chunk::new(chunk_type(), data);
+        // The fragments themselves still fit together.
+        assert!(join_fragments(&fragments).is_ok());
+        assert_eq!(manifest.join(&fragments), Err(FragmentError::Damaged(1)));
+    }
+
+    #[test]
+    fn test_manifest_names_every_bad_fragment() {
+        let mut fragments = split_payload(chunk_type(), b"abcdefg", 2).unwrap();
+        let manifest = Manifest::new(&fragments).unwrap();
+        let mut data = fragments[2].data().to_vec();
+        data[HEADER_LENGTH] ^= 1;
+        fragments[2] = Chunk::new(chunk_type(), data);
+        fragments.remove(3);
+        fragments.remove(0);
+        let error = manifest.join(&fragments).unwrap_err();
+        assert_eq!(
+            error,
+            FragmentError::Incomplete {
+                missing: vec![0, 3],
+                damaged: vec![2]
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Fragments missing: 0, 3; don't match the manifest: 2"
+        );
+    }
+
+    #[test]
+    fn test_manifest_checks_whole_payload() {
+        let fragments = split_payload(chunk_type(), b"abcdefg", 3).unwrap();
+        let mut data = Manifest::new(&fragments)
+            .unwrap()
+            .to_chunk()
+            .data()
+            .to_vec();
+        data[4] ^= 1;
+        let manifest =
+            Manifest::from_chunk(&Chunk::new(ChunkType::FRAGMENT_MANIFEST, data)).unwrap();
+        assert_eq!(
+            manifest.join(&fragments),
+            Err(FragmentError::PayloadDamaged)
+        );
+    }
+
+    #[test]
+    fn test_manifest_without_payload_digest() {
+        let fragments = split_payload(chunk_type(), b"abcdefg", 3).unwrap();
+        let mut data = Manifest::new(&fragments)
+            .unwrap()
+            .to_chunk()
+            .data()
+            .to_vec();
+        data.drain(4..4 + DIGEST_LENGTH);
+        let manifest =
+            Manifest::from_chunk(&Chunk::new(ChunkType::FRAGMENT_MANIFEST, data)).unwrap();
+        assert_eq!(manifest.payload_digest(), None);
+        assert_eq!(manifest.join(&fragments).unwrap(), b"abcdefg");
+    }
+
+    #[test]
+    fn test_verified_prefix() {
+        let fragments = split_payload(chunk_type(), b"abcdefg", 3).unwrap();
+        let manifest = Manifest::new(&fragments).unwrap();
+        assert_eq!(manifest.len(), 3);
+        assert_eq!(manifest.verified_prefix(&b"abcdefg"[..]).unwrap(), (3, 7));
+        assert_eq!(manifest.verified_prefix(&b"abcde"[..]).unwrap(), (1, 3));
+        assert_eq!(manifest.verified_prefix(&b"abcdXfg"[..]).unwrap(), (1, 3));
+        assert_eq!(manifest.verified_prefix(&b""[..]).unwrap(), (0, 0));
+        let pieces = manifest.pieces(fragments.iter().rev()).unwrap();
+        assert_eq!(pieces, vec![&b"abc"[..], b"def", b"g"]);
+    }
+
+    #[test]
+    fn test_manifest_catches_wrong_count() {
+        let fragments = split_payload(chunk_type(), b"abcdefg", 3).unwrap();
+        let manifest = Manifest::new(&fragments).unwrap();
+        let others = split_payload(chunk_type(), b"abcdefg", 2).unwrap();
+        assert_eq!(
+            manifest.join(&others),
+            Err(FragmentError::CountMismatch(3, 4))
+        );
+    }
+
+    #[test]
+    fn test_bad_manifest() {
+        assert!(
+            Manifest::from_chunk(&Chunk::new(ChunkType::FRAGMENT_MANIFEST, vec![1, 2])).is_err()
+        );
+        assert!(Manifest::from_chunk(&Chunk::new(chunk_type(), b"ruSt".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_stripe_and_join_carriers() {
+        let fragments = stripe_payload(chunk_type(), b"abcdefg", 3).unwrap();
+        assert_eq!(fragments.len(), 3);
+        let manifest = Manifest::new(&fragments).unwrap();
+        let mut pngs: Vec<Png> = fragments
+            .into_iter()
+            .map(|fragment| Png::from_chunks(vec![fragment, manifest.to_chunk()]))
+            .collect();
+        pngs.reverse();
+        // A PNG that carries nothing is skipped.
+        pngs.push(Png::from_chunks(vec![]));
+        assert_eq!(join_carriers(&pngs, chunk_type()).unwrap(), b"abcdefg");
+
+        pngs.remove(1);
+        assert_eq!(
+            join_carriers(&pngs, chunk_type()),
+            Err(FragmentError::Missing(1))
+        );
+        assert_eq!(
+            join_carriers(&pngs, ChunkType::TEXT),
+            Err(FragmentError::NoFragments)
+        );
+    }
+
+    #[test]
+    fn test_join_carriers_of_different_payloads() {
+        let one = stripe_payload(chunk_type(), b"abcdefg", 2).unwrap();
+        let other = stripe_payload(chunk_type(), b"gfedcba", 2).unwrap();
+        let pngs = vec![
+            Png::from_chunks(vec![Manifest::new(&one).unwrap().to_chunk()]),
+            Png::from_chunks(vec![Manifest::new(&other).unwrap().to_chunk()]),
+        ];
+        assert!(matches!(
+            join_carriers(&pngs, chunk_type()),
+            Err(FragmentError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_stripe_short_payload() {
+        assert_eq!(stripe_payload(chunk_type(), b"ab", 3).unwrap().len(), 2);
+        assert!(stripe_payload(chunk_type(), b"ab", 0).is_err());
+    }
+
+    #[test]
+    fn test_join_malformed() {
+        let fragments = vec![Chunk::new(chunk_type(), vec![0, 0, 0])];
+        assert!(join_fragments(&fragments).is_err());
+    }
+}
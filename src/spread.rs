@@ -0,0 +1,215 @@
+//! Hiding a payload across the ancillary chunks a PNG already has, instead of
+//! adding a new chunk that stands out.
+//!
+//! The payload is cut into one piece per carrier chunk, and each piece is
+//! appended to the end of its carrier's data, followed by a 12-byte footer:
+//! the piece's position as a 2-byte big-endian number, the number of pieces
+//! as another, the piece's length as a 4-byte big-endian number, and a CRC-32
+//! of the piece and the rest of the footer. The footers are the only index
+//! there is; they have no fixed magic bytes, so they look like any other
+//! data until the CRCs are checked. Each carrier's chunk CRC is recomputed.
+//!
+//! Decoders that expect a carrier to be a fixed size (like `gAMA`) may ignore
+//! it once it has grown, so this trades some compatibility for stealth.
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crc::crc32;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+/// The position, count, length, and CRC that follow each piece.
+pub(crate) const FOOTER_LENGTH: usize = 2 + 2 + 4 + 4;
+
+/// Cut `payload` into pieces and append them to the PNG's ancillary chunks.
+/// Chunks that pngme uses for its own bookkeeping are never used as carriers.
+/// Returns how many chunks now carry a piece.
+pub fn spread(png: &mut Png, payload: &[u8]) -> crate::Result<usize> {
+    if !pieces(png).is_empty() {
+        return Err(SpreadError::boxed(
+            "The PNG already carries a spread payload".to_string(),
+        ));
+    }
+    let carriers: Vec<usize> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| is_carrier(c))
+        .map(|(index, _)| index)
+        .take(usize::from(u16::MAX))
+        .collect();
+    if carriers.is_empty() {
+        return Err(SpreadError::boxed(
+            "There are no ancillary chunks to hide the payload in".to_string(),
+        ));
+    }
+    let count = carriers.len();
+    for (position, &index) in carriers.iter().enumerate() {
+        let piece =
+            &payload[payload.len() * position / count..payload.len() * (position + 1) / count];
+        let carrier = &png.chunks()[index];
+        let mut data = carrier.data().to_vec();
+        data.extend_from_slice(piece);
+        data.extend_from_slice(&(position as u16).to_be_bytes());
+        data.extend_from_slice(&(count as u16).to_be_bytes());
+        data.extend_from_slice(&(piece.len() as u32).to_be_bytes());
+        let crc = crc32::checksum_ieee(&data[data.len() - piece.len() - 8..]);
+        data.extend_from_slice(&crc.to_be_bytes());
+        if data.len() > Chunk::MAXIMUM_LENGTH as usize {
+            return Err(SpreadError::boxed(format!(
+                "The payload is too big to spread over {} chunk{}",
+                count,
+                if count == 1 { "" } else { "s" }
+            )));
+        }
+        let chunk_type = *carrier.chunk_type();
+        png.replace_chunk(index, Chunk::new(chunk_type, data));
+    }
+    Ok(count)
+}
+
+/// Put a payload hidden by [spread] back together.
+/// Returns `Err` if there isn't one or if any of its pieces are missing.
+pub fn gather(png: &Png) -> crate::Result<Vec<u8>> {
+    let pieces = pieces(png);
+    let count = match pieces.values().next() {
+        Some((count, _)) => *count,
+        None => {
+            return Err(SpreadError::boxed(
+                "No payload is spread across the chunks".to_string(),
+            ))
+        }
+    };
+    let mut payload = Vec::new();
+    for position in 0..count {
+        match pieces.get(&position) {
+            Some((c, piece)) if *c == count => payload.extend_from_slice(piece),
+            _ => {
+                return Err(SpreadError::boxed(format!(
+                    "Piece {} of {} is missing",
+                    position + 1,
+                    count
+                )))
+            }
+        }
+    }
+    if pieces.len() != usize::from(count) {
+        return Err(SpreadError::boxed(format!(
+            "Found {} pieces, but expected {}",
+            pieces.len(),
+            count
+        )));
+    }
+    Ok(payload)
+}
+
+pub(crate) fn is_carrier(chunk: &Chunk) -> bool {
+    !chunk.chunk_type().is_critical() && !ChunkType::PNGME_TYPES.contains(chunk.chunk_type())
+}
+
+/// Every piece found in the PNG, by position, with the number of pieces its
+/// footer says there are.
+fn pieces(png: &Png) -> BTreeMap<u16, (u16, &[u8])> {
+    png.chunks()
+        .iter()
+        .filter(|c| is_carrier(c))
+        .filter_map(|c| piece(c.data()))
+        .map(|(position, count, piece)| (position, (count, piece)))
+        .collect()
+}
+
+/// The piece at the end of a carrier's data, if its footer checks out.
+fn piece(data: &[u8]) -> Option<(u16, u16, &[u8])> {
+    let footer_start = data.len().checked_sub(FOOTER_LENGTH)?;
+    let footer = &data[footer_start..];
+    let position = u16::from_be_bytes([footer[0], footer[1]]);
+    let count = u16::from_be_bytes([footer[2], footer[3]]);
+    let length = u32::from_be_bytes([footer[4], footer[5], footer[6], footer[7]]);
+    let crc = u32::from_be_bytes([footer[8], footer[9], footer[10], footer[11]]);
+    let start = footer_start.checked_sub(usize::try_from(length).ok()?)?;
+    if position >= count || crc32::checksum_ieee(&data[start..footer_start + 8]) != crc {
+        return None;
+    }
+    Some((position, count, &data[start..footer_start]))
+}
+
+/// A payload could not be spread across a PNG's chunks, or put back together.
+#[derive(Debug)]
+pub struct SpreadError {
+    /// Why the payload could not be spread or gathered.
+    reason: String,
+}
+impl SpreadError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for SpreadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad spread payload: {}", self.reason)
+    }
+}
+impl Error for SpreadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn png() -> Png {
+        testing::png_with(vec![
+            testing::chunk("gAMA", &[0, 0, 177, 143]),
+            testing::text_chunk("Author", "Somebody"),
+            Chunk::new(ChunkType::TIME, vec![7; 7]),
+        ])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut png = png();
+        assert_eq!(spread(&mut png, b"This is a secret message!").unwrap(), 3);
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(gather(&png).unwrap(), b"This is a secret message!");
+        // Critical chunks are left alone, and carriers keep their data.
+        let original = testing::minimal_png();
+        assert_eq!(png.chunks()[0].data(), original.chunks()[0].data());
+        assert_eq!(png.chunks()[1].data(), original.chunks()[1].data());
+        assert!(png.chunks()[3].data().starts_with(b"Author\0Somebody"));
+    }
+
+    #[test]
+    fn test_short_payload() {
+        let mut png = png();
+        spread(&mut png, b"a").unwrap();
+        assert_eq!(gather(&png).unwrap(), b"a");
+    }
+
+    #[test]
+    fn test_only_once() {
+        let mut png = png();
+        spread(&mut png, b"first").unwrap();
+        assert!(spread(&mut png, b"second").is_err());
+    }
+
+    #[test]
+    fn test_no_carriers() {
+        let mut png = testing::png_with(vec![Chunk::new(ChunkType::SIGNATURE, vec![0; 68])]);
+        assert!(spread(&mut png, b"secret").is_err());
+    }
+
+    #[test]
+    fn test_nothing_to_gather() {
+        assert!(gather(&png()).is_err());
+    }
+
+    #[test]
+    fn test_missing_piece() {
+        let mut png = png();
+        spread(&mut png, b"This is a secret message!").unwrap();
+        png.retain_chunks(|c| c.chunk_type() != &ChunkType::TEXT);
+        assert!(gather(&png).is_err());
+    }
+}
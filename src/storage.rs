@@ -0,0 +1,497 @@
+//! Reading and writing files wherever they are.
+//!
+//! A path like `s3://bucket/key` or `gs://bucket/key` means an object in
+//! Amazon S3 or Google Cloud Storage, if pngme was built with the
+//! `object-store` feature. Objects are streamed rather than downloaded first:
+//! reading one only fetches what's read, and writing one uploads it in parts as
+//! it's written. Credentials come from the same environment variables the
+//! providers' own tools use (`AWS_ACCESS_KEY_ID`,
+//! `GOOGLE_APPLICATION_CREDENTIALS`, and so on).
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+
+/// The URI schemes that mean an object store, and which store they mean.
+const SCHEMES: [(&str, Provider); 2] = [("s3://", Provider::S3), ("gs://", Provider::Gcs)];
+
+/// Which object store a URI is for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Provider {
+    S3,
+    Gcs,
+}
+
+/// Something to read a file from, that can be read anywhere in.
+pub trait Input: Read + Seek {}
+impl<T: Read + Seek> Input for T {}
+
+/// Whether `path` is an object store URI rather than a file.
+pub fn is_remote(path: &Path) -> bool {
+    uri(path).is_some()
+}
+
+/// Which store `path` is in, and the rest of the URI after the scheme.
+fn uri(path: &Path) -> Option<(Provider, &str)> {
+    let path = path.to_str()?;
+    SCHEMES
+        .iter()
+        .find_map(|(scheme, provider)| Some((*provider, path.strip_prefix(scheme)?)))
+}
+
+/// The store, bucket, and key an object store URI names.
+fn parse(path: &Path) -> crate::Result<(Provider, &str, &str)> {
+    let (provider, rest) = uri(path).ok_or_else(|| {
+        ObjectStoreError::boxed(format!("{} isn't an object store URI", path.display()))
+    })?;
+    match rest.split_once('/') {
+        Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => Ok((provider, bucket, key)),
+        _ => Err(ObjectStoreError::boxed(format!(
+            "{} doesn't name an object (try s3://bucket/key)",
+            path.display()
+        ))),
+    }
+}
+
+/// Read all of the file or object at `path`.
+pub fn read(path: &Path) -> crate::Result<Vec<u8>> {
+    if !is_remote(path) {
+        return Ok(fs::read(path)?);
+    }
+    let mut bytes = Vec::new();
+    open(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Open the file or object at `path` for reading.
+pub fn open(path: &Path) -> crate::Result<Box<dyn Input>> {
+    if is_remote(path) {
+        Ok(Box::new(object::Object::parse(path)?.reader()))
+    } else {
+        Ok(Box::new(File::open(path)?))
+    }
+}
+
+/// Write `bytes` to the file or object at `path`, replacing it.
+pub fn write(path: &Path, bytes: &[u8]) -> crate::Result<()> {
+    if is_remote(path) {
+        object::Object::parse(path)?.put(bytes)
+    } else {
+        Ok(fs::write(path, bytes)?)
+    }
+}
+
+/// An object being written. Nothing replaces the object until [Upload::finish].
+pub struct Upload(object::Upload);
+
+impl Upload {
+    /// Start writing the object at `path`.
+    pub fn create(path: &Path) -> crate::Result<Self> {
+        Ok(Upload(object::Object::parse(path)?.upload()?))
+    }
+
+    /// Replace the object with what's been written.
+    pub fn finish(self) -> crate::Result<()> {
+        self.0.finish()
+    }
+
+    /// Throw away what's been written, leaving the object alone.
+    pub fn abort(self) {
+        self.0.abort()
+    }
+}
+
+impl Write for Upload {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(feature = "object-store")]
+mod object {
+    use super::{ObjectStoreError, Provider};
+    use bytes::Bytes;
+    use futures::stream::{BoxStream, StreamExt};
+    use object_store::aws::AmazonS3Builder;
+    use object_store::gcp::GoogleCloudStorageBuilder;
+    use object_store::path::Path as Location;
+    use object_store::{GetOptions, GetRange, ObjectStore, PutPayload, WriteMultipart};
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+    use std::path::Path;
+    use std::sync::{Arc, OnceLock};
+    use tokio::runtime::Runtime;
+
+    /// How many parts of an upload can be sent at once.
+    const PARTS_IN_FLIGHT: usize = 4;
+
+    /// The runtime that object store requests are run on. Everything else in
+    /// pngme is blocking, so requests are waited on as they're made.
+    fn runtime() -> &'static Runtime {
+        static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+        RUNTIME.get_or_init(|| {
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .expect("couldn't start the runtime for object store requests")
+        })
+    }
+
+    /// A missing object is reported like a missing file, and anything else
+    /// as an [ObjectStoreError].
+    fn io_error(error: object_store::Error) -> io::Error {
+        match error {
+            object_store::Error::NotFound { .. } => io::Error::new(io::ErrorKind::NotFound, error),
+            _ => io::Error::other(ObjectStoreError::boxed(error.to_string())),
+        }
+    }
+
+    fn error(error: object_store::Error) -> crate::Error {
+        match error {
+            object_store::Error::NotFound { .. } => Box::new(io_error(error)),
+            _ => ObjectStoreError::boxed(error.to_string()),
+        }
+    }
+
+    /// An object in a store.
+    pub struct Object {
+        store: Arc<dyn ObjectStore>,
+        location: Location,
+    }
+
+    impl Object {
+        pub fn parse(path: &Path) -> crate::Result<Self> {
+            let (provider, bucket, key) = super::parse(path)?;
+            let store: Arc<dyn ObjectStore> = match provider {
+                Provider::S3 => Arc::new(
+                    AmazonS3Builder::from_env()
+                        .with_bucket_name(bucket)
+                        .build()
+                        .map_err(error)?,
+                ),
+                Provider::Gcs => Arc::new(
+                    GoogleCloudStorageBuilder::from_env()
+                        .with_bucket_name(bucket)
+                        .build()
+                        .map_err(error)?,
+                ),
+            };
+            let location = Location::parse(key)
+                .map_err(|e| ObjectStoreError::boxed(format!("Bad object key: {}", e)))?;
+            Ok(Object { store, location })
+        }
+
+        pub fn reader(self) -> Reader {
+            Reader {
+                object: self,
+                position: 0,
+                size: None,
+                stream: None,
+                buffer: Bytes::new(),
+            }
+        }
+
+        pub fn put(&self, bytes: &[u8]) -> crate::Result<()> {
+            let payload = PutPayload::from(bytes.to_vec());
+            runtime()
+                .block_on(self.store.put(&self.location, payload))
+                .map_err(error)?;
+            Ok(())
+        }
+
+        pub fn upload(&self) -> crate::Result<Upload> {
+            let upload = runtime()
+                .block_on(self.store.put_multipart(&self.location))
+                .map_err(error)?;
+            Ok(Upload(WriteMultipart::new(upload)))
+        }
+    }
+
+    /// Reads an object from wherever it's been seeked to, fetching the rest of
+    /// it from there in one request.
+    pub struct Reader {
+        object: Object,
+        position: u64,
+        /// How long the object is, once a request has said.
+        size: Option<u64>,
+        stream: Option<BoxStream<'static, object_store::Result<Bytes>>>,
+        /// What's been fetched but not read yet.
+        buffer: Bytes,
+    }
+
+    impl Reader {
+        fn size(&mut self) -> io::Result<u64> {
+            if let Some(size) = self.size {
+                return Ok(size);
+            }
+            let meta = runtime()
+                .block_on(self.object.store.head(&self.object.location))
+                .map_err(io_error)?;
+            self.size = Some(meta.size);
+            Ok(meta.size)
+        }
+    }
+
+    impl Read for Reader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            while self.buffer.is_empty() {
+                let stream = match &mut self.stream {
+                    Some(stream) => stream,
+                    None => {
+                        if self.size.is_some_and(|size| self.position >= size) {
+                            return Ok(0);
+                        }
+                        // A range starting at 0 is an error for an empty object.
+                        let options = GetOptions {
+                            range: (self.position > 0).then_some(GetRange::Offset(self.position)),
+                            ..GetOptions::default()
+                        };
+                        let result = runtime()
+                            .block_on(self.object.store.get_opts(&self.object.location, options))
+                            .map_err(io_error)?;
+                        self.size = Some(result.meta.size);
+                        self.stream.insert(result.into_stream())
+                    }
+                };
+                match runtime().block_on(stream.next()) {
+                    Some(bytes) => self.buffer = bytes.map_err(io_error)?,
+                    None => return Ok(0),
+                }
+            }
+            let n = buf.len().min(self.buffer.len());
+            buf[..n].copy_from_slice(&self.buffer[..n]);
+            self.buffer = self.buffer.slice(n..);
+            self.position += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl Seek for Reader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            let position = match pos {
+                SeekFrom::Start(position) => Some(position),
+                SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+                SeekFrom::End(offset) => self.size()?.checked_add_signed(offset),
+            }
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Can't seek to before the start of an object",
+                )
+            })?;
+            if position != self.position {
+                self.position = position;
+                self.stream = None;
+                self.buffer = Bytes::new();
+            }
+            Ok(position)
+        }
+    }
+
+    /// Uploads an object in parts as it's written.
+    pub struct Upload(WriteMultipart);
+
+    impl Upload {
+        pub fn finish(self) -> crate::Result<()> {
+            runtime().block_on(self.0.finish()).map_err(error)?;
+            Ok(())
+        }
+
+        pub fn abort(self) {
+            let _ = runtime().block_on(self.0.abort());
+        }
+    }
+
+    impl Write for Upload {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            runtime()
+                .block_on(self.0.wait_for_capacity(PARTS_IN_FLIGHT))
+                .map_err(io_error)?;
+            // Parts are sent from tasks started by `write`.
+            let _runtime = runtime().enter();
+            self.0.write(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use object_store::memory::InMemory;
+
+        fn object() -> Object {
+            Object {
+                store: Arc::new(InMemory::new()),
+                location: Location::from("dice.png"),
+            }
+        }
+
+        #[test]
+        fn test_upload_then_read() {
+            let object = object();
+            let mut upload = object.upload().unwrap();
+            upload.write_all(b"\x89PNG").unwrap();
+            upload.write_all(b" and the rest").unwrap();
+            upload.finish().unwrap();
+
+            let mut reader = object.reader();
+            let mut start = [0; 4];
+            reader.read_exact(&mut start).unwrap();
+            assert_eq!(&start, b"\x89PNG");
+            reader.seek(SeekFrom::End(-4)).unwrap();
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).unwrap();
+            assert_eq!(rest, b"rest");
+            reader.seek(SeekFrom::Start(1)).unwrap();
+            let mut all = Vec::new();
+            reader.read_to_end(&mut all).unwrap();
+            assert_eq!(all, b"PNG and the rest");
+        }
+
+        #[test]
+        fn test_aborted_upload() {
+            let object = object();
+            object.put(b"old").unwrap();
+            let mut upload = object.upload().unwrap();
+            upload.write_all(b"new").unwrap();
+            upload.abort();
+            let mut bytes = Vec::new();
+            object.reader().read_to_end(&mut bytes).unwrap();
+            assert_eq!(bytes, b"old");
+        }
+
+        #[test]
+        fn test_missing_object() {
+            let error = object().reader().read(&mut [0; 4]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        }
+    }
+}
+
+/// Without the `object-store` feature, every object store URI is an error, so
+/// none of these can be made.
+#[cfg(not(feature = "object-store"))]
+mod object {
+    use super::ObjectStoreError;
+    use std::convert::Infallible;
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+    use std::path::Path;
+
+    pub struct Object(Infallible);
+
+    impl Object {
+        pub fn parse(path: &Path) -> crate::Result<Self> {
+            super::parse(path)?;
+            Err(ObjectStoreError::boxed(format!(
+                "Can't open {}, because pngme was built without the object-store feature",
+                path.display()
+            )))
+        }
+
+        pub fn reader(self) -> Reader {
+            match self.0 {}
+        }
+
+        pub fn put(&self, _bytes: &[u8]) -> crate::Result<()> {
+            match self.0 {}
+        }
+
+        pub fn upload(&self) -> crate::Result<Upload> {
+            match self.0 {}
+        }
+    }
+
+    pub struct Reader(Infallible);
+
+    impl Read for Reader {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            match self.0 {}
+        }
+    }
+
+    impl Seek for Reader {
+        fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+            match self.0 {}
+        }
+    }
+
+    pub struct Upload(Infallible);
+
+    impl Upload {
+        pub fn finish(self) -> crate::Result<()> {
+            match self.0 {}
+        }
+
+        pub fn abort(self) {
+            match self.0 {}
+        }
+    }
+
+    impl Write for Upload {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            match self.0 {}
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            match self.0 {}
+        }
+    }
+}
+
+/// An object store URI is malformed, or a request to the store failed.
+#[derive(Debug)]
+pub struct ObjectStoreError {
+    reason: String,
+}
+
+impl ObjectStoreError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for ObjectStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad object: {}", self.reason)
+    }
+}
+
+impl Error for ObjectStoreError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!(!is_remote(Path::new("dice.png")));
+        assert!(!is_remote(Path::new("s3:/bucket/dice.png")));
+        assert_eq!(
+            parse(Path::new("s3://bucket/images/dice.png")).unwrap(),
+            (Provider::S3, "bucket", "images/dice.png")
+        );
+        assert_eq!(
+            parse(Path::new("gs://bucket/dice.png")).unwrap(),
+            (Provider::Gcs, "bucket", "dice.png")
+        );
+        for uri in &["s3://bucket", "s3://bucket/", "gs:///dice.png"] {
+            assert!(is_remote(Path::new(uri)));
+            assert!(parse(Path::new(uri)).is_err(), "{}", uri);
+        }
+    }
+
+    #[cfg(not(feature = "object-store"))]
+    #[test]
+    fn test_without_the_feature() {
+        let error = read(Path::new("s3://bucket/dice.png")).err().unwrap();
+        assert!(error.to_string().contains("object-store feature"));
+    }
+}
@@ -0,0 +1,171 @@
+//! Chunks and PNGs to use in tests, both valid and deliberately broken.
+//!
+//! This module is only built with the `test-util` feature:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! pngme = { git = "https://github.com/gabebw/pngme", default-features = false, features = ["test-util"] }
+//! ```
+//!
+//! Everything here panics instead of returning `Err`, since a fixture that
+//! can't be built is a bug in the test.
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use std::str::FromStr;
+
+/// Chunk types that aren't valid, because they have a byte that isn't an
+/// ASCII letter.
+pub const INVALID_CHUNK_TYPES: [[u8; 4]; 4] = [*b"Ru1t", *b"ru t", *b"\0\0\0\0", *b"r\xffSt"];
+
+/// Build a chunk from a chunk type like "ruSt" and its data.
+///
+/// # Panics
+///
+/// Panics if `chunk_type` isn't a valid chunk type.
+pub fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+    let chunk_type = ChunkType::from_str(chunk_type)
+        .unwrap_or_else(|e| panic!("'{}' is not a valid chunk type: {}", chunk_type, e));
+    Chunk::new(chunk_type, data.to_vec())
+}
+
+/// A `tEXt` chunk with a keyword and some text.
+pub fn text_chunk(keyword: &str, text: &str) -> Chunk {
+    let mut data = keyword.as_bytes().to_vec();
+    data.push(0);
+    data.extend_from_slice(text.as_bytes());
+    Chunk::new(ChunkType::TEXT, data)
+}
+
+/// The smallest valid image, [Png::default](../struct.Png.html#impl-Default-for-Png):
+/// one transparent pixel, in 8-bit RGBA.
+pub fn minimal_png() -> Png {
+    Png::default()
+}
+
+/// [minimal_png](fn.minimal_png.html), with `chunks` added just before IEND.
+pub fn png_with(chunks: Vec<Chunk>) -> Png {
+    let mut png = minimal_png();
+    let iend = png.chunks().len() - 1;
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        png.insert_chunk(iend + i, chunk);
+    }
+    png
+}
+
+/// The bytes of `chunk`, with a CRC that doesn't match.
+pub fn chunk_bytes_with_bad_crc(chunk: &Chunk) -> Vec<u8> {
+    let mut bytes = chunk.as_bytes();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    bytes
+}
+
+/// The bytes of `chunk`, with a length that says there's one more byte of
+/// data than there is.
+pub fn chunk_bytes_with_bad_length(chunk: &Chunk) -> Vec<u8> {
+    let mut bytes = chunk.as_bytes();
+    bytes[..4].copy_from_slice(&(chunk.length() + 1).to_be_bytes());
+    bytes
+}
+
+/// The bytes of `png`, with the CRC of the chunk at `index` damaged.
+///
+/// # Panics
+///
+/// Panics if there is no chunk at `index`.
+pub fn with_bad_crc(png: &Png, index: usize) -> Vec<u8> {
+    let mut bytes = png.as_bytes();
+    let start = chunk_offset(png, index);
+    let crc = start + 8 + png.chunks()[index].data().len();
+    bytes[crc] ^= 0xff;
+    bytes
+}
+
+/// The bytes of `png`, with bit `bit` (counting from the most significant
+/// bit of the first byte) flipped.
+///
+/// # Panics
+///
+/// Panics if `bit` is past the end of the PNG.
+pub fn with_flipped_bit(png: &Png, bit: usize) -> Vec<u8> {
+    let mut bytes = png.as_bytes();
+    bytes[bit / 8] ^= 0x80 >> (bit % 8);
+    bytes
+}
+
+/// The bytes of `png`, with the signature at the start damaged.
+pub fn with_bad_signature(png: &Png) -> Vec<u8> {
+    let mut bytes = png.as_bytes();
+    bytes[1] = b'J';
+    bytes
+}
+
+/// The first `length` bytes of `png`.
+pub fn truncated(png: &Png, length: usize) -> Vec<u8> {
+    let mut bytes = png.as_bytes();
+    bytes.truncate(length);
+    bytes
+}
+
+/// Where the chunk at `index` starts in the bytes of `png`.
+pub fn chunk_offset(png: &Png, index: usize) -> usize {
+    Png::STANDARD_HEADER.len()
+        + png.chunks()[..index]
+            .iter()
+            .map(|c| 12 + c.data().len())
+            .sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::info::ImageHeader;
+    use flate2::read::ZlibDecoder;
+    use std::convert::TryFrom;
+    use std::io::Read;
+
+    #[test]
+    fn test_minimal_png_is_valid() {
+        let png = Png::try_from(minimal_png().as_bytes().as_slice()).unwrap();
+        let header = ImageHeader::from_png(&png).unwrap();
+        let mut raw = Vec::new();
+        ZlibDecoder::new(png.chunks()[1].data())
+            .read_to_end(&mut raw)
+            .unwrap();
+        assert_eq!(raw.len() as u64, header.raw_data_len());
+    }
+
+    #[test]
+    fn test_png_with() {
+        let png = png_with(vec![text_chunk("Comment", "hi"), chunk("ruSt", b"Secret")]);
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "IDAT", "tEXt", "ruSt", "IEND"]);
+        assert_eq!(png.chunks()[2].data(), b"Comment\0hi");
+    }
+
+    #[test]
+    fn test_broken_chunks() {
+        for bytes in &INVALID_CHUNK_TYPES {
+            assert!(ChunkType::try_from(*bytes).is_err());
+        }
+        let chunk = chunk("ruSt", b"Secret");
+        assert!(Chunk::try_from(chunk.as_bytes().as_slice()).is_ok());
+        assert!(Chunk::try_from(chunk_bytes_with_bad_crc(&chunk).as_slice()).is_err());
+        assert!(Chunk::try_from(chunk_bytes_with_bad_length(&chunk).as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_broken_pngs() {
+        let png = png_with(vec![chunk("ruSt", b"Secret")]);
+        assert!(Png::try_from(with_bad_crc(&png, 2).as_slice()).is_err());
+        assert!(Png::try_from(with_bad_signature(&png).as_slice()).is_err());
+        assert!(Png::try_from(truncated(&png, 20).as_slice()).is_err());
+        let secret_bit = (chunk_offset(&png, 2) + 8) * 8;
+        assert!(Png::try_from(with_flipped_bit(&png, secret_bit).as_slice()).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+//! Hiding payloads after the end of a PNG instead of in chunks.
+//!
+//! Image viewers stop reading at the IEND chunk, and some tools that strip
+//! ancillary chunks copy whatever comes after it untouched. Each payload is
+//! stored there as a record: the payload, then the 4-byte chunk type it is
+//! labelled with, then its length as a 4-byte big-endian number, then
+//! [TRAILER_MAGIC](constant.TRAILER_MAGIC.html). Records are read backwards
+//! from the end of the file, so they can follow other trailing data.
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+/// The bytes every record ends with.
+pub const TRAILER_MAGIC: [u8; 8] = *b"PNGmeTRL";
+
+/// The label, length, and magic that follow each payload.
+pub(crate) const FOOTER_LENGTH: usize = 4 + 4 + TRAILER_MAGIC.len();
+
+/// Add a record holding `payload`, labelled with `chunk_type`, after every
+/// other record.
+pub fn append_record(png: &mut Png, chunk_type: ChunkType, payload: &[u8]) -> crate::Result<()> {
+    let length = u32::try_from(payload.len()).map_err(|_| {
+        TrailerError::boxed(format!(
+            "Payload is too long ({} bytes, at most {})",
+            payload.len(),
+            u32::MAX
+        ))
+    })?;
+    let mut record = payload.to_vec();
+    record.extend_from_slice(&chunk_type.bytes());
+    record.extend_from_slice(&length.to_be_bytes());
+    record.extend_from_slice(&TRAILER_MAGIC);
+    png.append_trailing_data(&record);
+    Ok(())
+}
+
+/// Every record in `trailing_data`, in the order they were added, as the chunk
+/// type each is labelled with and its payload.
+pub fn records(trailing_data: &[u8]) -> Vec<(ChunkType, &[u8])> {
+    let mut records = Vec::new();
+    let mut rest = trailing_data;
+    while rest.len() >= FOOTER_LENGTH && rest.ends_with(&TRAILER_MAGIC) {
+        let footer = &rest[rest.len() - FOOTER_LENGTH..];
+        let chunk_type = match ChunkType::try_from([footer[0], footer[1], footer[2], footer[3]]) {
+            Ok(chunk_type) => chunk_type,
+            Err(_) => break,
+        };
+        let length = u32::from_be_bytes([footer[4], footer[5], footer[6], footer[7]]) as usize;
+        let before_footer = &rest[..rest.len() - FOOTER_LENGTH];
+        if before_footer.len() < length {
+            break;
+        }
+        let start = before_footer.len() - length;
+        records.push((chunk_type, &before_footer[start..]));
+        rest = &before_footer[..start];
+    }
+    records.reverse();
+    records
+}
+
+/// A payload could not be stored after the end of a PNG.
+#[derive(Debug)]
+pub struct TrailerError {
+    /// Why the payload could not be stored.
+    reason: String,
+}
+impl TrailerError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for TrailerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad trailer: {}", self.reason)
+    }
+}
+impl Error for TrailerError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use std::str::FromStr;
+
+    fn png() -> Png {
+        Png::from_chunks(vec![Chunk::new(ChunkType::IEND, vec![])])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut png = png();
+        let first = ChunkType::from_str("ruSt").unwrap();
+        let second = ChunkType::from_str("coDe").unwrap();
+        append_record(&mut png, first, b"Secret").unwrap();
+        append_record(&mut png, second, b"").unwrap();
+        append_record(&mut png, first, b"Another").unwrap();
+
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(
+            records(png.trailing_data()),
+            vec![
+                (first, &b"Secret"[..]),
+                (second, &b""[..]),
+                (first, &b"Another"[..])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_after_other_trailing_data() {
+        let mut png = png();
+        png.append_trailing_data(b"somebody else's data");
+        append_record(&mut png, ChunkType::from_str("ruSt").unwrap(), b"Secret").unwrap();
+        let found = records(png.trailing_data());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1, b"Secret");
+    }
+
+    #[test]
+    fn test_no_records() {
+        assert!(records(b"").is_empty());
+        assert!(records(b"just some bytes").is_empty());
+        // A record whose length runs off the front of the data.
+        assert!(records(b"ruSt\x00\x00\x00\x10PNGmeTRL").is_empty());
+    }
+}
@@ -0,0 +1,715 @@
+//! Processing a payload before it is embedded, and undoing that processing
+//! after it is extracted.
+//!
+//! A [Pipeline](struct.Pipeline.html) runs a payload through a series of
+//! [PayloadTransform](trait.PayloadTransform.html)s (for example, compress and
+//! then encrypt) and wraps the result in an envelope that records which
+//! transforms were applied, in order. Opening the envelope inverts them in
+//! reverse order, so the reader doesn't need to know how the payload was
+//! written. Splitting a sealed envelope across chunks is done afterwards by the
+//! [split](../split/index.html) module.
+//!
+//! Passphrases, derived keys, and the payload on its way through the
+//! transforms are wiped from memory once they're no longer needed. The
+//! payload handed to [seal](struct.Pipeline.html#method.seal) and the one
+//! returned by [open](struct.Pipeline.html#method.open) belong to the caller,
+//! who should wipe them too.
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use flate2::read::{ZlibDecoder, ZlibEncoder};
+use flate2::Compression;
+use rand::RngCore;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use zeroize::Zeroizing;
+
+/// The bytes every envelope starts with.
+pub const ENVELOPE_MAGIC: [u8; 5] = *b"PNGme";
+
+/// The version of the envelope format written by this version of pngme.
+const ENVELOPE_VERSION: u8 = 1;
+
+/// One reversible step in a [Pipeline](struct.Pipeline.html).
+pub trait PayloadTransform {
+    /// A number that identifies this kind of transform in an envelope.
+    /// Built-in transforms use 1 to 127; use 128 and up for your own.
+    fn id(&self) -> u8;
+
+    /// Transform the payload on its way in.
+    fn apply(&self, data: &[u8]) -> crate::Result<Vec<u8>>;
+
+    /// Undo [apply](#tymethod.apply).
+    fn invert(&self, data: &[u8]) -> crate::Result<Vec<u8>>;
+}
+
+/// An ordered list of [PayloadTransform](trait.PayloadTransform.html)s.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn PayloadTransform>>,
+}
+
+impl Pipeline {
+    /// A pipeline with no transforms, which seals the payload as-is.
+    pub fn new() -> Self {
+        Pipeline { stages: Vec::new() }
+    }
+
+    /// Add a transform that runs after every transform already added.
+    pub fn then<T: PayloadTransform + 'static>(mut self, transform: T) -> Self {
+        self.stages.push(Box::new(transform));
+        self
+    }
+
+    /// Run the payload through every transform and wrap it in an envelope.
+    pub fn seal(&self, payload: &[u8]) -> crate::Result<Vec<u8>> {
+        let count = u8::try_from(self.stages.len())
+            .map_err(|_| EnvelopeError::boxed("Too many transforms (at most 255)".to_string()))?;
+        let mut data = Zeroizing::new(payload.to_vec());
+        for stage in &self.stages {
+            data = Zeroizing::new(stage.apply(&data)?);
+        }
+        let mut envelope = ENVELOPE_MAGIC.to_vec();
+        envelope.push(ENVELOPE_VERSION);
+        envelope.push(count);
+        envelope.extend(self.stages.iter().map(|s| s.id()));
+        envelope.extend_from_slice(&data);
+        Ok(envelope)
+    }
+
+    /// Does this data look like an envelope written by [seal](#method.seal)?
+    /// It has to start with the envelope's magic, a version this version of
+    /// pngme writes, and room for every transform ID, so that a plain message
+    /// that happens to start with "PNGme" isn't taken for one.
+    pub fn is_envelope(data: &[u8]) -> bool {
+        match data.strip_prefix(&ENVELOPE_MAGIC[..]) {
+            Some([ENVELOPE_VERSION, count, rest @ ..]) => rest.len() >= usize::from(*count),
+            _ => false,
+        }
+    }
+
+    /// `payload` as it should be stored without any transforms: as-is, unless
+    /// it looks like an envelope, in which case it's sealed in one with no
+    /// transforms so that it's read back unchanged.
+    pub fn plain(payload: Vec<u8>) -> crate::Result<Vec<u8>> {
+        if Self::is_envelope(&payload) {
+            Self::new().seal(&payload)
+        } else {
+            Ok(payload)
+        }
+    }
+
+    /// Unwrap an envelope and invert every transform recorded in it, last one
+    /// first. `resolve` turns each recorded transform ID back into a transform;
+    /// it is only called for IDs that are actually in the envelope, so it can
+    /// do things like prompt for a passphrase only when one is needed.
+    pub fn open<F>(envelope: &[u8], mut resolve: F) -> crate::Result<Vec<u8>>
+    where
+        F: FnMut(u8) -> crate::Result<Box<dyn PayloadTransform>>,
+    {
+        if !envelope.starts_with(&ENVELOPE_MAGIC) {
+            return Err(EnvelopeError::boxed("Missing envelope header".to_string()));
+        }
+        let rest = &envelope[ENVELOPE_MAGIC.len()..];
+        let (version, count, rest) = match rest {
+            [version, count, rest @ ..] => (*version, usize::from(*count), rest),
+            _ => return Err(EnvelopeError::boxed("Envelope is truncated".to_string())),
+        };
+        if version != ENVELOPE_VERSION {
+            return Err(EnvelopeError::boxed(format!(
+                "Unknown envelope version {}",
+                version
+            )));
+        }
+        if rest.len() < count {
+            return Err(EnvelopeError::boxed("Envelope is truncated".to_string()));
+        }
+        let (ids, data) = rest.split_at(count);
+        let mut data = Zeroizing::new(data.to_vec());
+        for id in ids.iter().rev() {
+            data = Zeroizing::new(resolve(*id)?.invert(&data)?);
+        }
+        Ok(std::mem::take(&mut *data))
+    }
+}
+
+/// Resolves the IDs of the built-in transforms. `passphrase` is called only if
+/// the envelope was encrypted.
+pub fn builtin_transform<P, S>(id: u8, passphrase: P) -> crate::Result<Box<dyn PayloadTransform>>
+where
+    P: FnOnce() -> crate::Result<S>,
+    S: Into<Zeroizing<String>>,
+{
+    match id {
+        Compress::ID => Ok(Box::new(Compress::new())),
+        Pad::ID => Ok(Box::new(Pad)),
+        Encrypt::ID => Ok(Box::new(Encrypt::new(passphrase()?))),
+        _ => Err(EnvelopeError::boxed(format!("Unknown transform {}", id))),
+    }
+}
+
+/// Compresses the payload with zlib, at a level from 0 (no compression) to 9
+/// (smallest, and slowest). The level isn't needed to decompress, so it isn't
+/// recorded.
+pub struct Compress {
+    level: u32,
+}
+
+impl Compress {
+    pub const ID: u8 = 1;
+    /// zlib's own default level, a good balance of size and speed.
+    pub const DEFAULT_LEVEL: u32 = 6;
+    /// The most [sample_entropy](#method.sample_entropy) bits per byte that
+    /// a payload can have and still be worth compressing. Compressed and
+    /// encrypted data is very close to 8.
+    pub const MAX_ENTROPY: f64 = 7.5;
+    /// The most bytes a payload can decompress to. Anything longer is
+    /// rejected rather than filling up memory, since a few hundred KiB of
+    /// compressed data can otherwise decompress to gigabytes. Payloads longer
+    /// than this can't be compressed in the first place.
+    pub const MAX_DECOMPRESSED_LENGTH: u64 = 256 * 1024 * 1024;
+    /// How many bytes are sampled from each of the start, middle, and end of a
+    /// payload to measure its entropy.
+    const SAMPLE_LENGTH: usize = 16 * 1024;
+
+    /// Compress at [DEFAULT_LEVEL](#associatedconstant.DEFAULT_LEVEL).
+    pub fn new() -> Self {
+        Self::with_level(Self::DEFAULT_LEVEL)
+    }
+
+    /// Compress at `level`. Levels above 9 are treated as 9.
+    pub fn with_level(level: u32) -> Self {
+        Compress {
+            level: level.min(9),
+        }
+    }
+
+    /// The Shannon entropy, in bits per byte, of samples from the start,
+    /// middle, and end of `data`. Sampling keeps this quick for big payloads.
+    pub fn sample_entropy(data: &[u8]) -> f64 {
+        let length = Self::SAMPLE_LENGTH.min(data.len() / 3);
+        let samples: [&[u8]; 3] = if length == 0 {
+            [data, &[], &[]]
+        } else {
+            let middle = (data.len() - length) / 2;
+            [
+                &data[..length],
+                &data[middle..middle + length],
+                &data[data.len() - length..],
+            ]
+        };
+        let mut counts = [0u64; 256];
+        for &byte in samples.iter().flat_map(|s| s.iter()) {
+            counts[usize::from(byte)] += 1;
+        }
+        let total: u64 = counts.iter().sum();
+        counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / total as f64;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    /// Whether compressing `data` is likely to make it any smaller. Data that
+    /// is already compressed or encrypted isn't.
+    pub fn is_worthwhile(data: &[u8]) -> bool {
+        Self::sample_entropy(data) <= Self::MAX_ENTROPY
+    }
+}
+
+impl Default for Compress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PayloadTransform for Compress {
+    fn id(&self) -> u8 {
+        Self::ID
+    }
+
+    fn apply(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
+        if data.len() as u64 > Self::MAX_DECOMPRESSED_LENGTH {
+            return Err(EnvelopeError::boxed(format!(
+                "Payload is too long to compress ({} bytes, at most {})",
+                data.len(),
+                Self::MAX_DECOMPRESSED_LENGTH
+            )));
+        }
+        let mut compressed = Vec::new();
+        ZlibEncoder::new(data, Compression::new(self.level)).read_to_end(&mut compressed)?;
+        Ok(compressed)
+    }
+
+    fn invert(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
+        decompress(data, Self::MAX_DECOMPRESSED_LENGTH)
+    }
+}
+
+/// Decompress `data`, as long as it comes to at most `limit` bytes.
+fn decompress(data: &[u8], limit: u64) -> crate::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    ZlibDecoder::new(data)
+        .take(limit + 1)
+        .read_to_end(&mut decompressed)?;
+    if decompressed.len() as u64 > limit {
+        return Err(EnvelopeError::boxed(format!(
+            "Compressed payload is too long once decompressed (more than {} bytes)",
+            limit
+        )));
+    }
+    Ok(decompressed)
+}
+
+/// Pads the payload with random bytes, so that its length only gives away
+/// which bucket it's in. The buckets are powers of two, from
+/// [MIN_BUCKET](#associatedconstant.MIN_BUCKET) bytes up. The real length is
+/// stored in front of the payload as a 4-byte big-endian number.
+///
+/// Padding only hides anything when it's encrypted along with the payload,
+/// so it should come just before [Encrypt](struct.Encrypt.html).
+pub struct Pad;
+
+impl Pad {
+    pub const ID: u8 = 4;
+    /// The smallest bucket, so that short secrets all look alike.
+    pub const MIN_BUCKET: usize = 256;
+    const LENGTH_LENGTH: usize = 4;
+
+    /// How many bytes `length` bytes of payload take up once padded.
+    pub fn bucket(length: usize) -> usize {
+        (length + Self::LENGTH_LENGTH)
+            .next_power_of_two()
+            .max(Self::MIN_BUCKET)
+    }
+}
+
+impl PayloadTransform for Pad {
+    fn id(&self) -> u8 {
+        Self::ID
+    }
+
+    fn apply(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
+        let length = u32::try_from(data.len())
+            .map_err(|_| EnvelopeError::boxed("Payload is too long to pad".to_string()))?;
+        let mut padded = vec![0u8; Self::bucket(data.len())];
+        let (prefix, rest) = padded.split_at_mut(Self::LENGTH_LENGTH);
+        prefix.copy_from_slice(&length.to_be_bytes());
+        rest[..data.len()].copy_from_slice(data);
+        rand::thread_rng().fill_bytes(&mut rest[data.len()..]);
+        Ok(padded)
+    }
+
+    fn invert(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
+        let truncated = || EnvelopeError::boxed("Padded payload is truncated".to_string());
+        if data.len() < Self::LENGTH_LENGTH {
+            return Err(truncated());
+        }
+        let (prefix, rest) = data.split_at(Self::LENGTH_LENGTH);
+        let length = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+        rest.get(..length)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| truncated() as crate::Error)
+    }
+}
+
+/// How much work it takes to turn a passphrase into a key. More memory and
+/// more iterations make guessing passphrases slower, but also make encrypting
+/// and decrypting slower.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// How much memory Argon2id uses, in KiB.
+    pub memory_kib: u32,
+    /// How many passes Argon2id makes over that memory.
+    pub iterations: u32,
+}
+
+impl KdfParams {
+    /// The most memory, in KiB, a key is derived with: 1 GiB. The settings are
+    /// read from the payload, so without a ceiling a doctored PNG could ask
+    /// for terabytes.
+    pub const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+    /// The most passes a key is derived with.
+    pub const MAX_ITERATIONS: u32 = 64;
+
+    /// Returns `Err` if these settings are above
+    /// [MAX_MEMORY_KIB](#associatedconstant.MAX_MEMORY_KIB) or
+    /// [MAX_ITERATIONS](#associatedconstant.MAX_ITERATIONS).
+    pub fn check(self) -> crate::Result<()> {
+        if self.memory_kib > Self::MAX_MEMORY_KIB {
+            return Err(EnvelopeError::boxed(format!(
+                "Bad KDF settings: {} KiB of memory is more than the most allowed ({} KiB)",
+                self.memory_kib,
+                Self::MAX_MEMORY_KIB
+            )));
+        }
+        if self.iterations > Self::MAX_ITERATIONS {
+            return Err(EnvelopeError::boxed(format!(
+                "Bad KDF settings: {} passes is more than the most allowed ({})",
+                self.iterations,
+                Self::MAX_ITERATIONS
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for KdfParams {
+    /// The defaults recommended by the `argon2` crate: 19 MiB and 2 passes.
+    fn default() -> Self {
+        KdfParams {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+        }
+    }
+}
+
+/// Encrypts the payload with ChaCha20-Poly1305, using a key derived from a
+/// passphrase and a random salt with Argon2id. The KDF parameters, salt, and
+/// nonce are stored in front of the ciphertext, so that the key can be derived
+/// again without knowing how it was encrypted.
+///
+/// (Transform ID 2 was an older encryption that used a plain SHA-256 of the
+/// passphrase as the key. It is no longer supported.)
+pub struct Encrypt {
+    passphrase: Zeroizing<String>,
+    kdf: KdfParams,
+}
+
+impl Encrypt {
+    pub const ID: u8 = 3;
+    const PARAMS_LENGTH: usize = 8;
+    const SALT_LENGTH: usize = 16;
+    const NONCE_LENGTH: usize = 12;
+
+    /// Encrypt with the default [KdfParams](struct.KdfParams.html).
+    pub fn new<S: Into<Zeroizing<String>>>(passphrase: S) -> Self {
+        Self::with_kdf(passphrase, KdfParams::default())
+    }
+
+    pub fn with_kdf<S: Into<Zeroizing<String>>>(passphrase: S, kdf: KdfParams) -> Self {
+        Encrypt {
+            passphrase: passphrase.into(),
+            kdf,
+        }
+    }
+
+    fn cipher(&self, kdf: KdfParams, salt: &[u8]) -> crate::Result<ChaCha20Poly1305> {
+        kdf.check()?;
+        let bad_params =
+            |e: argon2::Error| EnvelopeError::boxed(format!("Bad KDF settings: {}", e));
+        let params =
+            Params::new(kdf.memory_kib, kdf.iterations, 1, Some(32)).map_err(bad_params)?;
+        // The cipher wipes its own copy of the key when it's dropped.
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(self.passphrase.as_bytes(), salt, &mut *key)
+            .map_err(bad_params)?;
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&*key)))
+    }
+}
+
+impl PayloadTransform for Encrypt {
+    fn id(&self) -> u8 {
+        Self::ID
+    }
+
+    fn apply(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
+        let mut rng = rand::thread_rng();
+        let mut salt = [0u8; Self::SALT_LENGTH];
+        let mut nonce = [0u8; Self::NONCE_LENGTH];
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher(self.kdf, &salt)?
+            .encrypt(Nonce::from_slice(&nonce), data)
+            .map_err(|_| EnvelopeError::boxed("Could not encrypt payload".to_string()))?;
+        Ok([
+            &self.kdf.memory_kib.to_be_bytes()[..],
+            &self.kdf.iterations.to_be_bytes()[..],
+            &salt[..],
+            &nonce[..],
+            &ciphertext[..],
+        ]
+        .concat())
+    }
+
+    fn invert(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
+        if data.len() < Self::PARAMS_LENGTH + Self::SALT_LENGTH + Self::NONCE_LENGTH {
+            return Err(EnvelopeError::boxed(
+                "Encrypted payload is truncated".to_string(),
+            ));
+        }
+        let (params, rest) = data.split_at(Self::PARAMS_LENGTH);
+        let (salt, rest) = rest.split_at(Self::SALT_LENGTH);
+        let (nonce, ciphertext) = rest.split_at(Self::NONCE_LENGTH);
+        let kdf = KdfParams {
+            memory_kib: u32::from_be_bytes([params[0], params[1], params[2], params[3]]),
+            iterations: u32::from_be_bytes([params[4], params[5], params[6], params[7]]),
+        };
+        let plaintext = self
+            .cipher(kdf, salt)?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                EnvelopeError::boxed(
+                    "Could not decrypt payload (wrong passphrase or damaged data)".to_string(),
+                )
+            })?;
+        Ok(plaintext)
+    }
+}
+
+/// Something went wrong while sealing or opening an envelope.
+#[derive(Debug)]
+pub struct EnvelopeError {
+    /// Why the envelope could not be sealed or opened.
+    reason: String,
+}
+impl EnvelopeError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad envelope: {}", self.reason)
+    }
+}
+impl Error for EnvelopeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(id: u8) -> crate::Result<Box<dyn PayloadTransform>> {
+        builtin_transform(id, || Ok("hunter2".to_string()))
+    }
+
+    /// The default KDF settings are deliberately slow, so use cheap ones.
+    fn encrypt(passphrase: &str) -> Encrypt {
+        let kdf = KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+        };
+        Encrypt::with_kdf(passphrase.to_string(), kdf)
+    }
+
+    #[test]
+    fn test_empty_pipeline() {
+        let envelope = Pipeline::new().seal(b"hello").unwrap();
+        assert_eq!(envelope, b"PNGme\x01\x00hello");
+        assert_eq!(Pipeline::open(&envelope, resolve).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_compress_then_encrypt() {
+        let payload = b"hello hello hello hello hello hello".to_vec();
+        let envelope = Pipeline::new()
+            .then(Compress::new())
+            .then(encrypt("hunter2"))
+            .seal(&payload)
+            .unwrap();
+        assert!(Pipeline::is_envelope(&envelope));
+        assert_eq!(&envelope[5..9], &[1, 2, Compress::ID, Encrypt::ID]);
+        assert_eq!(Pipeline::open(&envelope, resolve).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let envelope = Pipeline::new()
+            .then(encrypt("hunter2"))
+            .seal(b"hello")
+            .unwrap();
+        let result = Pipeline::open(&envelope, |id| {
+            builtin_transform(id, || Ok("hunter3".to_string()))
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_wiped_passphrase() {
+        let envelope = Pipeline::new()
+            .then(encrypt("hunter2"))
+            .seal(b"hello")
+            .unwrap();
+        let opened = Pipeline::open(&envelope, |id| {
+            builtin_transform(id, || Ok(Zeroizing::new("hunter2".to_string())))
+        });
+        assert_eq!(opened.unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_passphrase_only_requested_when_needed() {
+        let envelope = Pipeline::new()
+            .then(Compress::new())
+            .seal(b"hello")
+            .unwrap();
+        let result = Pipeline::open(&envelope, |id| {
+            builtin_transform::<_, String>(id, || panic!("Asked for a passphrase"))
+        });
+        assert_eq!(result.unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_kdf_params_are_recorded() {
+        let kdf = KdfParams {
+            memory_kib: 128,
+            iterations: 3,
+        };
+        let envelope = Pipeline::new()
+            .then(Encrypt::with_kdf("hunter2".to_string(), kdf))
+            .seal(b"hello")
+            .unwrap();
+        // The magic, version, stage count, and stage ID come first.
+        assert_eq!(&envelope[8..12], &128u32.to_be_bytes());
+        assert_eq!(&envelope[12..16], &3u32.to_be_bytes());
+        assert_eq!(Pipeline::open(&envelope, resolve).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_bad_kdf_params() {
+        let kdf = KdfParams {
+            memory_kib: 64,
+            iterations: 0,
+        };
+        let result = Pipeline::new()
+            .then(Encrypt::with_kdf("hunter2".to_string(), kdf))
+            .seal(b"hello");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_oversized_kdf_params() {
+        let envelope = Pipeline::new()
+            .then(encrypt("hunter2"))
+            .seal(b"hello")
+            .unwrap();
+        // The KDF settings come right after the header.
+        let mut doctored = envelope.clone();
+        doctored[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(Pipeline::open(&doctored, resolve).is_err());
+        let mut doctored = envelope;
+        doctored[12..16].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(Pipeline::open(&doctored, resolve).is_err());
+
+        let kdf = KdfParams {
+            memory_kib: KdfParams::MAX_MEMORY_KIB + 1,
+            iterations: 1,
+        };
+        assert!(Pipeline::new()
+            .then(Encrypt::with_kdf("hunter2".to_string(), kdf))
+            .seal(b"hello")
+            .is_err());
+    }
+
+    #[test]
+    fn test_compression_levels() {
+        let payload = b"hello hello hello hello hello hello".repeat(100);
+        let stored = Compress::with_level(0).apply(&payload).unwrap();
+        let smallest = Compress::with_level(9).apply(&payload).unwrap();
+        assert!(stored.len() > payload.len());
+        assert!(smallest.len() < payload.len() / 10);
+        // The level doesn't matter to decompressing.
+        assert_eq!(Compress::new().invert(&stored).unwrap(), payload);
+        assert_eq!(Compress::new().invert(&smallest).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_compression_worthwhile() {
+        let text = b"This is a secret message! ".repeat(10_000);
+        assert!(Compress::is_worthwhile(&text));
+        let mut random = vec![0u8; 100_000];
+        rand::thread_rng().fill_bytes(&mut random);
+        assert!(!Compress::is_worthwhile(&random));
+        assert!(Compress::is_worthwhile(b""));
+        assert_eq!(Compress::sample_entropy(&[7; 10]), 0.0);
+        assert_eq!(Compress::sample_entropy(&[0, 1, 0, 1, 0, 1]), 1.0);
+    }
+
+    #[test]
+    fn test_pad_hides_length() {
+        let seal = |payload: &[u8]| {
+            Pipeline::new()
+                .then(Pad)
+                .then(encrypt("hunter2"))
+                .seal(payload)
+                .unwrap()
+        };
+        let short = seal(b"a");
+        let longer = seal(&[b'a'; 200]);
+        assert_eq!(short.len(), longer.len());
+        assert_eq!(Pipeline::open(&longer, resolve).unwrap(), vec![b'a'; 200]);
+        assert!(seal(&[b'a'; 300]).len() > short.len());
+    }
+
+    #[test]
+    fn test_pad_buckets() {
+        assert_eq!(Pad::bucket(0), 256);
+        assert_eq!(Pad::bucket(252), 256);
+        assert_eq!(Pad::bucket(253), 512);
+        let padded = Pad.apply(b"hello").unwrap();
+        assert_eq!(padded.len(), 256);
+        assert_eq!(&padded[..9], b"\0\0\0\x05hello");
+        assert_eq!(Pad.invert(&padded).unwrap(), b"hello");
+        assert!(Pad.invert(&padded[..8]).is_err());
+        assert!(Pad.invert(b"\0\0").is_err());
+    }
+
+    #[test]
+    fn test_custom_transform() {
+        struct Reverse;
+        impl PayloadTransform for Reverse {
+            fn id(&self) -> u8 {
+                200
+            }
+            fn apply(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
+                Ok(data.iter().rev().copied().collect())
+            }
+            fn invert(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
+                self.apply(data)
+            }
+        }
+        let envelope = Pipeline::new().then(Reverse).seal(b"abc").unwrap();
+        assert!(envelope.ends_with(b"cba"));
+        let opened = Pipeline::open(&envelope, |id| match id {
+            200 => Ok(Box::new(Reverse) as Box<dyn PayloadTransform>),
+            _ => resolve(id),
+        });
+        assert_eq!(opened.unwrap(), b"abc");
+        assert!(Pipeline::open(&envelope, resolve).is_err());
+    }
+
+    #[test]
+    fn test_plain_payload_like_envelope() {
+        assert!(!Pipeline::is_envelope(b"PNGme hello"));
+        assert!(!Pipeline::is_envelope(b"PNGme\x01\x03\x01"));
+        assert_eq!(
+            Pipeline::plain(b"PNGme hello".to_vec()).unwrap(),
+            b"PNGme hello"
+        );
+        let ambiguous = b"PNGme\x01\x00hello".to_vec();
+        let stored = Pipeline::plain(ambiguous.clone()).unwrap();
+        assert_eq!(Pipeline::open(&stored, resolve).unwrap(), ambiguous);
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        let compressed = Compress::new().apply(&[0; 2000]).unwrap();
+        assert_eq!(decompress(&compressed, 2000).unwrap(), vec![0; 2000]);
+        assert!(decompress(&compressed, 1999).is_err());
+    }
+
+    #[test]
+    fn test_open_bad_envelopes() {
+        assert!(Pipeline::open(b"hello", resolve).is_err());
+        assert!(Pipeline::open(b"PNGme", resolve).is_err());
+        assert!(Pipeline::open(b"PNGme\x02\x00", resolve).is_err());
+        assert!(Pipeline::open(b"PNGme\x01\x03\x01", resolve).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use crate::output::WriteOptions;
+use crate::paths;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use pngme::{Chunk, ChunkType, Png};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc;
+
+/// The chunks to add to every new PNG, read from a TOML file like:
+///
+/// ```toml
+/// [[chunk]]
+/// type = "ruSt"
+/// message = "Rendered by the asset pipeline"
+/// ```
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct StampConfig {
+    #[serde(rename = "chunk")]
+    chunks: Vec<StampChunk>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct StampChunk {
+    #[serde(rename = "type")]
+    chunk_type: String,
+    message: String,
+}
+
+impl StampConfig {
+    pub fn from_toml(toml: &str) -> crate::Result<Self> {
+        let config: StampConfig = toml::from_str(toml)?;
+        // Check every chunk type up front rather than on the first new file.
+        config.chunks()?;
+        Ok(config)
+    }
+
+    fn chunks(&self) -> crate::Result<Vec<Chunk>> {
+        self.chunks
+            .iter()
+            .map(|c| {
+                let chunk_type: ChunkType = c.chunk_type.parse()?;
+                Ok(Chunk::new(chunk_type, c.message.as_bytes().to_vec()))
+            })
+            .collect()
+    }
+
+    /// The type of every configured chunk.
+    pub fn chunk_types(&self) -> crate::Result<Vec<ChunkType>> {
+        Ok(self.chunks()?.iter().map(|c| *c.chunk_type()).collect())
+    }
+
+    /// Add every configured chunk that the PNG doesn't already have.
+    /// Returns whether anything was added.
+    pub fn stamp(&self, png: &mut Png) -> crate::Result<bool> {
+        let mut changed = false;
+        for chunk in self.chunks()? {
+            let already_stamped = png
+                .chunks()
+                .iter()
+                .any(|c| c.chunk_type() == chunk.chunk_type() && c.data() == chunk.data());
+            if !already_stamped {
+                png.append_chunk(chunk);
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// Watch `dir` forever, stamping every PNG that is created or changed in it.
+pub fn watch(dir: &Path, config: &StampConfig, write_options: WriteOptions) -> crate::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&paths::long(dir), RecursiveMode::NonRecursive)?;
+    eprintln!("Watching {}", dir.display());
+    for event in receiver {
+        let event: Event = event?;
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            continue;
+        }
+        for path in event.paths.iter().filter(|p| is_png(p)) {
+            if let Err(e) = stamp_file(path, config, write_options) {
+                eprintln!("Skipping {}: {}", path.display(), e);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+}
+
+/// Stamp a single file. Stamping is skipped if the file already has every
+/// configured chunk, which also stops us from reacting to our own writes.
+/// A file that is still being written won't parse yet; we'll see it again
+/// when the next write to it finishes.
+fn stamp_file(path: &Path, config: &StampConfig, write_options: WriteOptions) -> crate::Result<()> {
+    let bytes = fs::read(path)?;
+    let mut png = Png::try_from(bytes.as_slice())?;
+    if config.stamp(&mut png)? {
+        write_options.write(path, path, &png.as_bytes())?;
+        eprintln!("Stamped {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [[chunk]]
+        type = "ruSt"
+        message = "Rendered by the asset pipeline"
+
+        [[chunk]]
+        type = "buIl"
+        message = "1234"
+    "#;
+
+    #[test]
+    fn test_stamp() {
+        let config = StampConfig::from_toml(CONFIG).unwrap();
+        let mut png = Png::from_chunks(vec![]);
+        assert!(config.stamp(&mut png).unwrap());
+        assert_eq!(png.chunks().len(), 2);
+        assert!(!config.stamp(&mut png).unwrap());
+        assert_eq!(png.chunks().len(), 2);
+    }
+
+    #[test]
+    fn test_bad_config() {
+        assert!(StampConfig::from_toml("[[chunk]]\ntype = \"ru5t\"\nmessage = \"\"").is_err());
+        assert!(StampConfig::from_toml("[[chunk]]\nmessage = \"\"").is_err());
+    }
+
+    #[test]
+    fn test_stamp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("render.png");
+        fs::write(&path, Png::from_chunks(vec![]).as_bytes()).unwrap();
+        let config = StampConfig::from_toml(CONFIG).unwrap();
+        stamp_file(&path, &config, WriteOptions::default()).unwrap();
+        let png = Png::try_from(fs::read(&path).unwrap().as_slice()).unwrap();
+        assert_eq!(png.chunks().len(), 2);
+    }
+
+    #[test]
+    fn test_is_png() {
+        assert!(is_png(Path::new("a/b.PNG")));
+        assert!(!is_png(Path::new("a/b.png.tmp")));
+    }
+}
@@ -0,0 +1,308 @@
+//! Hiding payloads in WebP files instead of PNGs.
+//!
+//! A WebP is a RIFF file: `RIFF`, the length of the rest of the file as a
+//! 4-byte little-endian number, `WEBP`, and then chunks much like a PNG's,
+//! each a four-character code, its length as a 4-byte little-endian number,
+//! and its data, padded to an even length. Decoders skip chunks they don't
+//! know, so each payload is kept in a chunk of its own, with its chunk type as
+//! the code.
+use crate::carrier::{Format, MetadataCarrier};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::ChunkNotFoundError;
+use std::convert::TryFrom;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+/// The codes of the chunks the WebP format itself uses, which can't be used
+/// for payloads even where they're valid chunk types.
+pub const WEBP_CHUNKS: [&[u8; 4]; 9] = [
+    b"VP8 ", b"VP8L", b"VP8X", b"ALPH", b"ANIM", b"ANMF", b"ICCP", b"EXIF", b"XMP ",
+];
+
+/// Whether `bytes` look like the start of a WebP.
+pub fn is_webp(bytes: &[u8]) -> bool {
+    bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP"
+}
+
+/// One chunk of a WebP.
+pub struct RiffChunk {
+    fourcc: [u8; 4],
+    data: Vec<u8>,
+}
+
+impl RiffChunk {
+    /// The chunk's four-character code, like `VP8 ` or `EXIF`.
+    pub fn fourcc(&self) -> [u8; 4] {
+        self.fourcc
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The payload's chunk type, if this chunk holds a payload rather than
+    /// part of the image or its metadata.
+    pub fn payload_type(&self) -> Option<ChunkType> {
+        if WEBP_CHUNKS.contains(&&self.fourcc) {
+            return None;
+        }
+        ChunkType::try_from(self.fourcc).ok()
+    }
+}
+
+impl fmt::Display for RiffChunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fourcc = String::from_utf8_lossy(&self.fourcc);
+        let length = self.data.len();
+        let bytes = format!("{} byte{}", length, if length == 1 { "" } else { "s" });
+        match &self.fourcc {
+            b"EXIF" => write!(f, "{}\tExif metadata ({})", fourcc, bytes),
+            b"XMP " => write!(f, "{}\t{}", fourcc, String::from_utf8_lossy(&self.data)),
+            b"ICCP" => write!(f, "{}\tICC profile ({})", fourcc, bytes),
+            _ => write!(f, "{}\t[{}]", fourcc, bytes),
+        }
+    }
+}
+
+/// A WebP, as its chunks.
+pub struct Webp {
+    chunks: Vec<RiffChunk>,
+    /// Anything after the end of the RIFF data.
+    trailing_data: Vec<u8>,
+}
+
+impl TryFrom<&[u8]> for Webp {
+    type Error = crate::Error;
+
+    fn try_from(bytes: &[u8]) -> crate::Result<Self> {
+        if !is_webp(bytes) {
+            return Err(WebpError::boxed(
+                "It doesn't start with a RIFF WEBP header".to_string(),
+            ));
+        }
+        let length = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        let end = length
+            .checked_add(8)
+            .filter(|&end| end >= 12 && end <= bytes.len())
+            .ok_or_else(|| {
+                WebpError::boxed(format!(
+                    "The RIFF header says the file is {} bytes long, but it's {}",
+                    length.saturating_add(8),
+                    bytes.len()
+                )) as crate::Error
+            })?;
+        let mut chunks = Vec::new();
+        let mut i = 12;
+        while i < end {
+            let header = bytes
+                .get(i..i + 8)
+                .filter(|_| i + 8 <= end)
+                .ok_or_else(|| {
+                    WebpError::boxed(format!("The chunk at byte {} is cut off", i)) as crate::Error
+                })?;
+            let fourcc: [u8; 4] = header[..4].try_into().unwrap();
+            let length = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+            let data = bytes
+                .get(i + 8..i + 8 + length)
+                .filter(|_| i + 8 + length <= end)
+                .ok_or_else(|| {
+                    WebpError::boxed(format!(
+                        "The {} chunk at byte {} has a bad length ({})",
+                        String::from_utf8_lossy(&fourcc),
+                        i,
+                        length
+                    )) as crate::Error
+                })?;
+            chunks.push(RiffChunk {
+                fourcc,
+                data: data.to_vec(),
+            });
+            // Odd-length chunks are followed by a padding byte.
+            i += 8 + length + length % 2;
+        }
+        Ok(Webp {
+            chunks,
+            trailing_data: bytes[end..].to_vec(),
+        })
+    }
+}
+
+impl Webp {
+    pub fn chunks(&self) -> &[RiffChunk] {
+        &self.chunks
+    }
+
+    /// The length the RIFF header gives: everything after it, up to the end
+    /// of the last chunk.
+    fn riff_length(&self) -> usize {
+        4 + self
+            .chunks
+            .iter()
+            .map(|c| 8 + c.data.len() + c.data.len() % 2)
+            .sum::<usize>()
+    }
+}
+
+impl MetadataCarrier for Webp {
+    fn format(&self) -> Format {
+        Format::Webp
+    }
+
+    /// Every payload, in order.
+    fn blocks(&self) -> crate::Result<Vec<Chunk>> {
+        Ok(self
+            .chunks
+            .iter()
+            .filter_map(|c| Some(Chunk::new(c.payload_type()?, c.data.clone())))
+            .collect())
+    }
+
+    /// Add a payload of type `chunk_type` after every other chunk. Returns
+    /// `Err` if the WebP format uses that chunk type itself.
+    fn append_block(&mut self, chunk_type: ChunkType, data: &[u8]) -> crate::Result<()> {
+        if WEBP_CHUNKS.contains(&&chunk_type.bytes()) {
+            return Err(WebpError::boxed(format!(
+                "{} is a WebP chunk, so it can't hold a payload",
+                chunk_type
+            )));
+        }
+        let total = self.riff_length() + 8 + data.len() + data.len() % 2;
+        if u32::try_from(total).is_err() {
+            return Err(WebpError::boxed(format!(
+                "Payload is too long ({} bytes) to fit in a RIFF file",
+                data.len()
+            )));
+        }
+        self.chunks.push(RiffChunk {
+            fourcc: chunk_type.bytes(),
+            data: data.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Remove the `n`th (counting from 0) payload of type `chunk_type`, and
+    /// return it.
+    fn remove_block(&mut self, chunk_type: ChunkType, n: usize) -> crate::Result<Chunk> {
+        let present: Vec<ChunkType> = self
+            .chunks
+            .iter()
+            .filter_map(|c| c.payload_type())
+            .collect();
+        let index = self
+            .chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.payload_type() == Some(chunk_type))
+            .map(|(i, _)| i)
+            .nth(n)
+            .ok_or_else(|| ChunkNotFoundError::new(chunk_type, n).with_present(present))?;
+        let removed = self.chunks.remove(index);
+        Ok(Chunk::new(chunk_type, removed.data))
+    }
+
+    /// The chunks that aren't payloads, like the image data and Exif
+    /// metadata, and then the payloads.
+    fn describe(&self) -> crate::Result<Vec<String>> {
+        let chunks = self.chunks.iter().filter(|c| c.payload_type().is_none());
+        let payloads = self.blocks()?;
+        Ok(chunks
+            .map(RiffChunk::to_string)
+            .chain(payloads.iter().map(Chunk::to_string))
+            .collect())
+    }
+
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(self.riff_length() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WEBP");
+        for chunk in &self.chunks {
+            bytes.extend_from_slice(&chunk.fourcc);
+            bytes.extend_from_slice(&(chunk.data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&chunk.data);
+            if chunk.data.len() % 2 == 1 {
+                bytes.push(0);
+            }
+        }
+        bytes.extend_from_slice(&self.trailing_data);
+        bytes
+    }
+}
+
+/// A WebP can't be read, or a payload can't be put in it.
+#[derive(Debug)]
+pub struct WebpError {
+    reason: String,
+}
+impl WebpError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for WebpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad WebP: {}", self.reason)
+    }
+}
+impl Error for WebpError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// A WebP with an extended header, stand-in image data with an odd
+    /// length, and some Exif metadata.
+    fn webp_bytes() -> Vec<u8> {
+        let mut chunks = Vec::new();
+        chunks.extend_from_slice(b"VP8X\x0a\0\0\0");
+        chunks.extend_from_slice(&[0x08, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        chunks.extend_from_slice(b"VP8 \x03\0\0\0\x01\x02\x03\0");
+        chunks.extend_from_slice(b"EXIF\x04\0\0\0II*\0");
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(4 + chunks.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WEBP");
+        bytes.extend_from_slice(&chunks);
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let webp = Webp::try_from(webp_bytes().as_slice()).unwrap();
+        let fourccs: Vec<[u8; 4]> = webp.chunks().iter().map(|c| c.fourcc()).collect();
+        assert_eq!(fourccs, vec![*b"VP8X", *b"VP8 ", *b"EXIF"]);
+        assert_eq!(
+            webp.chunks()[2].to_string(),
+            "EXIF\tExif metadata (4 bytes)"
+        );
+        assert!(webp.blocks().unwrap().is_empty());
+        assert_eq!(webp.as_bytes(), webp_bytes());
+    }
+
+    #[test]
+    fn test_payloads() {
+        let mut webp = Webp::try_from(webp_bytes().as_slice()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        webp.append_block(chunk_type, b"odd").unwrap();
+        webp.append_block(chunk_type, b"even").unwrap();
+        assert!(webp
+            .append_block(ChunkType::from_str("EXIF").unwrap(), b"no")
+            .is_err());
+
+        let mut webp = Webp::try_from(webp.as_bytes().as_slice()).unwrap();
+        assert_eq!(webp.block(chunk_type, 1).unwrap().data(), b"even");
+        assert_eq!(webp.remove_block(chunk_type, 0).unwrap().data(), b"odd");
+        assert_eq!(webp.blocks().unwrap().len(), 1);
+        assert!(webp.block(chunk_type, 1).is_err());
+    }
+
+    #[test]
+    fn test_not_a_webp() {
+        assert!(Webp::try_from(&b"RIFF\0\0\0\0WAVE"[..]).is_err());
+        let mut truncated = webp_bytes();
+        truncated.truncate(30);
+        assert!(Webp::try_from(truncated.as_slice()).is_err());
+    }
+}
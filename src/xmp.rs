@@ -0,0 +1,131 @@
+//! XMP metadata, as Adobe's tools write it into PNGs.
+//!
+//! Part 3 of the XMP specification says where an XMP packet goes in a PNG: a
+//! single `iTXt` chunk with the keyword [KEYWORD], no compression, and an
+//! empty language tag and translated keyword. Readers that look for it
+//! don't check anywhere else, so it's written exactly that way here, and
+//! before the image data so that it can be read without the whole file.
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::codec::{ChunkCodec, InternationalText, InternationalTextCodec};
+use crate::png::Png;
+use std::error::Error;
+use std::fmt;
+
+/// The keyword of the `iTXt` chunk that holds an XMP packet.
+pub const KEYWORD: &str = "XML:com.adobe.xmp";
+
+/// Whether `chunk` holds an XMP packet.
+fn is_xmp(chunk: &Chunk) -> bool {
+    chunk.chunk_type() == &ChunkType::ITXT
+        && chunk.data().starts_with(KEYWORD.as_bytes())
+        && chunk.data().get(KEYWORD.len()) == Some(&0)
+}
+
+/// The XMP packet in `png`, if it has one. Returns `Err` if the chunk it's
+/// in is damaged.
+pub fn read(png: &Png) -> crate::Result<Option<String>> {
+    match png.chunks().iter().find(|c| is_xmp(c)) {
+        Some(chunk) => Ok(Some(InternationalTextCodec.decode(chunk.data())?.text)),
+        None => Ok(None),
+    }
+}
+
+/// Like [read](fn.read.html), but returns `Err` if there's no XMP packet.
+pub fn require(png: &Png) -> crate::Result<String> {
+    read(png)?.ok_or_else(|| XmpError::boxed("There's no XMP packet".to_string()) as crate::Error)
+}
+
+/// Put `packet` in `png`, replacing any XMP packet already there. A new
+/// packet goes just before the image data.
+pub fn write(png: &mut Png, packet: &str) -> crate::Result<()> {
+    let chunk = InternationalTextCodec.to_chunk(&InternationalText {
+        keyword: KEYWORD.to_string(),
+        compressed: false,
+        language: String::new(),
+        translated_keyword: String::new(),
+        text: packet.to_string(),
+    })?;
+    let chunks = png.chunks();
+    let index = chunks
+        .iter()
+        .position(is_xmp)
+        .or_else(|| {
+            chunks
+                .iter()
+                .position(|c| c.chunk_type() == &ChunkType::IDAT)
+        })
+        .or_else(|| {
+            chunks
+                .iter()
+                .rposition(|c| c.chunk_type() == &ChunkType::IEND)
+        })
+        .unwrap_or(chunks.len());
+    // Nothing before `index` is an XMP chunk, so removing the ones after it
+    // leaves it pointing at the same place.
+    png.retain_chunks(|c| !is_xmp(c));
+    png.insert_chunk(index, chunk);
+    Ok(())
+}
+
+/// A PNG doesn't have the XMP packet it should.
+#[derive(Debug)]
+pub struct XmpError {
+    reason: String,
+}
+impl XmpError {
+    fn boxed(reason: String) -> Box<Self> {
+        Box::new(Self { reason })
+    }
+}
+
+impl fmt::Display for XmpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bad XMP: {}", self.reason)
+    }
+}
+impl Error for XmpError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const PACKET: &str = "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\
+        <x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/><?xpacket end=\"w\"?>";
+
+    #[test]
+    fn test_write_then_read() {
+        let mut png = testing::minimal_png();
+        assert_eq!(read(&png).unwrap(), None);
+        assert!(require(&png).is_err());
+        write(&mut png, PACKET).unwrap();
+        assert_eq!(require(&png).unwrap(), PACKET);
+
+        // The chunk is laid out exactly as the XMP spec says.
+        let chunk = &png.chunks()[1];
+        assert_eq!(chunk.chunk_type(), &ChunkType::ITXT);
+        let mut expected = b"XML:com.adobe.xmp\0\0\0\0\0".to_vec();
+        expected.extend_from_slice(PACKET.as_bytes());
+        assert_eq!(chunk.data(), expected.as_slice());
+        assert_eq!(png.chunks()[2].chunk_type(), &ChunkType::IDAT);
+    }
+
+    #[test]
+    fn test_write_replaces() {
+        let mut png = testing::png_with(vec![testing::text_chunk(KEYWORD, "not XMP")]);
+        write(&mut png, "<first/>").unwrap();
+        // A second XMP chunk, which a careless writer could have left.
+        let duplicate = Chunk::new(ChunkType::ITXT, png.chunks()[1].data().to_vec());
+        png.insert_chunk(png.chunks().len() - 1, duplicate);
+        write(&mut png, "<second/>").unwrap();
+        let xmp: Vec<&Chunk> = png.chunks().iter().filter(|c| is_xmp(c)).collect();
+        assert_eq!(xmp.len(), 1);
+        assert_eq!(read(&png).unwrap().as_deref(), Some("<second/>"));
+        // A tEXt chunk with the same keyword isn't XMP, and is left alone.
+        assert!(png
+            .chunks()
+            .iter()
+            .any(|c| c.chunk_type() == &ChunkType::TEXT));
+    }
+}